@@ -140,6 +140,7 @@ pub struct HarmonicNxo {
     ts: u64,
     midi_states: Arc<Vec<AtomicBool>>,
     last_midi_send: Arc<Mutex<Instant>>,
+    virtual_keyboard: VirtualMidiKeyboard,
 }
 
 impl Default for HarmonicNxo {
@@ -153,6 +154,7 @@ impl Default for HarmonicNxo {
             ts: 0,
             midi_states: Arc::new((0..128).map(|_| AtomicBool::new(false)).collect()),
             last_midi_send: Arc::new(Mutex::new(Instant::now())),
+            virtual_keyboard: VirtualMidiKeyboard::new(),
         }
     }
 }
@@ -197,6 +199,9 @@ impl Plugin for HarmonicNxo {
         while let Some(evt) = context.next_event() {
             events.push(evt);
         }
+        while let Some(evt) = self.virtual_keyboard.next_event() {
+            events.push(evt);
+        }
         for (sample_id, mut channels) in buffer.iter_samples().enumerate() {
             self.ts = self.ts.wrapping_add(1);
             for evt in events.iter().filter(|e| e.timing() as usize == sample_id) {
@@ -253,67 +258,77 @@ impl Plugin for HarmonicNxo {
     }
 
     fn editor(&mut self, _async_executor: AsyncExecutor<Self>) -> Option<Box<dyn Editor>> {
+        Some(Box::new(self.create_editor()))
+    }
+}
+
+impl HarmonicNxo {
+    fn create_editor(&self) -> WebViewEditor {
         let params = self.params.clone();
         let midi_states = self.midi_states.clone();
         let last_midi_send = self.last_midi_send.clone();
-        let editor = WebViewEditor::new(HTMLSource::URL("http://localhost:5173"), (1000, 750))
-            .with_developer_mode(true)
-            .with_keyboard_handler(move |event| {
-                println!("keyboard event: {event:#?}");
-                event.key == Key::Escape
-            })
-            .with_event_loop(move |ctx, setter, window| {
-                while let Ok(value) = ctx.next_event() {
-                    if let Ok(action) = serde_json::from_value(value) {
-                        match action {
-                            Action::SetGainDB { gain } => {
-                                setter.begin_set_parameter(&params.gain);
-                                setter.set_parameter(&params.gain, gain);
-                                setter.end_set_parameter(&params.gain);
-                            }
-
-                            Action::Init => {
-                                // no-op
-                            }
-                            Action::QueryCargoPackageVersion => {
-                                ctx.send_json(json!({
+        WebViewEditor::new(
+            HTMLSource::String(include_str!("dev_server_unavailable.html")),
+            (1000, 750),
+        )
+        .with_dev_server("http://localhost:5173")
+        .with_developer_mode(true)
+        .with_data_directory(DataDirectory::Plugin(String::from(Self::NAME)))
+        .with_keyboard_handler(move |event| event.key == Key::Escape)
+        .with_keyboard_policy(KeyboardPolicy::TextInputOnly)
+        .with_virtual_midi_keyboard(self.virtual_keyboard.clone())
+        .with_event_loop(move |ctx, setter, _window| {
+            while let Ok(value) = ctx.next_event() {
+                if let Ok(action) = serde_json::from_value(value) {
+                    match action {
+                        Action::SetGainDB { gain } => {
+                            setter.begin_set_parameter(&params.gain);
+                            setter.set_parameter(&params.gain, gain);
+                            setter.end_set_parameter(&params.gain);
+                        }
+
+                        Action::Init => {
+                            // no-op
+                        }
+                        // Sent again automatically when the dev server reloads the page
+                        Action::QueryCargoPackageVersion => {
+                            ctx.register_state(
+                                "version",
+                                json!({
                                     "type": "RespondCargoPackageVersion",
                                     "version": env!("CARGO_PKG_VERSION")
-                                }));
-                            }
-                            Action::QueryGain => {
-                                ctx.send_json(json!({
-                                    "type": "RespondGain",
-                                    "gain": params.gain.value()
-                                }));
-                            }
-
+                                }),
+                            );
+                        }
+                        Action::QueryGain => {
+                            ctx.send_json(json!({
+                                "type": "RespondGain",
+                                "gain": params.gain.value()
+                            }));
                         }
-                    } else {
-                        panic!("Invalid action received from web UI.")
                     }
+                } else {
+                    panic!("Invalid action received from web UI.")
                 }
+            }
 
-                {
-                    let mut last = last_midi_send.lock().unwrap();
-                    if last.elapsed() >= Duration::from_millis(100) {
-                        let states: Vec<bool> = midi_states
-                            .iter()
-                            .map(|s| s.load(Ordering::Relaxed))
-                            .collect();
-                        ctx.send_json(json!({
-                            "type": "MidiStateUpdate",
-                            "states": states
-                        }));
-                        *last = Instant::now();
-                    }
+            {
+                let mut last = last_midi_send.lock().unwrap();
+                if last.elapsed() >= Duration::from_millis(100) {
+                    let states: Vec<bool> = midi_states
+                        .iter()
+                        .map(|s| s.load(Ordering::Relaxed))
+                        .collect();
+                    ctx.send_json(json!({
+                        "type": "MidiStateUpdate",
+                        "states": states
+                    }));
+                    *last = Instant::now();
                 }
-            });
-        Some(Box::new(editor))
+            }
+        })
     }
-}
 
-impl HarmonicNxo {
     fn garbage_collect(&mut self) {
         self.active_voices
             .retain(|_, &mut i| !self.voices[i].is_released_and_done());
@@ -344,4 +359,131 @@ impl ClapPlugin for HarmonicNxo {
     ];
 }
 
-nih_export_clap!(HarmonicNxo);
\ No newline at end of file
+nih_export_clap!(HarmonicNxo);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nih_plug_webview::testing::{GuiContextEvent, TestHarness};
+
+    fn harness(plugin: &HarmonicNxo) -> TestHarness {
+        TestHarness::new(&plugin.create_editor(), plugin.params.clone())
+    }
+
+    #[test]
+    fn set_gain_is_a_single_gesture() {
+        let plugin = HarmonicNxo::default();
+        let mut harness = harness(&plugin);
+
+        harness.send_message(json!({ "type": "SetGainDB", "gain": -6.0 }));
+        harness.run_frame();
+
+        let events = harness.context().take_events();
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events[0],
+            GuiContextEvent::BeginSetParameter(String::from("gain"))
+        );
+        match &events[1] {
+            GuiContextEvent::SetParameterNormalized(id, normalized) => {
+                assert_eq!(id, "gain");
+                assert!((normalized - 0.8).abs() < 1e-6);
+            }
+            event => panic!("Unexpected event: {event:?}"),
+        }
+        assert_eq!(
+            events[2],
+            GuiContextEvent::EndSetParameter(String::from("gain"))
+        );
+    }
+
+    #[test]
+    fn query_gain_responds_with_current_value() {
+        let plugin = HarmonicNxo::default();
+        let mut harness = harness(&plugin);
+
+        harness.send_message(json!({ "type": "QueryGain" }));
+        harness.run_frame();
+
+        let messages = harness.take_messages();
+        assert!(messages.contains(&json!({ "type": "RespondGain", "gain": -9.0 })));
+        assert!(harness.context().take_events().is_empty());
+    }
+
+    #[test]
+    fn midi_states_are_sent_periodically() {
+        let plugin = HarmonicNxo::default();
+        plugin.midi_states[60].store(true, Ordering::Relaxed);
+        *plugin.last_midi_send.lock().unwrap() = Instant::now() - Duration::from_secs(1);
+        let mut harness = harness(&plugin);
+
+        harness.run_frame();
+
+        let messages = harness.take_messages();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["type"], "MidiStateUpdate");
+        assert_eq!(messages[0]["states"][60], true);
+        assert_eq!(messages[0]["states"][61], false);
+    }
+
+    #[test]
+    fn version_is_replayed_after_reload() {
+        let plugin = HarmonicNxo::default();
+        let mut harness = harness(&plugin);
+        let version = json!({
+            "type": "RespondCargoPackageVersion",
+            "version": env!("CARGO_PKG_VERSION")
+        });
+
+        harness.send_message(json!({ "type": "QueryCargoPackageVersion" }));
+        harness.run_frame();
+        assert!(harness.take_messages().contains(&version));
+
+        harness.reload_page();
+        harness.run_frame();
+        assert!(harness.take_messages().contains(&version));
+        assert!(harness
+            .take_script_calls()
+            .iter()
+            .any(|call| call.function == "onPluginParamsInternal"));
+    }
+
+    #[test]
+    fn unused_keys_are_forwarded_to_the_host() {
+        let plugin = HarmonicNxo::default();
+        let mut harness = harness(&plugin);
+        let space = KeyboardEvent {
+            key: Key::Character(String::from(" ")),
+            code: Code::Space,
+            ..Default::default()
+        };
+
+        harness.send_key_event(&space);
+        harness.run_frame();
+        assert_eq!(harness.window().forwarded_keys.len(), 1);
+
+        harness.set_text_input_focused(true);
+        harness.send_key_event(&space);
+        harness.run_frame();
+        assert_eq!(harness.window().forwarded_keys.len(), 1);
+    }
+
+    #[test]
+    fn computer_keyboard_plays_notes() {
+        let plugin = HarmonicNxo::default();
+        let mut harness = harness(&plugin);
+
+        harness.send_key_event(&KeyboardEvent {
+            key: Key::Character(String::from("a")),
+            code: Code::KeyA,
+            ..Default::default()
+        });
+        harness.run_frame();
+
+        assert!(harness.window().forwarded_keys.is_empty());
+        assert!(matches!(
+            plugin.virtual_keyboard.next_event(),
+            Some(NoteEvent::NoteOn { note: 60, .. })
+        ));
+    }
+}
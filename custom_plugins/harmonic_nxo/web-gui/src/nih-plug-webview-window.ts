@@ -1,6 +1,45 @@
+export type FileDrop =
+    | { type: "hovered"; paths: string[]; position: [number, number] }
+    | { type: "dropped"; paths: string[]; position: [number, number] }
+    | { type: "cancelled" }
+
+export interface FileDialogOptions {
+    title?: string
+    directory?: string
+    fileName?: string
+    filters?: { name: string; extensions: string[] }[]
+    multiple?: boolean
+    folder?: boolean
+}
+
+export interface SnapshotSlots {
+    numSlots: number
+    activeSlot: number
+    /** Whether a state has been stored in each slot */
+    filled: boolean[]
+}
+
 export interface NIHPlugWebviewWindow {
     
     sendToPlugin:<T extends Record<string, unknown>>(payload: T)=> void
     onPluginMessage?: <T extends Record<string, unknown>>(payload: T)=> void
+    onPluginFileDrop?: (event: FileDrop)=> void
+    /** The plugin's parameter values by ID, sent again whenever the page reloads */
+    pluginParams: Record<string, number | boolean | string>
+    onPluginParams?: (params: Record<string, number | boolean | string>)=> void
+
+    openFileDialog: (options?: FileDialogOptions)=> Promise<string[] | null>
+    saveFileDialog: (options?: FileDialogOptions)=> Promise<string | null>
+    /** Resolves to the file's contents encoded as base64 */
+    readFile: (path: string)=> Promise<string>
+    readTextFile: (path: string)=> Promise<string>
+    clipboardReadText: ()=> Promise<string>
+    clipboardWriteText: (text: string)=> Promise<null>
+    /** Resolves to `null` if the plugin doesn't have snapshot slots */
+    getSnapshots: ()=> Promise<SnapshotSlots | null>
+    storeSnapshot: (slot: number)=> Promise<null>
+    recallSnapshot: (slot: number)=> Promise<null>
+    /** Pass `null` to go back to detecting focused text fields automatically */
+    setTextInputFocused: (focused: boolean | null)=> void
 
-}
\ No newline at end of file
+}
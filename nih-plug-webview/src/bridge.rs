@@ -0,0 +1,297 @@
+//! Built-in IPC requests that the page can make through the `window.*` helpers defined in
+//! `script.js`. These cover things a sandboxed web page can't do by itself: opening native file
+//! dialogs, reading files the user picked or dropped onto the editor, accessing the system
+//! clipboard, and managing the plugin's snapshot slots. Requests are queued from the IPC handler
+//! and answered from [`WindowHandler::on_frame()`][baseview::WindowHandler::on_frame()] so they
+//! always run on the GUI thread.
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use nih_plug::prelude::GuiContext;
+
+use crate::keyboard::PageKeyEvent;
+use crate::transport::{EditorWindow, ParentWindow};
+
+/// The key used to tag bridge requests in messages coming from the page. Messages without this key
+/// or [`PAGE_EVENT_KEY`] are passed on to the editor's event loop as is.
+pub(crate) const BRIDGE_REQUEST_KEY: &str = "__nihPlugBridge";
+/// The key used to tag [`PageEvent`]s. Unlike bridge requests these don't get a response.
+pub(crate) const PAGE_EVENT_KEY: &str = "__nihPlugEvent";
+
+/// A file drag-and-drop event, forwarded to the page's `window.onPluginFileDrop()` callback. Paths
+/// from a [`FileDrop::Dropped`] event can afterwards be read with `window.readFile()`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum FileDrop {
+    /// Files are being dragged over the editor. `position` is in logical pixels relative to the
+    /// editor's top left corner.
+    Hovered {
+        paths: Vec<PathBuf>,
+        position: (f64, f64),
+    },
+    /// Files have been dropped onto the editor.
+    Dropped {
+        paths: Vec<PathBuf>,
+        position: (f64, f64),
+    },
+    /// The drag operation left the editor or was cancelled.
+    Cancelled,
+}
+
+/// A filter for the native file dialogs, e.g. `{ name: "Audio", extensions: ["wav", "flac"] }`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct FileDialogFilter {
+    pub name: String,
+    pub extensions: Vec<String>,
+}
+
+/// Options for [`WindowHandler::open_file_dialog()`][crate::WindowHandler::open_file_dialog()] and
+/// [`WindowHandler::save_file_dialog()`][crate::WindowHandler::save_file_dialog()]. These are also
+/// what the page passes to `window.openFileDialog()` and `window.saveFileDialog()`, with the field
+/// names in camel case.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FileDialogOptions {
+    pub title: Option<String>,
+    /// The directory the dialog starts in.
+    pub directory: Option<PathBuf>,
+    /// The suggested file name. Only used for save dialogs.
+    pub file_name: Option<String>,
+    pub filters: Vec<FileDialogFilter>,
+    /// Allow selecting more than one file. Only used for open dialogs.
+    pub multiple: bool,
+    /// Pick directories instead of files. Only used for open dialogs.
+    pub folder: bool,
+}
+
+/// A request from the page, together with the ID the page uses to match up the response.
+#[derive(Debug, Deserialize)]
+pub(crate) struct BridgeMessage {
+    pub id: u64,
+    #[serde(flatten)]
+    pub request: BridgeRequest,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub(crate) enum BridgeRequest {
+    OpenFileDialog(FileDialogOptions),
+    SaveFileDialog(FileDialogOptions),
+    /// Read a file as a base64 encoded string.
+    ReadFile {
+        path: PathBuf,
+    },
+    ReadTextFile {
+        path: PathBuf,
+    },
+    ClipboardReadText,
+    ClipboardWriteText {
+        text: String,
+    },
+    /// The number of snapshot slots and the active slot, or `null` if the plugin doesn't have
+    /// snapshots.
+    Snapshots,
+    StoreSnapshot {
+        slot: usize,
+    },
+    RecallSnapshot {
+        slot: usize,
+    },
+    /// A request that could not be parsed. This is never sent by the page, but it lets the editor
+    /// reject the page's promise instead of leaving it pending.
+    #[serde(skip_deserializing)]
+    Invalid {
+        error: String,
+    },
+}
+
+/// Notifications sent by `script.js` to the editor.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub(crate) enum PageEvent {
+    /// A text field gained or lost focus, or the page overrode the detection using
+    /// `window.setTextInputFocused()`.
+    TextInputFocus { focused: bool },
+    /// A key was pressed or released while no text field was focused.
+    Key(PageKeyEvent),
+    /// The page has been (re)loaded and `script.js` is ready to receive messages.
+    PageLoaded,
+    /// The webview is about to navigate to a new URL, which includes reloads. Sent by wry's
+    /// navigation handler rather than by the page.
+    #[serde(skip_deserializing)]
+    Navigation { url: String },
+}
+
+/// State for answering [`BridgeRequest`]s. Only accessed from the GUI thread.
+#[derive(Default)]
+pub(crate) struct Bridge {
+    /// The page can only read files the user explicitly handed to it, either by dropping them on
+    /// the editor or by picking them in a file dialog. Otherwise any script running in the webview
+    /// could read arbitrary files from the user's system.
+    granted_paths: RefCell<HashSet<PathBuf>>,
+    /// Created on first use since connecting to the clipboard may fail, for instance when running
+    /// without a display server.
+    clipboard: RefCell<Option<arboard::Clipboard>>,
+}
+
+impl Bridge {
+    /// Allow the page to read these paths.
+    pub fn grant_paths<'a>(&self, paths: impl IntoIterator<Item = &'a PathBuf>) {
+        self.granted_paths
+            .borrow_mut()
+            .extend(paths.into_iter().cloned());
+    }
+
+    /// Handle a request from the page, returning either the JSON value to resolve the page's
+    /// promise with or an error message to reject it with.
+    pub fn handle(
+        &self,
+        window: &dyn EditorWindow,
+        context: &dyn GuiContext,
+        request: BridgeRequest,
+    ) -> Result<Value, String> {
+        match request {
+            BridgeRequest::OpenFileDialog(options) => {
+                let paths = self.open_file_dialog(window, &options);
+                if let Some(paths) = &paths {
+                    self.grant_paths(paths);
+                }
+
+                Ok(serde_json::to_value(paths).expect("Paths are always serializable"))
+            }
+            BridgeRequest::SaveFileDialog(options) => {
+                let path = self.save_file_dialog(window, &options);
+                if let Some(path) = &path {
+                    self.grant_paths([path]);
+                }
+
+                Ok(serde_json::to_value(path).expect("Paths are always serializable"))
+            }
+            BridgeRequest::ReadFile { path } => {
+                let bytes = self.read_granted_file(&path)?;
+                Ok(Value::String(
+                    base64::engine::general_purpose::STANDARD.encode(bytes),
+                ))
+            }
+            BridgeRequest::ReadTextFile { path } => {
+                let bytes = self.read_granted_file(&path)?;
+                String::from_utf8(bytes)
+                    .map(Value::String)
+                    .map_err(|_| format!("'{}' is not a UTF-8 text file", path.display()))
+            }
+            BridgeRequest::ClipboardReadText => self.clipboard_text().map(Value::String),
+            BridgeRequest::ClipboardWriteText { text } => {
+                self.set_clipboard_text(&text).map(|()| Value::Null)
+            }
+            BridgeRequest::Snapshots => Ok(match context.snapshots() {
+                Some(snapshots) => serde_json::json!({
+                    "numSlots": snapshots.num_slots(),
+                    "activeSlot": snapshots.active_slot(),
+                    "filled": (0..snapshots.num_slots())
+                        .map(|slot| snapshots.has_snapshot(slot))
+                        .collect::<Vec<_>>(),
+                }),
+                None => Value::Null,
+            }),
+            BridgeRequest::StoreSnapshot { slot } => {
+                if context.store_snapshot(slot) {
+                    Ok(Value::Null)
+                } else {
+                    Err(format!("Could not store snapshot slot {slot}"))
+                }
+            }
+            BridgeRequest::RecallSnapshot { slot } => {
+                if context.recall_snapshot(slot) {
+                    Ok(Value::Null)
+                } else {
+                    Err(format!("Could not recall snapshot slot {slot}"))
+                }
+            }
+            BridgeRequest::Invalid { error } => Err(error),
+        }
+    }
+
+    pub fn open_file_dialog(
+        &self,
+        window: &dyn EditorWindow,
+        options: &FileDialogOptions,
+    ) -> Option<Vec<PathBuf>> {
+        let dialog = build_dialog(window, options);
+        match (options.folder, options.multiple) {
+            (true, true) => dialog.pick_folders(),
+            (true, false) => dialog.pick_folder().map(|path| vec![path]),
+            (false, true) => dialog.pick_files(),
+            (false, false) => dialog.pick_file().map(|path| vec![path]),
+        }
+    }
+
+    pub fn save_file_dialog(
+        &self,
+        window: &dyn EditorWindow,
+        options: &FileDialogOptions,
+    ) -> Option<PathBuf> {
+        let mut dialog = build_dialog(window, options);
+        if let Some(file_name) = &options.file_name {
+            dialog = dialog.set_file_name(file_name);
+        }
+
+        dialog.save_file()
+    }
+
+    pub fn clipboard_text(&self) -> Result<String, String> {
+        self.with_clipboard(|clipboard| clipboard.get_text())
+    }
+
+    pub fn set_clipboard_text(&self, text: &str) -> Result<(), String> {
+        self.with_clipboard(|clipboard| clipboard.set_text(text))
+    }
+
+    fn with_clipboard<T>(
+        &self,
+        f: impl FnOnce(&mut arboard::Clipboard) -> Result<T, arboard::Error>,
+    ) -> Result<T, String> {
+        let mut clipboard = self.clipboard.borrow_mut();
+        if clipboard.is_none() {
+            *clipboard = Some(
+                arboard::Clipboard::new()
+                    .map_err(|err| format!("Could not access the clipboard: {err}"))?,
+            );
+        }
+
+        f(clipboard.as_mut().unwrap()).map_err(|err| format!("Clipboard error: {err}"))
+    }
+
+    fn read_granted_file(&self, path: &Path) -> Result<Vec<u8>, String> {
+        if !self.granted_paths.borrow().contains(path) {
+            return Err(format!(
+                "'{}' has not been dropped onto the editor or selected in a file dialog",
+                path.display()
+            ));
+        }
+
+        std::fs::read(path).map_err(|err| format!("Could not read '{}': {err}", path.display()))
+    }
+}
+
+fn build_dialog(window: &dyn EditorWindow, options: &FileDialogOptions) -> rfd::FileDialog {
+    let mut dialog = rfd::FileDialog::new();
+    if let Some(handle) = window.raw_handle() {
+        dialog = dialog.set_parent(&ParentWindow(handle));
+    }
+    if let Some(title) = &options.title {
+        dialog = dialog.set_title(title);
+    }
+    if let Some(directory) = &options.directory {
+        dialog = dialog.set_directory(directory);
+    }
+    for filter in &options.filters {
+        dialog = dialog.add_filter(&filter.name, filter.extensions.as_slice());
+    }
+
+    dialog
+}
@@ -0,0 +1,65 @@
+//! Support for loading the editor from a development server, like Vite's, during GUI development.
+//! When the dev server reloads the page, the editor replays the parameter values and the state
+//! registered with [`WindowHandler::register_state()`][crate::WindowHandler::register_state()] so
+//! the new page doesn't have to request everything again.
+
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use wry::http::Uri;
+
+/// How long to wait for the dev server before falling back to the embedded assets. This blocks
+/// opening the editor, so it should stay short.
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Check whether something is listening at the URL's host and port. This doesn't make an HTTP
+/// request, since all that matters is whether the page could be loaded at all.
+pub(crate) fn is_reachable(url: &str) -> bool {
+    let Ok(uri) = url.parse::<Uri>() else {
+        nih_plug::nih_warn!("Invalid dev server URL '{}'", url);
+        return false;
+    };
+    let Some(host) = uri.host() else {
+        return false;
+    };
+    let port = uri
+        .port_u16()
+        .unwrap_or(if uri.scheme_str() == Some("https") {
+            443
+        } else {
+            80
+        });
+
+    let Ok(addresses) = (host, port).to_socket_addrs() else {
+        return false;
+    };
+    addresses
+        .into_iter()
+        .any(|address| TcpStream::connect_timeout(&address, CONNECT_TIMEOUT).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn reachable_dev_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        assert!(is_reachable(&format!("http://127.0.0.1:{port}/")));
+    }
+
+    #[test]
+    fn unreachable_dev_server() {
+        // Binding and immediately dropping the listener gives us a port that's very likely unused
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        assert!(!is_reachable(&format!("http://127.0.0.1:{port}/")));
+        assert!(!is_reachable("not a url"));
+    }
+}
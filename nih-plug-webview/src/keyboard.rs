@@ -0,0 +1,532 @@
+//! Keyboard handling for the editor. Once the webview has keyboard focus, baseview no longer
+//! receives any key events, so the page reports the keys it doesn't need through `script.js`. The
+//! editor then decides based on its [`KeyboardPolicy`] whether those keys should be forwarded to
+//! the host, so shortcuts like space-to-play keep working while the editor is focused.
+
+use crossbeam::queue::ArrayQueue;
+use keyboard_types::{Code, Key, KeyState, KeyboardEvent, Location, Modifiers};
+use nih_plug::prelude::NoteEvent;
+use parking_lot::Mutex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Decides which of the keys pressed while the page has keyboard focus are consumed by the page.
+/// Keys the page doesn't consume are forwarded to the host's window.
+#[derive(Clone)]
+pub enum KeyboardPolicy {
+    /// The page consumes all keys and nothing is forwarded to the host. This is the default.
+    ConsumeAll,
+    /// The page only consumes keys while a text field is focused. All other keys are forwarded to
+    /// the host.
+    TextInputOnly,
+    /// Decide for every key. The second argument indicates whether a text field is focused. Return
+    /// `true` to let the page consume the key, or `false` to forward it to the host.
+    Custom(Arc<dyn Fn(&KeyboardEvent, bool) -> bool + Send + Sync>),
+}
+
+impl KeyboardPolicy {
+    pub fn page_consumes(&self, event: &KeyboardEvent, text_input_focused: bool) -> bool {
+        match self {
+            KeyboardPolicy::ConsumeAll => true,
+            KeyboardPolicy::TextInputOnly => text_input_focused,
+            KeyboardPolicy::Custom(policy) => policy(event, text_input_focused),
+        }
+    }
+}
+
+/// A key event reported by the page. These are only sent while no text field is focused.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PageKeyEvent {
+    key: String,
+    code: String,
+    down: bool,
+    location: u32,
+    repeat: bool,
+    shift_key: bool,
+    ctrl_key: bool,
+    alt_key: bool,
+    meta_key: bool,
+}
+
+impl From<PageKeyEvent> for KeyboardEvent {
+    fn from(event: PageKeyEvent) -> Self {
+        let mut modifiers = Modifiers::empty();
+        modifiers.set(Modifiers::SHIFT, event.shift_key);
+        modifiers.set(Modifiers::CONTROL, event.ctrl_key);
+        modifiers.set(Modifiers::ALT, event.alt_key);
+        modifiers.set(Modifiers::META, event.meta_key);
+
+        KeyboardEvent {
+            state: if event.down {
+                KeyState::Down
+            } else {
+                KeyState::Up
+            },
+            key: Key::from_str(&event.key).unwrap_or(Key::Unidentified),
+            code: Code::from_str(&event.code).unwrap_or(Code::Unidentified),
+            location: match event.location {
+                1 => Location::Left,
+                2 => Location::Right,
+                3 => Location::Numpad,
+                _ => Location::Standard,
+            },
+            modifiers,
+            repeat: event.repeat,
+            is_composing: false,
+        }
+    }
+}
+
+/// Turns the computer keyboard into a MIDI keyboard using the layout most DAWs use: the `A` row
+/// plays the white keys starting at C, the `W` row plays the black keys, `Z` and `X` change the
+/// octave, and `C` and `V` change the velocity. Keys are only used while no text field is focused.
+///
+/// This is a cheap handle that can be cloned. Pass one copy to
+/// [`WebViewEditor::with_virtual_midi_keyboard()`][crate::WebViewEditor::with_virtual_midi_keyboard()]
+/// and keep another one in the plugin to read the notes from in `process()` using
+/// [`next_event()`][Self::next_event()].
+#[derive(Clone)]
+pub struct VirtualMidiKeyboard {
+    /// Notes are sent from the GUI thread to the audio thread through this queue.
+    events: Arc<ArrayQueue<NoteEvent<()>>>,
+    state: Arc<Mutex<VirtualMidiKeyboardState>>,
+    channel: u8,
+}
+
+struct VirtualMidiKeyboardState {
+    /// The MIDI note number of the `A` key.
+    base_note: i32,
+    velocity: f32,
+    /// The notes that are currently held down. A key is always released with the note it started,
+    /// even if the octave has been changed in the meantime.
+    held_notes: HashMap<Code, u8>,
+}
+
+/// The note offsets relative to the keyboard's base note.
+const NOTE_KEYS: [(Code, i32); 18] = [
+    (Code::KeyA, 0),
+    (Code::KeyW, 1),
+    (Code::KeyS, 2),
+    (Code::KeyE, 3),
+    (Code::KeyD, 4),
+    (Code::KeyF, 5),
+    (Code::KeyT, 6),
+    (Code::KeyG, 7),
+    (Code::KeyY, 8),
+    (Code::KeyH, 9),
+    (Code::KeyU, 10),
+    (Code::KeyJ, 11),
+    (Code::KeyK, 12),
+    (Code::KeyO, 13),
+    (Code::KeyL, 14),
+    (Code::KeyP, 15),
+    (Code::Semicolon, 16),
+    (Code::Quote, 17),
+];
+
+const VELOCITY_STEP: f32 = 0.1;
+
+impl Default for VirtualMidiKeyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VirtualMidiKeyboard {
+    /// Create a virtual keyboard that starts at C3 (MIDI note 60), sends on the first channel, and
+    /// has a velocity of 0.8.
+    pub fn new() -> Self {
+        Self {
+            events: Arc::new(ArrayQueue::new(256)),
+            state: Arc::new(Mutex::new(VirtualMidiKeyboardState {
+                base_note: 60,
+                velocity: 0.8,
+                held_notes: HashMap::new(),
+            })),
+            channel: 0,
+        }
+    }
+
+    /// Send notes on this channel, in `0..16`.
+    pub fn with_channel(mut self, channel: u8) -> Self {
+        self.channel = channel.min(15);
+        self
+    }
+
+    /// Get the next note event played on the virtual keyboard. This is realtime-safe and should be
+    /// called from the plugin's `process()` function. The events' timings are always 0.
+    pub fn next_event(&self) -> Option<NoteEvent<()>> {
+        self.events.pop()
+    }
+
+    /// Handle a key event. Returns `true` if the key is used by the virtual keyboard.
+    pub fn handle_key_event(&self, event: &KeyboardEvent) -> bool {
+        // Shortcuts using these modifiers are left alone so they can still reach the host
+        if event
+            .modifiers
+            .intersects(Modifiers::CONTROL | Modifiers::ALT | Modifiers::META)
+        {
+            return false;
+        }
+
+        let mut state = self.state.lock();
+        if let Some(offset) = NOTE_KEYS
+            .iter()
+            .find(|(code, _)| *code == event.code)
+            .map(|(_, offset)| *offset)
+        {
+            match event.state {
+                KeyState::Down if !state.held_notes.contains_key(&event.code) => {
+                    let note = (state.base_note + offset).clamp(0, 127) as u8;
+                    state.held_notes.insert(event.code, note);
+                    self.push_event(NoteEvent::NoteOn {
+                        timing: 0,
+                        voice_id: None,
+                        channel: self.channel,
+                        note,
+                        velocity: state.velocity,
+                    });
+                }
+                // Key repeats are ignored
+                KeyState::Down => (),
+                KeyState::Up => {
+                    if let Some(note) = state.held_notes.remove(&event.code) {
+                        self.push_event(NoteEvent::NoteOff {
+                            timing: 0,
+                            voice_id: None,
+                            channel: self.channel,
+                            note,
+                            velocity: 0.0,
+                        });
+                    }
+                }
+            }
+
+            return true;
+        }
+
+        if event.state == KeyState::Down {
+            match event.code {
+                Code::KeyZ => state.base_note = (state.base_note - 12).max(0),
+                Code::KeyX => state.base_note = (state.base_note + 12).min(120),
+                Code::KeyC => state.velocity = (state.velocity - VELOCITY_STEP).max(VELOCITY_STEP),
+                Code::KeyV => state.velocity = (state.velocity + VELOCITY_STEP).min(1.0),
+                _ => return false,
+            }
+        }
+
+        matches!(
+            event.code,
+            Code::KeyZ | Code::KeyX | Code::KeyC | Code::KeyV
+        )
+    }
+
+    /// Release all held notes, for instance when the editor loses focus or gets closed.
+    pub fn release_all(&self) {
+        let mut state = self.state.lock();
+        for (_, note) in state.held_notes.drain() {
+            self.push_event(NoteEvent::NoteOff {
+                timing: 0,
+                voice_id: None,
+                channel: self.channel,
+                note,
+                velocity: 0.0,
+            });
+        }
+    }
+
+    fn push_event(&self, event: NoteEvent<()>) {
+        if self.events.push(event).is_err() {
+            nih_plug::nih_debug_assert_failure!("The virtual MIDI keyboard's event queue is full");
+        }
+    }
+}
+
+/// Send a key event to the host's window. Returns `false` if this isn't supported on the current
+/// platform or if the key has no native equivalent.
+pub(crate) fn forward_to_host(
+    handle: raw_window_handle::RawWindowHandle,
+    event: &KeyboardEvent,
+) -> bool {
+    match handle {
+        #[cfg(target_os = "windows")]
+        raw_window_handle::RawWindowHandle::Win32(handle) => {
+            windows::forward_to_host(handle.hwnd, event)
+        }
+        #[cfg(target_os = "macos")]
+        raw_window_handle::RawWindowHandle::AppKit(handle) => {
+            macos::forward_to_host(handle.ns_view, event)
+        }
+        // TODO: Forward keys to the host on Linux using `XSendEvent()`
+        _ => false,
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod windows {
+    use keyboard_types::{Code, KeyState, KeyboardEvent};
+    use std::ffi::c_void;
+
+    const GA_ROOT: u32 = 2;
+    const MAPVK_VK_TO_VSC: u32 = 0;
+    const WM_KEYDOWN: u32 = 0x0100;
+    const WM_KEYUP: u32 = 0x0101;
+
+    #[link(name = "user32")]
+    extern "system" {
+        fn GetAncestor(hwnd: *mut c_void, flags: u32) -> *mut c_void;
+        fn MapVirtualKeyW(code: u32, map_type: u32) -> u32;
+        fn PostMessageW(hwnd: *mut c_void, msg: u32, wparam: usize, lparam: isize) -> i32;
+    }
+
+    /// Post the key event to the host's top level window, since that's where most hosts handle
+    /// their shortcuts.
+    pub fn forward_to_host(hwnd: *mut c_void, event: &KeyboardEvent) -> bool {
+        let Some(virtual_key) = virtual_key(event.code) else {
+            return false;
+        };
+
+        unsafe {
+            let root = GetAncestor(hwnd, GA_ROOT);
+            if root.is_null() || root == hwnd {
+                return false;
+            }
+
+            // See the documentation for `WM_KEYDOWN` for the meaning of these bits
+            let scan_code = MapVirtualKeyW(virtual_key, MAPVK_VK_TO_VSC) as isize;
+            let (msg, lparam) = match event.state {
+                KeyState::Down if event.repeat => (WM_KEYDOWN, 1 | (scan_code << 16) | (1 << 30)),
+                KeyState::Down => (WM_KEYDOWN, 1 | (scan_code << 16)),
+                KeyState::Up => (WM_KEYUP, 1 | (scan_code << 16) | (1 << 30) | (1 << 31)),
+            };
+
+            PostMessageW(root, msg, virtual_key as usize, lparam) != 0
+        }
+    }
+
+    fn virtual_key(code: Code) -> Option<u32> {
+        #[rustfmt::skip]
+        let virtual_key = match code {
+            Code::KeyA => 0x41, Code::KeyB => 0x42, Code::KeyC => 0x43, Code::KeyD => 0x44,
+            Code::KeyE => 0x45, Code::KeyF => 0x46, Code::KeyG => 0x47, Code::KeyH => 0x48,
+            Code::KeyI => 0x49, Code::KeyJ => 0x4A, Code::KeyK => 0x4B, Code::KeyL => 0x4C,
+            Code::KeyM => 0x4D, Code::KeyN => 0x4E, Code::KeyO => 0x4F, Code::KeyP => 0x50,
+            Code::KeyQ => 0x51, Code::KeyR => 0x52, Code::KeyS => 0x53, Code::KeyT => 0x54,
+            Code::KeyU => 0x55, Code::KeyV => 0x56, Code::KeyW => 0x57, Code::KeyX => 0x58,
+            Code::KeyY => 0x59, Code::KeyZ => 0x5A,
+            Code::Digit0 => 0x30, Code::Digit1 => 0x31, Code::Digit2 => 0x32, Code::Digit3 => 0x33,
+            Code::Digit4 => 0x34, Code::Digit5 => 0x35, Code::Digit6 => 0x36, Code::Digit7 => 0x37,
+            Code::Digit8 => 0x38, Code::Digit9 => 0x39,
+            Code::Numpad0 => 0x60, Code::Numpad1 => 0x61, Code::Numpad2 => 0x62, Code::Numpad3 => 0x63,
+            Code::Numpad4 => 0x64, Code::Numpad5 => 0x65, Code::Numpad6 => 0x66, Code::Numpad7 => 0x67,
+            Code::Numpad8 => 0x68, Code::Numpad9 => 0x69,
+            Code::NumpadMultiply => 0x6A, Code::NumpadAdd => 0x6B, Code::NumpadSubtract => 0x6D,
+            Code::NumpadDecimal => 0x6E, Code::NumpadDivide => 0x6F, Code::NumpadEnter => 0x0D,
+            Code::F1 => 0x70, Code::F2 => 0x71, Code::F3 => 0x72, Code::F4 => 0x73,
+            Code::F5 => 0x74, Code::F6 => 0x75, Code::F7 => 0x76, Code::F8 => 0x77,
+            Code::F9 => 0x78, Code::F10 => 0x79, Code::F11 => 0x7A, Code::F12 => 0x7B,
+            Code::Space => 0x20, Code::Enter => 0x0D, Code::Tab => 0x09, Code::Escape => 0x1B,
+            Code::Backspace => 0x08, Code::Delete => 0x2E, Code::Insert => 0x2D,
+            Code::Home => 0x24, Code::End => 0x23, Code::PageUp => 0x21, Code::PageDown => 0x22,
+            Code::ArrowLeft => 0x25, Code::ArrowUp => 0x26, Code::ArrowRight => 0x27,
+            Code::ArrowDown => 0x28,
+            Code::ShiftLeft => 0xA0, Code::ShiftRight => 0xA1, Code::ControlLeft => 0xA2,
+            Code::ControlRight => 0xA3, Code::AltLeft => 0xA4, Code::AltRight => 0xA5,
+            Code::Semicolon => 0xBA, Code::Equal => 0xBB, Code::Comma => 0xBC, Code::Minus => 0xBD,
+            Code::Period => 0xBE, Code::Slash => 0xBF, Code::Backquote => 0xC0,
+            Code::BracketLeft => 0xDB, Code::Backslash => 0xDC, Code::BracketRight => 0xDD,
+            Code::Quote => 0xDE,
+            _ => return None,
+        };
+
+        Some(virtual_key)
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use keyboard_types::{Code, Key, KeyState, KeyboardEvent, Modifiers};
+    use objc::runtime::{Object, BOOL, NO, YES};
+    use objc::{class, msg_send, sel, sel_impl};
+    use std::ffi::{c_void, CString};
+
+    const NS_EVENT_TYPE_KEY_DOWN: u64 = 10;
+    const NS_EVENT_TYPE_KEY_UP: u64 = 11;
+    const NS_EVENT_MODIFIER_FLAG_SHIFT: u64 = 1 << 17;
+    const NS_EVENT_MODIFIER_FLAG_CONTROL: u64 = 1 << 18;
+    const NS_EVENT_MODIFIER_FLAG_OPTION: u64 = 1 << 19;
+    const NS_EVENT_MODIFIER_FLAG_COMMAND: u64 = 1 << 20;
+
+    #[repr(C)]
+    struct NSPoint {
+        x: f64,
+        y: f64,
+    }
+
+    /// Synthesize an `NSEvent` for the key and send it to the editor view's next responder, which
+    /// is the host's view the editor is embedded in.
+    pub fn forward_to_host(ns_view: *mut c_void, event: &KeyboardEvent) -> bool {
+        let Some(key_code) = key_code(event.code) else {
+            return false;
+        };
+
+        let characters = match &event.key {
+            Key::Character(characters) => characters.as_str(),
+            Key::Enter => "\r",
+            Key::Tab => "\t",
+            _ => "",
+        };
+        let characters = CString::new(characters).unwrap_or_default();
+
+        let mut modifier_flags = 0;
+        if event.modifiers.contains(Modifiers::SHIFT) {
+            modifier_flags |= NS_EVENT_MODIFIER_FLAG_SHIFT;
+        }
+        if event.modifiers.contains(Modifiers::CONTROL) {
+            modifier_flags |= NS_EVENT_MODIFIER_FLAG_CONTROL;
+        }
+        if event.modifiers.contains(Modifiers::ALT) {
+            modifier_flags |= NS_EVENT_MODIFIER_FLAG_OPTION;
+        }
+        if event.modifiers.contains(Modifiers::META) {
+            modifier_flags |= NS_EVENT_MODIFIER_FLAG_COMMAND;
+        }
+
+        unsafe {
+            let view = ns_view as *mut Object;
+            let window: *mut Object = msg_send![view, window];
+            let next_responder: *mut Object = msg_send![view, nextResponder];
+            if window.is_null() || next_responder.is_null() {
+                return false;
+            }
+
+            let window_number: i64 = msg_send![window, windowNumber];
+            let characters: *mut Object =
+                msg_send![class!(NSString), stringWithUTF8String: characters.as_ptr()];
+            let is_repeat: BOOL = if event.repeat { YES } else { NO };
+            let event_type = match event.state {
+                KeyState::Down => NS_EVENT_TYPE_KEY_DOWN,
+                KeyState::Up => NS_EVENT_TYPE_KEY_UP,
+            };
+            let ns_event: *mut Object = msg_send![
+                class!(NSEvent),
+                keyEventWithType: event_type
+                location: NSPoint { x: 0.0, y: 0.0 }
+                modifierFlags: modifier_flags
+                timestamp: 0.0f64
+                windowNumber: window_number
+                context: std::ptr::null_mut::<Object>()
+                characters: characters
+                charactersIgnoringModifiers: characters
+                isARepeat: is_repeat
+                keyCode: key_code
+            ];
+            if ns_event.is_null() {
+                return false;
+            }
+
+            match event.state {
+                KeyState::Down => {
+                    let () = msg_send![next_responder, keyDown: ns_event];
+                }
+                KeyState::Up => {
+                    let () = msg_send![next_responder, keyUp: ns_event];
+                }
+            }
+        }
+
+        true
+    }
+
+    /// The `kVK_*` virtual key code for the key.
+    fn key_code(code: Code) -> Option<u16> {
+        #[rustfmt::skip]
+        let key_code = match code {
+            Code::KeyA => 0x00, Code::KeyS => 0x01, Code::KeyD => 0x02, Code::KeyF => 0x03,
+            Code::KeyH => 0x04, Code::KeyG => 0x05, Code::KeyZ => 0x06, Code::KeyX => 0x07,
+            Code::KeyC => 0x08, Code::KeyV => 0x09, Code::KeyB => 0x0B, Code::KeyQ => 0x0C,
+            Code::KeyW => 0x0D, Code::KeyE => 0x0E, Code::KeyR => 0x0F, Code::KeyY => 0x10,
+            Code::KeyT => 0x11, Code::KeyO => 0x1F, Code::KeyU => 0x20, Code::KeyI => 0x22,
+            Code::KeyP => 0x23, Code::KeyL => 0x25, Code::KeyJ => 0x26, Code::KeyK => 0x28,
+            Code::KeyN => 0x2D, Code::KeyM => 0x2E,
+            Code::Digit1 => 0x12, Code::Digit2 => 0x13, Code::Digit3 => 0x14, Code::Digit4 => 0x15,
+            Code::Digit6 => 0x16, Code::Digit5 => 0x17, Code::Digit9 => 0x19, Code::Digit7 => 0x1A,
+            Code::Digit8 => 0x1C, Code::Digit0 => 0x1D,
+            Code::Equal => 0x18, Code::Minus => 0x1B, Code::BracketRight => 0x1E,
+            Code::BracketLeft => 0x21, Code::Quote => 0x27, Code::Semicolon => 0x29,
+            Code::Backslash => 0x2A, Code::Comma => 0x2B, Code::Slash => 0x2C,
+            Code::Period => 0x2F, Code::Backquote => 0x32,
+            Code::Enter => 0x24, Code::Tab => 0x30, Code::Space => 0x31, Code::Backspace => 0x33,
+            Code::Escape => 0x35, Code::Delete => 0x75, Code::Home => 0x73, Code::End => 0x77,
+            Code::PageUp => 0x74, Code::PageDown => 0x79,
+            Code::ArrowLeft => 0x7B, Code::ArrowRight => 0x7C, Code::ArrowDown => 0x7D,
+            Code::ArrowUp => 0x7E,
+            Code::F1 => 0x7A, Code::F2 => 0x78, Code::F3 => 0x63, Code::F4 => 0x76,
+            Code::F5 => 0x60, Code::F6 => 0x61, Code::F7 => 0x62, Code::F8 => 0x64,
+            Code::F9 => 0x65, Code::F10 => 0x6D, Code::F11 => 0x67, Code::F12 => 0x6F,
+            _ => return None,
+        };
+
+        Some(key_code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: Code, state: KeyState) -> KeyboardEvent {
+        KeyboardEvent {
+            state,
+            code,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn virtual_keyboard_releases_original_note_after_octave_change() {
+        let keyboard = VirtualMidiKeyboard::new();
+
+        assert!(keyboard.handle_key_event(&key(Code::KeyA, KeyState::Down)));
+        assert!(keyboard.handle_key_event(&key(Code::KeyX, KeyState::Down)));
+        assert!(keyboard.handle_key_event(&key(Code::KeyA, KeyState::Up)));
+        assert!(keyboard.handle_key_event(&key(Code::KeyA, KeyState::Down)));
+
+        assert!(matches!(
+            keyboard.next_event(),
+            Some(NoteEvent::NoteOn { note: 60, .. })
+        ));
+        assert!(matches!(
+            keyboard.next_event(),
+            Some(NoteEvent::NoteOff { note: 60, .. })
+        ));
+        assert!(matches!(
+            keyboard.next_event(),
+            Some(NoteEvent::NoteOn { note: 72, .. })
+        ));
+        assert_eq!(keyboard.next_event(), None);
+    }
+
+    #[test]
+    fn virtual_keyboard_ignores_repeats_and_shortcuts() {
+        let keyboard = VirtualMidiKeyboard::new();
+        let mut repeat = key(Code::KeyS, KeyState::Down);
+        repeat.repeat = true;
+        let mut shortcut = key(Code::KeyS, KeyState::Down);
+        shortcut.modifiers = Modifiers::CONTROL;
+
+        assert!(!keyboard.handle_key_event(&shortcut));
+        assert!(keyboard.handle_key_event(&key(Code::KeyS, KeyState::Down)));
+        assert!(keyboard.handle_key_event(&repeat));
+        assert!(!keyboard.handle_key_event(&key(Code::Space, KeyState::Down)));
+
+        assert!(matches!(
+            keyboard.next_event(),
+            Some(NoteEvent::NoteOn { note: 62, .. })
+        ));
+        assert_eq!(keyboard.next_event(), None);
+    }
+}
@@ -1,326 +1,823 @@
-use baseview::{
-    Event, Size, Window, WindowEvent, WindowHandle, WindowOpenOptions, WindowScalePolicy,
-};
-use nih_plug::prelude::{Editor, GuiContext, ParamSetter};
-use serde_json::Value;
-use std::{
-    borrow::Cow,
-    sync::{
-        atomic::{AtomicU32, Ordering},
-        Arc,
-    },
-};
-use wry::{
-    http::{Request, Response},
-    WebContext, WebView, WebViewBuilder,
-};
-
-use crossbeam::channel::{unbounded, Receiver};
-
-pub use wry::http;
-
-pub use baseview::{DropData, DropEffect, EventStatus, MouseEvent};
-pub use keyboard_types::*;
-
-type EventLoopHandler = dyn Fn(&WindowHandler, ParamSetter, &mut Window) + Send + Sync;
-type KeyboardHandler = dyn Fn(KeyboardEvent) -> bool + Send + Sync;
-type MouseHandler = dyn Fn(MouseEvent) -> EventStatus + Send + Sync;
-type CustomProtocolHandler =
-    dyn Fn(&Request<Vec<u8>>) -> wry::Result<Response<Cow<'static, [u8]>>> + Send + Sync;
-
-pub struct WebViewEditor {
-    source: Arc<HTMLSource>,
-    width: Arc<AtomicU32>,
-    height: Arc<AtomicU32>,
-    event_loop_handler: Arc<EventLoopHandler>,
-    keyboard_handler: Arc<KeyboardHandler>,
-    mouse_handler: Arc<MouseHandler>,
-    custom_protocol: Option<(String, Arc<CustomProtocolHandler>)>,
-    developer_mode: bool,
-    background_color: (u8, u8, u8, u8),
-}
-
-pub enum HTMLSource {
-    String(&'static str),
-    URL(&'static str),
-}
-
-impl WebViewEditor {
-    pub fn new(source: HTMLSource, size: (u32, u32)) -> Self {
-        let width = Arc::new(AtomicU32::new(size.0));
-        let height = Arc::new(AtomicU32::new(size.1));
-        Self {
-            source: Arc::new(source),
-            width,
-            height,
-            developer_mode: false,
-            background_color: (255, 255, 255, 255),
-            event_loop_handler: Arc::new(|_, _, _| {}),
-            keyboard_handler: Arc::new(|_| false),
-            mouse_handler: Arc::new(|_| EventStatus::Ignored),
-            custom_protocol: None,
-        }
-    }
-
-    pub fn with_background_color(mut self, background_color: (u8, u8, u8, u8)) -> Self {
-        self.background_color = background_color;
-        self
-    }
-
-    pub fn with_custom_protocol<F>(mut self, name: String, handler: F) -> Self
-    where
-        F: Fn(&Request<Vec<u8>>) -> wry::Result<Response<Cow<'static, [u8]>>>
-            + 'static
-            + Send
-            + Sync,
-    {
-        self.custom_protocol = Some((name, Arc::new(handler)));
-        self
-    }
-
-    pub fn with_event_loop<F>(mut self, handler: F) -> Self
-    where
-        F: Fn(&WindowHandler, ParamSetter, &mut baseview::Window) + 'static + Send + Sync,
-    {
-        self.event_loop_handler = Arc::new(handler);
-        self
-    }
-
-    pub fn with_developer_mode(mut self, mode: bool) -> Self {
-        self.developer_mode = mode;
-        self
-    }
-
-    pub fn with_keyboard_handler<F>(mut self, handler: F) -> Self
-    where
-        F: Fn(KeyboardEvent) -> bool + Send + Sync + 'static,
-    {
-        self.keyboard_handler = Arc::new(handler);
-        self
-    }
-
-    pub fn with_mouse_handler<F>(mut self, handler: F) -> Self
-    where
-        F: Fn(MouseEvent) -> EventStatus + Send + Sync + 'static,
-    {
-        self.mouse_handler = Arc::new(handler);
-        self
-    }
-}
-
-pub struct WindowHandler {
-    context: Arc<dyn GuiContext>,
-    event_loop_handler: Arc<EventLoopHandler>,
-    keyboard_handler: Arc<KeyboardHandler>,
-    mouse_handler: Arc<MouseHandler>,
-    webview: WebView,
-    events_receiver: Receiver<Value>,
-    pub width: Arc<AtomicU32>,
-    pub height: Arc<AtomicU32>,
-    current_width: u32,
-    current_height: u32,
-}
-
-impl WindowHandler {
-    pub fn resize(&self, window: &mut baseview::Window, width: u32, height: u32) {
-        self.webview.set_bounds(wry::Rect {
-            x: 0,
-            y: 0,
-            width,
-            height,
-        });
-        self.width.store(width, Ordering::Relaxed);
-        self.height.store(height, Ordering::Relaxed);
-        self.context.request_resize();
-        window.resize(Size {
-            width: width as f64,
-            height: height as f64,
-        });
-    }
-
-    pub fn send_json(&self, json: Value) {
-        let json_str = json.to_string();
-        let json_str_quoted =
-            serde_json::to_string(&json_str).expect("Should not fail: the value is always string");
-        self.webview
-            .evaluate_script(&format!("onPluginMessageInternal({});", json_str_quoted))
-            .unwrap();
-    }
-
-    pub fn next_event(&self) -> Result<Value, crossbeam::channel::TryRecvError> {
-        self.events_receiver.try_recv()
-    }
-}
-
-impl baseview::WindowHandler for WindowHandler {
-    fn on_frame(&mut self, window: &mut baseview::Window) {
-        let desired_w = self.width.load(Ordering::Relaxed);
-        let desired_h = self.height.load(Ordering::Relaxed);
-
-        if desired_w != self.current_width || desired_h != self.current_height {
-            self.resize(window, desired_w, desired_h);
-            self.current_width = desired_w;
-            self.current_height = desired_h;
-        }
-
-        let setter = ParamSetter::new(&*self.context);
-        (self.event_loop_handler)(&self, setter, window);
-    }
-
-    fn on_event(&mut self, window: &mut baseview::Window, event: Event) -> EventStatus {
-        match event {
-            Event::Keyboard(event) => {
-                if (self.keyboard_handler)(event) {
-                    EventStatus::Captured
-                } else {
-                    EventStatus::Ignored
-                }
-            }
-            Event::Mouse(mouse_event) => (self.mouse_handler)(mouse_event),
-            Event::Window(window_event) => match window_event {
-                WindowEvent::Resized(window_info) => {
-                    let logical_size = window_info.logical_size();
-                    let width = logical_size.width.round() as u32;
-                    let height = logical_size.height.round() as u32;
-
-                    self.current_width = width;
-                    self.current_height = height;
-
-                    self.webview.set_bounds(wry::Rect {
-                        x: 0,
-                        y: 0,
-                        width,
-                        height,
-                    });
-                    self.width.store(width, Ordering::Relaxed);
-                    self.height.store(height, Ordering::Relaxed);
-                    EventStatus::Captured
-                }
-                _ => EventStatus::Ignored,
-            },
-            _ => EventStatus::Ignored,
-        }
-    }
-}
-
-struct Instance {
-    window_handle: WindowHandle,
-}
-
-impl Drop for Instance {
-    fn drop(&mut self) {
-        self.window_handle.close();
-    }
-}
-
-unsafe impl Send for Instance {}
-
-impl Editor for WebViewEditor {
-    fn can_resize(&self) -> bool {
-        true
-    }
-
-    fn set_size(&self, _width: u32, _height: u32) -> bool {
-        self.width.store(_width, Ordering::Relaxed);
-        self.height.store(_height, Ordering::Relaxed);
-        true
-    }
-
-    fn spawn(
-        &self,
-        parent: nih_plug::prelude::ParentWindowHandle,
-        context: Arc<dyn GuiContext>,
-    ) -> Box<dyn std::any::Any + Send> {
-        let options = WindowOpenOptions {
-            scale: WindowScalePolicy::SystemScaleFactor,
-            size: Size {
-                width: self.width.load(Ordering::Relaxed) as f64,
-                height: self.height.load(Ordering::Relaxed) as f64,
-            },
-            title: "Plug-in".to_owned(),
-        };
-
-        let width = self.width.clone();
-        let height = self.height.clone();
-        let developer_mode = self.developer_mode;
-        let source = self.source.clone();
-        let background_color = self.background_color;
-        let custom_protocol = self.custom_protocol.clone();
-        let event_loop_handler = self.event_loop_handler.clone();
-        let keyboard_handler = self.keyboard_handler.clone();
-        let mouse_handler = self.mouse_handler.clone();
-
-        let window_handle = baseview::Window::open_parented(&parent, options, move |window| {
-            let (events_sender, events_receiver) = unbounded();
-
-            let mut web_context = WebContext::new(Some(std::env::temp_dir()));
-
-            let mut webview_builder = WebViewBuilder::new_as_child(window)
-                .with_bounds(wry::Rect {
-                    x: 0,
-                    y: 0,
-                    width: width.load(Ordering::Relaxed) as u32,
-                    height: height.load(Ordering::Relaxed) as u32,
-                })
-                .with_accept_first_mouse(true)
-                .with_devtools(developer_mode)
-                .with_web_context(&mut web_context)
-                .with_initialization_script(include_str!("script.js"))
-                .with_ipc_handler(move |msg: String| {
-                    if let Ok(json_value) = serde_json::from_str(&msg) {
-                        let _ = events_sender.send(json_value);
-                    } else {
-                        panic!("Invalid JSON from web view: {}.", msg);
-                    }
-                })
-                .with_background_color(background_color);
-
-            if let Some(custom_protocol) = custom_protocol.as_ref() {
-                let handler = custom_protocol.1.clone();
-                webview_builder = webview_builder
-                    .with_custom_protocol(custom_protocol.0.to_owned(), move |request| {
-                        handler(&request).unwrap()
-                    });
-            }
-
-            let webview = match source.as_ref() {
-                HTMLSource::String(html_str) => webview_builder.with_html(*html_str),
-                HTMLSource::URL(url) => webview_builder.with_url(*url),
-            }
-            .unwrap()
-            .build();
-
-            WindowHandler {
-                context,
-                event_loop_handler,
-                webview: webview.unwrap_or_else(|e| panic!("Failed to construct webview. {}", e)),
-                events_receiver,
-                keyboard_handler,
-                mouse_handler,
-                width,
-                height,
-                current_width: 0,
-                current_height: 0,
-            }
-        });
-        return Box::new(Instance { window_handle });
-    }
-
-    fn size(&self) -> (u32, u32) {
-        (
-            self.width.load(Ordering::Relaxed),
-            self.height.load(Ordering::Relaxed),
-        )
-    }
-
-    fn set_scale_factor(&self, _factor: f32) -> bool {
-        // TODO: implement for Windows and Linux
-        return false;
-    }
-
-    fn param_values_changed(&self) {}
-
-    fn param_value_changed(&self, _id: &str, _normalized_value: f32) {}
-
-    fn param_modulation_changed(&self, _id: &str, _modulation_offset: f32) {}
-}
+use baseview::{Event, Size, WindowEvent, WindowHandle, WindowOpenOptions, WindowScalePolicy};
+use nih_plug::prelude::{Editor, GuiContext, ParamSetter};
+use nih_plug::wrapper::state::ParamValue;
+use nih_plug::{nih_error, nih_log, nih_warn};
+use serde_json::Value;
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+use wry::{
+    http::{Request, Response},
+    FileDropEvent, WebContext, WebViewBuilder,
+};
+
+use crossbeam::channel::{unbounded, Receiver, Sender};
+
+mod bridge;
+mod dev_server;
+mod keyboard;
+mod profile;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod transport;
+
+use bridge::{Bridge, BridgeMessage, BridgeRequest, PageEvent, BRIDGE_REQUEST_KEY, PAGE_EVENT_KEY};
+use profile::Profile;
+use transport::NoWebView;
+
+pub use wry::http;
+
+pub use baseview::{DropData, DropEffect, EventStatus, MouseEvent};
+pub use bridge::{FileDialogFilter, FileDialogOptions, FileDrop};
+pub use keyboard::{KeyboardPolicy, VirtualMidiKeyboard};
+pub use keyboard_types::*;
+pub use profile::DataDirectory;
+pub use transport::{EditorWindow, WebViewTransport};
+
+type EventLoopHandler = dyn Fn(&WindowHandler, ParamSetter, &mut dyn EditorWindow) + Send + Sync;
+type KeyboardHandler = dyn Fn(KeyboardEvent) -> bool + Send + Sync;
+type MouseHandler = dyn Fn(MouseEvent) -> EventStatus + Send + Sync;
+type CustomProtocolHandler =
+    dyn Fn(&Request<Vec<u8>>) -> wry::Result<Response<Cow<'static, [u8]>>> + Send + Sync;
+
+pub struct WebViewEditor {
+    source: Arc<HTMLSource>,
+    dev_server_url: Option<&'static str>,
+    width: Arc<AtomicU32>,
+    height: Arc<AtomicU32>,
+    event_loop_handler: Arc<EventLoopHandler>,
+    keyboard_handler: Arc<KeyboardHandler>,
+    keyboard_policy: KeyboardPolicy,
+    virtual_midi_keyboard: Option<VirtualMidiKeyboard>,
+    mouse_handler: Arc<MouseHandler>,
+    custom_protocol: Option<(String, Arc<CustomProtocolHandler>)>,
+    developer_mode: bool,
+    background_color: (u8, u8, u8, u8),
+    data_directory: DataDirectory,
+    isolate_instances: bool,
+}
+
+pub enum HTMLSource {
+    String(&'static str),
+    URL(&'static str),
+}
+
+impl WebViewEditor {
+    pub fn new(source: HTMLSource, size: (u32, u32)) -> Self {
+        let width = Arc::new(AtomicU32::new(size.0));
+        let height = Arc::new(AtomicU32::new(size.1));
+        Self {
+            source: Arc::new(source),
+            dev_server_url: None,
+            width,
+            height,
+            developer_mode: false,
+            background_color: (255, 255, 255, 255),
+            event_loop_handler: Arc::new(|_, _, _| {}),
+            keyboard_handler: Arc::new(|_| false),
+            keyboard_policy: KeyboardPolicy::ConsumeAll,
+            virtual_midi_keyboard: None,
+            mouse_handler: Arc::new(|_| EventStatus::Ignored),
+            custom_protocol: None,
+            data_directory: DataDirectory::default(),
+            isolate_instances: false,
+        }
+    }
+
+    pub fn with_background_color(mut self, background_color: (u8, u8, u8, u8)) -> Self {
+        self.background_color = background_color;
+        self
+    }
+
+    pub fn with_custom_protocol<F>(mut self, name: String, handler: F) -> Self
+    where
+        F: Fn(&Request<Vec<u8>>) -> wry::Result<Response<Cow<'static, [u8]>>>
+            + 'static
+            + Send
+            + Sync,
+    {
+        self.custom_protocol = Some((name, Arc::new(handler)));
+        self
+    }
+
+    pub fn with_event_loop<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&WindowHandler, ParamSetter, &mut dyn EditorWindow) + 'static + Send + Sync,
+    {
+        self.event_loop_handler = Arc::new(handler);
+        self
+    }
+
+    /// Load the page from a development server, e.g. `http://localhost:5173` for Vite, instead of
+    /// from the editor's [`HTMLSource`]. If the dev server can't be reached when the editor is
+    /// opened, the editor falls back to the [`HTMLSource`]. Whenever the page reloads, the current
+    /// parameter values and the state registered with [`WindowHandler::register_state()`] are sent
+    /// to the new page.
+    pub fn with_dev_server(mut self, url: &'static str) -> Self {
+        self.dev_server_url = Some(url);
+        self
+    }
+
+    pub fn with_developer_mode(mut self, mode: bool) -> Self {
+        self.developer_mode = mode;
+        self
+    }
+
+    /// Set the directory the webview stores its local storage, cookies, and cache in. Every plugin
+    /// should use its own directory. Defaults to [`DataDirectory::Temporary`].
+    pub fn with_data_directory(mut self, data_directory: DataDirectory) -> Self {
+        self.data_directory = data_directory;
+        self
+    }
+
+    /// Give every editor instance its own temporary profile inside of the data directory, so
+    /// instances don't share local storage. The profile is removed when the editor is closed, and
+    /// profiles left behind by crashed hosts are cleaned up the next time an editor is opened.
+    pub fn with_instance_isolation(mut self, isolate: bool) -> Self {
+        self.isolate_instances = isolate;
+        self
+    }
+
+    pub fn with_keyboard_handler<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(KeyboardEvent) -> bool + Send + Sync + 'static,
+    {
+        self.keyboard_handler = Arc::new(handler);
+        self
+    }
+
+    /// Decide which keys the page consumes while it has keyboard focus. Keys the page doesn't
+    /// consume are forwarded to the host, so the host's shortcuts keep working while the editor is
+    /// focused. Keys are always passed to the keyboard handler first.
+    pub fn with_keyboard_policy(mut self, policy: KeyboardPolicy) -> Self {
+        self.keyboard_policy = policy;
+        self
+    }
+
+    /// Play notes on a [`VirtualMidiKeyboard`] using the computer keyboard while no text field is
+    /// focused. Keys used by the virtual keyboard are not passed on to the page's policy.
+    pub fn with_virtual_midi_keyboard(mut self, keyboard: VirtualMidiKeyboard) -> Self {
+        self.virtual_midi_keyboard = Some(keyboard);
+        self
+    }
+
+    pub fn with_mouse_handler<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(MouseEvent) -> EventStatus + Send + Sync + 'static,
+    {
+        self.mouse_handler = Arc::new(handler);
+        self
+    }
+
+    /// A copy of this editor's configuration that can be moved into the window's build closure.
+    /// The size and all handlers are shared with the original editor.
+    fn clone_handlers(&self) -> Self {
+        Self {
+            source: self.source.clone(),
+            dev_server_url: self.dev_server_url,
+            width: self.width.clone(),
+            height: self.height.clone(),
+            event_loop_handler: self.event_loop_handler.clone(),
+            keyboard_handler: self.keyboard_handler.clone(),
+            keyboard_policy: self.keyboard_policy.clone(),
+            virtual_midi_keyboard: self.virtual_midi_keyboard.clone(),
+            mouse_handler: self.mouse_handler.clone(),
+            custom_protocol: self.custom_protocol.clone(),
+            developer_mode: self.developer_mode,
+            background_color: self.background_color,
+            data_directory: self.data_directory.clone(),
+            isolate_instances: self.isolate_instances,
+        }
+    }
+}
+
+pub struct WindowHandler {
+    context: Arc<dyn GuiContext>,
+    event_loop_handler: Arc<EventLoopHandler>,
+    keyboard_handler: Arc<KeyboardHandler>,
+    keyboard_policy: KeyboardPolicy,
+    virtual_midi_keyboard: Option<VirtualMidiKeyboard>,
+    mouse_handler: Arc<MouseHandler>,
+    transport: Box<dyn WebViewTransport>,
+    /// Set if the webview could not be created, in which case `transport` is a no-op.
+    webview_error: Option<String>,
+    events_receiver: Receiver<Value>,
+    bridge: Bridge,
+    bridge_receiver: Receiver<BridgeMessage>,
+    page_event_receiver: Receiver<PageEvent>,
+    text_input_focused: Cell<bool>,
+    /// Messages sent to the page again whenever it (re)loads, in the order they were registered.
+    registered_state: RefCell<Vec<(String, Value)>>,
+    file_drop_receiver: Receiver<FileDrop>,
+    pub width: Arc<AtomicU32>,
+    pub height: Arc<AtomicU32>,
+    current_width: u32,
+    current_height: u32,
+    /// The web context and the profile directory need to outlive the webview, so these are dropped
+    /// last.
+    web_context: Option<WebContext>,
+    profile: Option<Profile>,
+}
+
+/// Sends messages from the page's IPC handler to the [`WindowHandler`]. Bridge requests are
+/// separated from the messages meant for the editor's event loop here.
+#[derive(Clone)]
+struct IpcRouter {
+    events_sender: Sender<Value>,
+    bridge_sender: Sender<BridgeMessage>,
+    page_event_sender: Sender<PageEvent>,
+}
+
+impl IpcRouter {
+    fn navigated(&self, url: String) {
+        let _ = self.page_event_sender.send(PageEvent::Navigation { url });
+    }
+
+    /// Malformed messages are logged and dropped. Panicking here would take down the host.
+    fn route(&self, msg: &str) {
+        let mut json_value = match serde_json::from_str::<Value>(msg) {
+            Ok(json_value) => json_value,
+            Err(err) => {
+                nih_error!("Invalid JSON from web view: {} ({}).", msg, err);
+                return;
+            }
+        };
+
+        if let Some(request) = json_value
+            .as_object_mut()
+            .and_then(|object| object.remove(BRIDGE_REQUEST_KEY))
+        {
+            let id = request.get("id").and_then(Value::as_u64);
+            match serde_json::from_value(request) {
+                Ok(request) => {
+                    let _ = self.bridge_sender.send(request);
+                }
+                Err(err) => {
+                    nih_error!("Invalid bridge request from web view: {}.", err);
+
+                    // If the request at least has an ID then the page's promise can be rejected
+                    if let Some(id) = id {
+                        let _ = self.bridge_sender.send(BridgeMessage {
+                            id,
+                            request: BridgeRequest::Invalid {
+                                error: format!("Invalid bridge request: {err}"),
+                            },
+                        });
+                    }
+                }
+            }
+        } else if let Some(event) = json_value
+            .as_object_mut()
+            .and_then(|object| object.remove(PAGE_EVENT_KEY))
+        {
+            match serde_json::from_value(event) {
+                Ok(event) => {
+                    let _ = self.page_event_sender.send(event);
+                }
+                Err(err) => nih_error!("Invalid page event from web view: {}.", err),
+            }
+        } else {
+            let _ = self.events_sender.send(json_value);
+        }
+    }
+}
+
+impl WindowHandler {
+    /// Create the window handler for an editor. The receivers are the other ends of the channels
+    /// fed by the page's IPC handler and wry's file drop handler.
+    fn new(
+        editor: &WebViewEditor,
+        context: Arc<dyn GuiContext>,
+        transport: Box<dyn WebViewTransport>,
+        events_receiver: Receiver<Value>,
+        bridge_receiver: Receiver<BridgeMessage>,
+        page_event_receiver: Receiver<PageEvent>,
+        file_drop_receiver: Receiver<FileDrop>,
+    ) -> Self {
+        Self {
+            context,
+            event_loop_handler: editor.event_loop_handler.clone(),
+            keyboard_handler: editor.keyboard_handler.clone(),
+            keyboard_policy: editor.keyboard_policy.clone(),
+            virtual_midi_keyboard: editor.virtual_midi_keyboard.clone(),
+            mouse_handler: editor.mouse_handler.clone(),
+            transport,
+            webview_error: None,
+            events_receiver,
+            bridge: Bridge::default(),
+            bridge_receiver,
+            page_event_receiver,
+            text_input_focused: Cell::new(false),
+            registered_state: RefCell::new(Vec::new()),
+            file_drop_receiver,
+            width: editor.width.clone(),
+            height: editor.height.clone(),
+            current_width: 0,
+            current_height: 0,
+            web_context: None,
+            profile: None,
+        }
+    }
+
+    pub fn resize(&self, window: &mut dyn EditorWindow, width: u32, height: u32) {
+        self.transport.set_bounds(width, height);
+        self.width.store(width, Ordering::Relaxed);
+        self.height.store(height, Ordering::Relaxed);
+        self.context.request_resize();
+        window.resize(Size {
+            width: width as f64,
+            height: height as f64,
+        });
+    }
+
+    pub fn send_json(&self, json: Value) {
+        self.call_script_function("onPluginMessageInternal", &json);
+    }
+
+    pub fn next_event(&self) -> Result<Value, crossbeam::channel::TryRecvError> {
+        self.events_receiver.try_recv()
+    }
+
+    /// Send `message` to the page like [`send_json()`][Self::send_json()], and send it again every
+    /// time the page (re)loads. Registering another message with the same key replaces the old
+    /// one. This keeps the page in sync during development without it having to request
+    /// everything again after a hot reload.
+    pub fn register_state(&self, key: &str, message: Value) {
+        {
+            let mut registered_state = self.registered_state.borrow_mut();
+            match registered_state.iter_mut().find(|(k, _)| k == key) {
+                Some((_, value)) => *value = message.clone(),
+                None => registered_state.push((key.to_owned(), message.clone())),
+            }
+        }
+
+        self.send_json(message);
+    }
+
+    /// Stop sending the message registered under `key` after reloads.
+    pub fn unregister_state(&self, key: &str) {
+        self.registered_state.borrow_mut().retain(|(k, _)| k != key);
+    }
+
+    /// Show a native file open dialog. This blocks until the dialog is closed. Returns `None` if
+    /// the user cancelled the dialog. The selected files can afterwards also be read by the page.
+    pub fn open_file_dialog(
+        &self,
+        window: &dyn EditorWindow,
+        options: &FileDialogOptions,
+    ) -> Option<Vec<PathBuf>> {
+        let paths = self.bridge.open_file_dialog(window, options)?;
+        self.bridge.grant_paths(&paths);
+
+        Some(paths)
+    }
+
+    /// Show a native file save dialog. This blocks until the dialog is closed. Returns `None` if
+    /// the user cancelled the dialog.
+    pub fn save_file_dialog(
+        &self,
+        window: &dyn EditorWindow,
+        options: &FileDialogOptions,
+    ) -> Option<PathBuf> {
+        let path = self.bridge.save_file_dialog(window, options)?;
+        self.bridge.grant_paths([&path]);
+
+        Some(path)
+    }
+
+    /// The reason the webview could not be created, if it failed to start. On Linux this usually
+    /// means WebKitGTK is not installed. Everything sent to the page is discarded in that case.
+    pub fn webview_error(&self) -> Option<&str> {
+        self.webview_error.as_deref()
+    }
+
+    /// Whether a text field in the page currently has keyboard focus. The page can override the
+    /// automatic detection using `window.setTextInputFocused()`.
+    pub fn text_input_focused(&self) -> bool {
+        self.text_input_focused.get()
+    }
+
+    /// Read the system clipboard's text contents.
+    pub fn clipboard_text(&self) -> Result<String, String> {
+        self.bridge.clipboard_text()
+    }
+
+    /// Replace the system clipboard's contents with `text`.
+    pub fn set_clipboard_text(&self, text: &str) -> Result<(), String> {
+        self.bridge.set_clipboard_text(text)
+    }
+
+    /// Call a global function defined in `script.js` with a JSON argument. The JSON is passed as a
+    /// string literal so it can't be interpreted as arbitrary code.
+    fn call_script_function(&self, function: &str, json: &Value) {
+        let json_str = json.to_string();
+        let json_str_quoted =
+            serde_json::to_string(&json_str).expect("Should not fail: the value is always string");
+        self.transport
+            .evaluate_script(&format!("{}({});", function, json_str_quoted));
+    }
+
+    fn send_file_drop(&self, file_drop: FileDrop) {
+        if let FileDrop::Dropped { paths, .. } = &file_drop {
+            self.bridge.grant_paths(paths);
+        }
+
+        self.call_script_function(
+            "onPluginFileDropInternal",
+            &serde_json::to_value(&file_drop).expect("File drops are always serializable"),
+        );
+    }
+
+    /// Answer the page's queued bridge requests. File dialogs block until they're closed.
+    fn handle_bridge_requests(&self, window: &dyn EditorWindow) {
+        while let Ok(BridgeMessage { id, request }) = self.bridge_receiver.try_recv() {
+            let response = match self.bridge.handle(window, &*self.context, request) {
+                Ok(value) => serde_json::json!({ "id": id, "ok": true, "value": value }),
+                Err(error) => serde_json::json!({ "id": id, "ok": false, "error": error }),
+            };
+            self.call_script_function("onPluginBridgeResponseInternal", &response);
+        }
+    }
+
+    /// Pass a key event to the keyboard handler and the virtual MIDI keyboard. Returns `true` if
+    /// either of them used the key.
+    fn handle_key_event(&self, event: &KeyboardEvent) -> bool {
+        if (self.keyboard_handler)(event.clone()) {
+            return true;
+        }
+
+        match &self.virtual_midi_keyboard {
+            Some(keyboard) if !self.text_input_focused.get() => keyboard.handle_key_event(event),
+            _ => false,
+        }
+    }
+
+    fn handle_page_events(&self, window: &mut dyn EditorWindow) {
+        while let Ok(event) = self.page_event_receiver.try_recv() {
+            match event {
+                PageEvent::TextInputFocus { focused } => {
+                    self.text_input_focused.set(focused);
+                    // Otherwise notes would keep hanging if a text field gets focused while
+                    // playing
+                    if let (true, Some(keyboard)) = (focused, &self.virtual_midi_keyboard) {
+                        keyboard.release_all();
+                    }
+                }
+                PageEvent::Key(event) => {
+                    let event = KeyboardEvent::from(event);
+                    if !self.handle_key_event(&event)
+                        && !self
+                            .keyboard_policy
+                            .page_consumes(&event, self.text_input_focused.get())
+                    {
+                        window.forward_key_to_host(&event);
+                    }
+                }
+                PageEvent::PageLoaded => self.replay_state(),
+                // The old page's focus and held keys don't carry over to the new page
+                PageEvent::Navigation { url } => {
+                    nih_log!("The editor is navigating to '{}'", url);
+                    self.text_input_focused.set(false);
+                    if let Some(keyboard) = &self.virtual_midi_keyboard {
+                        keyboard.release_all();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Send the current parameter values and all registered state to a freshly loaded page.
+    fn replay_state(&self) {
+        let params: serde_json::Map<String, Value> = self
+            .context
+            .get_state()
+            .params
+            .into_iter()
+            .map(|(id, value)| {
+                let value = match value {
+                    ParamValue::F32(value) => Value::from(value),
+                    ParamValue::I32(value) => Value::from(value),
+                    ParamValue::Bool(value) => Value::from(value),
+                    ParamValue::String(value) => Value::from(value),
+                };
+
+                (id, value)
+            })
+            .collect();
+        self.call_script_function("onPluginParamsInternal", &Value::Object(params));
+
+        for (_, message) in self.registered_state.borrow().iter() {
+            self.send_json(message.clone());
+        }
+    }
+
+    /// Forward baseview's drag-and-drop events to the page. These are only sent for drags that
+    /// reach the parent window. Drops onto the webview itself go through wry's file drop handler.
+    fn handle_drag_event(&self, event: MouseEvent) -> EventStatus {
+        match event {
+            MouseEvent::DragEntered {
+                position,
+                data: DropData::Files(paths),
+                ..
+            }
+            | MouseEvent::DragMoved {
+                position,
+                data: DropData::Files(paths),
+                ..
+            } => {
+                self.send_file_drop(FileDrop::Hovered {
+                    paths,
+                    position: (position.x, position.y),
+                });
+                EventStatus::AcceptDrop(DropEffect::Copy)
+            }
+            MouseEvent::DragDropped {
+                position,
+                data: DropData::Files(paths),
+                ..
+            } => {
+                self.send_file_drop(FileDrop::Dropped {
+                    paths,
+                    position: (position.x, position.y),
+                });
+                EventStatus::AcceptDrop(DropEffect::Copy)
+            }
+            MouseEvent::DragLeft => {
+                self.send_file_drop(FileDrop::Cancelled);
+                EventStatus::Ignored
+            }
+            _ => EventStatus::Ignored,
+        }
+    }
+
+    /// The body of [`baseview::WindowHandler::on_frame()`]. This is separate so it can also be
+    /// driven by the `testing` module's harness.
+    fn frame(&mut self, window: &mut dyn EditorWindow) {
+        let desired_w = self.width.load(Ordering::Relaxed);
+        let desired_h = self.height.load(Ordering::Relaxed);
+
+        if desired_w != self.current_width || desired_h != self.current_height {
+            self.resize(window, desired_w, desired_h);
+            self.current_width = desired_w;
+            self.current_height = desired_h;
+        }
+
+        while let Ok(file_drop) = self.file_drop_receiver.try_recv() {
+            self.send_file_drop(file_drop);
+        }
+        self.handle_bridge_requests(window);
+        self.handle_page_events(window);
+
+        let setter = ParamSetter::new(&*self.context);
+        (self.event_loop_handler)(self, setter, window);
+    }
+}
+
+impl baseview::WindowHandler for WindowHandler {
+    fn on_frame(&mut self, window: &mut baseview::Window) {
+        self.frame(window);
+    }
+
+    fn on_event(&mut self, window: &mut baseview::Window, event: Event) -> EventStatus {
+        match event {
+            // These are only received when the webview doesn't have keyboard focus. Ignored events
+            // are passed on to the host by baseview.
+            Event::Keyboard(event) => {
+                if self.handle_key_event(&event) {
+                    EventStatus::Captured
+                } else {
+                    EventStatus::Ignored
+                }
+            }
+            // Drag-and-drop events the mouse handler doesn't handle are forwarded to the page
+            Event::Mouse(mouse_event) => match (self.mouse_handler)(mouse_event.clone()) {
+                EventStatus::Ignored => self.handle_drag_event(mouse_event),
+                status => status,
+            },
+            Event::Window(window_event) => match window_event {
+                WindowEvent::Resized(window_info) => {
+                    let logical_size = window_info.logical_size();
+                    let width = logical_size.width.round() as u32;
+                    let height = logical_size.height.round() as u32;
+
+                    self.current_width = width;
+                    self.current_height = height;
+
+                    self.transport.set_bounds(width, height);
+                    self.width.store(width, Ordering::Relaxed);
+                    self.height.store(height, Ordering::Relaxed);
+                    EventStatus::Captured
+                }
+                _ => EventStatus::Ignored,
+            },
+            _ => EventStatus::Ignored,
+        }
+    }
+}
+
+struct Instance {
+    window_handle: WindowHandle,
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        self.window_handle.close();
+    }
+}
+
+unsafe impl Send for Instance {}
+
+impl Editor for WebViewEditor {
+    fn can_resize(&self) -> bool {
+        true
+    }
+
+    fn set_size(&self, _width: u32, _height: u32) -> bool {
+        self.width.store(_width, Ordering::Relaxed);
+        self.height.store(_height, Ordering::Relaxed);
+        true
+    }
+
+    fn spawn(
+        &self,
+        parent: nih_plug::prelude::ParentWindowHandle,
+        context: Arc<dyn GuiContext>,
+    ) -> Box<dyn std::any::Any + Send> {
+        let options = WindowOpenOptions {
+            scale: WindowScalePolicy::SystemScaleFactor,
+            size: Size {
+                width: self.width.load(Ordering::Relaxed) as f64,
+                height: self.height.load(Ordering::Relaxed) as f64,
+            },
+            title: "Plug-in".to_owned(),
+        };
+
+        let editor = self.clone_handlers();
+
+        let window_handle = baseview::Window::open_parented(&parent, options, move |window| {
+            let WebViewEditor {
+                width,
+                height,
+                developer_mode,
+                source,
+                dev_server_url,
+                background_color,
+                custom_protocol,
+                data_directory,
+                isolate_instances,
+                ..
+            } = &editor;
+
+            let (events_sender, events_receiver) = unbounded();
+            let (bridge_sender, bridge_receiver) = unbounded();
+            let (page_event_sender, page_event_receiver) = unbounded();
+            let (file_drop_sender, file_drop_receiver) = unbounded();
+            let ipc_router = IpcRouter {
+                events_sender,
+                bridge_sender,
+                page_event_sender,
+            };
+            let navigation_router = ipc_router.clone();
+
+            let profile = match Profile::create(data_directory, *isolate_instances) {
+                Ok(profile) => Some(profile),
+                Err(err) => {
+                    nih_error!(
+                        "Could not create the webview's data directory in '{}', using the default \
+                         directory instead: {}",
+                        data_directory.path().display(),
+                        err
+                    );
+                    None
+                }
+            };
+            let mut web_context =
+                WebContext::new(profile.as_ref().map(|profile| profile.path().to_owned()));
+
+            let mut webview_builder = WebViewBuilder::new_as_child(window)
+                .with_bounds(wry::Rect {
+                    x: 0,
+                    y: 0,
+                    width: width.load(Ordering::Relaxed) as u32,
+                    height: height.load(Ordering::Relaxed) as u32,
+                })
+                .with_accept_first_mouse(true)
+                .with_devtools(*developer_mode)
+                .with_web_context(&mut web_context)
+                .with_initialization_script(include_str!("script.js"))
+                .with_ipc_handler(move |msg: String| ipc_router.route(&msg))
+                .with_navigation_handler(move |url: String| {
+                    navigation_router.navigated(url);
+                    true
+                })
+                .with_file_drop_handler(move |event: FileDropEvent| {
+                    let file_drop = match event {
+                        FileDropEvent::Hovered { paths, position } => FileDrop::Hovered {
+                            paths,
+                            position: (position.0 as f64, position.1 as f64),
+                        },
+                        FileDropEvent::Dropped { paths, position } => FileDrop::Dropped {
+                            paths,
+                            position: (position.0 as f64, position.1 as f64),
+                        },
+                        _ => FileDrop::Cancelled,
+                    };
+                    let _ = file_drop_sender.send(file_drop);
+
+                    // This prevents the webview from navigating to the dropped file
+                    true
+                })
+                .with_background_color(*background_color);
+
+            if let Some(custom_protocol) = custom_protocol.as_ref() {
+                let handler = custom_protocol.1.clone();
+                webview_builder = webview_builder
+                    .with_custom_protocol(custom_protocol.0.to_owned(), move |request| {
+                        handler(&request).unwrap()
+                    });
+            }
+
+            let dev_server_url = dev_server_url.filter(|url| {
+                let reachable = dev_server::is_reachable(url);
+                if !reachable {
+                    nih_warn!(
+                        "The dev server at '{}' is not reachable, using the embedded assets \
+                         instead",
+                        url
+                    );
+                }
+
+                reachable
+            });
+            let webview = match (dev_server_url, &**source) {
+                (Some(url), _) => webview_builder.with_url(url),
+                (None, HTMLSource::String(html_str)) => webview_builder.with_html(*html_str),
+                (None, HTMLSource::URL(url)) => webview_builder.with_url(*url),
+            }
+            .and_then(|webview_builder| webview_builder.build());
+
+            // Failing to create the webview, for instance because WebKitGTK is missing, should not
+            // take down the host
+            let (transport, webview_error): (Box<dyn WebViewTransport>, _) = match webview {
+                Ok(webview) => (Box::new(webview), None),
+                Err(err) => {
+                    let message = webview_error_message(&err);
+                    nih_error!("{}", message);
+                    (Box::new(NoWebView), Some(message))
+                }
+            };
+
+            let mut handler = WindowHandler::new(
+                &editor,
+                context,
+                transport,
+                events_receiver,
+                bridge_receiver,
+                page_event_receiver,
+                file_drop_receiver,
+            );
+            handler.webview_error = webview_error;
+            handler.web_context = Some(web_context);
+            handler.profile = profile;
+
+            handler
+        });
+        return Box::new(Instance { window_handle });
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (
+            self.width.load(Ordering::Relaxed),
+            self.height.load(Ordering::Relaxed),
+        )
+    }
+
+    fn set_scale_factor(&self, _factor: f32) -> bool {
+        // TODO: implement for Windows and Linux
+        return false;
+    }
+
+    fn param_values_changed(&self) {}
+
+    fn param_value_changed(&self, _id: &str, _normalized_value: f32) {}
+
+    fn param_modulation_changed(&self, _id: &str, _modulation_offset: f32) {}
+}
+
+fn webview_error_message(err: &wry::Error) -> String {
+    if cfg!(target_os = "linux") {
+        format!(
+            "Failed to construct webview. Make sure WebKitGTK 4.1 (libwebkit2gtk-4.1) is \
+             installed. {}",
+            err
+        )
+    } else {
+        format!("Failed to construct webview. {}", err)
+    }
+}
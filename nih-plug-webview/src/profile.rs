@@ -0,0 +1,304 @@
+//! The webview's data directory, which holds its profile: local storage, IndexedDB, cookies, and
+//! the HTTP cache. Each plugin should use its own directory, since two plugins sharing a profile
+//! will overwrite each other's local storage and can corrupt each other's databases.
+//!
+//! Instances can optionally be isolated from each other. Every editor then gets its own temporary
+//! profile in the `instances` subdirectory, which is removed again when the editor is closed.
+//! These profiles are locked while they're in use, so profiles left behind by a crashed host can
+//! be told apart from profiles used by another running host and cleaned up. Every profile contains
+//! a marker file, and directories without that marker are never removed.
+
+use fs2::FileExt;
+use nih_plug::util::sanitize_file_name;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The name of the directory inside of the data directory that holds the per-instance profiles.
+const INSTANCES_DIR: &str = "instances";
+/// The directory inside of the system's temporary directory that holds the per-instance profiles
+/// when using [`DataDirectory::Temporary`], since that directory is shared with other applications.
+const TEMPORARY_NAMESPACE: &str = "nih-plug-webview";
+/// Written to every per-instance profile. Only directories containing this file are removed when
+/// cleaning up stale profiles.
+const PROFILE_MARKER: &str = ".nih-plug-webview-profile";
+const LOCK_EXTENSION: &str = "lock";
+/// How many new profile names to try when the lock for a new profile can't be acquired.
+const MAX_LOCK_ATTEMPTS: usize = 8;
+
+/// Where the webview stores its data. Set using
+/// [`WebViewEditor::with_data_directory()`][crate::WebViewEditor::with_data_directory()].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum DataDirectory {
+    /// Use the system's temporary directory. This directory is shared with every other plugin that
+    /// uses this option, so it should only be used for editors that don't store anything. This is
+    /// the default.
+    #[default]
+    Temporary,
+    /// Use a directory for this plugin in the user's cache directory, e.g.
+    /// `~/.cache/<name>/webview` on Linux, `~/Library/Caches/<name>/webview` on macOS, and
+    /// `%LOCALAPPDATA%\<name>\webview` on Windows. `name` should be unique to the plugin, for
+    /// instance its name or its CLAP ID.
+    Plugin(String),
+    /// Use this directory. It is created if it doesn't exist yet.
+    Custom(PathBuf),
+}
+
+impl DataDirectory {
+    /// The directory this resolves to. Falls back to the temporary directory if the user's cache
+    /// directory can't be determined.
+    pub fn path(&self) -> PathBuf {
+        match self {
+            DataDirectory::Temporary => std::env::temp_dir(),
+            DataDirectory::Plugin(name) => dirs::cache_dir()
+                .unwrap_or_else(std::env::temp_dir)
+                .join(sanitize_file_name(name))
+                .join("webview"),
+            DataDirectory::Custom(path) => path.clone(),
+        }
+    }
+
+    /// Remove the per-instance profiles in this data directory that are no longer in use, for
+    /// instance because the host crashed while the editor was open. This is done automatically
+    /// when opening an editor with instance isolation enabled.
+    pub fn remove_stale_profiles(&self) -> io::Result<()> {
+        remove_stale_profiles(&self.instances_dir())
+    }
+
+    /// The directory holding the per-instance profiles. This is namespaced when using the shared
+    /// temporary directory.
+    fn instances_dir(&self) -> PathBuf {
+        match self {
+            DataDirectory::Temporary => self.path().join(TEMPORARY_NAMESPACE).join(INSTANCES_DIR),
+            DataDirectory::Plugin(_) | DataDirectory::Custom(_) => self.path().join(INSTANCES_DIR),
+        }
+    }
+}
+
+/// A webview profile that's in use by an editor. For isolated instances this holds the lock on the
+/// profile and removes the profile again when dropped.
+#[derive(Debug)]
+pub(crate) struct Profile {
+    path: PathBuf,
+    /// Only set for per-instance profiles.
+    lock: Option<(File, PathBuf)>,
+}
+
+impl Profile {
+    /// Create the profile for a new editor. If `isolated` is set, this creates a new temporary
+    /// profile inside of the data directory and removes any stale profiles first.
+    pub fn create(data_directory: &DataDirectory, isolated: bool) -> io::Result<Self> {
+        let path = data_directory.path();
+        if !isolated {
+            fs::create_dir_all(&path)?;
+            return Ok(Profile { path, lock: None });
+        }
+
+        let instances_dir = data_directory.instances_dir();
+        fs::create_dir_all(&instances_dir)?;
+        if let Err(err) = remove_stale_profiles(&instances_dir) {
+            nih_plug::nih_warn!(
+                "Could not remove stale webview profiles from '{}': {}",
+                instances_dir.display(),
+                err
+            );
+        }
+
+        // The lock file is created and locked before the profile directory, so a profile directory
+        // without a lock file can safely be considered stale. If the lock can't be acquired, then
+        // another instance is using or cleaning up that lock file, and a fresh profile is used
+        // instead.
+        for _ in 0..MAX_LOCK_ATTEMPTS {
+            let name = unique_instance_name();
+            let lock_path = instances_dir.join(format!("{name}.{LOCK_EXTENSION}"));
+            let Some(lock_file) = try_lock(&lock_path)? else {
+                continue;
+            };
+
+            let path = instances_dir.join(name);
+            fs::create_dir(&path)?;
+            File::create(path.join(PROFILE_MARKER))?;
+
+            return Ok(Profile {
+                path,
+                lock: Some((lock_file, lock_path)),
+            });
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            "Could not lock a new webview profile",
+        ))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Profile {
+    fn drop(&mut self) {
+        if let Some((lock_file, lock_path)) = self.lock.take() {
+            // On Windows WebView2 may still hold on to some of these files for a moment after the
+            // webview has been closed. Anything left behind is removed during the next cleanup.
+            let _ = fs::remove_dir_all(&self.path);
+            drop(lock_file);
+            let _ = fs::remove_file(lock_path);
+        }
+    }
+}
+
+/// Create or open a lock file and lock it without blocking. The file is never truncated, since
+/// another instance may be holding the lock. Returns `None` if the file is already locked, or if it
+/// was removed by another instance's stale profile cleanup before it could be locked.
+fn try_lock(lock_path: &Path) -> io::Result<Option<File>> {
+    let lock_file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(lock_path)?;
+    if lock_file.try_lock_exclusive().is_err() || !lock_path.exists() {
+        return Ok(None);
+    }
+
+    Ok(Some(lock_file))
+}
+
+/// Remove all profiles in `instances_dir` that aren't locked by a running editor. Directories
+/// without a [`PROFILE_MARKER`] were not created by this crate and are left alone.
+fn remove_stale_profiles(instances_dir: &Path) -> io::Result<()> {
+    if !instances_dir.exists() {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(instances_dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            let lock_path = path.with_extension(LOCK_EXTENSION);
+            if path.join(PROFILE_MARKER).exists() && !lock_path.exists() {
+                let _ = fs::remove_dir_all(&path);
+            }
+        } else if path.extension().map_or(false, |ext| ext == LOCK_EXTENSION)
+            && path.with_extension("").join(PROFILE_MARKER).exists()
+        {
+            let lock_file = File::open(&path)?;
+            if lock_file.try_lock_exclusive().is_ok() {
+                let _ = fs::remove_dir_all(path.with_extension(""));
+                drop(lock_file);
+                let _ = fs::remove_file(&path);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A name that's unique across all instances in all processes.
+fn unique_instance_name() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis());
+    format!(
+        "{}-{}-{}",
+        std::process::id(),
+        timestamp,
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_data_directory(name: &str) -> DataDirectory {
+        let path = std::env::temp_dir().join(format!(
+            "nih-plug-webview-test-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&path);
+
+        DataDirectory::Custom(path)
+    }
+
+    #[test]
+    fn isolated_profiles_are_removed_when_dropped() {
+        let data_directory = test_data_directory("drop");
+        let first = Profile::create(&data_directory, true).unwrap();
+        let second = Profile::create(&data_directory, true).unwrap();
+        assert_ne!(first.path(), second.path());
+        assert!(first.path().starts_with(data_directory.path()));
+
+        let first_path = first.path().to_owned();
+        drop(first);
+        assert!(!first_path.exists());
+        assert!(second.path().exists());
+
+        drop(second);
+        let _ = fs::remove_dir_all(data_directory.path());
+    }
+
+    #[test]
+    fn stale_profiles_are_removed() {
+        let data_directory = test_data_directory("stale");
+        let instances_dir = data_directory.path().join(INSTANCES_DIR);
+        let in_use = Profile::create(&data_directory, true).unwrap();
+
+        // A profile left behind by a crash, with an unlocked lock file
+        let stale = instances_dir.join("stale");
+        fs::create_dir(&stale).unwrap();
+        File::create(stale.join(PROFILE_MARKER)).unwrap();
+        File::create(stale.with_extension(LOCK_EXTENSION)).unwrap();
+        // And one whose lock file was removed
+        let orphaned = instances_dir.join("orphaned");
+        fs::create_dir(&orphaned).unwrap();
+        File::create(orphaned.join(PROFILE_MARKER)).unwrap();
+        // Directories not created by this crate are never removed
+        let foreign = instances_dir.join("foreign");
+        fs::create_dir(&foreign).unwrap();
+        File::create(foreign.with_extension(LOCK_EXTENSION)).unwrap();
+
+        data_directory.remove_stale_profiles().unwrap();
+        assert!(!stale.exists());
+        assert!(!stale.with_extension(LOCK_EXTENSION).exists());
+        assert!(!orphaned.exists());
+        assert!(foreign.exists());
+        assert!(foreign.with_extension(LOCK_EXTENSION).exists());
+        assert!(in_use.path().exists());
+
+        drop(in_use);
+        let _ = fs::remove_dir_all(data_directory.path());
+    }
+
+    #[test]
+    fn locked_lock_files_are_not_reused() {
+        let data_directory = test_data_directory("lock");
+        fs::create_dir_all(data_directory.path()).unwrap();
+        let lock_path = data_directory.path().join(format!("test.{LOCK_EXTENSION}"));
+
+        fs::write(&lock_path, "in use").unwrap();
+
+        // Existing lock files are locked without truncating them
+        let lock_file = try_lock(&lock_path).unwrap().unwrap();
+        assert!(try_lock(&lock_path).unwrap().is_none());
+        drop(lock_file);
+        assert_eq!(fs::read_to_string(&lock_path).unwrap(), "in use");
+
+        let _ = fs::remove_dir_all(data_directory.path());
+    }
+
+    #[test]
+    fn temporary_profiles_are_namespaced() {
+        let instances_dir = DataDirectory::Temporary.instances_dir();
+        assert_eq!(
+            instances_dir,
+            std::env::temp_dir()
+                .join(TEMPORARY_NAMESPACE)
+                .join(INSTANCES_DIR)
+        );
+    }
+}
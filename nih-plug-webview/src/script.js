@@ -1,10 +1,146 @@
-window.sendToPlugin = function (msg) {
-  window.ipc.postMessage(JSON.stringify(msg));
-}
-
-window.onPluginMessage = function() {};
-
-window.onPluginMessageInternal = function(msg) {
-  const json = JSON.parse(msg);
-  window.onPluginMessage && window.onPluginMessage(json);
-}
+window.sendToPlugin = function (msg) {
+  window.ipc.postMessage(JSON.stringify(msg));
+}
+
+// Messages that arrive before the page has installed its `onPluginMessage()` handler, like the
+// state the editor replays when the page (re)loads, are queued until the handler is set
+const MAX_PENDING_PLUGIN_MESSAGES = 1024;
+const pendingPluginMessages = [];
+let onPluginMessage = null;
+
+Object.defineProperty(window, "onPluginMessage", {
+  get: () => onPluginMessage,
+  set: (handler) => {
+    onPluginMessage = handler;
+    while (onPluginMessage && pendingPluginMessages.length > 0) {
+      onPluginMessage(pendingPluginMessages.shift());
+    }
+  },
+});
+
+window.onPluginMessageInternal = function(msg) {
+  const json = JSON.parse(msg);
+  if (onPluginMessage) {
+    onPluginMessage(json);
+  } else {
+    if (pendingPluginMessages.length >= MAX_PENDING_PLUGIN_MESSAGES) {
+      pendingPluginMessages.shift();
+    }
+    pendingPluginMessages.push(json);
+  }
+}
+
+// The plugin's parameter values, keyed by parameter ID. These are sent whenever the page (re)loads.
+window.pluginParams = {};
+window.onPluginParams = function() {};
+
+window.onPluginParamsInternal = function(msg) {
+  window.pluginParams = JSON.parse(msg);
+  window.onPluginParams && window.onPluginParams(window.pluginParams);
+}
+
+window.onPluginFileDrop = function() {};
+
+window.onPluginFileDropInternal = function(msg) {
+  const json = JSON.parse(msg);
+  window.onPluginFileDrop && window.onPluginFileDrop(json);
+}
+
+// Built-in requests handled by the editor itself, see `bridge.rs`. These all return promises.
+let nextBridgeRequestId = 0;
+const pendingBridgeRequests = new Map();
+
+function sendBridgeRequest(type, args) {
+  return new Promise((resolve, reject) => {
+    const id = nextBridgeRequestId++;
+    pendingBridgeRequests.set(id, { resolve, reject });
+    window.ipc.postMessage(JSON.stringify({ __nihPlugBridge: { ...args, id, type } }));
+  });
+}
+
+window.onPluginBridgeResponseInternal = function(msg) {
+  const response = JSON.parse(msg);
+  const request = pendingBridgeRequests.get(response.id);
+  if (request) {
+    pendingBridgeRequests.delete(response.id);
+    response.ok ? request.resolve(response.value) : request.reject(new Error(response.error));
+  }
+}
+
+window.openFileDialog = (options = {}) => sendBridgeRequest("openFileDialog", options);
+window.saveFileDialog = (options = {}) => sendBridgeRequest("saveFileDialog", options);
+window.readFile = (path) => sendBridgeRequest("readFile", { path });
+window.readTextFile = (path) => sendBridgeRequest("readTextFile", { path });
+window.clipboardReadText = () => sendBridgeRequest("clipboardReadText");
+window.clipboardWriteText = (text) => sendBridgeRequest("clipboardWriteText", { text });
+window.getSnapshots = () => sendBridgeRequest("snapshots");
+window.storeSnapshot = (slot) => sendBridgeRequest("storeSnapshot", { slot });
+window.recallSnapshot = (slot) => sendBridgeRequest("recallSnapshot", { slot });
+
+// Keyboard passthrough, see `keyboard.rs`. Keys are only reported while no text field is focused,
+// and the editor decides whether they should be forwarded to the host.
+let textInputFocused = false;
+let textInputFocusedOverride = null;
+
+function sendPageEvent(event) {
+  window.ipc.postMessage(JSON.stringify({ __nihPlugEvent: event }));
+}
+
+function isTextInput(element) {
+  if (!element) {
+    return false;
+  }
+  if (element.isContentEditable || element.tagName === "TEXTAREA") {
+    return true;
+  }
+
+  const nonTextInputTypes = [
+    "button", "checkbox", "color", "file", "hidden", "image", "radio", "range", "reset", "submit",
+  ];
+  return element.tagName === "INPUT" && !nonTextInputTypes.includes(element.type);
+}
+
+function updateTextInputFocused() {
+  const focused = textInputFocusedOverride ?? isTextInput(document.activeElement);
+  if (focused !== textInputFocused) {
+    textInputFocused = focused;
+    sendPageEvent({ type: "textInputFocus", focused });
+  }
+}
+
+// Pages with custom text fields (for instance drawn on a canvas) can indicate that they need the
+// keyboard with `setTextInputFocused(true)`. Passing `null` restores the automatic detection.
+window.setTextInputFocused = function(focused) {
+  textInputFocusedOverride = focused;
+  updateTextInputFocused();
+}
+
+document.addEventListener("focusin", updateTextInputFocused);
+// `document.activeElement` is only updated after the `focusout` event
+document.addEventListener("focusout", () => setTimeout(updateTextInputFocused));
+
+function reportKeyEvent(e) {
+  if (textInputFocused || e.isComposing) {
+    return;
+  }
+
+  sendPageEvent({
+    type: "key",
+    key: e.key,
+    code: e.code,
+    down: e.type === "keydown",
+    location: e.location,
+    repeat: e.repeat,
+    shiftKey: e.shiftKey,
+    ctrlKey: e.ctrlKey,
+    altKey: e.altKey,
+    metaKey: e.metaKey,
+  });
+}
+
+window.addEventListener("keydown", reportKeyEvent, true);
+window.addEventListener("keyup", reportKeyEvent, true);
+
+// Lets the editor replay the parameter values and its registered state after a reload, for
+// instance when a dev server reloads the page
+sendPageEvent({ type: "pageLoaded" });
@@ -0,0 +1,391 @@
+//! A headless harness for testing a [`WebViewEditor`]'s event loop. This runs the closure passed to
+//! [`WebViewEditor::with_event_loop()`] without opening a window or starting a webview, so it also
+//! works in CI on machines without a display server.
+//!
+//! ```ignore
+//! let mut harness = TestHarness::new(&editor, params.clone());
+//! harness.send_message(json!({ "type": "SetGainDB", "gain": -6.0 }));
+//! harness.run_frame();
+//!
+//! assert_eq!(
+//!     harness.context().take_events(),
+//!     [
+//!         GuiContextEvent::BeginSetParameter(String::from("gain")),
+//!         GuiContextEvent::SetParameterNormalized(String::from("gain"), 0.8),
+//!         GuiContextEvent::EndSetParameter(String::from("gain")),
+//!     ]
+//! );
+//! ```
+
+use baseview::Size;
+use crossbeam::channel::{unbounded, Sender};
+use keyboard_types::{KeyState, KeyboardEvent, Modifiers};
+use nih_plug::prelude::{GuiContext, ParamPtr, Params, PluginApi, PluginState};
+use parking_lot::Mutex;
+use raw_window_handle::RawWindowHandle;
+use serde_json::Value;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use crate::bridge::PAGE_EVENT_KEY;
+use crate::transport::{EditorWindow, WebViewTransport};
+use crate::{FileDrop, IpcRouter, WebViewEditor, WindowHandler};
+
+/// Drives a [`WebViewEditor`]'s event loop without a window. Messages are injected as if they were
+/// sent by the page, and everything the editor sends back to the page is captured.
+pub struct TestHarness {
+    handler: WindowHandler,
+    window: MockWindow,
+    ipc_router: IpcRouter,
+    file_drop_sender: Sender<FileDrop>,
+    script_calls: Arc<Mutex<Vec<ScriptCall>>>,
+    context: Arc<RecordingGuiContext>,
+    /// Mirrors the page's own text input focus tracking in `script.js`.
+    text_input_focused: Cell<bool>,
+}
+
+/// A call to one of the global functions defined in `script.js`, captured by the [`TestHarness`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptCall {
+    /// The name of the function, e.g. `onPluginMessageInternal`.
+    pub function: String,
+    /// The JSON value that was passed to the function.
+    pub argument: Value,
+}
+
+/// Something the editor did through its [`GuiContext`], as recorded by [`RecordingGuiContext`].
+/// Parameters are identified by their string IDs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GuiContextEvent {
+    BeginSetParameter(String),
+    SetParameterNormalized(String, f32),
+    EndSetParameter(String),
+    RequestResize,
+    /// [`GuiContext::set_state()`] was called. The state itself can be retrieved with
+    /// [`GuiContext::get_state()`].
+    SetState,
+}
+
+/// A [`GuiContext`] that records all calls made to it instead of forwarding them to a host.
+///
+/// # Note
+///
+/// Only the plugin wrappers can change a parameter's value, so parameter changes made through a
+/// [`ParamSetter`][nih_plug::prelude::ParamSetter] are recorded but not applied.
+pub struct RecordingGuiContext {
+    /// Keeps the parameters the pointers in `param_ids` point to alive.
+    _params: Arc<dyn Params>,
+    param_ids: HashMap<ParamPtr, String>,
+    events: Mutex<Vec<GuiContextEvent>>,
+    state: Mutex<PluginState>,
+}
+
+/// A [`WebViewTransport`] that records the scripts evaluated by the editor.
+struct MockTransport {
+    script_calls: Arc<Mutex<Vec<ScriptCall>>>,
+}
+
+/// A headless [`EditorWindow`]. Resizes and forwarded keys are recorded but otherwise ignored.
+#[derive(Debug, Default)]
+pub struct MockWindow {
+    /// The sizes passed to [`EditorWindow::resize()`], in order.
+    pub resizes: Vec<(u32, u32)>,
+    /// The key events the editor forwarded to the host, in order.
+    pub forwarded_keys: Vec<KeyboardEvent>,
+}
+
+impl TestHarness {
+    /// Create a harness for an editor. The plugin's parameters are needed to map the parameters
+    /// set by the editor back to their IDs.
+    pub fn new(editor: &WebViewEditor, params: Arc<dyn Params>) -> Self {
+        let (events_sender, events_receiver) = unbounded();
+        let (bridge_sender, bridge_receiver) = unbounded();
+        let (page_event_sender, page_event_receiver) = unbounded();
+        let (file_drop_sender, file_drop_receiver) = unbounded();
+        let script_calls = Arc::new(Mutex::new(Vec::new()));
+        let context = Arc::new(RecordingGuiContext::new(params));
+
+        let mut handler = WindowHandler::new(
+            editor,
+            context.clone(),
+            Box::new(MockTransport {
+                script_calls: script_calls.clone(),
+            }),
+            events_receiver,
+            bridge_receiver,
+            page_event_receiver,
+            file_drop_receiver,
+        );
+        // There's no actual window, so there's no need for the initial resize
+        handler.current_width = handler.width.load(Ordering::Relaxed);
+        handler.current_height = handler.height.load(Ordering::Relaxed);
+
+        Self {
+            handler,
+            window: MockWindow::default(),
+            ipc_router: IpcRouter {
+                events_sender,
+                bridge_sender,
+                page_event_sender,
+            },
+            file_drop_sender,
+            script_calls,
+            context,
+            text_input_focused: Cell::new(false),
+        }
+    }
+
+    /// Send a message to the editor as if the page called `window.sendToPlugin(message)`. This is
+    /// handled during the next [`run_frame()`][Self::run_frame()].
+    pub fn send_message(&self, message: Value) {
+        self.ipc_router.route(&message.to_string());
+    }
+
+    /// Simulate a text field in the page gaining or losing focus.
+    pub fn set_text_input_focused(&self, focused: bool) {
+        self.text_input_focused.set(focused);
+        self.ipc_router.route(
+            &serde_json::json!({ PAGE_EVENT_KEY: { "type": "textInputFocus", "focused": focused } })
+                .to_string(),
+        );
+    }
+
+    /// Simulate a key being pressed or released while the page has keyboard focus. Like in the
+    /// real page, this does nothing while a text field is focused. This is handled during the next
+    /// [`run_frame()`][Self::run_frame()].
+    pub fn send_key_event(&self, event: &KeyboardEvent) {
+        if self.text_input_focused.get() {
+            return;
+        }
+
+        self.ipc_router.route(
+            &serde_json::json!({
+                PAGE_EVENT_KEY: {
+                    "type": "key",
+                    "key": event.key.to_string(),
+                    "code": event.code.to_string(),
+                    "down": event.state == KeyState::Down,
+                    "location": event.location as u32,
+                    "repeat": event.repeat,
+                    "shiftKey": event.modifiers.contains(Modifiers::SHIFT),
+                    "ctrlKey": event.modifiers.contains(Modifiers::CONTROL),
+                    "altKey": event.modifiers.contains(Modifiers::ALT),
+                    "metaKey": event.modifiers.contains(Modifiers::META),
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    /// Simulate the page being reloaded, for instance by a dev server. The editor replays the
+    /// parameter values and its registered state during the next [`run_frame()`][Self::run_frame()].
+    pub fn reload_page(&self) {
+        self.text_input_focused.set(false);
+        self.ipc_router.navigated(String::from("http://localhost/"));
+        self.ipc_router
+            .route(&serde_json::json!({ PAGE_EVENT_KEY: { "type": "pageLoaded" } }).to_string());
+    }
+
+    /// Simulate files being dragged onto the webview. This is handled during the next
+    /// [`run_frame()`][Self::run_frame()].
+    pub fn drop_files(&self, file_drop: FileDrop) {
+        let _ = self.file_drop_sender.send(file_drop);
+    }
+
+    /// Run a single iteration of the editor's event loop, like baseview would do on every frame.
+    pub fn run_frame(&mut self) {
+        self.handler.frame(&mut self.window);
+    }
+
+    /// Take all messages sent to the page using [`WindowHandler::send_json()`] since the last call.
+    pub fn take_messages(&self) -> Vec<Value> {
+        let mut script_calls = self.script_calls.lock();
+        let (messages, other_calls) = script_calls
+            .drain(..)
+            .partition::<Vec<_>, _>(|call| call.function == "onPluginMessageInternal");
+        *script_calls = other_calls;
+
+        messages.into_iter().map(|call| call.argument).collect()
+    }
+
+    /// Take all calls the editor made to the page since the last call, including the file drop and
+    /// bridge callbacks.
+    pub fn take_script_calls(&self) -> Vec<ScriptCall> {
+        std::mem::take(&mut *self.script_calls.lock())
+    }
+
+    /// The window handler, for calling its methods directly.
+    pub fn handler(&self) -> &WindowHandler {
+        &self.handler
+    }
+
+    /// The context that recorded all parameter changes made by the editor.
+    pub fn context(&self) -> &RecordingGuiContext {
+        &self.context
+    }
+
+    pub fn window(&self) -> &MockWindow {
+        &self.window
+    }
+}
+
+impl RecordingGuiContext {
+    pub fn new(params: Arc<dyn Params>) -> Self {
+        Self {
+            param_ids: params
+                .param_map()
+                .into_iter()
+                .map(|(id, ptr, _)| (ptr, id))
+                .collect(),
+            _params: params,
+            events: Mutex::new(Vec::new()),
+            state: Mutex::new(PluginState {
+                version: String::new(),
+                params: BTreeMap::new(),
+                fields: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Take all events recorded since the last call.
+    pub fn take_events(&self) -> Vec<GuiContextEvent> {
+        std::mem::take(&mut *self.events.lock())
+    }
+
+    fn param_id(&self, param: ParamPtr) -> String {
+        match self.param_ids.get(&param) {
+            Some(id) => id.clone(),
+            None => panic!("The editor set a parameter that's not part of the plugin's Params"),
+        }
+    }
+
+    fn record(&self, event: GuiContextEvent) {
+        self.events.lock().push(event);
+    }
+}
+
+impl GuiContext for RecordingGuiContext {
+    fn plugin_api(&self) -> PluginApi {
+        PluginApi::Standalone
+    }
+
+    fn request_resize(&self) -> bool {
+        self.record(GuiContextEvent::RequestResize);
+        true
+    }
+
+    unsafe fn raw_begin_set_parameter(&self, param: ParamPtr) {
+        self.record(GuiContextEvent::BeginSetParameter(self.param_id(param)));
+    }
+
+    unsafe fn raw_set_parameter_normalized(&self, param: ParamPtr, normalized: f32) {
+        self.record(GuiContextEvent::SetParameterNormalized(
+            self.param_id(param),
+            normalized,
+        ));
+    }
+
+    unsafe fn raw_end_set_parameter(&self, param: ParamPtr) {
+        self.record(GuiContextEvent::EndSetParameter(self.param_id(param)));
+    }
+
+    fn get_state(&self) -> PluginState {
+        self.state.lock().clone()
+    }
+
+    fn set_state(&self, state: PluginState) {
+        *self.state.lock() = state;
+        self.record(GuiContextEvent::SetState);
+    }
+}
+
+impl WebViewTransport for MockTransport {
+    fn evaluate_script(&self, script: &str) {
+        let call = parse_script_call(script)
+            .unwrap_or_else(|| panic!("Unexpected script evaluated by the editor: {script}"));
+        self.script_calls.lock().push(call);
+    }
+
+    fn set_bounds(&self, _width: u32, _height: u32) {}
+}
+
+impl EditorWindow for MockWindow {
+    fn resize(&mut self, size: Size) {
+        self.resizes
+            .push((size.width.round() as u32, size.height.round() as u32));
+    }
+
+    fn raw_handle(&self) -> Option<RawWindowHandle> {
+        None
+    }
+
+    fn forward_key_to_host(&mut self, event: &KeyboardEvent) -> bool {
+        self.forwarded_keys.push(event.clone());
+        true
+    }
+}
+
+/// Parse the `function("json string");` calls made by
+/// [`WindowHandler::call_script_function()`].
+fn parse_script_call(script: &str) -> Option<ScriptCall> {
+    let (function, argument) = script.strip_suffix(");")?.split_once('(')?;
+    let json_str: String = serde_json::from_str(argument).ok()?;
+
+    Some(ScriptCall {
+        function: function.to_owned(),
+        argument: serde_json::from_str(&json_str).ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bridge::{BridgeRequest, BRIDGE_REQUEST_KEY};
+    use serde_json::json;
+
+    #[test]
+    fn parse_script_call_roundtrip() {
+        let argument = json!({ "type": "Message", "text": "quotes \" and ); parens" });
+        let script = format!(
+            "onPluginMessageInternal({});",
+            serde_json::to_string(&argument.to_string()).unwrap()
+        );
+
+        assert_eq!(
+            parse_script_call(&script),
+            Some(ScriptCall {
+                function: String::from("onPluginMessageInternal"),
+                argument,
+            })
+        );
+    }
+
+    #[test]
+    fn malformed_ipc_messages_are_dropped() {
+        let (events_sender, events_receiver) = unbounded();
+        let (bridge_sender, bridge_receiver) = unbounded();
+        let (page_event_sender, page_event_receiver) = unbounded();
+        let ipc_router = IpcRouter {
+            events_sender,
+            bridge_sender,
+            page_event_sender,
+        };
+
+        ipc_router.route("{ not json");
+        ipc_router.route(&json!({ PAGE_EVENT_KEY: { "type": "doesNotExist" } }).to_string());
+        ipc_router.route(&json!({ BRIDGE_REQUEST_KEY: { "type": "doesNotExist" } }).to_string());
+        assert!(events_receiver.is_empty());
+        assert!(page_event_receiver.is_empty());
+        assert!(bridge_receiver.is_empty());
+
+        // Requests with an ID are still answered so the page's promise gets rejected
+        ipc_router.route(
+            &json!({ BRIDGE_REQUEST_KEY: { "id": 3, "type": "readFile", "path": 42 } }).to_string(),
+        );
+        let message = bridge_receiver.try_recv().unwrap();
+        assert_eq!(message.id, 3);
+        assert!(matches!(message.request, BridgeRequest::Invalid { .. }));
+    }
+}
@@ -0,0 +1,85 @@
+//! Abstractions over the webview and the window it's embedded in. In a plugin these are backed by
+//! wry and baseview, while the `testing` module provides headless versions so the editor's event
+//! loop can be tested without a display server.
+
+use baseview::Size;
+use keyboard_types::KeyboardEvent;
+use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+use wry::WebView;
+
+use crate::keyboard;
+
+/// The page side of the editor. Everything [`WindowHandler`][crate::WindowHandler] sends to the
+/// page goes through [`evaluate_script()`][Self::evaluate_script()].
+pub trait WebViewTransport {
+    /// Evaluate a snippet of JavaScript in the page.
+    fn evaluate_script(&self, script: &str);
+
+    /// Resize the page's viewport to the specified size in logical pixels.
+    fn set_bounds(&self, width: u32, height: u32);
+}
+
+impl WebViewTransport for WebView {
+    fn evaluate_script(&self, script: &str) {
+        WebView::evaluate_script(self, script).unwrap();
+    }
+
+    fn set_bounds(&self, width: u32, height: u32) {
+        WebView::set_bounds(
+            self,
+            wry::Rect {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            },
+        );
+    }
+}
+
+/// Used in place of the webview if it could not be created. Scripts are silently discarded.
+pub(crate) struct NoWebView;
+
+impl WebViewTransport for NoWebView {
+    fn evaluate_script(&self, _script: &str) {}
+
+    fn set_bounds(&self, _width: u32, _height: u32) {}
+}
+
+/// The editor's window as passed to the event loop set with
+/// [`WebViewEditor::with_event_loop()`][crate::WebViewEditor::with_event_loop()]. This is the
+/// baseview window when running in a host.
+pub trait EditorWindow {
+    /// Resize the window to the specified size in logical pixels.
+    fn resize(&mut self, size: Size);
+
+    /// The window's raw handle, used to parent native dialogs. Headless windows return `None`.
+    fn raw_handle(&self) -> Option<RawWindowHandle>;
+
+    /// Send a key event the page didn't consume to the host's window. Returns `false` if the event
+    /// could not be forwarded.
+    fn forward_key_to_host(&mut self, event: &KeyboardEvent) -> bool;
+}
+
+impl EditorWindow for baseview::Window<'_> {
+    fn resize(&mut self, size: Size) {
+        baseview::Window::resize(self, size);
+    }
+
+    fn raw_handle(&self) -> Option<RawWindowHandle> {
+        Some(self.raw_window_handle())
+    }
+
+    fn forward_key_to_host(&mut self, event: &KeyboardEvent) -> bool {
+        keyboard::forward_to_host(self.raw_window_handle(), event)
+    }
+}
+
+/// Used to parent native dialogs to the editor window.
+pub(crate) struct ParentWindow(pub RawWindowHandle);
+
+unsafe impl HasRawWindowHandle for ParentWindow {
+    fn raw_window_handle(&self) -> RawWindowHandle {
+        self.0
+    }
+}
@@ -1,16 +1,24 @@
-use proc_macro::TokenStream;
-
-mod enums;
-mod params;
-
-/// Derive the `Enum` trait for simple enum parameters. See `EnumParam` for more information.
-#[proc_macro_derive(Enum, attributes(name, id))]
-pub fn derive_enum(input: TokenStream) -> TokenStream {
-    enums::derive_enum(input)
-}
-
-/// Derive the `Params` trait for your plugin's parameters struct. See the `Plugin` trait.
-#[proc_macro_derive(Params, attributes(id, persist, nested))]
-pub fn derive_params(input: TokenStream) -> TokenStream {
-    params::derive_params(input)
-}
+use proc_macro::TokenStream;
+
+mod enums;
+mod params;
+mod sysex;
+
+/// Derive the `Enum` trait for simple enum parameters. See `EnumParam` for more information.
+#[proc_macro_derive(Enum, attributes(name, id))]
+pub fn derive_enum(input: TokenStream) -> TokenStream {
+    enums::derive_enum(input)
+}
+
+/// Derive the `Params` trait for your plugin's parameters struct. See the `Plugin` trait.
+#[proc_macro_derive(Params, attributes(id, persist, nested))]
+pub fn derive_params(input: TokenStream) -> TokenStream {
+    params::derive_params(input)
+}
+
+/// Derive the `SysExMessage` trait for manufacturer-specific SysEx message enums. See `SysExField`
+/// for more information.
+#[proc_macro_derive(SysExMessage, attributes(sysex))]
+pub fn derive_sysex_message(input: TokenStream) -> TokenStream {
+    sysex::derive_sysex_message(input)
+}
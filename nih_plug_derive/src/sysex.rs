@@ -0,0 +1,273 @@
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+
+pub fn derive_sysex_message(input: TokenStream) -> TokenStream {
+    let ast = syn::parse_macro_input!(input as syn::DeriveInput);
+
+    let enum_name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let variants = match &ast.data {
+        syn::Data::Enum(syn::DataEnum { variants, .. }) if !variants.is_empty() => variants,
+        _ => {
+            return syn::Error::new(
+                ast.span(),
+                "Deriving SysExMessage is only supported on enums with at least one variant",
+            )
+            .to_compile_error()
+            .into()
+        }
+    };
+
+    // Every message starts with `0xf0`, followed by the manufacturer ID, followed by the variant's
+    // command bytes, followed by the fields in declaration order, and it ends with `0xf7`
+    let manufacturer = match parse_sysex_attr(&ast.attrs, "manufacturer") {
+        Ok(Some((bytes, span))) => {
+            let is_valid = match bytes.as_slice() {
+                [id] => *id != 0x00,
+                [0x00, _, _] => true,
+                _ => false,
+            };
+            if !is_valid {
+                return syn::Error::new(
+                    span,
+                    "Manufacturer IDs should either be a single non-zero byte or three bytes \
+                     starting with 0x00",
+                )
+                .to_compile_error()
+                .into();
+            }
+
+            bytes
+        }
+        Ok(None) => {
+            return syn::Error::new(
+                ast.span(),
+                "Missing manufacturer ID, add a #[sysex(manufacturer = 0x7d)] or \
+                 #[sysex(manufacturer(0x00, 0x21, 0x09))] attribute to the enum",
+            )
+            .to_compile_error()
+            .into()
+        }
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let mut commands: Vec<Vec<u8>> = Vec::new();
+    let mut variant_sizes = Vec::new();
+    let mut from_buffer_tokens = Vec::new();
+    let mut to_buffer_tokens = Vec::new();
+    for variant in variants {
+        let command = match parse_sysex_attr(&variant.attrs, "command") {
+            Ok(Some((bytes, span))) => {
+                if bytes.is_empty() {
+                    return syn::Error::new(span, "Commands need to contain at least one byte")
+                        .to_compile_error()
+                        .into();
+                }
+
+                // Parsing matches on the command bytes, so one command being a prefix of another
+                // would make the messages ambiguous
+                if commands
+                    .iter()
+                    .any(|other| other.starts_with(&bytes) || bytes.starts_with(other))
+                {
+                    return syn::Error::new(
+                        span,
+                        "Commands must be unique and cannot be a prefix of another variant's \
+                         command",
+                    )
+                    .to_compile_error()
+                    .into();
+                }
+
+                commands.push(bytes.clone());
+                bytes
+            }
+            Ok(None) => {
+                return syn::Error::new(
+                    variant.span(),
+                    "Missing command, add a #[sysex(command = 0x01)] or #[sysex(command(0x01, \
+                     0x02))] attribute to the variant",
+                )
+                .to_compile_error()
+                .into()
+            }
+            Err(err) => return err.to_compile_error().into(),
+        };
+
+        let variant_name = &variant.ident;
+        let field_types: Vec<_> = variant.fields.iter().map(|field| &field.ty).collect();
+        let field_bindings: Vec<_> = (0..field_types.len())
+            .map(|idx| format_ident!("__field_{}", idx))
+            .collect();
+        let pattern = match &variant.fields {
+            syn::Fields::Named(fields) => {
+                let field_names = fields.named.iter().map(|field| &field.ident);
+                quote! { Self::#variant_name { #(#field_names: #field_bindings),* } }
+            }
+            syn::Fields::Unnamed(_) => quote! { Self::#variant_name(#(#field_bindings),*) },
+            syn::Fields::Unit => quote! { Self::#variant_name },
+        };
+
+        // The fields' offsets are computed from the sizes of the fields before them
+        let field_sizes: Vec<_> = field_types
+            .iter()
+            .map(|ty| quote! { <#ty as ::nih_plug::midi::sysex::SysExField>::SIZE })
+            .collect();
+        let field_offsets: Vec<_> = (0..field_sizes.len())
+            .map(|idx| {
+                let previous_sizes = &field_sizes[..idx];
+                quote! { 0 #(+ #previous_sizes)* }
+            })
+            .collect();
+        let fields_size = quote! { 0 #(+ #field_sizes)* };
+
+        let command_len = command.len();
+        let manufacturer_len = manufacturer.len();
+        variant_sizes.push(quote! { 2 + #manufacturer_len + #command_len + #fields_size });
+
+        from_buffer_tokens.push(quote! {
+            if let Some(data) = payload.strip_prefix(&[#(#command),*]) {
+                if data.len() == #fields_size {
+                    #(let #field_bindings = <#field_types as ::nih_plug::midi::sysex::SysExField>::read(
+                        &data[#field_offsets..#field_offsets + #field_sizes],
+                    )?;)*
+
+                    return Some(#pattern);
+                }
+            }
+        });
+        to_buffer_tokens.push(quote! {
+            #pattern => {
+                buffer[pos..pos + #command_len].copy_from_slice(&[#(#command),*]);
+                pos += #command_len;
+                #(::nih_plug::midi::sysex::SysExField::write(
+                    &#field_bindings,
+                    &mut buffer[pos + #field_offsets..pos + #field_offsets + #field_sizes],
+                );)*
+                pos += #fields_size;
+            }
+        });
+    }
+
+    let buffer_size = quote! {
+        {
+            let mut size = 0usize;
+            #(if #variant_sizes > size {
+                size = #variant_sizes;
+            })*
+
+            size
+        }
+    };
+    let manufacturer_len = manufacturer.len();
+
+    quote! {
+        impl #impl_generics ::nih_plug::midi::sysex::SysExMessage for #enum_name #ty_generics #where_clause {
+            type Buffer = [u8; #buffer_size];
+
+            fn from_buffer(buffer: &[u8]) -> Option<Self> {
+                let payload = buffer
+                    .strip_prefix(&[0xf0u8, #(#manufacturer),*])?
+                    .strip_suffix(&[0xf7u8])?;
+
+                #(#from_buffer_tokens)*
+
+                None
+            }
+
+            fn to_buffer(self) -> (Self::Buffer, usize) {
+                let mut buffer = [0u8; #buffer_size];
+                buffer[..1 + #manufacturer_len].copy_from_slice(&[0xf0u8, #(#manufacturer),*]);
+                let mut pos = 1 + #manufacturer_len;
+                match self {
+                    #(#to_buffer_tokens)*
+                }
+                buffer[pos] = 0xf7;
+
+                (buffer, pos + 1)
+            }
+        }
+    }
+    .into()
+}
+
+/// Parse the bytes from a `#[sysex(key = 0x01)]` or `#[sysex(key(0x01, 0x02))]` attribute. Returns
+/// the bytes alongside the attribute's span for error reporting.
+fn parse_sysex_attr(
+    attrs: &[syn::Attribute],
+    key: &str,
+) -> syn::Result<Option<(Vec<u8>, proc_macro2::Span)>> {
+    let mut result = None;
+    for attr in attrs {
+        if !attr.path.is_ident("sysex") {
+            continue;
+        }
+
+        let nested_attrs = match attr.parse_meta()? {
+            syn::Meta::List(syn::MetaList { nested, .. }) => nested,
+            _ => {
+                return Err(syn::Error::new(
+                    attr.span(),
+                    format!(
+                        "The sysex attribute should be a list: #[sysex({key} = 0x01)] or \
+                         #[sysex({key}(0x01, 0x02))]"
+                    ),
+                ))
+            }
+        };
+
+        for nested_attr in nested_attrs {
+            let literals: Vec<_> =
+                match &nested_attr {
+                    syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                        path,
+                        lit,
+                        ..
+                    })) if path.is_ident(key) => vec![lit.clone()],
+                    syn::NestedMeta::Meta(syn::Meta::List(syn::MetaList {
+                        path, nested, ..
+                    })) if path.is_ident(key) => nested
+                        .iter()
+                        .map(|byte| match byte {
+                            syn::NestedMeta::Lit(lit) => Ok(lit.clone()),
+                            _ => Err(syn::Error::new(byte.span(), "Expected a byte literal")),
+                        })
+                        .collect::<syn::Result<_>>()?,
+                    _ => {
+                        return Err(syn::Error::new(
+                            nested_attr.span(),
+                            format!("Unknown or unexpected sysex attribute, expected '{key}'"),
+                        ))
+                    }
+                };
+
+            if result.is_some() {
+                return Err(syn::Error::new(
+                    nested_attr.span(),
+                    format!("Duplicate '{key}' attribute"),
+                ));
+            }
+
+            let mut bytes = Vec::with_capacity(literals.len());
+            for lit in literals {
+                let byte = match &lit {
+                    syn::Lit::Int(i) => i.base10_parse::<u8>()?,
+                    _ => return Err(syn::Error::new(lit.span(), "Expected a byte literal")),
+                };
+                if byte >= 0x80 {
+                    return Err(syn::Error::new(
+                        lit.span(),
+                        "SysEx data bytes must be in the range [0x00, 0x7f]",
+                    ));
+                }
+
+                bytes.push(byte);
+            }
+
+            result = Some((bytes, nested_attr.span()));
+        }
+    }
+
+    Ok(result)
+}
@@ -1,318 +1,447 @@
-use nih_plug::prelude::*;
-
-#[derive(Params)]
-struct FlatParams {
-    #[id = "one"]
-    pub one: BoolParam,
-
-    #[id = "two"]
-    pub two: FloatParam,
-
-    #[id = "three"]
-    pub three: IntParam,
-}
-
-impl Default for FlatParams {
-    fn default() -> Self {
-        FlatParams {
-            one: BoolParam::new("one", true),
-            two: FloatParam::new("two", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 }),
-            three: IntParam::new("three", 0, IntRange::Linear { min: 0, max: 100 }),
-        }
-    }
-}
-
-#[derive(Params)]
-struct GroupedParams {
-    #[id = "one"]
-    pub one: BoolParam,
-
-    #[nested(group = "Some Group", id_prefix = "group1")]
-    pub group1: FlatParams,
-
-    #[id = "three"]
-    pub three: IntParam,
-
-    #[nested(group = "Another Group", id_prefix = "group2")]
-    pub group2: FlatParams,
-}
-
-impl Default for GroupedParams {
-    fn default() -> Self {
-        GroupedParams {
-            one: BoolParam::new("one", true),
-            group1: FlatParams::default(),
-            three: IntParam::new("three", 0, IntRange::Linear { min: 0, max: 100 }),
-            group2: FlatParams::default(),
-        }
-    }
-}
-
-// This should result in the same `.param_map()` as `GroupedParams`
-#[derive(Default, Params)]
-struct PlainNestedParams {
-    #[nested]
-    pub inner: GroupedParams,
-}
-
-#[derive(Default, Params)]
-struct GroupedGroupedParams {
-    #[nested(group = "Top-level group")]
-    pub one: GroupedParams,
-}
-
-#[derive(Params)]
-struct NestedParams {
-    #[id = "one"]
-    pub one: BoolParam,
-
-    #[nested(id_prefix = "two")]
-    pub two: FlatParams,
-
-    #[id = "three"]
-    pub three: IntParam,
-}
-
-impl Default for NestedParams {
-    fn default() -> Self {
-        NestedParams {
-            one: BoolParam::new("one", true),
-            two: FlatParams::default(),
-            three: IntParam::new("three", 0, IntRange::Linear { min: 0, max: 100 }),
-        }
-    }
-}
-
-#[derive(Params)]
-struct NestedArrayParams {
-    #[id = "one"]
-    pub one: BoolParam,
-
-    #[nested(array, group = "Nested Params")]
-    pub lots_of_twos: [FlatParams; 3],
-
-    #[id = "three"]
-    pub three: IntParam,
-}
-
-impl Default for NestedArrayParams {
-    fn default() -> Self {
-        NestedArrayParams {
-            one: BoolParam::new("one", true),
-            lots_of_twos: [
-                FlatParams::default(),
-                FlatParams::default(),
-                FlatParams::default(),
-            ],
-            three: IntParam::new("three", 0, IntRange::Linear { min: 0, max: 100 }),
-        }
-    }
-}
-
-mod param_order {
-    use super::*;
-
-    #[test]
-    fn flat() {
-        let p = FlatParams::default();
-
-        // Parameters must have the same order as they are defined in
-        let param_ids: Vec<String> = p.param_map().into_iter().map(|(id, _, _)| id).collect();
-        assert_eq!(param_ids, ["one", "two", "three"]);
-    }
-
-    #[test]
-    fn grouped() {
-        let p = GroupedParams::default();
-
-        let param_ids: Vec<String> = p.param_map().into_iter().map(|(id, _, _)| id).collect();
-        assert_eq!(
-            param_ids,
-            [
-                "one",
-                "group1_one",
-                "group1_two",
-                "group1_three",
-                "three",
-                "group2_one",
-                "group2_two",
-                "group2_three",
-            ]
-        );
-    }
-
-    #[test]
-    fn plain_nested() {
-        let plain_nested = PlainNestedParams::default();
-        let grouped = GroupedParams::default();
-
-        let plain_nested_ids_groups: Vec<(String, String)> = plain_nested
-            .param_map()
-            .into_iter()
-            .map(|(id, _, group)| (id, group))
-            .collect();
-        let grouped_param_ids_groups: Vec<(String, String)> = grouped
-            .param_map()
-            .into_iter()
-            .map(|(id, _, group)| (id, group))
-            .collect();
-
-        assert_eq!(plain_nested_ids_groups, grouped_param_ids_groups);
-    }
-
-    #[test]
-    fn grouped_groups() {
-        let p = GroupedGroupedParams::default();
-
-        // These don't have ID prefixes, so the IDs should be the same as in `groups()`
-        let param_ids: Vec<String> = p.param_map().into_iter().map(|(id, _, _)| id).collect();
-        assert_eq!(
-            param_ids,
-            [
-                "one",
-                "group1_one",
-                "group1_two",
-                "group1_three",
-                "three",
-                "group2_one",
-                "group2_two",
-                "group2_three",
-            ]
-        );
-    }
-
-    #[test]
-    fn nested() {
-        let p = NestedParams::default();
-
-        // Parameters must have the same order as they are defined in. The position of nested
-        // parameters which are not grouped explicitly is preserved.
-        let param_ids: Vec<String> = p.param_map().into_iter().map(|(id, _, _)| id).collect();
-
-        assert_eq!(
-            param_ids,
-            ["one", "two_one", "two_two", "two_three", "three"]
-        );
-    }
-
-    #[test]
-    fn nested_array() {
-        let p = NestedArrayParams::default();
-
-        // Arrays of nested parameter structs have generated IDs
-        let param_ids: Vec<String> = p.param_map().into_iter().map(|(id, _, _)| id).collect();
-        assert_eq!(
-            param_ids,
-            [
-                "one", "one_1", "two_1", "three_1", "one_2", "two_2", "three_2", "one_3", "two_3",
-                "three_3", "three"
-            ]
-        );
-    }
-}
-
-mod param_groups {
-    use super::*;
-
-    #[test]
-    fn flat() {
-        let p = FlatParams::default();
-
-        // These groups should be all empty
-        let param_groups: Vec<String> = p
-            .param_map()
-            .into_iter()
-            .map(|(_, _, group)| group)
-            .collect();
-        assert_eq!(param_groups, ["", "", ""]);
-    }
-
-    #[test]
-    fn grouped() {
-        let p = GroupedParams::default();
-
-        let param_groups: Vec<String> = p
-            .param_map()
-            .into_iter()
-            .map(|(_, _, group)| group)
-            .collect();
-        assert_eq!(
-            param_groups,
-            [
-                "",
-                "Some Group",
-                "Some Group",
-                "Some Group",
-                "",
-                "Another Group",
-                "Another Group",
-                "Another Group",
-            ]
-        );
-    }
-
-    #[test]
-    fn grouped_groups() {
-        let p = GroupedGroupedParams::default();
-
-        let param_groups: Vec<String> = p
-            .param_map()
-            .into_iter()
-            .map(|(_, _, group)| group)
-            .collect();
-        assert_eq!(
-            param_groups,
-            [
-                "Top-level group",
-                "Top-level group/Some Group",
-                "Top-level group/Some Group",
-                "Top-level group/Some Group",
-                "Top-level group",
-                "Top-level group/Another Group",
-                "Top-level group/Another Group",
-                "Top-level group/Another Group",
-            ]
-        );
-    }
-
-    #[test]
-    fn nested() {
-        let p = NestedParams::default();
-
-        // The nested structs here don't have any groups assigned to them
-        let param_groups: Vec<String> = p
-            .param_map()
-            .into_iter()
-            .map(|(_, _, group)| group)
-            .collect();
-        assert_eq!(param_groups, ["", "", "", "", ""]);
-    }
-
-    #[test]
-    fn nested_array() {
-        let p = NestedArrayParams::default();
-
-        // The groups get a numeric suffix here
-        let param_groups: Vec<String> = p
-            .param_map()
-            .into_iter()
-            .map(|(_, _, group)| group)
-            .collect();
-        assert_eq!(
-            param_groups,
-            [
-                "",
-                "Nested Params 1",
-                "Nested Params 1",
-                "Nested Params 1",
-                "Nested Params 2",
-                "Nested Params 2",
-                "Nested Params 2",
-                "Nested Params 3",
-                "Nested Params 3",
-                "Nested Params 3",
-                ""
-            ]
-        );
-    }
-}
+use nih_plug::prelude::*;
+
+#[derive(Params)]
+struct FlatParams {
+    #[id = "one"]
+    pub one: BoolParam,
+
+    #[id = "two"]
+    pub two: FloatParam,
+
+    #[id = "three"]
+    pub three: IntParam,
+}
+
+impl Default for FlatParams {
+    fn default() -> Self {
+        FlatParams {
+            one: BoolParam::new("one", true),
+            two: FloatParam::new("two", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 }),
+            three: IntParam::new("three", 0, IntRange::Linear { min: 0, max: 100 }),
+        }
+    }
+}
+
+#[derive(Params)]
+struct GroupedParams {
+    #[id = "one"]
+    pub one: BoolParam,
+
+    #[nested(group = "Some Group", id_prefix = "group1")]
+    pub group1: FlatParams,
+
+    #[id = "three"]
+    pub three: IntParam,
+
+    #[nested(group = "Another Group", id_prefix = "group2")]
+    pub group2: FlatParams,
+}
+
+impl Default for GroupedParams {
+    fn default() -> Self {
+        GroupedParams {
+            one: BoolParam::new("one", true),
+            group1: FlatParams::default(),
+            three: IntParam::new("three", 0, IntRange::Linear { min: 0, max: 100 }),
+            group2: FlatParams::default(),
+        }
+    }
+}
+
+// This should result in the same `.param_map()` as `GroupedParams`
+#[derive(Default, Params)]
+struct PlainNestedParams {
+    #[nested]
+    pub inner: GroupedParams,
+}
+
+#[derive(Default, Params)]
+struct GroupedGroupedParams {
+    #[nested(group = "Top-level group")]
+    pub one: GroupedParams,
+}
+
+#[derive(Params)]
+struct NestedParams {
+    #[id = "one"]
+    pub one: BoolParam,
+
+    #[nested(id_prefix = "two")]
+    pub two: FlatParams,
+
+    #[id = "three"]
+    pub three: IntParam,
+}
+
+impl Default for NestedParams {
+    fn default() -> Self {
+        NestedParams {
+            one: BoolParam::new("one", true),
+            two: FlatParams::default(),
+            three: IntParam::new("three", 0, IntRange::Linear { min: 0, max: 100 }),
+        }
+    }
+}
+
+#[derive(Params)]
+struct NestedArrayParams {
+    #[id = "one"]
+    pub one: BoolParam,
+
+    #[nested(array, group = "Nested Params")]
+    pub lots_of_twos: [FlatParams; 3],
+
+    #[id = "three"]
+    pub three: IntParam,
+}
+
+impl Default for NestedArrayParams {
+    fn default() -> Self {
+        NestedArrayParams {
+            one: BoolParam::new("one", true),
+            lots_of_twos: [
+                FlatParams::default(),
+                FlatParams::default(),
+                FlatParams::default(),
+            ],
+            three: IntParam::new("three", 0, IntRange::Linear { min: 0, max: 100 }),
+        }
+    }
+}
+
+#[derive(Params)]
+struct BypassableParams {
+    #[id = "bypass"]
+    pub bypass: BoolParam,
+
+    #[nested(group = "Inner", group_order = 1)]
+    pub inner: FlatParams,
+}
+
+impl Default for BypassableParams {
+    fn default() -> Self {
+        BypassableParams {
+            bypass: BoolParam::new("bypass", false),
+            inner: FlatParams::default(),
+        }
+    }
+}
+
+#[derive(Default, Params)]
+struct DescribedGroupParams {
+    #[nested(
+        group = "fx",
+        group_name = "Effects",
+        group_description = "The effects section",
+        group_order = 1,
+        group_bypass = "bypass",
+        id_prefix = "fx"
+    )]
+    pub fx: BypassableParams,
+
+    #[nested(array, group = "Voice", group_bypass = "one")]
+    pub voices: [FlatParams; 2],
+}
+
+mod param_order {
+    use super::*;
+
+    #[test]
+    fn flat() {
+        let p = FlatParams::default();
+
+        // Parameters must have the same order as they are defined in
+        let param_ids: Vec<String> = p.param_map().into_iter().map(|(id, _, _)| id).collect();
+        assert_eq!(param_ids, ["one", "two", "three"]);
+    }
+
+    #[test]
+    fn grouped() {
+        let p = GroupedParams::default();
+
+        let param_ids: Vec<String> = p.param_map().into_iter().map(|(id, _, _)| id).collect();
+        assert_eq!(
+            param_ids,
+            [
+                "one",
+                "group1_one",
+                "group1_two",
+                "group1_three",
+                "three",
+                "group2_one",
+                "group2_two",
+                "group2_three",
+            ]
+        );
+    }
+
+    #[test]
+    fn plain_nested() {
+        let plain_nested = PlainNestedParams::default();
+        let grouped = GroupedParams::default();
+
+        let plain_nested_ids_groups: Vec<(String, String)> = plain_nested
+            .param_map()
+            .into_iter()
+            .map(|(id, _, group)| (id, group))
+            .collect();
+        let grouped_param_ids_groups: Vec<(String, String)> = grouped
+            .param_map()
+            .into_iter()
+            .map(|(id, _, group)| (id, group))
+            .collect();
+
+        assert_eq!(plain_nested_ids_groups, grouped_param_ids_groups);
+    }
+
+    #[test]
+    fn grouped_groups() {
+        let p = GroupedGroupedParams::default();
+
+        // These don't have ID prefixes, so the IDs should be the same as in `groups()`
+        let param_ids: Vec<String> = p.param_map().into_iter().map(|(id, _, _)| id).collect();
+        assert_eq!(
+            param_ids,
+            [
+                "one",
+                "group1_one",
+                "group1_two",
+                "group1_three",
+                "three",
+                "group2_one",
+                "group2_two",
+                "group2_three",
+            ]
+        );
+    }
+
+    #[test]
+    fn nested() {
+        let p = NestedParams::default();
+
+        // Parameters must have the same order as they are defined in. The position of nested
+        // parameters which are not grouped explicitly is preserved.
+        let param_ids: Vec<String> = p.param_map().into_iter().map(|(id, _, _)| id).collect();
+
+        assert_eq!(
+            param_ids,
+            ["one", "two_one", "two_two", "two_three", "three"]
+        );
+    }
+
+    #[test]
+    fn nested_array() {
+        let p = NestedArrayParams::default();
+
+        // Arrays of nested parameter structs have generated IDs
+        let param_ids: Vec<String> = p.param_map().into_iter().map(|(id, _, _)| id).collect();
+        assert_eq!(
+            param_ids,
+            [
+                "one", "one_1", "two_1", "three_1", "one_2", "two_2", "three_2", "one_3", "two_3",
+                "three_3", "three"
+            ]
+        );
+    }
+}
+
+mod param_groups {
+    use super::*;
+
+    #[test]
+    fn flat() {
+        let p = FlatParams::default();
+
+        // These groups should be all empty
+        let param_groups: Vec<String> = p
+            .param_map()
+            .into_iter()
+            .map(|(_, _, group)| group)
+            .collect();
+        assert_eq!(param_groups, ["", "", ""]);
+    }
+
+    #[test]
+    fn grouped() {
+        let p = GroupedParams::default();
+
+        let param_groups: Vec<String> = p
+            .param_map()
+            .into_iter()
+            .map(|(_, _, group)| group)
+            .collect();
+        assert_eq!(
+            param_groups,
+            [
+                "",
+                "Some Group",
+                "Some Group",
+                "Some Group",
+                "",
+                "Another Group",
+                "Another Group",
+                "Another Group",
+            ]
+        );
+    }
+
+    #[test]
+    fn grouped_groups() {
+        let p = GroupedGroupedParams::default();
+
+        let param_groups: Vec<String> = p
+            .param_map()
+            .into_iter()
+            .map(|(_, _, group)| group)
+            .collect();
+        assert_eq!(
+            param_groups,
+            [
+                "Top-level group",
+                "Top-level group/Some Group",
+                "Top-level group/Some Group",
+                "Top-level group/Some Group",
+                "Top-level group",
+                "Top-level group/Another Group",
+                "Top-level group/Another Group",
+                "Top-level group/Another Group",
+            ]
+        );
+    }
+
+    #[test]
+    fn nested() {
+        let p = NestedParams::default();
+
+        // The nested structs here don't have any groups assigned to them
+        let param_groups: Vec<String> = p
+            .param_map()
+            .into_iter()
+            .map(|(_, _, group)| group)
+            .collect();
+        assert_eq!(param_groups, ["", "", "", "", ""]);
+    }
+
+    #[test]
+    fn nested_array() {
+        let p = NestedArrayParams::default();
+
+        // The groups get a numeric suffix here
+        let param_groups: Vec<String> = p
+            .param_map()
+            .into_iter()
+            .map(|(_, _, group)| group)
+            .collect();
+        assert_eq!(
+            param_groups,
+            [
+                "",
+                "Nested Params 1",
+                "Nested Params 1",
+                "Nested Params 1",
+                "Nested Params 2",
+                "Nested Params 2",
+                "Nested Params 2",
+                "Nested Params 3",
+                "Nested Params 3",
+                "Nested Params 3",
+                ""
+            ]
+        );
+    }
+}
+
+mod group_tree {
+    use super::*;
+    use nih_plug::params::group;
+
+    #[test]
+    fn flat() {
+        let p = FlatParams::default();
+
+        assert!(p.param_groups().is_empty());
+    }
+
+    #[test]
+    fn grouped_groups() {
+        let p = GroupedGroupedParams::default();
+
+        // Nested groups get their parent's ID as a prefix, matching the groups from `param_map()`
+        let group_ids_parents: Vec<(String, Option<String>)> = p
+            .param_groups()
+            .into_iter()
+            .map(|group| (group.id, group.parent))
+            .collect();
+        assert_eq!(
+            group_ids_parents,
+            [
+                (String::from("Top-level group"), None),
+                (
+                    String::from("Top-level group/Some Group"),
+                    Some(String::from("Top-level group"))
+                ),
+                (
+                    String::from("Top-level group/Another Group"),
+                    Some(String::from("Top-level group"))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn described() {
+        let p = DescribedGroupParams::default();
+        let groups = p.param_groups();
+
+        let fx = &groups[0];
+        assert_eq!(fx.id, "fx");
+        assert_eq!(fx.name, "Effects");
+        assert_eq!(fx.description.as_deref(), Some("The effects section"));
+        assert_eq!(fx.order, 1);
+        // The bypass parameter's ID includes the ID prefix
+        assert_eq!(fx.bypass_param_id.as_deref(), Some("fx_bypass"));
+
+        let inner = &groups[1];
+        assert_eq!(inner.id, "fx/Inner");
+        assert_eq!(inner.name, "Inner");
+        assert_eq!(inner.parent.as_deref(), Some("fx"));
+        assert_eq!(inner.bypass_param_id, None);
+
+        // Array groups get the same suffixes as their parameters
+        let voice_ids_bypasses: Vec<(&str, &str, Option<&str>)> = groups[2..]
+            .iter()
+            .map(|group| {
+                (
+                    group.id.as_str(),
+                    group.name.as_str(),
+                    group.bypass_param_id.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            voice_ids_bypasses,
+            [
+                ("Voice 1", "Voice 1", Some("one_1")),
+                ("Voice 2", "Voice 2", Some("one_2"))
+            ]
+        );
+
+        // Every group used by a parameter is described
+        for (_, _, group_id) in p.param_map() {
+            assert!(group_id.is_empty() || groups.iter().any(|group| group.id == group_id));
+        }
+    }
+
+    #[test]
+    fn display_order() {
+        let p = DescribedGroupParams::default();
+        let groups = p.param_groups();
+
+        let ids: Vec<&str> = group::display_order(&groups)
+            .into_iter()
+            .map(|group| group.id.as_str())
+            .collect();
+        assert_eq!(ids, ["Voice 1", "Voice 2", "fx", "fx/Inner"]);
+        assert_eq!(group::display_path(&groups, "fx/Inner"), "Effects/Inner");
+    }
+}
@@ -0,0 +1,98 @@
+use std::borrow::Borrow;
+
+use nih_plug::prelude::*;
+
+#[derive(Debug, Clone, PartialEq, SysExMessage)]
+#[sysex(manufacturer = 0x7d)]
+enum ShortIdMessage {
+    #[sysex(command = 0x01)]
+    SelectProgram { program: u8 },
+    #[sysex(command(0x10, 0x02))]
+    SetParameter(u8, u16),
+    #[sysex(command = 0x7f)]
+    Reset,
+}
+
+#[derive(Debug, Clone, PartialEq, SysExMessage)]
+#[sysex(manufacturer(0x00, 0x21, 0x09))]
+enum ExtendedIdMessage {
+    #[sysex(command = 0x03)]
+    Patch {
+        name: [u8; 4],
+        enabled: bool,
+        length: u32,
+    },
+}
+
+fn roundtrip<M: SysExMessage>(message: M) -> Vec<u8> {
+    let (buffer, len) = message.clone().to_buffer();
+    let bytes = Borrow::<[u8]>::borrow(&buffer)[..len].to_vec();
+    assert_eq!(M::from_buffer(&bytes), Some(message));
+
+    bytes
+}
+
+#[test]
+fn short_manufacturer_id() {
+    assert_eq!(
+        roundtrip(ShortIdMessage::SelectProgram { program: 5 }),
+        [0xf0, 0x7d, 0x01, 0x05, 0xf7]
+    );
+    assert_eq!(
+        roundtrip(ShortIdMessage::SetParameter(3, 0x1234)),
+        [0xf0, 0x7d, 0x10, 0x02, 0x03, 0x24, 0x34, 0xf7]
+    );
+    assert_eq!(roundtrip(ShortIdMessage::Reset), [0xf0, 0x7d, 0x7f, 0xf7]);
+}
+
+#[test]
+fn extended_manufacturer_id() {
+    assert_eq!(
+        roundtrip(ExtendedIdMessage::Patch {
+            name: *b"Lead",
+            enabled: true,
+            length: 0x81,
+        }),
+        [
+            0xf0, 0x00, 0x21, 0x09, 0x03, b'L', b'e', b'a', b'd', 0x01, 0x00, 0x00, 0x01, 0x01,
+            0xf7
+        ]
+    );
+}
+
+#[test]
+fn buffer_size() {
+    // The largest message is `SetParameter`, which takes up 8 bytes
+    assert_eq!(
+        std::mem::size_of::<<ShortIdMessage as SysExMessage>::Buffer>(),
+        8
+    );
+}
+
+#[test]
+fn invalid_messages() {
+    // Wrong manufacturer
+    assert_eq!(
+        ShortIdMessage::from_buffer(&[0xf0, 0x7e, 0x01, 0x05, 0xf7]),
+        None
+    );
+    // Wrong length
+    assert_eq!(
+        ShortIdMessage::from_buffer(&[0xf0, 0x7d, 0x01, 0x05, 0x06, 0xf7]),
+        None
+    );
+    // Data bytes can't have their high bit set
+    assert_eq!(
+        ShortIdMessage::from_buffer(&[0xf0, 0x7d, 0x01, 0x85, 0xf7]),
+        None
+    );
+    // Unknown command
+    assert_eq!(ShortIdMessage::from_buffer(&[0xf0, 0x7d, 0x02, 0xf7]), None);
+    assert_eq!(
+        ExtendedIdMessage::from_buffer(&[
+            0xf0, 0x00, 0x21, 0x09, 0x03, b'L', b'e', b'a', b'd', 0x02, 0x00, 0x00, 0x01, 0x01,
+            0xf7
+        ]),
+        None
+    );
+}
@@ -1,66 +1,101 @@
-use anyhow::{Context, Result};
-use std::fs;
-use std::path::Path;
-use std::process::Command;
-
-use crate::CompilationTarget;
-
-/// Acts the same as [`reflink::reflink_or_copy()`], but it removes existing files first. This works
-/// around a limitation of macOS that the reflink crate also applies to other platforms to stay
-/// consistent. See the [`reflink`] crate documentation or #26 for more information.
-pub fn reflink<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> Result<Option<u64>> {
-    let to = to.as_ref();
-    if to.exists() {
-        fs::remove_file(to).context("Could not remove file before reflinking")?;
-    }
-
-    reflink::reflink_or_copy(from, to).context("Could not reflink or copy file")
-}
-
-/// Either reflink `from` to `to` if `from` contains a single element, or combine multiple binaries
-/// into `to` depending on the compilation target
-pub fn reflink_or_combine<P: AsRef<Path>>(
-    from: &[&Path],
-    to: P,
-    compilation_target: CompilationTarget,
-) -> Result<()> {
-    match (from, compilation_target) {
-        ([], _) => anyhow::bail!("The 'from' slice is empty"),
-        ([path], _) => {
-            reflink(path, to.as_ref()).with_context(|| {
-                format!(
-                    "Could not copy {} to {}",
-                    path.display(),
-                    to.as_ref().display()
-                )
-            })?;
-        }
-        (paths, CompilationTarget::MacOSUniversal) => {
-            lipo(paths, to.as_ref())
-                .with_context(|| format!("Could not create universal binary from {paths:?}"))?;
-        }
-        _ => anyhow::bail!(
-            "Combining multiple binaries is not yet supported for {compilation_target:?}."
-        ),
-    };
-
-    Ok(())
-}
-
-/// Combine multiple macOS binaries into a universal macOS binary.
-pub fn lipo(inputs: &[&Path], target: &Path) -> Result<()> {
-    let status = Command::new("lipo")
-        .arg("-create")
-        .arg("-output")
-        .arg(target)
-        .args(inputs)
-        .status()
-        .context("Could not call the 'lipo' binary to create a universal macOS binary")?;
-    if !status.success() {
-        anyhow::bail!(
-            "Could not call the 'lipo' binary to create a universal macOS binary from {inputs:?}",
-        );
-    } else {
-        Ok(())
-    }
-}
+use anyhow::{Context, Result};
+use std::ffi::CString;
+use std::fs;
+use std::os::raw::c_char;
+use std::path::Path;
+use std::process::Command;
+
+use crate::CompilationTarget;
+
+/// Acts the same as [`reflink::reflink_or_copy()`], but it removes existing files first. This works
+/// around a limitation of macOS that the reflink crate also applies to other platforms to stay
+/// consistent. See the [`reflink`] crate documentation or #26 for more information.
+pub fn reflink<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> Result<Option<u64>> {
+    let to = to.as_ref();
+    if to.exists() {
+        fs::remove_file(to).context("Could not remove file before reflinking")?;
+    }
+
+    reflink::reflink_or_copy(from, to).context("Could not reflink or copy file")
+}
+
+/// Generate an LV2 bundle's Turtle files by loading the plugin library and calling the
+/// `nih_plug_lv2_write_ttl()` function exported by `nih_export_lv2!()`. Since this needs to load the
+/// library, this only works when the library was compiled for the current platform.
+pub fn write_lv2_ttl(library: &Path, bundle_home: &Path, binary_name: &str) -> Result<()> {
+    let bundle_home_cstr = CString::new(
+        bundle_home
+            .to_str()
+            .context("The LV2 bundle path is not valid UTF-8")?,
+    )?;
+    let binary_name_cstr = CString::new(binary_name)?;
+
+    unsafe {
+        let library = libloading::Library::new(library).with_context(|| {
+            format!(
+                "Could not load '{}' to generate the LV2 bundle's Turtle files, LV2 bundles cannot \
+                 be created when cross compiling",
+                library.display()
+            )
+        })?;
+        let write_ttl: libloading::Symbol<
+            unsafe extern "C" fn(*const c_char, *const c_char) -> bool,
+        > = library
+            .get(b"nih_plug_lv2_write_ttl\0")
+            .context("The library does not export the LV2 Turtle file generator")?;
+
+        if !write_ttl(bundle_home_cstr.as_ptr(), binary_name_cstr.as_ptr()) {
+            anyhow::bail!("Could not write the LV2 bundle's Turtle files");
+        }
+    }
+
+    Ok(())
+}
+
+/// Either reflink `from` to `to` if `from` contains a single element, or combine multiple binaries
+/// into `to` depending on the compilation target
+pub fn reflink_or_combine<P: AsRef<Path>>(
+    from: &[&Path],
+    to: P,
+    compilation_target: CompilationTarget,
+) -> Result<()> {
+    match (from, compilation_target) {
+        ([], _) => anyhow::bail!("The 'from' slice is empty"),
+        ([path], _) => {
+            reflink(path, to.as_ref()).with_context(|| {
+                format!(
+                    "Could not copy {} to {}",
+                    path.display(),
+                    to.as_ref().display()
+                )
+            })?;
+        }
+        (paths, CompilationTarget::MacOSUniversal) => {
+            lipo(paths, to.as_ref())
+                .with_context(|| format!("Could not create universal binary from {paths:?}"))?;
+        }
+        _ => anyhow::bail!(
+            "Combining multiple binaries is not yet supported for {compilation_target:?}."
+        ),
+    };
+
+    Ok(())
+}
+
+/// Combine multiple macOS binaries into a universal macOS binary.
+pub fn lipo(inputs: &[&Path], target: &Path) -> Result<()> {
+    let status = Command::new("lipo")
+        .arg("-create")
+        .arg("-output")
+        .arg(target)
+        .args(inputs)
+        .status()
+        .context("Could not call the 'lipo' binary to create a universal macOS binary")?;
+    if !status.success() {
+        anyhow::bail!(
+            "Could not call the 'lipo' binary to create a universal macOS binary from {inputs:?}",
+        );
+    } else {
+        Ok(())
+    }
+}
@@ -1,29 +1,33 @@
-//! Different contexts the plugin can use to make callbacks to the host in different...contexts.
-
-use std::fmt::Display;
-
-pub mod gui;
-pub mod init;
-pub mod process;
-
-// Contexts for more plugin-API specific features
-pub mod remote_controls;
-
-/// The currently active plugin API. This may be useful to display in an about screen in the
-/// plugin's GUI for debugging purposes.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum PluginApi {
-    Clap,
-    Standalone,
-    Vst3,
-}
-
-impl Display for PluginApi {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            PluginApi::Clap => write!(f, "CLAP"),
-            PluginApi::Standalone => write!(f, "standalone"),
-            PluginApi::Vst3 => write!(f, "VST3"),
-        }
-    }
-}
+//! Different contexts the plugin can use to make callbacks to the host in different...contexts.
+
+use std::fmt::Display;
+
+pub mod gui;
+pub mod init;
+pub mod process;
+pub mod snapshots;
+pub mod undo;
+
+// Contexts for more plugin-API specific features
+pub mod remote_controls;
+
+/// The currently active plugin API. This may be useful to display in an about screen in the
+/// plugin's GUI for debugging purposes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluginApi {
+    Clap,
+    Lv2,
+    Standalone,
+    Vst3,
+}
+
+impl Display for PluginApi {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PluginApi::Clap => write!(f, "CLAP"),
+            PluginApi::Lv2 => write!(f, "LV2"),
+            PluginApi::Standalone => write!(f, "standalone"),
+            PluginApi::Vst3 => write!(f, "VST3"),
+        }
+    }
+}
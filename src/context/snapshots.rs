@@ -0,0 +1,563 @@
+//! Snapshot slots for quickly switching between different versions of a plugin's state, like the
+//! A/B comparison buttons found in many plugins. Enable these by setting
+//! [`Plugin::SNAPSHOT_CONFIG`][crate::prelude::Plugin::SNAPSHOT_CONFIG], and use
+//! [`GuiContext::store_snapshot()`] and [`GuiContext::recall_snapshot()`] from the editor to manage
+//! the slots.
+//!
+//! Recalling a snapshot crossfades the plugin's parameters from their current values to the values
+//! stored in the snapshot. The audio thread loads the snapshot's state at the end of a processing
+//! cycle, and then moves the parameters towards their new values over the next couple of blocks
+//! the same way automation would. Continuous parameters are interpolated while discrete parameters
+//! switch halfway through the crossfade. This avoids the clicks caused by parameter values jumping
+//! and by reinitializing the plugin. Snapshots with different `#[persist]` fields than the current
+//! state are loaded right away instead, as the plugin may need to be reinitialized to respond to
+//! those fields.
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::prelude::{GuiContext, ParamPtr, PluginState};
+
+/// The key used to store the snapshot slots in [`PluginState::fields`] when
+/// [`SnapshotConfig::persist`] is enabled.
+const SNAPSHOTS_FIELD_KEY: &str = "nih_plug_snapshots";
+
+/// Configuration for a plugin's snapshot slots. See
+/// [`Plugin::SNAPSHOT_CONFIG`][crate::prelude::Plugin::SNAPSHOT_CONFIG].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SnapshotConfig {
+    /// The number of snapshot slots. Two slots gives you a classic A/B comparison.
+    pub num_slots: usize,
+    /// The length of the crossfade between the current parameter values and the recalled
+    /// snapshot's parameter values, in milliseconds. Set this to zero to switch without
+    /// crossfading.
+    pub crossfade_length_ms: f32,
+    /// Whether the snapshot slots should be saved together with the plugin's state. If this is
+    /// disabled, then the slots are lost when the plugin instance is closed.
+    pub persist: bool,
+}
+
+impl SnapshotConfig {
+    /// Two slots with a 20 millisecond crossfade, persisted with the plugin's state.
+    pub const AB: Self = Self {
+        num_slots: 2,
+        crossfade_length_ms: 20.0,
+        persist: true,
+    };
+}
+
+/// A plugin's snapshot slots. This is owned by the wrapper, so the slots are kept when the editor
+/// is closed and opened again. Access this through [`GuiContext::snapshots()`].
+#[derive(Debug)]
+pub struct Snapshots {
+    config: SnapshotConfig,
+    slots: Mutex<Vec<Option<PluginState>>>,
+    active_slot: AtomicUsize,
+    /// The state the audio thread should crossfade to at the end of the current processing cycle.
+    pending_state: Mutex<Option<PluginState>>,
+    /// The state most recently loaded by the audio thread. This is kept around so it can be
+    /// deallocated on the GUI thread during the next recall instead of on the audio thread.
+    loaded_state: Mutex<Option<PluginState>>,
+    /// The crossfade that's currently in progress. Only used from the audio thread, except for when
+    /// the host loads a new state.
+    crossfade: Mutex<SnapshotCrossfade>,
+    /// Whether the wrapper is currently processing audio. If it isn't, then recalled snapshots are
+    /// loaded right away since there is nothing to crossfade.
+    processing: AtomicBool,
+}
+
+/// The snapshots as they're stored in [`PluginState::fields`].
+#[derive(Serialize, Deserialize)]
+struct PersistedSnapshots {
+    active: usize,
+    slots: Vec<Option<PluginState>>,
+}
+
+/// A crossfade between the parameter values from before and after a snapshot was loaded.
+#[derive(Debug)]
+struct SnapshotCrossfade {
+    /// The parameters that have a different value in the recalled snapshot. The allocation is
+    /// reused for later crossfades.
+    params: Vec<CrossfadeParam>,
+    /// How far along the crossfade is, between 0 and 1. The crossfade has finished when this is 1.
+    progress: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CrossfadeParam {
+    param: ParamPtr,
+    /// The parameter's unmodulated normalized value before the snapshot was loaded.
+    from: f32,
+    /// The parameter's unmodulated normalized value in the snapshot.
+    to: f32,
+}
+
+impl Snapshots {
+    /// Create empty snapshot slots for a configuration. The first slot is active.
+    pub fn new(config: SnapshotConfig) -> Self {
+        Self {
+            config,
+            slots: Mutex::new(vec![None; config.num_slots]),
+            active_slot: AtomicUsize::new(0),
+            pending_state: Mutex::new(None),
+            loaded_state: Mutex::new(None),
+            crossfade: Mutex::new(SnapshotCrossfade {
+                params: Vec::new(),
+                progress: 1.0,
+            }),
+            processing: AtomicBool::new(false),
+        }
+    }
+
+    /// The configuration these slots were created with.
+    pub fn config(&self) -> &SnapshotConfig {
+        &self.config
+    }
+
+    /// The number of snapshot slots.
+    pub fn num_slots(&self) -> usize {
+        self.config.num_slots
+    }
+
+    /// The index of the slot that was last stored or recalled.
+    pub fn active_slot(&self) -> usize {
+        self.active_slot.load(Ordering::Relaxed)
+    }
+
+    /// Whether a state has been stored in a slot. Returns `false` if the slot doesn't exist.
+    pub fn has_snapshot(&self, slot: usize) -> bool {
+        matches!(self.slots.lock().get(slot), Some(Some(_)))
+    }
+
+    /// Get a copy of the state stored in a slot, if any.
+    pub fn snapshot(&self, slot: usize) -> Option<PluginState> {
+        self.slots.lock().get(slot).cloned().flatten()
+    }
+
+    /// Store a state in a slot. Returns `false` if the slot doesn't exist. This does not change the
+    /// active slot. Use [`GuiContext::store_snapshot()`] to store the plugin's current state.
+    pub fn store(&self, slot: usize, state: PluginState) -> bool {
+        match self.slots.lock().get_mut(slot) {
+            Some(stored_state) => {
+                *stored_state = Some(state);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Clear a slot. Returns `false` if the slot doesn't exist.
+    pub fn clear(&self, slot: usize) -> bool {
+        match self.slots.lock().get_mut(slot) {
+            Some(stored_state) => {
+                *stored_state = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Mark a slot as the active slot.
+    pub(crate) fn set_active_slot(&self, slot: usize) {
+        self.active_slot.store(slot, Ordering::Relaxed);
+    }
+
+    /// Called by the wrapper when it starts or stops processing audio.
+    pub(crate) fn set_processing(&self, processing: bool) {
+        self.processing.store(processing, Ordering::SeqCst);
+    }
+
+    /// Whether a recalled snapshot can be crossfaded to. If the plugin is not processing audio or if
+    /// crossfading has been disabled, then the snapshot should be loaded right away.
+    fn can_crossfade(&self) -> bool {
+        self.config.crossfade_length_ms > 0.0 && self.processing.load(Ordering::SeqCst)
+    }
+
+    /// Set or clear the state the audio thread should crossfade to. Called from the GUI thread.
+    fn set_pending_state(&self, state: Option<PluginState>) {
+        // The state loaded during the previous recall is deallocated here instead of on the audio
+        // thread. This needs to happen before a new state is scheduled, as the audio thread will
+        // overwrite the field after loading that state.
+        let loaded_state = self.loaded_state.lock().take();
+        drop(loaded_state);
+
+        let previous_state = std::mem::replace(&mut *self.pending_state.lock(), state);
+        drop(previous_state);
+    }
+
+    /// Take the state scheduled by a recall and start crossfading towards it. The wrapper calls this
+    /// from the audio thread at the end of a processing cycle. `params` are all of the plugin's
+    /// parameters, and `load_state` should load the state's parameter values and fields without
+    /// reinitializing the plugin. The parameters are then set back to their old values so
+    /// [`advance_crossfade()`][Self::advance_crossfade()] can move them towards the loaded values.
+    /// Returns `false` if no state was scheduled.
+    ///
+    /// # Safety
+    ///
+    /// The parameter pointers need to stay valid until the crossfade has finished.
+    pub(crate) unsafe fn start_crossfade(
+        &self,
+        params: impl IntoIterator<Item = ParamPtr>,
+        sample_rate: f32,
+        load_state: impl FnOnce(&mut PluginState) -> bool,
+    ) -> bool {
+        // The GUI thread only holds this lock for a moment, so the next cycle can try again
+        let mut state = match self
+            .pending_state
+            .try_lock()
+            .and_then(|mut pending_state| pending_state.take())
+        {
+            Some(state) => state,
+            None => return false,
+        };
+
+        let mut crossfade = self.crossfade.lock();
+        crossfade.params.clear();
+        crossfade
+            .params
+            .extend(params.into_iter().map(|param| CrossfadeParam {
+                param,
+                from: param.unmodulated_normalized_value(),
+                to: param.unmodulated_normalized_value(),
+            }));
+        if load_state(&mut state) {
+            for param in crossfade.params.iter_mut() {
+                param.to = param.param.unmodulated_normalized_value();
+                param.param.set_normalized_value(param.from);
+                param.param.update_smoother(sample_rate, true);
+            }
+            crossfade.params.retain(|param| param.from != param.to);
+            crossfade.progress = 0.0;
+        }
+
+        // This is always empty at this point, see `set_pending_state()`
+        *self.loaded_state.lock() = Some(state);
+
+        true
+    }
+
+    /// Move the parameters that are being crossfaded towards their new values. Called from the
+    /// audio thread before the plugin processes a block of `num_samples` samples. `set_param` is
+    /// called with the parameters' new normalized values, and it should update the parameter the
+    /// same way automation would. Returns `true` if the crossfade finished during this call, in
+    /// which case the wrapper should let the host know that the parameter values have changed.
+    ///
+    /// # Safety
+    ///
+    /// The parameter pointers passed to [`start_crossfade()`][Self::start_crossfade()] still need
+    /// to be valid.
+    pub(crate) unsafe fn advance_crossfade(
+        &self,
+        num_samples: usize,
+        sample_rate: f32,
+        mut set_param: impl FnMut(ParamPtr, f32),
+    ) -> bool {
+        let mut crossfade = match self.crossfade.try_lock() {
+            Some(crossfade) => crossfade,
+            None => return false,
+        };
+        if crossfade.progress >= 1.0 {
+            return false;
+        }
+
+        let length_samples = self.config.crossfade_length_ms / 1000.0 * sample_rate;
+        let previous_progress = crossfade.progress;
+        let progress = if length_samples >= 1.0 {
+            (previous_progress + num_samples as f32 / length_samples).min(1.0)
+        } else {
+            1.0
+        };
+        crossfade.progress = progress;
+
+        // Discrete parameters can't be interpolated, so they switch halfway through the crossfade
+        for param in &crossfade.params {
+            if param.param.step_count().is_none() {
+                let value = param.from + ((param.to - param.from) * progress);
+                set_param(param.param, value);
+            } else if previous_progress < 0.5 && progress >= 0.5 {
+                set_param(param.param, param.to);
+            }
+        }
+
+        progress >= 1.0
+    }
+
+    /// Stop the crossfade that's currently in progress, if any. The wrapper calls this when it
+    /// loads a new state so the crossfade doesn't overwrite the new state's parameter values.
+    pub(crate) fn stop_crossfade(&self) {
+        self.crossfade.lock().progress = 1.0;
+    }
+
+    /// Add the snapshot slots to a state object that's about to be saved by the host if
+    /// [`SnapshotConfig::persist`] is enabled.
+    pub(crate) fn write_to_state(&self, state: &mut PluginState) {
+        if !self.config.persist {
+            return;
+        }
+
+        let persisted = PersistedSnapshots {
+            active: self.active_slot(),
+            slots: self.slots.lock().clone(),
+        };
+        match serde_json::to_string(&persisted) {
+            Ok(json) => {
+                state.fields.insert(String::from(SNAPSHOTS_FIELD_KEY), json);
+            }
+            Err(err) => nih_debug_assert_failure!("Could not serialize the snapshots: {}", err),
+        }
+    }
+
+    /// Restore the snapshot slots from a state object loaded by the host, and remove them from the
+    /// state so they don't end up in the snapshots or presets created from this state. If
+    /// [`SnapshotConfig::persist`] is enabled and the state doesn't contain any snapshots, then the
+    /// slots are cleared.
+    pub(crate) fn read_from_state(&self, state: &mut PluginState) {
+        let json = state.fields.remove(SNAPSHOTS_FIELD_KEY);
+        if !self.config.persist {
+            return;
+        }
+
+        let mut slots = self.slots.lock();
+        slots.iter_mut().for_each(|slot| *slot = None);
+        self.set_active_slot(0);
+
+        let persisted: PersistedSnapshots = match json.map(|json| serde_json::from_str(&json)) {
+            Some(Ok(persisted)) => persisted,
+            Some(Err(err)) => {
+                nih_debug_assert_failure!("Could not deserialize the snapshots: {}", err);
+                return;
+            }
+            None => return,
+        };
+
+        // The number of slots may have changed since the state was saved
+        for (slot, persisted_slot) in slots.iter_mut().zip(persisted.slots) {
+            *slot = persisted_slot;
+        }
+        if persisted.active < slots.len() {
+            self.set_active_slot(persisted.active);
+        }
+    }
+}
+
+/// Recall a snapshot through a GUI context. See [`GuiContext::recall_snapshot()`].
+pub(crate) fn recall<C: GuiContext + ?Sized>(
+    context: &C,
+    snapshots: &Snapshots,
+    slot: usize,
+) -> bool {
+    if slot >= snapshots.num_slots() {
+        return false;
+    }
+
+    let active_slot = snapshots.active_slot();
+    if slot == active_slot {
+        return true;
+    }
+
+    // The changes made to the active slot are kept so switching back and forth works like the
+    // A/B buttons on a hardware unit. Recalling an empty slot copies the current state into it.
+    let current_state = context.get_state();
+    snapshots.store(active_slot, current_state.clone());
+    match snapshots.snapshot(slot) {
+        // Changes to `#[persist]` fields may require the plugin to be reinitialized, so those
+        // snapshots are loaded right away instead
+        Some(state) if snapshots.can_crossfade() && state.fields == current_state.fields => {
+            // The audio thread loads the state directly, so the undo step that `set_state()` would
+            // have recorded is recorded here
+            if let Some(history) = context.undo_history() {
+                history.record_set_state(|| current_state, &state);
+            }
+            snapshots.set_pending_state(Some(state));
+        }
+        Some(state) => {
+            snapshots.set_pending_state(None);
+            context.set_state(state);
+        }
+        None => {
+            snapshots.store(slot, current_state);
+        }
+    }
+    snapshots.set_active_slot(slot);
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    use crate::context::undo::UndoHistory;
+    use crate::prelude::{BoolParam, FloatParam, FloatRange, Param, ParamValue, PluginApi};
+
+    /// A minimal context that stores the state directly, with the snapshots and undo history a
+    /// wrapper would have.
+    struct TestContext {
+        history: UndoHistory,
+        snapshots: Snapshots,
+        state: Mutex<PluginState>,
+    }
+
+    impl GuiContext for TestContext {
+        fn plugin_api(&self) -> PluginApi {
+            PluginApi::Standalone
+        }
+
+        fn request_resize(&self) -> bool {
+            true
+        }
+
+        unsafe fn raw_begin_set_parameter(&self, _param: ParamPtr) {}
+
+        unsafe fn raw_set_parameter_normalized(&self, _param: ParamPtr, _normalized: f32) {}
+
+        unsafe fn raw_end_set_parameter(&self, _param: ParamPtr) {}
+
+        fn get_state(&self) -> PluginState {
+            self.state.lock().clone()
+        }
+
+        fn set_state(&self, state: PluginState) {
+            self.history.record_set_state(|| self.get_state(), &state);
+            *self.state.lock() = state;
+        }
+
+        fn undo_history(&self) -> Option<&UndoHistory> {
+            Some(&self.history)
+        }
+
+        fn snapshots(&self) -> Option<&Snapshots> {
+            Some(&self.snapshots)
+        }
+    }
+
+    fn make_state(value: f32) -> PluginState {
+        PluginState {
+            version: String::from("1.0.0"),
+            params: BTreeMap::from([(String::from("gain"), ParamValue::F32(value))]),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    fn gain_of(state: &PluginState) -> f32 {
+        match state.params.get("gain") {
+            Some(ParamValue::F32(value)) => *value,
+            _ => panic!("Missing gain parameter"),
+        }
+    }
+
+    #[test]
+    fn persist_round_trip() {
+        let snapshots = Snapshots::new(SnapshotConfig::AB);
+        assert!(snapshots.store(0, make_state(0.25)));
+        assert!(snapshots.store(1, make_state(0.75)));
+        assert!(!snapshots.store(2, make_state(1.0)));
+        snapshots.set_active_slot(1);
+
+        let mut state = make_state(0.75);
+        snapshots.write_to_state(&mut state);
+        assert!(state.fields.contains_key(SNAPSHOTS_FIELD_KEY));
+
+        let restored = Snapshots::new(SnapshotConfig::AB);
+        restored.read_from_state(&mut state);
+        assert!(!state.fields.contains_key(SNAPSHOTS_FIELD_KEY));
+        assert_eq!(restored.active_slot(), 1);
+        assert_eq!(gain_of(&restored.snapshot(0).unwrap()), 0.25);
+        assert_eq!(gain_of(&restored.snapshot(1).unwrap()), 0.75);
+    }
+
+    #[test]
+    fn no_persist() {
+        let config = SnapshotConfig {
+            persist: false,
+            ..SnapshotConfig::AB
+        };
+        let snapshots = Snapshots::new(config);
+        snapshots.store(0, make_state(0.25));
+
+        let mut state = make_state(0.25);
+        snapshots.write_to_state(&mut state);
+        assert!(state.fields.is_empty());
+
+        // Loading a state without snapshots keeps the existing slots when they're not persisted
+        snapshots.read_from_state(&mut state);
+        assert!(snapshots.has_snapshot(0));
+    }
+
+    #[test]
+    fn crossfade_parameters() {
+        let snapshots = Snapshots::new(SnapshotConfig::AB);
+        let gain = FloatParam::new("Gain", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 });
+        let bypass = BoolParam::new("Bypass", false);
+        let params = [gain.as_ptr(), bypass.as_ptr()];
+
+        // Nothing happens until a state has been scheduled
+        assert!(!unsafe { snapshots.start_crossfade(params, 48_000.0, |_| true) });
+
+        snapshots.set_pending_state(Some(make_state(1.0)));
+        let started = unsafe {
+            snapshots.start_crossfade(params, 48_000.0, |state| {
+                gain.as_ptr().set_normalized_value(gain_of(state));
+                bypass.as_ptr().set_normalized_value(1.0);
+                true
+            })
+        };
+        assert!(started);
+        assert_eq!(gain.unmodulated_normalized_value(), 0.0);
+        assert!(!bypass.value());
+        assert!(snapshots.loaded_state.lock().is_some());
+
+        // A 20 ms crossfade at 48 kHz takes 960 samples, and the bypass switches halfway through
+        let set_param = |param: ParamPtr, normalized: f32| unsafe {
+            param.set_normalized_value(normalized);
+        };
+        assert!(!unsafe { snapshots.advance_crossfade(240, 48_000.0, set_param) });
+        assert_eq!(gain.unmodulated_normalized_value(), 0.25);
+        assert!(!bypass.value());
+        assert!(!unsafe { snapshots.advance_crossfade(480, 48_000.0, set_param) });
+        assert_eq!(gain.unmodulated_normalized_value(), 0.75);
+        assert!(bypass.value());
+        assert!(unsafe { snapshots.advance_crossfade(480, 48_000.0, set_param) });
+        assert_eq!(gain.unmodulated_normalized_value(), 1.0);
+        assert!(!unsafe { snapshots.advance_crossfade(480, 48_000.0, set_param) });
+
+        // The next recall deallocates the loaded state on the GUI thread
+        snapshots.set_pending_state(Some(make_state(0.5)));
+        assert!(snapshots.loaded_state.lock().is_none());
+        unsafe { snapshots.start_crossfade(params, 48_000.0, |_| true) };
+        snapshots.stop_crossfade();
+        assert!(!unsafe { snapshots.advance_crossfade(480, 48_000.0, set_param) });
+    }
+
+    #[test]
+    fn crossfaded_recalls_are_undoable() {
+        let context = TestContext {
+            history: UndoHistory::new(10),
+            snapshots: Snapshots::new(SnapshotConfig::AB),
+            state: Mutex::new(make_state(0.25)),
+        };
+
+        // Without audio processing the state is loaded right away
+        assert!(context.store_snapshot(0));
+        context.snapshots.store(1, make_state(0.5));
+        assert!(context.recall_snapshot(1));
+        assert_eq!(gain_of(&context.get_state()), 0.5);
+        assert!(context.snapshots.pending_state.lock().is_none());
+
+        // While processing audio the state is left to the audio thread, but the recall should still
+        // be recorded as an undo step
+        context.snapshots.set_processing(true);
+        assert!(context.recall_snapshot(0));
+        assert_eq!(gain_of(&context.get_state()), 0.5);
+        assert_eq!(
+            gain_of(context.snapshots.pending_state.lock().as_ref().unwrap()),
+            0.25
+        );
+        assert_eq!(
+            context.history.undo_steps(),
+            vec!["Load state", "Load state"]
+        );
+    }
+}
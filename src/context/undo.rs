@@ -0,0 +1,438 @@
+//! An undo history for changes made from the plugin's editor. The host's own undo history usually
+//! only covers automatable parameter changes, so changes to persistent fields and whole-state loads
+//! can't be undone through the host.
+//!
+//! Every parameter gesture started with
+//! [`ParamSetter::begin_set_parameter()`][crate::prelude::ParamSetter::begin_set_parameter()] and
+//! finished with [`ParamSetter::end_set_parameter()`][crate::prelude::ParamSetter::end_set_parameter()]
+//! becomes a single undo step, and so does every [`GuiContext::set_state()`] call. Other changes,
+//! like changes to `#[persist]` fields, can be recorded by wrapping them in
+//! [`GuiContext::begin_undo_step()`] and [`GuiContext::end_undo_step()`].
+
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+
+use crate::prelude::{GuiContext, ParamPtr, PluginState};
+
+/// The default maximum number of undo steps.
+pub const DEFAULT_MAX_UNDO_STEPS: usize = 128;
+
+/// The undo and redo history for a plugin instance. This is owned by the wrapper, so the history is
+/// kept when the editor is closed and opened again. Access this through
+/// [`GuiContext::undo_history()`], and use [`GuiContext::undo()`] and [`GuiContext::redo()`] to
+/// move through the history.
+#[derive(Debug)]
+pub struct UndoHistory {
+    inner: Mutex<UndoHistoryInner>,
+}
+
+#[derive(Debug)]
+struct UndoHistoryInner {
+    /// The oldest steps are at the front.
+    undo_stack: VecDeque<UndoStep>,
+    /// The most recently undone step is at the back.
+    redo_stack: Vec<UndoStep>,
+    max_steps: usize,
+    /// Parameter gestures that have been started but not yet finished.
+    open_gestures: Vec<ParamChange>,
+    /// The description and the state before the change for the step started with
+    /// [`GuiContext::begin_undo_step()`], if any. Gestures and state loads are not recorded
+    /// separately while this is active.
+    open_step: Option<(String, PluginState)>,
+    /// Set while an undo or redo step is being applied so the resulting changes aren't recorded
+    /// as new steps.
+    applying: bool,
+}
+
+/// A single step in the undo history.
+#[derive(Debug, Clone)]
+pub(crate) struct UndoStep {
+    description: String,
+    change: UndoChange,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum UndoChange {
+    /// A parameter gesture. The parameter is set to the old or new normalized value.
+    Param(ParamChange),
+    /// A state change. The entire state is swapped out.
+    State {
+        before: PluginState,
+        after: PluginState,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ParamChange {
+    pub param: ParamPtr,
+    pub before: f32,
+    pub after: f32,
+}
+
+impl Default for UndoHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_UNDO_STEPS)
+    }
+}
+
+impl UndoHistory {
+    /// Create an empty history that keeps up to `max_steps` undo steps.
+    pub fn new(max_steps: usize) -> Self {
+        Self {
+            inner: Mutex::new(UndoHistoryInner {
+                undo_stack: VecDeque::new(),
+                redo_stack: Vec::new(),
+                max_steps: max_steps.max(1),
+                open_gestures: Vec::new(),
+                open_step: None,
+                applying: false,
+            }),
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.inner.lock().undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.inner.lock().redo_stack.is_empty()
+    }
+
+    /// The descriptions of the steps that can be undone, with the step that would be undone next
+    /// coming first.
+    pub fn undo_steps(&self) -> Vec<String> {
+        self.inner
+            .lock()
+            .undo_stack
+            .iter()
+            .rev()
+            .map(|step| step.description.clone())
+            .collect()
+    }
+
+    /// The descriptions of the steps that can be redone, with the step that would be redone next
+    /// coming first.
+    pub fn redo_steps(&self) -> Vec<String> {
+        self.inner
+            .lock()
+            .redo_stack
+            .iter()
+            .rev()
+            .map(|step| step.description.clone())
+            .collect()
+    }
+
+    /// Remove all undo and redo steps.
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock();
+        inner.undo_stack.clear();
+        inner.redo_stack.clear();
+    }
+
+    /// Record the start of a parameter gesture. Called by the [`GuiContext`] implementation from
+    /// `raw_begin_set_parameter()`.
+    ///
+    /// # Safety
+    ///
+    /// `param` needs to belong to the plugin.
+    pub(crate) unsafe fn begin_gesture(&self, param: ParamPtr) {
+        let mut inner = self.inner.lock();
+        if inner.is_recording() && !inner.open_gestures.iter().any(|g| g.param == param) {
+            let value = param.unmodulated_normalized_value();
+            inner.open_gestures.push(ParamChange {
+                param,
+                before: value,
+                after: value,
+            });
+        }
+    }
+
+    /// Record a parameter change that's part of a gesture. The parameter's value may only be
+    /// updated after the gesture has ended, so the new value is stored here.
+    pub(crate) fn set_parameter(&self, param: ParamPtr, normalized: f32) {
+        let mut inner = self.inner.lock();
+        if let Some(gesture) = inner.open_gestures.iter_mut().find(|g| g.param == param) {
+            gesture.after = normalized;
+        }
+    }
+
+    /// Finish a parameter gesture and turn it into an undo step if the value has changed.
+    ///
+    /// # Safety
+    ///
+    /// `param` needs to belong to the plugin.
+    pub(crate) unsafe fn end_gesture(&self, param: ParamPtr) {
+        let mut inner = self.inner.lock();
+        let gesture_idx = match inner.open_gestures.iter().position(|g| g.param == param) {
+            Some(idx) => idx,
+            None => return,
+        };
+
+        let gesture = inner.open_gestures.remove(gesture_idx);
+        if gesture.before != gesture.after {
+            inner.push(UndoStep {
+                description: format!("Change {}", param.name()),
+                change: UndoChange::Param(gesture),
+            });
+        }
+    }
+
+    /// Record a state load. `current_state` is only called when the change is recorded.
+    pub(crate) fn record_set_state(
+        &self,
+        current_state: impl FnOnce() -> PluginState,
+        new_state: &PluginState,
+    ) {
+        let mut inner = self.inner.lock();
+        if inner.is_recording() {
+            inner.push(UndoStep {
+                description: String::from("Load state"),
+                change: UndoChange::State {
+                    before: current_state(),
+                    after: new_state.clone(),
+                },
+            });
+        }
+    }
+
+    /// Start grouping changes into a single step. Returns `false` if another step is already open.
+    pub(crate) fn begin_step(&self, description: &str, current_state: PluginState) -> bool {
+        let mut inner = self.inner.lock();
+        if inner.open_step.is_some() || inner.applying {
+            return false;
+        }
+
+        inner.open_step = Some((String::from(description), current_state));
+        true
+    }
+
+    /// Finish the step started with [`begin_step()`][Self::begin_step()].
+    pub(crate) fn end_step(&self, current_state: impl FnOnce() -> PluginState) {
+        let mut inner = self.inner.lock();
+        if let Some((description, before)) = inner.open_step.take() {
+            inner.push(UndoStep {
+                description,
+                change: UndoChange::State {
+                    before,
+                    after: current_state(),
+                },
+            });
+        }
+    }
+
+    /// Take the next step to undo. Until [`finish_applying()`][Self::finish_applying()] is called
+    /// no new changes are recorded.
+    pub(crate) fn start_undo(&self) -> Option<UndoChange> {
+        let mut inner = self.inner.lock();
+        if inner.applying || inner.open_step.is_some() {
+            return None;
+        }
+
+        let step = inner.undo_stack.pop_back()?;
+        let change = step.change.clone();
+        inner.redo_stack.push(step);
+        inner.applying = true;
+
+        Some(change)
+    }
+
+    /// Take the next step to redo. Until [`finish_applying()`][Self::finish_applying()] is called
+    /// no new changes are recorded.
+    pub(crate) fn start_redo(&self) -> Option<UndoChange> {
+        let mut inner = self.inner.lock();
+        if inner.applying || inner.open_step.is_some() {
+            return None;
+        }
+
+        let step = inner.redo_stack.pop()?;
+        let change = step.change.clone();
+        inner.undo_stack.push_back(step);
+        inner.applying = true;
+
+        Some(change)
+    }
+
+    pub(crate) fn finish_applying(&self) {
+        self.inner.lock().applying = false;
+    }
+}
+
+impl UndoHistoryInner {
+    fn is_recording(&self) -> bool {
+        !self.applying && self.open_step.is_none()
+    }
+
+    /// Add a new step. This clears the redo history.
+    fn push(&mut self, step: UndoStep) {
+        self.redo_stack.clear();
+        self.undo_stack.push_back(step);
+        while self.undo_stack.len() > self.max_steps {
+            self.undo_stack.pop_front();
+        }
+    }
+}
+
+/// Apply an undo or redo step through a [`GuiContext`]. Parameter changes are sent as a gesture so
+/// the host is informed about them.
+pub(crate) fn apply_change<C: GuiContext + ?Sized>(context: &C, change: UndoChange, undo: bool) {
+    match change {
+        UndoChange::Param(ParamChange {
+            param,
+            before,
+            after,
+        }) => unsafe {
+            context.raw_begin_set_parameter(param);
+            context.raw_set_parameter_normalized(param, if undo { before } else { after });
+            context.raw_end_set_parameter(param);
+        },
+        UndoChange::State { before, after } => {
+            context.set_state(if undo { before } else { after });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::{FloatParam, FloatRange, Param, PluginApi};
+    use std::collections::BTreeMap;
+
+    /// A minimal context that sets parameters immediately.
+    struct TestContext {
+        history: UndoHistory,
+        state: Mutex<PluginState>,
+    }
+
+    impl GuiContext for TestContext {
+        fn plugin_api(&self) -> PluginApi {
+            PluginApi::Standalone
+        }
+
+        fn request_resize(&self) -> bool {
+            true
+        }
+
+        unsafe fn raw_begin_set_parameter(&self, param: ParamPtr) {
+            self.history.begin_gesture(param);
+        }
+
+        unsafe fn raw_set_parameter_normalized(&self, param: ParamPtr, normalized: f32) {
+            self.history.set_parameter(param, normalized);
+            param.set_normalized_value(normalized);
+        }
+
+        unsafe fn raw_end_set_parameter(&self, param: ParamPtr) {
+            self.history.end_gesture(param);
+        }
+
+        fn get_state(&self) -> PluginState {
+            self.state.lock().clone()
+        }
+
+        fn set_state(&self, state: PluginState) {
+            self.history.record_set_state(|| self.get_state(), &state);
+            *self.state.lock() = state;
+        }
+
+        fn undo_history(&self) -> Option<&UndoHistory> {
+            Some(&self.history)
+        }
+    }
+
+    fn make_context(max_steps: usize) -> TestContext {
+        TestContext {
+            history: UndoHistory::new(max_steps),
+            state: Mutex::new(make_state("0")),
+        }
+    }
+
+    fn make_state(version: &str) -> PluginState {
+        PluginState {
+            version: String::from(version),
+            params: BTreeMap::new(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    fn make_param() -> FloatParam {
+        FloatParam::new("Gain", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+    }
+
+    fn drag(context: &TestContext, param: &FloatParam, values: &[f32]) {
+        let ptr = param.as_ptr();
+        unsafe {
+            context.raw_begin_set_parameter(ptr);
+            for value in values {
+                context.raw_set_parameter_normalized(ptr, *value);
+            }
+            context.raw_end_set_parameter(ptr);
+        }
+    }
+
+    #[test]
+    fn gesture_is_single_step() {
+        let context = make_context(DEFAULT_MAX_UNDO_STEPS);
+        let param = make_param();
+
+        drag(&context, &param, &[0.1, 0.2, 0.3]);
+        assert_eq!(context.history.undo_steps(), vec!["Change Gain"]);
+
+        assert!(context.undo());
+        assert_eq!(param.unmodulated_normalized_value(), 0.0);
+        assert!(!context.history.can_undo());
+        // Undoing a step should not record a new step
+        assert_eq!(context.history.redo_steps(), vec!["Change Gain"]);
+
+        assert!(context.redo());
+        assert_eq!(param.unmodulated_normalized_value(), 0.3);
+        assert!(!context.history.can_redo());
+    }
+
+    #[test]
+    fn unchanged_gesture_is_not_recorded() {
+        let context = make_context(DEFAULT_MAX_UNDO_STEPS);
+        let param = make_param();
+
+        drag(&context, &param, &[0.5, 0.0]);
+        assert!(!context.history.can_undo());
+    }
+
+    #[test]
+    fn state_loads_and_grouped_steps_can_be_undone() {
+        let context = make_context(DEFAULT_MAX_UNDO_STEPS);
+
+        context.set_state(make_state("1"));
+        context.begin_undo_step("Edit sample");
+        *context.state.lock() = make_state("2");
+        context.end_undo_step();
+        assert_eq!(
+            context.history.undo_steps(),
+            vec!["Edit sample", "Load state"]
+        );
+
+        assert!(context.undo());
+        assert_eq!(context.get_state().version, "1");
+        assert!(context.undo());
+        assert_eq!(context.get_state().version, "0");
+        assert!(!context.undo());
+
+        assert!(context.redo());
+        assert_eq!(context.get_state().version, "1");
+    }
+
+    #[test]
+    fn new_step_clears_redo_history_and_history_is_bounded() {
+        let context = make_context(2);
+        let param = make_param();
+
+        drag(&context, &param, &[0.1]);
+        drag(&context, &param, &[0.2]);
+        drag(&context, &param, &[0.3]);
+        assert_eq!(context.history.undo_steps().len(), 2);
+
+        assert!(context.undo());
+        assert!(context.history.can_redo());
+        drag(&context, &param, &[0.4]);
+        assert!(!context.history.can_redo());
+    }
+}
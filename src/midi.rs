@@ -1,820 +1,1718 @@
-//! Constants and definitions surrounding MIDI support.
-
-use midi_consts::channel_event as midi;
-
-use self::sysex::SysExMessage;
-use crate::prelude::Plugin;
-
-pub mod sysex;
-
-pub use midi_consts::channel_event::control_change;
-
-/// A plugin-specific note event type.
-///
-/// The reason why this is defined like this instead of parameterizing `NoteEvent` with `P` is
-/// because deriving trait bounds requires all of the plugin's generic parameters to implement those
-/// traits. And we can't require `P` to implement things like `Clone`.
-///
-/// <https://github.com/rust-lang/rust/issues/26925>
-pub type PluginNoteEvent<P> = NoteEvent<<P as Plugin>::SysExMessage>;
-
-/// Determines which note events a plugin can send and receive.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-pub enum MidiConfig {
-    /// The plugin will not have a note input or output port and will thus not receive any not
-    /// events.
-    None,
-    /// The plugin receives note on/off/choke events, pressure, and potentially a couple
-    /// standardized expression types depending on the plugin standard and host. If the plugin sets
-    /// up configuration for polyphonic modulation (see [`ClapPlugin`][crate::prelude::ClapPlugin])
-    /// and assigns polyphonic modulation IDs to some of its parameters, then it will also receive
-    /// polyphonic modulation events. This level is also needed to be able to send SysEx events.
-    Basic,
-    /// The plugin receives full MIDI CCs as well as pitch bend information. For VST3 plugins this
-    /// involves adding 130*16 parameters to bind to the the 128 MIDI CCs, pitch bend, and channel
-    /// pressure.
-    MidiCCs,
-}
-
-// FIXME: Like the voice ID, channel and note number can also be omitted in CLAP. And instead of an
-//        Option, maybe this should use a dedicated type to more clearly indicate that missing
-//        values should be treated as wildcards.
-
-/// Event for (incoming) notes. The set of supported note events depends on the value of
-/// [`Plugin::MIDI_INPUT`][crate::prelude::Plugin::MIDI_INPUT]. Also check out the
-/// [`util`][crate::util] module for convenient conversion functions.
-///
-/// `S` is a MIDI SysEx message type that needs to implement [`SysExMessage`] to allow converting
-/// this `NoteEvent` to and from raw MIDI data. `()` is provided as a default implementing for
-/// plugins that don't use SysEx.
-///
-/// All of the timings are sample offsets within the current buffer. Out of bound timings are
-/// clamped to the current buffer's length. All sample, channel and note numbers are zero-indexed.
-#[derive(Debug, Clone, Copy, PartialEq)]
-#[non_exhaustive]
-pub enum NoteEvent<S> {
-    /// A note on event, available on [`MidiConfig::Basic`] and up.
-    NoteOn {
-        timing: u32,
-        /// A unique identifier for this note, if available. Using this to refer to a note is
-        /// required when allowing overlapping voices for CLAP plugins.
-        voice_id: Option<i32>,
-        /// The note's channel, in `0..16`.
-        channel: u8,
-        /// The note's MIDI key number, in `0..128`.
-        note: u8,
-        /// The note's velocity, in `[0, 1]`. Some plugin APIs may allow higher precision than the
-        /// 128 levels available in MIDI.
-        velocity: f32,
-    },
-    /// A note off event, available on [`MidiConfig::Basic`] and up. Bitwig Studio does not provide
-    /// a voice ID for this event.
-    NoteOff {
-        timing: u32,
-        /// A unique identifier for this note, if available. Using this to refer to a note is
-        /// required when allowing overlapping voices for CLAP plugins.
-        voice_id: Option<i32>,
-        /// The note's channel, in `0..16`.
-        channel: u8,
-        /// The note's MIDI key number, in `0..128`.
-        note: u8,
-        /// The note's velocity, in `[0, 1]`. Some plugin APIs may allow higher precision than the
-        /// 128 levels available in MIDI.
-        velocity: f32,
-    },
-    /// A note choke event, available on [`MidiConfig::Basic`] and up. When the host sends this to
-    /// the plugin, it indicates that a voice or all sound associated with a note should immediately
-    /// stop playing.
-    Choke {
-        timing: u32,
-        /// A unique identifier for this note, if available. Using this to refer to a note is
-        /// required when allowing overlapping voices for CLAP plugins.
-        voice_id: Option<i32>,
-        /// The note's channel, in `0..16`.
-        channel: u8,
-        /// The note's MIDI key number, in `0..128`.
-        note: u8,
-    },
-
-    /// Sent by the plugin to the host to indicate that a voice has ended. This **needs** to be sent
-    /// when a voice terminates when using polyphonic modulation. Otherwise you can ignore this
-    /// event.
-    VoiceTerminated {
-        timing: u32,
-        /// The voice's unique identifier. Setting this allows a single voice to be terminated if
-        /// the plugin allows multiple overlapping voices for a single key.
-        voice_id: Option<i32>,
-        /// The note's channel, in `0..16`.
-        channel: u8,
-        /// The note's MIDI key number, in `0..128`.
-        note: u8,
-    },
-    /// A polyphonic modulation event, available on [`MidiConfig::Basic`] and up. This will only be
-    /// sent for parameters that were decorated with the `.with_poly_modulation_id()` modifier, and
-    /// only by supported hosts. This event contains a _normalized offset value_ for the parameter's
-    /// current, **unmodulated** value. That is, an offset for the current value before monophonic
-    /// modulation is applied, as polyphonic modulation overrides monophonic modulation. There are
-    /// multiple ways to incorporate this polyphonic modulation into a synthesizer, but a simple way
-    /// to incorporate this would work as follows:
-    ///
-    /// - By default, a voice uses the parameter's global value, which may or may not include
-    ///   monophonic modulation. This is `parameter.value` for unsmoothed parameters, and smoothed
-    ///   parameters should use block smoothing so the smoothed values can be reused by multiple
-    ///   voices.
-    /// - If a `PolyModulation` event is emitted for the voice, that voice should use the the
-    ///   _normalized offset_ contained within the event to compute the voice's modulated value and
-    ///   use that in place of the global value.
-    ///   - This value can be obtained by calling `param.preview_plain(param.normalized_value() +
-    ///     event.normalized_offset)`. These functions automatically clamp the values as necessary.
-    ///   - If the parameter uses smoothing, then the parameter's smoother can be copied to the
-    ///     voice. [`Smoother::set_target()`][crate::prelude::Smoother::set_target()] can then be
-    ///     used to have the smoother use the modulated value.
-    ///   - One caveat with smoothing is that copying the smoother like this only works correctly if it last
-    ///     produced a value during the sample before the `PolyModulation` event. Otherwise there
-    ///     may still be an audible jump in parameter values. A solution for this would be to first
-    ///     call the [`Smoother::reset()`][crate::prelude::Smoother::reset()] with the current
-    ///     sample's global value before calling `set_target()`.
-    ///   - Finally, if the polyphonic modulation happens on the same sample as the `NoteOn` event,
-    ///     then the smoothing should not start at the current global value. In this case, `reset()`
-    ///     should be called with the voice's modulated value.
-    /// - If a `MonoAutomation` event is emitted for a parameter, then the values or target values
-    ///   (if the parameter uses smoothing) for all voices must be updated. The normalized value
-    ///   from the `MonoAutomation` and the voice's normalized modulation offset must be added and
-    ///   converted back to a plain value. This value can be used directly for unsmoothed
-    ///   parameters, or passed to `set_target()` for smoothed parameters. The global value will
-    ///   have already been updated, so this event only serves as a notification to update
-    ///   polyphonic modulation.
-    /// - When a voice ends, either because the amplitude envelope has hit zero or because the voice
-    ///   was stolen, the plugin must send a `VoiceTerminated` to the host to let it know that it
-    ///   can reuse the resources it used to modulate the value.
-    PolyModulation {
-        timing: u32,
-        /// The identifier of the voice this polyphonic modulation event should affect. This voice
-        /// should use the values from this and subsequent polyphonic modulation events instead of
-        /// the global value.
-        voice_id: i32,
-        /// The ID that was set for the modulated parameter using the `.with_poly_modulation_id()`
-        /// method.
-        poly_modulation_id: u32,
-        /// The normalized offset value. See the event's docstring for more information.
-        normalized_offset: f32,
-    },
-    /// A notification to inform the plugin that a polyphonically modulated parameter has received a
-    /// new automation value. This is used in conjunction with the `PolyModulation` event. See that
-    /// event's documentation for more details. The parameter's global value has already been
-    /// updated when this event is emitted.
-    MonoAutomation {
-        timing: u32,
-        /// The ID that was set for the modulated parameter using the `.with_poly_modulation_id()`
-        /// method.
-        poly_modulation_id: u32,
-        /// The parameter's new normalized value. This needs to be added to a voice's normalized
-        /// offset to get that voice's modulated normalized value. See the `PolyModulation` event's
-        /// docstring for more information.
-        normalized_value: f32,
-    },
-
-    /// A polyphonic note pressure/aftertouch event, available on [`MidiConfig::Basic`] and up. Not
-    /// all hosts may support polyphonic aftertouch.
-    ///
-    /// # Note
-    ///
-    /// When implementing MPE support you should use MIDI channel pressure instead as polyphonic key
-    /// pressure + MPE is undefined as per the MPE specification. Or as a more generic catch all,
-    /// you may manually combine the polyphonic key pressure and MPE channel pressure.
-    PolyPressure {
-        timing: u32,
-        /// A unique identifier for this note, if available. Using this to refer to a note is
-        /// required when allowing overlapping voices for CLAP plugins.
-        voice_id: Option<i32>,
-        /// The note's channel, in `0..16`.
-        channel: u8,
-        /// The note's MIDI key number, in `0..128`.
-        note: u8,
-        /// The note's pressure, in `[0, 1]`.
-        pressure: f32,
-    },
-    /// A volume expression event, available on [`MidiConfig::Basic`] and up. Not all hosts may
-    /// support these expressions.
-    PolyVolume {
-        timing: u32,
-        /// A unique identifier for this note, if available. Using this to refer to a note is
-        /// required when allowing overlapping voices for CLAP plugins.
-        voice_id: Option<i32>,
-        /// The note's channel, in `0..16`.
-        channel: u8,
-        /// The note's MIDI key number, in `0..128`.
-        note: u8,
-        /// The note's voltage gain ratio, where 1.0 is unity gain.
-        gain: f32,
-    },
-    /// A panning expression event, available on [`MidiConfig::Basic`] and up. Not all hosts may
-    /// support these expressions.
-    PolyPan {
-        timing: u32,
-        /// A unique identifier for this note, if available. Using this to refer to a note is
-        /// required when allowing overlapping voices for CLAP plugins.
-        voice_id: Option<i32>,
-        /// The note's channel, in `0..16`.
-        channel: u8,
-        /// The note's MIDI key number, in `0..128`.
-        note: u8,
-        /// The note's panning from, in `[-1, 1]`, with -1 being panned hard left, and 1
-        /// being panned hard right.
-        pan: f32,
-    },
-    /// A tuning expression event, available on [`MidiConfig::Basic`] and up. Not all hosts may support
-    /// these expressions.
-    PolyTuning {
-        timing: u32,
-        /// A unique identifier for this note, if available. Using this to refer to a note is
-        /// required when allowing overlapping voices for CLAP plugins.
-        voice_id: Option<i32>,
-        /// The note's channel, in `0..16`.
-        channel: u8,
-        /// The note's MIDI key number, in `0..128`.
-        note: u8,
-        /// The note's tuning in semitones, in `[-128, 128]`.
-        tuning: f32,
-    },
-    /// A vibrato expression event, available on [`MidiConfig::Basic`] and up. Not all hosts may support
-    /// these expressions.
-    PolyVibrato {
-        timing: u32,
-        /// A unique identifier for this note, if available. Using this to refer to a note is
-        /// required when allowing overlapping voices for CLAP plugins.
-        voice_id: Option<i32>,
-        /// The note's channel, in `0..16`.
-        channel: u8,
-        /// The note's MIDI key number, in `0..128`.
-        note: u8,
-        /// The note's vibrato amount, in `[0, 1]`.
-        vibrato: f32,
-    },
-    /// A expression expression (yes, expression expression) event, available on
-    /// [`MidiConfig::Basic`] and up. Not all hosts may support these expressions.
-    PolyExpression {
-        timing: u32,
-        /// A unique identifier for this note, if available. Using this to refer to a note is
-        /// required when allowing overlapping voices for CLAP plugins.
-        voice_id: Option<i32>,
-        /// The note's channel, in `0..16`.
-        channel: u8,
-        /// The note's MIDI key number, in `0..128`.
-        note: u8,
-        /// The note's expression amount, in `[0, 1]`.
-        expression: f32,
-    },
-    /// A brightness expression event, available on [`MidiConfig::Basic`] and up. Not all hosts may support
-    /// these expressions.
-    PolyBrightness {
-        timing: u32,
-        /// A unique identifier for this note, if available. Using this to refer to a note is
-        /// required when allowing overlapping voices for CLAP plugins.
-        voice_id: Option<i32>,
-        /// The note's channel, in `0..16`.
-        channel: u8,
-        /// The note's MIDI key number, in `0..128`.
-        note: u8,
-        /// The note's brightness amount, in `[0, 1]`.
-        brightness: f32,
-    },
-    /// A MIDI channel pressure event, available on [`MidiConfig::MidiCCs`] and up.
-    MidiChannelPressure {
-        timing: u32,
-        /// The affected channel, in `0..16`.
-        channel: u8,
-        /// The pressure, normalized to `[0, 1]` to match the poly pressure event.
-        pressure: f32,
-    },
-    /// A MIDI pitch bend, available on [`MidiConfig::MidiCCs`] and up.
-    MidiPitchBend {
-        timing: u32,
-        /// The affected channel, in `0..16`.
-        channel: u8,
-        /// The pressure, normalized to `[0, 1]`. `0.5` means no pitch bend.
-        value: f32,
-    },
-    /// A MIDI control change event, available on [`MidiConfig::MidiCCs`] and up.
-    ///
-    /// # Note
-    ///
-    /// The wrapper does not perform any special handling for two message 14-bit CCs (where the CC
-    /// number is in `0..32`, and the next CC is that number plus 32) or for four message RPN
-    /// messages. For now you will need to handle these CCs yourself.
-    MidiCC {
-        timing: u32,
-        /// The affected channel, in `0..16`.
-        channel: u8,
-        /// The control change number. See [`control_change`] for a list of CC numbers.
-        cc: u8,
-        /// The CC's value, normalized to `[0, 1]`. Multiply by 127 to get the original raw value.
-        value: f32,
-    },
-    /// A MIDI program change event, available on [`MidiConfig::MidiCCs`] and up. VST3 plugins
-    /// cannot receive these events.
-    MidiProgramChange {
-        timing: u32,
-        /// The affected channel, in `0..16`.
-        channel: u8,
-        /// The program number, in `0..128`.
-        program: u8,
-    },
-    /// A MIDI SysEx message supported by the plugin's `SysExMessage` type, available on
-    /// [`MidiConfig::Basic`] and up. If the conversion from the raw byte array fails (e.g. the
-    /// plugin doesn't support this kind of message), then this will be logged during debug builds
-    /// of the plugin, and no event is emitted.
-    MidiSysEx { timing: u32, message: S },
-}
-
-/// The result of converting a `NoteEvent<S>` to MIDI. This is a bit weirder than it would have to
-/// be because it's not possible to use associated constants in type definitions.
-#[derive(Debug, Clone)]
-pub enum MidiResult<S: SysExMessage> {
-    /// A basic three byte MIDI event.
-    Basic([u8; 3]),
-    /// A SysEx event. The message was written to the `S::Buffer` and may include padding at the
-    /// end. The `usize` value indicates the message's actual length, including headers and end of
-    /// SysEx byte.
-    SysEx(S::Buffer, usize),
-}
-
-impl<S> NoteEvent<S> {
-    /// Returns the sample within the current buffer this event belongs to.
-    pub fn timing(&self) -> u32 {
-        match self {
-            NoteEvent::NoteOn { timing, .. } => *timing,
-            NoteEvent::NoteOff { timing, .. } => *timing,
-            NoteEvent::Choke { timing, .. } => *timing,
-            NoteEvent::VoiceTerminated { timing, .. } => *timing,
-            NoteEvent::PolyModulation { timing, .. } => *timing,
-            NoteEvent::MonoAutomation { timing, .. } => *timing,
-            NoteEvent::PolyPressure { timing, .. } => *timing,
-            NoteEvent::PolyVolume { timing, .. } => *timing,
-            NoteEvent::PolyPan { timing, .. } => *timing,
-            NoteEvent::PolyTuning { timing, .. } => *timing,
-            NoteEvent::PolyVibrato { timing, .. } => *timing,
-            NoteEvent::PolyExpression { timing, .. } => *timing,
-            NoteEvent::PolyBrightness { timing, .. } => *timing,
-            NoteEvent::MidiChannelPressure { timing, .. } => *timing,
-            NoteEvent::MidiPitchBend { timing, .. } => *timing,
-            NoteEvent::MidiCC { timing, .. } => *timing,
-            NoteEvent::MidiProgramChange { timing, .. } => *timing,
-            NoteEvent::MidiSysEx { timing, .. } => *timing,
-        }
-    }
-
-    /// Returns the event's voice ID, if it has any.
-    pub fn voice_id(&self) -> Option<i32> {
-        match self {
-            NoteEvent::NoteOn { voice_id, .. } => *voice_id,
-            NoteEvent::NoteOff { voice_id, .. } => *voice_id,
-            NoteEvent::Choke { voice_id, .. } => *voice_id,
-            NoteEvent::VoiceTerminated { voice_id, .. } => *voice_id,
-            NoteEvent::PolyModulation { voice_id, .. } => Some(*voice_id),
-            NoteEvent::MonoAutomation { .. } => None,
-            NoteEvent::PolyPressure { voice_id, .. } => *voice_id,
-            NoteEvent::PolyVolume { voice_id, .. } => *voice_id,
-            NoteEvent::PolyPan { voice_id, .. } => *voice_id,
-            NoteEvent::PolyTuning { voice_id, .. } => *voice_id,
-            NoteEvent::PolyVibrato { voice_id, .. } => *voice_id,
-            NoteEvent::PolyExpression { voice_id, .. } => *voice_id,
-            NoteEvent::PolyBrightness { voice_id, .. } => *voice_id,
-            NoteEvent::MidiChannelPressure { .. } => None,
-            NoteEvent::MidiPitchBend { .. } => None,
-            NoteEvent::MidiCC { .. } => None,
-            NoteEvent::MidiProgramChange { .. } => None,
-            NoteEvent::MidiSysEx { .. } => None,
-        }
-    }
-
-    /// Returns the event's channel, if it has any.
-    pub fn channel(&self) -> Option<u8> {
-        match self {
-            NoteEvent::NoteOn { channel, .. } => Some(*channel),
-            NoteEvent::NoteOff { channel, .. } => Some(*channel),
-            NoteEvent::Choke { channel, .. } => Some(*channel),
-            NoteEvent::VoiceTerminated { channel, .. } => Some(*channel),
-            NoteEvent::PolyModulation { .. } => None,
-            NoteEvent::MonoAutomation { .. } => None,
-            NoteEvent::PolyPressure { channel, .. } => Some(*channel),
-            NoteEvent::PolyVolume { channel, .. } => Some(*channel),
-            NoteEvent::PolyPan { channel, .. } => Some(*channel),
-            NoteEvent::PolyTuning { channel, .. } => Some(*channel),
-            NoteEvent::PolyVibrato { channel, .. } => Some(*channel),
-            NoteEvent::PolyExpression { channel, .. } => Some(*channel),
-            NoteEvent::PolyBrightness { channel, .. } => Some(*channel),
-            NoteEvent::MidiChannelPressure { channel, .. } => Some(*channel),
-            NoteEvent::MidiPitchBend { channel, .. } => Some(*channel),
-            NoteEvent::MidiCC { channel, .. } => Some(*channel),
-            NoteEvent::MidiProgramChange { channel, .. } => Some(*channel),
-            NoteEvent::MidiSysEx { .. } => None,
-        }
-    }
-}
-
-impl<S: SysExMessage> NoteEvent<S> {
-    /// Parse MIDI into a [`NoteEvent`]. Supports both basic three bytes messages as well as SysEx.
-    /// Will return `Err(event_type)` if the parsing failed.
-    pub fn from_midi(timing: u32, midi_data: &[u8]) -> Result<Self, u8> {
-        let status_byte = midi_data.first().copied().unwrap_or_default();
-        let event_type = status_byte & midi::EVENT_TYPE_MASK;
-        let channel = status_byte & midi::MIDI_CHANNEL_MASK;
-
-        if midi_data.len() >= 3 {
-            // TODO: Maybe add special handling for 14-bit CCs and RPN messages at some
-            //       point, right now the plugin has to figure it out for itself
-            match event_type {
-                // You thought this was a note on? Think again! This is a cleverly disguised note off
-                // event straight from the 80s when Baud rate was still a limiting factor!
-                midi::NOTE_ON if midi_data[2] == 0 => {
-                    return Ok(NoteEvent::NoteOff {
-                        timing,
-                        voice_id: None,
-                        channel,
-                        note: midi_data[1],
-                        // Few things use release velocity. Just having this be zero here is fine, right?
-                        velocity: 0.0,
-                    });
-                }
-                midi::NOTE_ON => {
-                    return Ok(NoteEvent::NoteOn {
-                        timing,
-                        voice_id: None,
-                        channel,
-                        note: midi_data[1],
-                        velocity: midi_data[2] as f32 / 127.0,
-                    });
-                }
-                midi::NOTE_OFF => {
-                    return Ok(NoteEvent::NoteOff {
-                        timing,
-                        voice_id: None,
-                        channel,
-                        note: midi_data[1],
-                        velocity: midi_data[2] as f32 / 127.0,
-                    });
-                }
-                midi::POLYPHONIC_KEY_PRESSURE => {
-                    return Ok(NoteEvent::PolyPressure {
-                        timing,
-                        voice_id: None,
-                        channel,
-                        note: midi_data[1],
-                        pressure: midi_data[2] as f32 / 127.0,
-                    });
-                }
-                midi::PITCH_BEND_CHANGE => {
-                    return Ok(NoteEvent::MidiPitchBend {
-                        timing,
-                        channel,
-                        value: (midi_data[1] as u16 + ((midi_data[2] as u16) << 7)) as f32
-                            / ((1 << 14) - 1) as f32,
-                    });
-                }
-                midi::CONTROL_CHANGE => {
-                    return Ok(NoteEvent::MidiCC {
-                        timing,
-                        channel,
-                        cc: midi_data[1],
-                        value: midi_data[2] as f32 / 127.0,
-                    });
-                }
-                _ => (),
-            }
-        }
-        if midi_data.len() >= 2 {
-            match event_type {
-                midi::CHANNEL_KEY_PRESSURE => {
-                    return Ok(NoteEvent::MidiChannelPressure {
-                        timing,
-                        channel,
-                        pressure: midi_data[1] as f32 / 127.0,
-                    });
-                }
-                midi::PROGRAM_CHANGE => {
-                    return Ok(NoteEvent::MidiProgramChange {
-                        timing,
-                        channel,
-                        program: midi_data[1],
-                    });
-                }
-                _ => (),
-            }
-        }
-
-        // Every other message is parsed as SysEx, even if they don't have the `0xf0` status byte.
-        // This allows the `SysExMessage` trait to have a bit more flexibility if needed. Regular
-        // note event parsing however still has higher priority.
-        match S::from_buffer(midi_data) {
-            Some(message) => Ok(NoteEvent::MidiSysEx { timing, message }),
-            None => {
-                if event_type == 0xf0 {
-                    if midi_data.len() <= 32 {
-                        nih_trace!("Unhandled MIDI system message: {midi_data:02x?}");
-                    } else {
-                        nih_trace!("Unhandled MIDI system message of {} bytes", midi_data.len());
-                    }
-                } else {
-                    nih_trace!("Unhandled MIDI status byte {status_byte:#x}");
-                }
-
-                Err(event_type)
-            }
-        }
-    }
-
-    /// Create a MIDI message from this note event. Returns `None` if this even does not have a
-    /// direct MIDI equivalent. `PolyPressure` will be converted to polyphonic key pressure, but the
-    /// other polyphonic note expression types will not be converted to MIDI CC messages.
-    pub fn as_midi(self) -> Option<MidiResult<S>> {
-        match self {
-            NoteEvent::NoteOn {
-                timing: _,
-                voice_id: _,
-                channel,
-                note,
-                velocity,
-            } => Some(MidiResult::Basic([
-                midi::NOTE_ON | channel,
-                note,
-                // MIDI treats note ons with zero velocity as note offs, because reasons
-                (velocity * 127.0).round().clamp(1.0, 127.0) as u8,
-            ])),
-            NoteEvent::NoteOff {
-                timing: _,
-                voice_id: _,
-                channel,
-                note,
-                velocity,
-            } => Some(MidiResult::Basic([
-                midi::NOTE_OFF | channel,
-                note,
-                (velocity * 127.0).round().clamp(0.0, 127.0) as u8,
-            ])),
-            NoteEvent::PolyPressure {
-                timing: _,
-                voice_id: _,
-                channel,
-                note,
-                pressure,
-            } => Some(MidiResult::Basic([
-                midi::POLYPHONIC_KEY_PRESSURE | channel,
-                note,
-                (pressure * 127.0).round().clamp(0.0, 127.0) as u8,
-            ])),
-            NoteEvent::MidiChannelPressure {
-                timing: _,
-                channel,
-                pressure,
-            } => Some(MidiResult::Basic([
-                midi::CHANNEL_KEY_PRESSURE | channel,
-                (pressure * 127.0).round().clamp(0.0, 127.0) as u8,
-                0,
-            ])),
-            NoteEvent::MidiPitchBend {
-                timing: _,
-                channel,
-                value,
-            } => {
-                const PITCH_BEND_RANGE: f32 = ((1 << 14) - 1) as f32;
-                let midi_value = (value * PITCH_BEND_RANGE)
-                    .round()
-                    .clamp(0.0, PITCH_BEND_RANGE) as u16;
-
-                Some(MidiResult::Basic([
-                    midi::PITCH_BEND_CHANGE | channel,
-                    (midi_value & ((1 << 7) - 1)) as u8,
-                    (midi_value >> 7) as u8,
-                ]))
-            }
-            NoteEvent::MidiCC {
-                timing: _,
-                channel,
-                cc,
-                value,
-            } => Some(MidiResult::Basic([
-                midi::CONTROL_CHANGE | channel,
-                cc,
-                (value * 127.0).round().clamp(0.0, 127.0) as u8,
-            ])),
-            NoteEvent::MidiProgramChange {
-                timing: _,
-                channel,
-                program,
-            } => Some(MidiResult::Basic([
-                midi::PROGRAM_CHANGE | channel,
-                program,
-                0,
-            ])),
-            // `message` is serialized and written to `sysex_buffer`, and the result contains the
-            // message's actual length
-            NoteEvent::MidiSysEx { timing: _, message } => {
-                let (padded_sysex_buffer, length) = message.to_buffer();
-                Some(MidiResult::SysEx(padded_sysex_buffer, length))
-            }
-            NoteEvent::Choke { .. }
-            | NoteEvent::VoiceTerminated { .. }
-            | NoteEvent::PolyModulation { .. }
-            | NoteEvent::MonoAutomation { .. }
-            | NoteEvent::PolyVolume { .. }
-            | NoteEvent::PolyPan { .. }
-            | NoteEvent::PolyTuning { .. }
-            | NoteEvent::PolyVibrato { .. }
-            | NoteEvent::PolyExpression { .. }
-            | NoteEvent::PolyBrightness { .. } => None,
-        }
-    }
-
-    /// Subtract a sample offset from this event's timing, needed to compensate for the block
-    /// splitting in the VST3 wrapper implementation because all events have to be read upfront.
-    #[cfg_attr(not(feature = "vst3"), allow(dead_code))]
-    pub(crate) fn subtract_timing(&mut self, samples: u32) {
-        match self {
-            NoteEvent::NoteOn { timing, .. } => *timing -= samples,
-            NoteEvent::NoteOff { timing, .. } => *timing -= samples,
-            NoteEvent::Choke { timing, .. } => *timing -= samples,
-            NoteEvent::VoiceTerminated { timing, .. } => *timing -= samples,
-            NoteEvent::PolyModulation { timing, .. } => *timing -= samples,
-            NoteEvent::MonoAutomation { timing, .. } => *timing -= samples,
-            NoteEvent::PolyPressure { timing, .. } => *timing -= samples,
-            NoteEvent::PolyVolume { timing, .. } => *timing -= samples,
-            NoteEvent::PolyPan { timing, .. } => *timing -= samples,
-            NoteEvent::PolyTuning { timing, .. } => *timing -= samples,
-            NoteEvent::PolyVibrato { timing, .. } => *timing -= samples,
-            NoteEvent::PolyExpression { timing, .. } => *timing -= samples,
-            NoteEvent::PolyBrightness { timing, .. } => *timing -= samples,
-            NoteEvent::MidiChannelPressure { timing, .. } => *timing -= samples,
-            NoteEvent::MidiPitchBend { timing, .. } => *timing -= samples,
-            NoteEvent::MidiCC { timing, .. } => *timing -= samples,
-            NoteEvent::MidiProgramChange { timing, .. } => *timing -= samples,
-            NoteEvent::MidiSysEx { timing, .. } => *timing -= samples,
-        }
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    pub use super::*;
-
-    pub const TIMING: u32 = 5;
-
-    /// Converts an event to and from MIDI. Panics if any part of the conversion fails.
-    fn roundtrip_basic_event(event: NoteEvent<()>) -> NoteEvent<()> {
-        let midi_data = match event.as_midi().unwrap() {
-            MidiResult::Basic(midi_data) => midi_data,
-            MidiResult::SysEx(_, _) => panic!("Unexpected SysEx result"),
-        };
-
-        NoteEvent::from_midi(TIMING, &midi_data).unwrap()
-    }
-
-    #[test]
-    fn test_note_on_midi_conversion() {
-        let event = NoteEvent::<()>::NoteOn {
-            timing: TIMING,
-            voice_id: None,
-            channel: 1,
-            note: 2,
-            // The value will be rounded in the conversion to MIDI, hence this overly specific value
-            velocity: 0.6929134,
-        };
-
-        assert_eq!(roundtrip_basic_event(event), event);
-    }
-
-    #[test]
-    fn test_note_off_midi_conversion() {
-        let event = NoteEvent::<()>::NoteOff {
-            timing: TIMING,
-            voice_id: None,
-            channel: 1,
-            note: 2,
-            velocity: 0.6929134,
-        };
-
-        assert_eq!(roundtrip_basic_event(event), event);
-    }
-
-    #[test]
-    fn test_poly_pressure_midi_conversion() {
-        let event = NoteEvent::<()>::PolyPressure {
-            timing: TIMING,
-            voice_id: None,
-            channel: 1,
-            note: 2,
-            pressure: 0.6929134,
-        };
-
-        assert_eq!(roundtrip_basic_event(event), event);
-    }
-
-    #[test]
-    fn test_channel_pressure_midi_conversion() {
-        let event = NoteEvent::<()>::MidiChannelPressure {
-            timing: TIMING,
-            channel: 1,
-            pressure: 0.6929134,
-        };
-
-        assert_eq!(roundtrip_basic_event(event), event);
-    }
-
-    #[test]
-    fn test_pitch_bend_midi_conversion() {
-        let event = NoteEvent::<()>::MidiPitchBend {
-            timing: TIMING,
-            channel: 1,
-            value: 0.6929134,
-        };
-
-        assert_eq!(roundtrip_basic_event(event), event);
-    }
-
-    #[test]
-    fn test_cc_midi_conversion() {
-        let event = NoteEvent::<()>::MidiCC {
-            timing: TIMING,
-            channel: 1,
-            cc: 2,
-            value: 0.6929134,
-        };
-
-        assert_eq!(roundtrip_basic_event(event), event);
-    }
-
-    #[test]
-    fn test_program_change_midi_conversion() {
-        let event = NoteEvent::<()>::MidiProgramChange {
-            timing: TIMING,
-            channel: 1,
-            program: 42,
-        };
-
-        assert_eq!(roundtrip_basic_event(event), event);
-    }
-
-    mod sysex {
-        use super::*;
-
-        #[derive(Clone, Debug, PartialEq)]
-        enum MessageType {
-            Foo(f32),
-        }
-
-        impl SysExMessage for MessageType {
-            type Buffer = [u8; 4];
-
-            fn from_buffer(buffer: &[u8]) -> Option<Self> {
-                match buffer {
-                    [0xf0, 0x69, n, 0xf7] => Some(MessageType::Foo(*n as f32 / 127.0)),
-                    _ => None,
-                }
-            }
-
-            fn to_buffer(self) -> (Self::Buffer, usize) {
-                match self {
-                    MessageType::Foo(x) => ([0xf0, 0x69, (x * 127.0).round() as u8, 0xf7], 4),
-                }
-            }
-        }
-
-        #[test]
-        fn test_parse_from_buffer() {
-            let midi_data = [0xf0, 0x69, 127, 0xf7];
-            let parsed = NoteEvent::from_midi(TIMING, &midi_data).unwrap();
-
-            assert_eq!(
-                parsed,
-                NoteEvent::MidiSysEx {
-                    timing: TIMING,
-                    message: MessageType::Foo(1.0)
-                }
-            );
-        }
-
-        #[test]
-        fn test_convert_to_buffer() {
-            let message = MessageType::Foo(1.0);
-            let event = NoteEvent::MidiSysEx {
-                timing: TIMING,
-                message,
-            };
-
-            match event.as_midi() {
-                Some(MidiResult::SysEx(padded_sysex_buffer, length)) => {
-                    assert_eq!(padded_sysex_buffer[..length], [0xf0, 0x69, 127, 0xf7])
-                }
-                result => panic!("Unexpected result: {result:?}"),
-            }
-        }
-
-        #[test]
-        fn test_invalid_parse() {
-            let midi_data = [0xf0, 0x0, 127, 0xf7];
-            let parsed = NoteEvent::<MessageType>::from_midi(TIMING, &midi_data);
-
-            assert!(parsed.is_err());
-        }
-    }
-}
+//! Constants and definitions surrounding MIDI support.
+
+use midi_consts::channel_event as midi;
+
+use self::sysex::SysExMessage;
+use crate::prelude::Plugin;
+
+pub mod arpeggiator;
+pub mod clock;
+pub mod learn;
+pub mod note_names;
+pub mod queue;
+pub mod smf;
+pub mod sysex;
+pub mod tracker;
+pub mod ump;
+
+pub use midi_consts::channel_event::control_change;
+
+/// A plugin-specific note event type.
+///
+/// The reason why this is defined like this instead of parameterizing `NoteEvent` with `P` is
+/// because deriving trait bounds requires all of the plugin's generic parameters to implement those
+/// traits. And we can't require `P` to implement things like `Clone`.
+///
+/// <https://github.com/rust-lang/rust/issues/26925>
+pub type PluginNoteEvent<P> = NoteEvent<<P as Plugin>::SysExMessage>;
+
+/// Determines which note events a plugin can send and receive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MidiConfig {
+    /// The plugin will not have a note input or output port and will thus not receive any not
+    /// events.
+    None,
+    /// The plugin receives note on/off/choke events, pressure, and potentially a couple
+    /// standardized expression types depending on the plugin standard and host. If the plugin sets
+    /// up configuration for polyphonic modulation (see [`ClapPlugin`][crate::prelude::ClapPlugin])
+    /// and assigns polyphonic modulation IDs to some of its parameters, then it will also receive
+    /// polyphonic modulation events. This level is also needed to be able to send SysEx events.
+    Basic,
+    /// The plugin receives full MIDI CCs as well as pitch bend information. For VST3 plugins this
+    /// involves adding 130*16 parameters to bind to the the 128 MIDI CCs, pitch bend, and channel
+    /// pressure.
+    MidiCCs,
+    /// The same as [`MidiConfig::MidiCCs`], but the wrapper will also decode two message 14-bit
+    /// CCs, RPN messages, and NRPN messages using a [`MidiCCDecoder`]. The plugin will then receive
+    /// [`NoteEvent::MidiHighResCC`], [`NoteEvent::MidiRpn`], and [`NoteEvent::MidiNrpn`] events
+    /// instead of the individual CC messages making up those events. All other CCs are passed
+    /// through as is.
+    HighResMidiCCs,
+}
+
+// FIXME: Like the voice ID, channel and note number can also be omitted in CLAP. And instead of an
+//        Option, maybe this should use a dedicated type to more clearly indicate that missing
+//        values should be treated as wildcards.
+
+/// Event for (incoming) notes. The set of supported note events depends on the value of
+/// [`Plugin::MIDI_INPUT`][crate::prelude::Plugin::MIDI_INPUT]. Also check out the
+/// [`util`][crate::util] module for convenient conversion functions.
+///
+/// `S` is a MIDI SysEx message type that needs to implement [`SysExMessage`] to allow converting
+/// this `NoteEvent` to and from raw MIDI data. `()` is provided as a default implementing for
+/// plugins that don't use SysEx.
+///
+/// All of the timings are sample offsets within the current buffer. Out of bound timings are
+/// clamped to the current buffer's length. All sample, channel and note numbers are zero-indexed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum NoteEvent<S> {
+    /// A note on event, available on [`MidiConfig::Basic`] and up.
+    NoteOn {
+        timing: u32,
+        /// A unique identifier for this note, if available. Using this to refer to a note is
+        /// required when allowing overlapping voices for CLAP plugins.
+        voice_id: Option<i32>,
+        /// The note's channel, in `0..16`.
+        channel: u8,
+        /// The note's MIDI key number, in `0..128`.
+        note: u8,
+        /// The note's velocity, in `[0, 1]`. Some plugin APIs may allow higher precision than the
+        /// 128 levels available in MIDI.
+        velocity: f32,
+    },
+    /// A note off event, available on [`MidiConfig::Basic`] and up. Bitwig Studio does not provide
+    /// a voice ID for this event.
+    NoteOff {
+        timing: u32,
+        /// A unique identifier for this note, if available. Using this to refer to a note is
+        /// required when allowing overlapping voices for CLAP plugins.
+        voice_id: Option<i32>,
+        /// The note's channel, in `0..16`.
+        channel: u8,
+        /// The note's MIDI key number, in `0..128`.
+        note: u8,
+        /// The note's velocity, in `[0, 1]`. Some plugin APIs may allow higher precision than the
+        /// 128 levels available in MIDI.
+        velocity: f32,
+    },
+    /// A note choke event, available on [`MidiConfig::Basic`] and up. When the host sends this to
+    /// the plugin, it indicates that a voice or all sound associated with a note should immediately
+    /// stop playing.
+    Choke {
+        timing: u32,
+        /// A unique identifier for this note, if available. Using this to refer to a note is
+        /// required when allowing overlapping voices for CLAP plugins.
+        voice_id: Option<i32>,
+        /// The note's channel, in `0..16`.
+        channel: u8,
+        /// The note's MIDI key number, in `0..128`.
+        note: u8,
+    },
+
+    /// Sent by the plugin to the host to indicate that a voice has ended. This **needs** to be sent
+    /// when a voice terminates when using polyphonic modulation. Otherwise you can ignore this
+    /// event.
+    VoiceTerminated {
+        timing: u32,
+        /// The voice's unique identifier. Setting this allows a single voice to be terminated if
+        /// the plugin allows multiple overlapping voices for a single key.
+        voice_id: Option<i32>,
+        /// The note's channel, in `0..16`.
+        channel: u8,
+        /// The note's MIDI key number, in `0..128`.
+        note: u8,
+    },
+    /// A polyphonic modulation event, available on [`MidiConfig::Basic`] and up. This will only be
+    /// sent for parameters that were decorated with the `.with_poly_modulation_id()` modifier, and
+    /// only by supported hosts. This event contains a _normalized offset value_ for the parameter's
+    /// current, **unmodulated** value. That is, an offset for the current value before monophonic
+    /// modulation is applied, as polyphonic modulation overrides monophonic modulation. There are
+    /// multiple ways to incorporate this polyphonic modulation into a synthesizer, but a simple way
+    /// to incorporate this would work as follows:
+    ///
+    /// - By default, a voice uses the parameter's global value, which may or may not include
+    ///   monophonic modulation. This is `parameter.value` for unsmoothed parameters, and smoothed
+    ///   parameters should use block smoothing so the smoothed values can be reused by multiple
+    ///   voices.
+    /// - If a `PolyModulation` event is emitted for the voice, that voice should use the the
+    ///   _normalized offset_ contained within the event to compute the voice's modulated value and
+    ///   use that in place of the global value.
+    ///   - This value can be obtained by calling `param.preview_plain(param.normalized_value() +
+    ///     event.normalized_offset)`. These functions automatically clamp the values as necessary.
+    ///   - If the parameter uses smoothing, then the parameter's smoother can be copied to the
+    ///     voice. [`Smoother::set_target()`][crate::prelude::Smoother::set_target()] can then be
+    ///     used to have the smoother use the modulated value.
+    ///   - One caveat with smoothing is that copying the smoother like this only works correctly if it last
+    ///     produced a value during the sample before the `PolyModulation` event. Otherwise there
+    ///     may still be an audible jump in parameter values. A solution for this would be to first
+    ///     call the [`Smoother::reset()`][crate::prelude::Smoother::reset()] with the current
+    ///     sample's global value before calling `set_target()`.
+    ///   - Finally, if the polyphonic modulation happens on the same sample as the `NoteOn` event,
+    ///     then the smoothing should not start at the current global value. In this case, `reset()`
+    ///     should be called with the voice's modulated value.
+    /// - If a `MonoAutomation` event is emitted for a parameter, then the values or target values
+    ///   (if the parameter uses smoothing) for all voices must be updated. The normalized value
+    ///   from the `MonoAutomation` and the voice's normalized modulation offset must be added and
+    ///   converted back to a plain value. This value can be used directly for unsmoothed
+    ///   parameters, or passed to `set_target()` for smoothed parameters. The global value will
+    ///   have already been updated, so this event only serves as a notification to update
+    ///   polyphonic modulation.
+    /// - When a voice ends, either because the amplitude envelope has hit zero or because the voice
+    ///   was stolen, the plugin must send a `VoiceTerminated` to the host to let it know that it
+    ///   can reuse the resources it used to modulate the value.
+    PolyModulation {
+        timing: u32,
+        /// The identifier of the voice this polyphonic modulation event should affect. This voice
+        /// should use the values from this and subsequent polyphonic modulation events instead of
+        /// the global value.
+        voice_id: i32,
+        /// The ID that was set for the modulated parameter using the `.with_poly_modulation_id()`
+        /// method.
+        poly_modulation_id: u32,
+        /// The normalized offset value. See the event's docstring for more information.
+        normalized_offset: f32,
+    },
+    /// A notification to inform the plugin that a polyphonically modulated parameter has received a
+    /// new automation value. This is used in conjunction with the `PolyModulation` event. See that
+    /// event's documentation for more details. The parameter's global value has already been
+    /// updated when this event is emitted.
+    MonoAutomation {
+        timing: u32,
+        /// The ID that was set for the modulated parameter using the `.with_poly_modulation_id()`
+        /// method.
+        poly_modulation_id: u32,
+        /// The parameter's new normalized value. This needs to be added to a voice's normalized
+        /// offset to get that voice's modulated normalized value. See the `PolyModulation` event's
+        /// docstring for more information.
+        normalized_value: f32,
+    },
+
+    /// A polyphonic note pressure/aftertouch event, available on [`MidiConfig::Basic`] and up. Not
+    /// all hosts may support polyphonic aftertouch.
+    ///
+    /// # Note
+    ///
+    /// When implementing MPE support you should use MIDI channel pressure instead as polyphonic key
+    /// pressure + MPE is undefined as per the MPE specification. Or as a more generic catch all,
+    /// you may manually combine the polyphonic key pressure and MPE channel pressure.
+    PolyPressure {
+        timing: u32,
+        /// A unique identifier for this note, if available. Using this to refer to a note is
+        /// required when allowing overlapping voices for CLAP plugins.
+        voice_id: Option<i32>,
+        /// The note's channel, in `0..16`.
+        channel: u8,
+        /// The note's MIDI key number, in `0..128`.
+        note: u8,
+        /// The note's pressure, in `[0, 1]`.
+        pressure: f32,
+    },
+    /// A volume expression event, available on [`MidiConfig::Basic`] and up. Not all hosts may
+    /// support these expressions.
+    PolyVolume {
+        timing: u32,
+        /// A unique identifier for this note, if available. Using this to refer to a note is
+        /// required when allowing overlapping voices for CLAP plugins.
+        voice_id: Option<i32>,
+        /// The note's channel, in `0..16`.
+        channel: u8,
+        /// The note's MIDI key number, in `0..128`.
+        note: u8,
+        /// The note's voltage gain ratio, where 1.0 is unity gain.
+        gain: f32,
+    },
+    /// A panning expression event, available on [`MidiConfig::Basic`] and up. Not all hosts may
+    /// support these expressions.
+    PolyPan {
+        timing: u32,
+        /// A unique identifier for this note, if available. Using this to refer to a note is
+        /// required when allowing overlapping voices for CLAP plugins.
+        voice_id: Option<i32>,
+        /// The note's channel, in `0..16`.
+        channel: u8,
+        /// The note's MIDI key number, in `0..128`.
+        note: u8,
+        /// The note's panning from, in `[-1, 1]`, with -1 being panned hard left, and 1
+        /// being panned hard right.
+        pan: f32,
+    },
+    /// A tuning expression event, available on [`MidiConfig::Basic`] and up. Not all hosts may support
+    /// these expressions.
+    PolyTuning {
+        timing: u32,
+        /// A unique identifier for this note, if available. Using this to refer to a note is
+        /// required when allowing overlapping voices for CLAP plugins.
+        voice_id: Option<i32>,
+        /// The note's channel, in `0..16`.
+        channel: u8,
+        /// The note's MIDI key number, in `0..128`.
+        note: u8,
+        /// The note's tuning in semitones, in `[-128, 128]`.
+        tuning: f32,
+    },
+    /// A vibrato expression event, available on [`MidiConfig::Basic`] and up. Not all hosts may support
+    /// these expressions.
+    PolyVibrato {
+        timing: u32,
+        /// A unique identifier for this note, if available. Using this to refer to a note is
+        /// required when allowing overlapping voices for CLAP plugins.
+        voice_id: Option<i32>,
+        /// The note's channel, in `0..16`.
+        channel: u8,
+        /// The note's MIDI key number, in `0..128`.
+        note: u8,
+        /// The note's vibrato amount, in `[0, 1]`.
+        vibrato: f32,
+    },
+    /// A expression expression (yes, expression expression) event, available on
+    /// [`MidiConfig::Basic`] and up. Not all hosts may support these expressions.
+    PolyExpression {
+        timing: u32,
+        /// A unique identifier for this note, if available. Using this to refer to a note is
+        /// required when allowing overlapping voices for CLAP plugins.
+        voice_id: Option<i32>,
+        /// The note's channel, in `0..16`.
+        channel: u8,
+        /// The note's MIDI key number, in `0..128`.
+        note: u8,
+        /// The note's expression amount, in `[0, 1]`.
+        expression: f32,
+    },
+    /// A brightness expression event, available on [`MidiConfig::Basic`] and up. Not all hosts may support
+    /// these expressions.
+    PolyBrightness {
+        timing: u32,
+        /// A unique identifier for this note, if available. Using this to refer to a note is
+        /// required when allowing overlapping voices for CLAP plugins.
+        voice_id: Option<i32>,
+        /// The note's channel, in `0..16`.
+        channel: u8,
+        /// The note's MIDI key number, in `0..128`.
+        note: u8,
+        /// The note's brightness amount, in `[0, 1]`.
+        brightness: f32,
+    },
+    /// A MIDI channel pressure event, available on [`MidiConfig::MidiCCs`] and up.
+    MidiChannelPressure {
+        timing: u32,
+        /// The affected channel, in `0..16`.
+        channel: u8,
+        /// The pressure, normalized to `[0, 1]` to match the poly pressure event.
+        pressure: f32,
+    },
+    /// A MIDI pitch bend, available on [`MidiConfig::MidiCCs`] and up.
+    MidiPitchBend {
+        timing: u32,
+        /// The affected channel, in `0..16`.
+        channel: u8,
+        /// The pressure, normalized to `[0, 1]`. `0.5` means no pitch bend.
+        value: f32,
+    },
+    /// A MIDI control change event, available on [`MidiConfig::MidiCCs`] and up.
+    ///
+    /// # Note
+    ///
+    /// The wrapper only performs special handling for two message 14-bit CCs (where the CC number
+    /// is in `0..32`, and the next CC is that number plus 32) and for four message (N)RPN messages
+    /// when the plugin opts into [`MidiConfig::HighResMidiCCs`]. Otherwise you will need to handle
+    /// these CCs yourself, possibly using a [`MidiCCDecoder`].
+    MidiCC {
+        timing: u32,
+        /// The affected channel, in `0..16`.
+        channel: u8,
+        /// The control change number. See [`control_change`] for a list of CC numbers.
+        cc: u8,
+        /// The CC's value, normalized to `[0, 1]`. Multiply by 127 to get the original raw value.
+        /// CCs parsed from MIDI 2.0 messages have the full 32-bit precision instead.
+        value: f32,
+    },
+    /// A MIDI program change event, available on [`MidiConfig::MidiCCs`] and up. VST3 plugins
+    /// cannot receive these events.
+    MidiProgramChange {
+        timing: u32,
+        /// The affected channel, in `0..16`.
+        channel: u8,
+        /// The program number, in `0..128`.
+        program: u8,
+    },
+    /// A 14-bit MIDI control change event decoded from an MSB and LSB CC pair, available on
+    /// [`MidiConfig::HighResMidiCCs`] and up. Plugins can output these events with
+    /// [`MidiConfig::MidiCCs`] and up, in which case they're converted to two regular CC messages.
+    ///
+    /// An MSB without a following LSB results in an event where the LSB is zero. If the LSB does
+    /// follow, then another event with the full 14-bit value is emitted.
+    MidiHighResCC {
+        timing: u32,
+        /// The affected channel, in `0..16`.
+        channel: u8,
+        /// The MSB's control change number, in `0..32`. The LSB uses this number plus 32.
+        cc: u8,
+        /// The CC's value, normalized to `[0, 1]`. Multiply by 16383 to get the original raw value.
+        value: f32,
+    },
+    /// A registered parameter number message, available on [`MidiConfig::HighResMidiCCs`] and up.
+    /// Plugins can output these events with [`MidiConfig::MidiCCs`] and up, in which case they're
+    /// converted to a sequence of CC messages.
+    ///
+    /// Like with 14-bit CCs, a data entry MSB results in an event with a zero LSB, and a following
+    /// data entry LSB emits another event with the full value. Data increment and decrement
+    /// messages also emit a new event.
+    MidiRpn {
+        timing: u32,
+        /// The affected channel, in `0..16`.
+        channel: u8,
+        /// The parsed RPN message.
+        message: RpnMessage,
+    },
+    /// A non-registered parameter number message, available on [`MidiConfig::HighResMidiCCs`] and
+    /// up. Plugins can output these events with [`MidiConfig::MidiCCs`] and up, in which case
+    /// they're converted to a sequence of CC messages. These are emitted under the same rules as
+    /// [`NoteEvent::MidiRpn`].
+    MidiNrpn {
+        timing: u32,
+        /// The affected channel, in `0..16`.
+        channel: u8,
+        /// The 14-bit parameter number, in `0..16384`.
+        parameter: u16,
+        /// The parameter's value, normalized to `[0, 1]`. Multiply by 16383 to get the original raw
+        /// value.
+        value: f32,
+    },
+    /// A MIDI 2.0 per-note pitch bend event, available on [`MidiConfig::MidiCCs`] and up. These
+    /// are only sent by CLAP hosts using the MIDI 2.0 note dialect. See the [`ump`] module.
+    MidiPerNotePitchBend {
+        timing: u32,
+        /// A unique identifier for this note, if available. Using this to refer to a note is
+        /// required when allowing overlapping voices for CLAP plugins.
+        voice_id: Option<i32>,
+        /// The note's channel, in `0..16`.
+        channel: u8,
+        /// The note's MIDI key number, in `0..128`.
+        note: u8,
+        /// The pitch bend, normalized to `[0, 1]`. `0.5` means no pitch bend. The range is
+        /// determined by the sender, and it defaults to 48 semitones in either direction.
+        value: f32,
+    },
+    /// A MIDI 2.0 registered or assignable per-note controller event, available on
+    /// [`MidiConfig::MidiCCs`] and up. These are only sent by CLAP hosts using the MIDI 2.0 note
+    /// dialect. See the [`ump`] module.
+    MidiPerNoteController {
+        timing: u32,
+        /// A unique identifier for this note, if available. Using this to refer to a note is
+        /// required when allowing overlapping voices for CLAP plugins.
+        voice_id: Option<i32>,
+        /// The note's channel, in `0..16`.
+        channel: u8,
+        /// The note's MIDI key number, in `0..128`.
+        note: u8,
+        /// Whether this is a registered per-note controller. Otherwise this is an assignable
+        /// per-note controller.
+        registered: bool,
+        /// The controller's index, in `0..128`.
+        controller: u8,
+        /// The controller's value, normalized to `[0, 1]`.
+        value: f32,
+    },
+    /// A MIDI timing clock message, available on [`MidiConfig::Basic`] and up. These are sent 24
+    /// times per quarter note while the song is playing. See [`clock::MidiClockGenerator`] for a
+    /// way to generate these from the host's transport information.
+    MidiTimingClock { timing: u32 },
+    /// A MIDI start message, available on [`MidiConfig::Basic`] and up. This starts playback from
+    /// the beginning of the song.
+    MidiStart { timing: u32 },
+    /// A MIDI continue message, available on [`MidiConfig::Basic`] and up. This resumes playback
+    /// from the last song position.
+    MidiContinue { timing: u32 },
+    /// A MIDI stop message, available on [`MidiConfig::Basic`] and up.
+    MidiStop { timing: u32 },
+    /// A MIDI song position pointer message, available on [`MidiConfig::Basic`] and up.
+    MidiSongPosition {
+        timing: u32,
+        /// The position in MIDI beats since the start of the song, in `0..16384`. A MIDI beat is a
+        /// sixteenth note, or six timing clocks.
+        position: u16,
+    },
+    /// A MIDI time code quarter frame message, available on [`MidiConfig::Basic`] and up. Eight
+    /// consecutive quarter frames make up a full timecode.
+    MidiTimecodeQuarterFrame {
+        timing: u32,
+        /// The part of the timecode this message contains, in `0..8`.
+        piece: u8,
+        /// The piece's 4-bit value, in `0..16`.
+        value: u8,
+    },
+    /// A MIDI SysEx message supported by the plugin's `SysExMessage` type, available on
+    /// [`MidiConfig::Basic`] and up. If the conversion from the raw byte array fails (e.g. the
+    /// plugin doesn't support this kind of message), then this will be logged during debug builds
+    /// of the plugin, and no event is emitted.
+    MidiSysEx { timing: u32, message: S },
+}
+
+/// The result of converting a `NoteEvent<S>` to MIDI. This is a bit weirder than it would have to
+/// be because it's not possible to use associated constants in type definitions.
+#[derive(Debug, Clone)]
+pub enum MidiResult<S: SysExMessage> {
+    /// A basic three byte MIDI event.
+    Basic([u8; 3]),
+    /// A SysEx event. The message was written to the `S::Buffer` and may include padding at the
+    /// end. The `usize` value indicates the message's actual length, including headers and end of
+    /// SysEx byte.
+    SysEx(S::Buffer, usize),
+    /// A sequence of basic three byte MIDI events, used for 14-bit CCs and (N)RPN messages. Only
+    /// the first `usize` events are part of the sequence.
+    Sequence([[u8; 3]; MAX_MIDI_SEQUENCE_LENGTH], usize),
+    /// A system common or system real time message, like timing clock or song position pointer
+    /// messages. These are one to three bytes long. Only the first `usize` bytes are part of the
+    /// message, the rest is padding.
+    System([u8; 3], usize),
+}
+
+/// The maximum number of MIDI messages in a [`MidiResult::Sequence`]. An (N)RPN message is sent as
+/// a parameter number MSB and LSB, a data entry MSB and LSB, and a trailing null RPN MSB and LSB.
+pub const MAX_MIDI_SEQUENCE_LENGTH: usize = 6;
+
+/// A parsed RPN message. The common registered parameters are parsed into their own variants, and
+/// all other parameters are represented by [`RpnMessage::Other`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RpnMessage {
+    /// RPN 0, the pitch bend range in semitones. The data entry LSB is interpreted as cents.
+    PitchBendSensitivity { semitones: f32 },
+    /// RPN 1, the channel's fine tuning in cents, in `[-100, 100)`.
+    FineTuning { cents: f32 },
+    /// RPN 2, the channel's coarse tuning in semitones, in `-64..64`.
+    CoarseTuning { semitones: i8 },
+    /// RPN 6, the MPE configuration message. This is sent on the zone's manager channel (channel 0
+    /// for the lower zone and channel 15 for the upper zone), and contains the number of member
+    /// channels in the zone. Zero disables the zone.
+    MpeConfiguration { member_channels: u8 },
+    /// Any other registered parameter.
+    Other {
+        /// The 14-bit parameter number, in `0..16384`.
+        parameter: u16,
+        /// The parameter's raw 14-bit value, in `0..16384`.
+        value: u16,
+    },
+}
+
+/// A stateful decoder for 14-bit CCs, RPN messages, and NRPN messages. The wrapper uses this when
+/// the plugin's [`Plugin::MIDI_INPUT`] is set to [`MidiConfig::HighResMidiCCs`], but it can also be
+/// used directly when parsing MIDI from other sources. The decoder does not allocate, and it keeps
+/// track of the state for all 16 channels separately.
+#[derive(Debug, Clone, Default)]
+pub struct MidiCCDecoder {
+    channels: [ChannelDecoderState; 16],
+}
+
+/// The decoding state for a single MIDI channel.
+#[derive(Debug, Clone, Copy, Default)]
+struct ChannelDecoderState {
+    /// The most recent MSB values for CCs `0..32`. These are combined with the LSBs sent on CCs
+    /// `32..64`.
+    cc_msbs: [u8; 32],
+    /// The currently selected RPN or NRPN parameter, if any.
+    selected_parameter: Option<SelectedParameter>,
+    /// The parameter number MSB and LSB sent on CC 101 and 100 or on CC 99 and 98. The booleans
+    /// indicate whether these were sent for an NRPN.
+    parameter_msb: Option<(u8, bool)>,
+    parameter_lsb: Option<(u8, bool)>,
+    /// The current data entry value for the selected parameter.
+    data_value: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SelectedParameter {
+    Rpn(u16),
+    Nrpn(u16),
+}
+
+/// The value used as the parameter number for the null RPN.
+const NULL_RPN: u16 = (127 << 7) | 127;
+/// The largest 14-bit value.
+const MAX_14_BIT_VALUE: u16 = (1 << 14) - 1;
+/// The data increment and decrement CCs used to nudge the selected (N)RPN's value. These are not
+/// part of the `midi_consts` crate.
+const DATA_INCREMENT: u8 = 96;
+const DATA_DECREMENT: u8 = 97;
+/// Status bytes for the supported system common and system real time messages. These don't have a
+/// channel, so they're matched against the entire status byte.
+const MTC_QUARTER_FRAME: u8 = 0xf1;
+const SONG_POSITION_POINTER: u8 = 0xf2;
+const TIMING_CLOCK: u8 = 0xf8;
+const START: u8 = 0xfa;
+const CONTINUE: u8 = 0xfb;
+const STOP: u8 = 0xfc;
+
+impl MidiCCDecoder {
+    /// Create a new decoder with no selected parameters and all MSBs set to zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reset the decoder's state for all channels.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// The order simultaneous events should be fed to [`decode()`][Self::decode()] in. CCs that
+    /// are received from separate sources, like the VST3 wrapper's per-CC parameter queues, can
+    /// arrive in any order when they share the same timing. Stably sorting those events by their
+    /// timing and then by this key makes sure that (N)RPN parameter numbers are selected before
+    /// their data entry messages, and that MSBs are decoded before their LSBs.
+    pub fn decoding_order<S>(event: &NoteEvent<S>) -> u8 {
+        match event {
+            NoteEvent::MidiCC { cc, .. } => match *cc {
+                control_change::REGISTERED_PARAMETER_NUMBER_MSB
+                | control_change::NON_REGISTERED_PARAMETER_NUMBER_MSB => 0,
+                control_change::REGISTERED_PARAMETER_NUMBER_LSB
+                | control_change::NON_REGISTERED_PARAMETER_NUMBER_LSB => 1,
+                // This includes the data entry LSB
+                32..=63 => 3,
+                _ => 2,
+            },
+            _ => 2,
+        }
+    }
+
+    /// Feed an event to the decoder. Non-CC events and CCs not involved in 14-bit CCs or (N)RPN
+    /// messages are returned as is. CCs that are part of a 14-bit CC or a data entry message for an
+    /// (N)RPN are converted to [`NoteEvent::MidiHighResCC`], [`NoteEvent::MidiRpn`], or
+    /// [`NoteEvent::MidiNrpn`] events. Parameter number selection messages only update the
+    /// decoder's state, and `None` is returned for those. Data entry messages without a selected
+    /// parameter are passed through as regular CC events.
+    pub fn decode<S>(&mut self, event: NoteEvent<S>) -> Option<NoteEvent<S>> {
+        let (timing, channel, cc, value) = match event {
+            NoteEvent::MidiCC {
+                timing,
+                channel,
+                cc,
+                value,
+            } if channel < 16 => (
+                timing,
+                channel,
+                cc,
+                (value * 127.0).round().clamp(0.0, 127.0) as u8,
+            ),
+            event => return Some(event),
+        };
+
+        let state = &mut self.channels[channel as usize];
+        match cc {
+            control_change::DATA_ENTRY_MSB
+            | control_change::DATA_ENTRY_LSB
+            | DATA_INCREMENT
+            | DATA_DECREMENT => {
+                let Some(parameter) = state.selected_parameter else {
+                    return Some(event);
+                };
+
+                state.data_value = match cc {
+                    control_change::DATA_ENTRY_MSB => (value as u16) << 7,
+                    control_change::DATA_ENTRY_LSB => {
+                        (state.data_value & !0b1111111) | value as u16
+                    }
+                    DATA_INCREMENT => (state.data_value + 1).min(MAX_14_BIT_VALUE),
+                    _ => state.data_value.saturating_sub(1),
+                };
+
+                Some(match parameter {
+                    SelectedParameter::Rpn(parameter) => NoteEvent::MidiRpn {
+                        timing,
+                        channel,
+                        message: RpnMessage::from_raw(parameter, state.data_value),
+                    },
+                    SelectedParameter::Nrpn(parameter) => NoteEvent::MidiNrpn {
+                        timing,
+                        channel,
+                        parameter,
+                        value: state.data_value as f32 / MAX_14_BIT_VALUE as f32,
+                    },
+                })
+            }
+            control_change::REGISTERED_PARAMETER_NUMBER_MSB
+            | control_change::NON_REGISTERED_PARAMETER_NUMBER_MSB => {
+                let is_nrpn = cc == control_change::NON_REGISTERED_PARAMETER_NUMBER_MSB;
+                state.parameter_msb = Some((value, is_nrpn));
+                state.update_selected_parameter();
+
+                None
+            }
+            control_change::REGISTERED_PARAMETER_NUMBER_LSB
+            | control_change::NON_REGISTERED_PARAMETER_NUMBER_LSB => {
+                let is_nrpn = cc == control_change::NON_REGISTERED_PARAMETER_NUMBER_LSB;
+                state.parameter_lsb = Some((value, is_nrpn));
+                state.update_selected_parameter();
+
+                None
+            }
+            0..=31 => {
+                state.cc_msbs[cc as usize] = value;
+
+                Some(NoteEvent::MidiHighResCC {
+                    timing,
+                    channel,
+                    cc,
+                    value: ((value as u16) << 7) as f32 / MAX_14_BIT_VALUE as f32,
+                })
+            }
+            32..=63 => {
+                let msb_cc = cc - 32;
+                let msb = state.cc_msbs[msb_cc as usize];
+
+                Some(NoteEvent::MidiHighResCC {
+                    timing,
+                    channel,
+                    cc: msb_cc,
+                    value: (((msb as u16) << 7) | value as u16) as f32 / MAX_14_BIT_VALUE as f32,
+                })
+            }
+            _ => Some(event),
+        }
+    }
+}
+
+impl ChannelDecoderState {
+    /// Select a new RPN or NRPN parameter once both the parameter number's MSB and LSB have been
+    /// received for the same parameter type. The null RPN deselects the current parameter.
+    fn update_selected_parameter(&mut self) {
+        if let (Some((msb, msb_is_nrpn)), Some((lsb, lsb_is_nrpn))) =
+            (self.parameter_msb, self.parameter_lsb)
+        {
+            if msb_is_nrpn != lsb_is_nrpn {
+                return;
+            }
+
+            let parameter = ((msb as u16) << 7) | lsb as u16;
+            self.selected_parameter = match (parameter, msb_is_nrpn) {
+                (NULL_RPN, _) => None,
+                (parameter, true) => Some(SelectedParameter::Nrpn(parameter)),
+                (parameter, false) => Some(SelectedParameter::Rpn(parameter)),
+            };
+            self.data_value = 0;
+        }
+    }
+}
+
+impl RpnMessage {
+    /// Parse an RPN message from a 14-bit parameter number and a 14-bit value.
+    pub fn from_raw(parameter: u16, value: u16) -> Self {
+        let msb = (value >> 7) as u8;
+        let lsb = (value & 0b1111111) as u8;
+        match parameter {
+            0 => RpnMessage::PitchBendSensitivity {
+                semitones: msb as f32 + (lsb as f32 / 100.0),
+            },
+            1 => RpnMessage::FineTuning {
+                cents: (value as f32 - 8192.0) / 8192.0 * 100.0,
+            },
+            2 => RpnMessage::CoarseTuning {
+                semitones: msb as i8 - 64,
+            },
+            6 => RpnMessage::MpeConfiguration {
+                member_channels: msb,
+            },
+            parameter => RpnMessage::Other { parameter, value },
+        }
+    }
+
+    /// Convert this message back to a 14-bit parameter number and a 14-bit value.
+    pub fn to_raw(self) -> (u16, u16) {
+        match self {
+            RpnMessage::PitchBendSensitivity { semitones } => {
+                let semitones = semitones.clamp(0.0, 127.99);
+                let msb = semitones.trunc() as u16;
+                let lsb = ((semitones.fract() * 100.0).round() as u16).min(99);
+
+                (0, (msb << 7) | lsb)
+            }
+            RpnMessage::FineTuning { cents } => (
+                1,
+                ((cents / 100.0 * 8192.0) + 8192.0)
+                    .round()
+                    .clamp(0.0, MAX_14_BIT_VALUE as f32) as u16,
+            ),
+            RpnMessage::CoarseTuning { semitones } => {
+                (2, ((semitones.clamp(-64, 63) + 64) as u16) << 7)
+            }
+            RpnMessage::MpeConfiguration { member_channels } => {
+                (6, (member_channels.min(15) as u16) << 7)
+            }
+            RpnMessage::Other { parameter, value } => {
+                (parameter & MAX_14_BIT_VALUE, value & MAX_14_BIT_VALUE)
+            }
+        }
+    }
+}
+
+impl<S> NoteEvent<S> {
+    /// Returns the sample within the current buffer this event belongs to.
+    pub fn timing(&self) -> u32 {
+        match self {
+            NoteEvent::NoteOn { timing, .. } => *timing,
+            NoteEvent::NoteOff { timing, .. } => *timing,
+            NoteEvent::Choke { timing, .. } => *timing,
+            NoteEvent::VoiceTerminated { timing, .. } => *timing,
+            NoteEvent::PolyModulation { timing, .. } => *timing,
+            NoteEvent::MonoAutomation { timing, .. } => *timing,
+            NoteEvent::PolyPressure { timing, .. } => *timing,
+            NoteEvent::PolyVolume { timing, .. } => *timing,
+            NoteEvent::PolyPan { timing, .. } => *timing,
+            NoteEvent::PolyTuning { timing, .. } => *timing,
+            NoteEvent::PolyVibrato { timing, .. } => *timing,
+            NoteEvent::PolyExpression { timing, .. } => *timing,
+            NoteEvent::PolyBrightness { timing, .. } => *timing,
+            NoteEvent::MidiChannelPressure { timing, .. } => *timing,
+            NoteEvent::MidiPitchBend { timing, .. } => *timing,
+            NoteEvent::MidiCC { timing, .. } => *timing,
+            NoteEvent::MidiProgramChange { timing, .. } => *timing,
+            NoteEvent::MidiHighResCC { timing, .. } => *timing,
+            NoteEvent::MidiRpn { timing, .. } => *timing,
+            NoteEvent::MidiNrpn { timing, .. } => *timing,
+            NoteEvent::MidiPerNotePitchBend { timing, .. } => *timing,
+            NoteEvent::MidiPerNoteController { timing, .. } => *timing,
+            NoteEvent::MidiTimingClock { timing } => *timing,
+            NoteEvent::MidiStart { timing } => *timing,
+            NoteEvent::MidiContinue { timing } => *timing,
+            NoteEvent::MidiStop { timing } => *timing,
+            NoteEvent::MidiSongPosition { timing, .. } => *timing,
+            NoteEvent::MidiTimecodeQuarterFrame { timing, .. } => *timing,
+            NoteEvent::MidiSysEx { timing, .. } => *timing,
+        }
+    }
+
+    /// Returns the event's voice ID, if it has any.
+    pub fn voice_id(&self) -> Option<i32> {
+        match self {
+            NoteEvent::NoteOn { voice_id, .. } => *voice_id,
+            NoteEvent::NoteOff { voice_id, .. } => *voice_id,
+            NoteEvent::Choke { voice_id, .. } => *voice_id,
+            NoteEvent::VoiceTerminated { voice_id, .. } => *voice_id,
+            NoteEvent::PolyModulation { voice_id, .. } => Some(*voice_id),
+            NoteEvent::MonoAutomation { .. } => None,
+            NoteEvent::PolyPressure { voice_id, .. } => *voice_id,
+            NoteEvent::PolyVolume { voice_id, .. } => *voice_id,
+            NoteEvent::PolyPan { voice_id, .. } => *voice_id,
+            NoteEvent::PolyTuning { voice_id, .. } => *voice_id,
+            NoteEvent::PolyVibrato { voice_id, .. } => *voice_id,
+            NoteEvent::PolyExpression { voice_id, .. } => *voice_id,
+            NoteEvent::PolyBrightness { voice_id, .. } => *voice_id,
+            NoteEvent::MidiChannelPressure { .. } => None,
+            NoteEvent::MidiPitchBend { .. } => None,
+            NoteEvent::MidiCC { .. } => None,
+            NoteEvent::MidiProgramChange { .. } => None,
+            NoteEvent::MidiHighResCC { .. } => None,
+            NoteEvent::MidiRpn { .. } => None,
+            NoteEvent::MidiNrpn { .. } => None,
+            NoteEvent::MidiPerNotePitchBend { voice_id, .. } => *voice_id,
+            NoteEvent::MidiPerNoteController { voice_id, .. } => *voice_id,
+            NoteEvent::MidiTimingClock { .. } => None,
+            NoteEvent::MidiStart { .. } => None,
+            NoteEvent::MidiContinue { .. } => None,
+            NoteEvent::MidiStop { .. } => None,
+            NoteEvent::MidiSongPosition { .. } => None,
+            NoteEvent::MidiTimecodeQuarterFrame { .. } => None,
+            NoteEvent::MidiSysEx { .. } => None,
+        }
+    }
+
+    /// Returns the event's channel, if it has any.
+    pub fn channel(&self) -> Option<u8> {
+        match self {
+            NoteEvent::NoteOn { channel, .. } => Some(*channel),
+            NoteEvent::NoteOff { channel, .. } => Some(*channel),
+            NoteEvent::Choke { channel, .. } => Some(*channel),
+            NoteEvent::VoiceTerminated { channel, .. } => Some(*channel),
+            NoteEvent::PolyModulation { .. } => None,
+            NoteEvent::MonoAutomation { .. } => None,
+            NoteEvent::PolyPressure { channel, .. } => Some(*channel),
+            NoteEvent::PolyVolume { channel, .. } => Some(*channel),
+            NoteEvent::PolyPan { channel, .. } => Some(*channel),
+            NoteEvent::PolyTuning { channel, .. } => Some(*channel),
+            NoteEvent::PolyVibrato { channel, .. } => Some(*channel),
+            NoteEvent::PolyExpression { channel, .. } => Some(*channel),
+            NoteEvent::PolyBrightness { channel, .. } => Some(*channel),
+            NoteEvent::MidiChannelPressure { channel, .. } => Some(*channel),
+            NoteEvent::MidiPitchBend { channel, .. } => Some(*channel),
+            NoteEvent::MidiCC { channel, .. } => Some(*channel),
+            NoteEvent::MidiProgramChange { channel, .. } => Some(*channel),
+            NoteEvent::MidiHighResCC { channel, .. } => Some(*channel),
+            NoteEvent::MidiRpn { channel, .. } => Some(*channel),
+            NoteEvent::MidiNrpn { channel, .. } => Some(*channel),
+            NoteEvent::MidiPerNotePitchBend { channel, .. } => Some(*channel),
+            NoteEvent::MidiPerNoteController { channel, .. } => Some(*channel),
+            NoteEvent::MidiTimingClock { .. } => None,
+            NoteEvent::MidiStart { .. } => None,
+            NoteEvent::MidiContinue { .. } => None,
+            NoteEvent::MidiStop { .. } => None,
+            NoteEvent::MidiSongPosition { .. } => None,
+            NoteEvent::MidiTimecodeQuarterFrame { .. } => None,
+            NoteEvent::MidiSysEx { .. } => None,
+        }
+    }
+}
+
+impl<S: SysExMessage> NoteEvent<S> {
+    /// Parse MIDI into a [`NoteEvent`]. Supports both basic three bytes messages as well as SysEx.
+    /// Will return `Err(event_type)` if the parsing failed. See [`from_ump()`][Self::from_ump()]
+    /// for parsing MIDI 2.0 messages.
+    pub fn from_midi(timing: u32, midi_data: &[u8]) -> Result<Self, u8> {
+        let status_byte = midi_data.first().copied().unwrap_or_default();
+        let event_type = status_byte & midi::EVENT_TYPE_MASK;
+        let channel = status_byte & midi::MIDI_CHANNEL_MASK;
+
+        if midi_data.len() >= 3 {
+            // 14-bit CCs and (N)RPN messages span multiple messages, so those are handled
+            // separately by the stateful `MidiCCDecoder`
+            match event_type {
+                // You thought this was a note on? Think again! This is a cleverly disguised note off
+                // event straight from the 80s when Baud rate was still a limiting factor!
+                midi::NOTE_ON if midi_data[2] == 0 => {
+                    return Ok(NoteEvent::NoteOff {
+                        timing,
+                        voice_id: None,
+                        channel,
+                        note: midi_data[1],
+                        // Few things use release velocity. Just having this be zero here is fine, right?
+                        velocity: 0.0,
+                    });
+                }
+                midi::NOTE_ON => {
+                    return Ok(NoteEvent::NoteOn {
+                        timing,
+                        voice_id: None,
+                        channel,
+                        note: midi_data[1],
+                        velocity: midi_data[2] as f32 / 127.0,
+                    });
+                }
+                midi::NOTE_OFF => {
+                    return Ok(NoteEvent::NoteOff {
+                        timing,
+                        voice_id: None,
+                        channel,
+                        note: midi_data[1],
+                        velocity: midi_data[2] as f32 / 127.0,
+                    });
+                }
+                midi::POLYPHONIC_KEY_PRESSURE => {
+                    return Ok(NoteEvent::PolyPressure {
+                        timing,
+                        voice_id: None,
+                        channel,
+                        note: midi_data[1],
+                        pressure: midi_data[2] as f32 / 127.0,
+                    });
+                }
+                midi::PITCH_BEND_CHANGE => {
+                    return Ok(NoteEvent::MidiPitchBend {
+                        timing,
+                        channel,
+                        value: (midi_data[1] as u16 + ((midi_data[2] as u16) << 7)) as f32
+                            / ((1 << 14) - 1) as f32,
+                    });
+                }
+                midi::CONTROL_CHANGE => {
+                    return Ok(NoteEvent::MidiCC {
+                        timing,
+                        channel,
+                        cc: midi_data[1],
+                        value: midi_data[2] as f32 / 127.0,
+                    });
+                }
+                _ => (),
+            }
+        }
+        if midi_data.len() >= 2 {
+            match event_type {
+                midi::CHANNEL_KEY_PRESSURE => {
+                    return Ok(NoteEvent::MidiChannelPressure {
+                        timing,
+                        channel,
+                        pressure: midi_data[1] as f32 / 127.0,
+                    });
+                }
+                midi::PROGRAM_CHANGE => {
+                    return Ok(NoteEvent::MidiProgramChange {
+                        timing,
+                        channel,
+                        program: midi_data[1],
+                    });
+                }
+                _ => (),
+            }
+        }
+
+        // System messages don't have a channel, and real time messages don't have any data bytes
+        match (status_byte, midi_data) {
+            (TIMING_CLOCK, _) => return Ok(NoteEvent::MidiTimingClock { timing }),
+            (START, _) => return Ok(NoteEvent::MidiStart { timing }),
+            (CONTINUE, _) => return Ok(NoteEvent::MidiContinue { timing }),
+            (STOP, _) => return Ok(NoteEvent::MidiStop { timing }),
+            (SONG_POSITION_POINTER, [_, lsb, msb, ..]) => {
+                return Ok(NoteEvent::MidiSongPosition {
+                    timing,
+                    position: (*lsb as u16 + ((*msb as u16) << 7)) & MAX_14_BIT_VALUE,
+                });
+            }
+            (MTC_QUARTER_FRAME, [_, data, ..]) => {
+                return Ok(NoteEvent::MidiTimecodeQuarterFrame {
+                    timing,
+                    piece: (data >> 4) & 0b111,
+                    value: data & 0b1111,
+                });
+            }
+            _ => (),
+        }
+
+        // Every other message is parsed as SysEx, even if they don't have the `0xf0` status byte.
+        // This allows the `SysExMessage` trait to have a bit more flexibility if needed. Regular
+        // note event parsing however still has higher priority.
+        match S::from_buffer(midi_data) {
+            Some(message) => Ok(NoteEvent::MidiSysEx { timing, message }),
+            None => {
+                if event_type == 0xf0 {
+                    if midi_data.len() <= 32 {
+                        nih_trace!("Unhandled MIDI system message: {midi_data:02x?}");
+                    } else {
+                        nih_trace!("Unhandled MIDI system message of {} bytes", midi_data.len());
+                    }
+                } else {
+                    nih_trace!("Unhandled MIDI status byte {status_byte:#x}");
+                }
+
+                Err(event_type)
+            }
+        }
+    }
+
+    /// Create a MIDI message from this note event. Returns `None` if this even does not have a
+    /// direct MIDI equivalent. `PolyPressure` will be converted to polyphonic key pressure, but the
+    /// other polyphonic note expression types will not be converted to MIDI CC messages.
+    pub fn as_midi(self) -> Option<MidiResult<S>> {
+        match self {
+            NoteEvent::NoteOn {
+                timing: _,
+                voice_id: _,
+                channel,
+                note,
+                velocity,
+            } => Some(MidiResult::Basic([
+                midi::NOTE_ON | channel,
+                note,
+                // MIDI treats note ons with zero velocity as note offs, because reasons
+                (velocity * 127.0).round().clamp(1.0, 127.0) as u8,
+            ])),
+            NoteEvent::NoteOff {
+                timing: _,
+                voice_id: _,
+                channel,
+                note,
+                velocity,
+            } => Some(MidiResult::Basic([
+                midi::NOTE_OFF | channel,
+                note,
+                (velocity * 127.0).round().clamp(0.0, 127.0) as u8,
+            ])),
+            NoteEvent::PolyPressure {
+                timing: _,
+                voice_id: _,
+                channel,
+                note,
+                pressure,
+            } => Some(MidiResult::Basic([
+                midi::POLYPHONIC_KEY_PRESSURE | channel,
+                note,
+                (pressure * 127.0).round().clamp(0.0, 127.0) as u8,
+            ])),
+            NoteEvent::MidiChannelPressure {
+                timing: _,
+                channel,
+                pressure,
+            } => Some(MidiResult::Basic([
+                midi::CHANNEL_KEY_PRESSURE | channel,
+                (pressure * 127.0).round().clamp(0.0, 127.0) as u8,
+                0,
+            ])),
+            NoteEvent::MidiPitchBend {
+                timing: _,
+                channel,
+                value,
+            } => {
+                const PITCH_BEND_RANGE: f32 = ((1 << 14) - 1) as f32;
+                let midi_value = (value * PITCH_BEND_RANGE)
+                    .round()
+                    .clamp(0.0, PITCH_BEND_RANGE) as u16;
+
+                Some(MidiResult::Basic([
+                    midi::PITCH_BEND_CHANGE | channel,
+                    (midi_value & ((1 << 7) - 1)) as u8,
+                    (midi_value >> 7) as u8,
+                ]))
+            }
+            NoteEvent::MidiCC {
+                timing: _,
+                channel,
+                cc,
+                value,
+            } => Some(MidiResult::Basic([
+                midi::CONTROL_CHANGE | channel,
+                cc,
+                (value * 127.0).round().clamp(0.0, 127.0) as u8,
+            ])),
+            NoteEvent::MidiProgramChange {
+                timing: _,
+                channel,
+                program,
+            } => Some(MidiResult::Basic([
+                midi::PROGRAM_CHANGE | channel,
+                program,
+                0,
+            ])),
+            NoteEvent::MidiHighResCC {
+                timing: _,
+                channel,
+                cc,
+                value,
+            } => {
+                let midi_value = (value * MAX_14_BIT_VALUE as f32)
+                    .round()
+                    .clamp(0.0, MAX_14_BIT_VALUE as f32) as u16;
+
+                let mut sequence = [[0u8; 3]; MAX_MIDI_SEQUENCE_LENGTH];
+                sequence[0] = [
+                    midi::CONTROL_CHANGE | channel,
+                    cc & 0b11111,
+                    (midi_value >> 7) as u8,
+                ];
+                sequence[1] = [
+                    midi::CONTROL_CHANGE | channel,
+                    (cc & 0b11111) + 32,
+                    (midi_value & 0b1111111) as u8,
+                ];
+
+                Some(MidiResult::Sequence(sequence, 2))
+            }
+            NoteEvent::MidiRpn {
+                timing: _,
+                channel,
+                message,
+            } => {
+                let (parameter, value) = message.to_raw();
+                Some(MidiResult::Sequence(
+                    parameter_number_sequence(channel, false, parameter, value),
+                    MAX_MIDI_SEQUENCE_LENGTH,
+                ))
+            }
+            NoteEvent::MidiNrpn {
+                timing: _,
+                channel,
+                parameter,
+                value,
+            } => {
+                let value = (value * MAX_14_BIT_VALUE as f32)
+                    .round()
+                    .clamp(0.0, MAX_14_BIT_VALUE as f32) as u16;
+                Some(MidiResult::Sequence(
+                    parameter_number_sequence(channel, true, parameter, value),
+                    MAX_MIDI_SEQUENCE_LENGTH,
+                ))
+            }
+            // `message` is serialized and written to `sysex_buffer`, and the result contains the
+            // message's actual length
+            NoteEvent::MidiSysEx { timing: _, message } => {
+                let (padded_sysex_buffer, length) = message.to_buffer();
+                Some(MidiResult::SysEx(padded_sysex_buffer, length))
+            }
+            NoteEvent::Choke { .. }
+            | NoteEvent::VoiceTerminated { .. }
+            | NoteEvent::PolyModulation { .. }
+            | NoteEvent::MonoAutomation { .. }
+            | NoteEvent::PolyVolume { .. }
+            | NoteEvent::PolyPan { .. }
+            | NoteEvent::PolyTuning { .. }
+            | NoteEvent::PolyVibrato { .. }
+            | NoteEvent::PolyExpression { .. }
+            | NoteEvent::PolyBrightness { .. }
+            | NoteEvent::MidiPerNotePitchBend { .. }
+            | NoteEvent::MidiPerNoteController { .. } => None,
+            NoteEvent::MidiTimingClock { timing: _ } => {
+                Some(MidiResult::System([TIMING_CLOCK, 0, 0], 1))
+            }
+            NoteEvent::MidiStart { timing: _ } => Some(MidiResult::System([START, 0, 0], 1)),
+            NoteEvent::MidiContinue { timing: _ } => Some(MidiResult::System([CONTINUE, 0, 0], 1)),
+            NoteEvent::MidiStop { timing: _ } => Some(MidiResult::System([STOP, 0, 0], 1)),
+            NoteEvent::MidiSongPosition {
+                timing: _,
+                position,
+            } => Some(MidiResult::System(
+                [
+                    SONG_POSITION_POINTER,
+                    (position & 0b1111111) as u8,
+                    ((position >> 7) & 0b1111111) as u8,
+                ],
+                3,
+            )),
+            NoteEvent::MidiTimecodeQuarterFrame {
+                timing: _,
+                piece,
+                value,
+            } => Some(MidiResult::System(
+                [
+                    MTC_QUARTER_FRAME,
+                    ((piece & 0b111) << 4) | (value & 0b1111),
+                    0,
+                ],
+                2,
+            )),
+        }
+    }
+
+    /// Subtract a sample offset from this event's timing, needed to compensate for the block
+    /// splitting in the VST3 wrapper implementation because all events have to be read upfront.
+    pub(crate) fn subtract_timing(&mut self, samples: u32) {
+        match self {
+            NoteEvent::NoteOn { timing, .. } => *timing -= samples,
+            NoteEvent::NoteOff { timing, .. } => *timing -= samples,
+            NoteEvent::Choke { timing, .. } => *timing -= samples,
+            NoteEvent::VoiceTerminated { timing, .. } => *timing -= samples,
+            NoteEvent::PolyModulation { timing, .. } => *timing -= samples,
+            NoteEvent::MonoAutomation { timing, .. } => *timing -= samples,
+            NoteEvent::PolyPressure { timing, .. } => *timing -= samples,
+            NoteEvent::PolyVolume { timing, .. } => *timing -= samples,
+            NoteEvent::PolyPan { timing, .. } => *timing -= samples,
+            NoteEvent::PolyTuning { timing, .. } => *timing -= samples,
+            NoteEvent::PolyVibrato { timing, .. } => *timing -= samples,
+            NoteEvent::PolyExpression { timing, .. } => *timing -= samples,
+            NoteEvent::PolyBrightness { timing, .. } => *timing -= samples,
+            NoteEvent::MidiChannelPressure { timing, .. } => *timing -= samples,
+            NoteEvent::MidiPitchBend { timing, .. } => *timing -= samples,
+            NoteEvent::MidiCC { timing, .. } => *timing -= samples,
+            NoteEvent::MidiProgramChange { timing, .. } => *timing -= samples,
+            NoteEvent::MidiHighResCC { timing, .. } => *timing -= samples,
+            NoteEvent::MidiRpn { timing, .. } => *timing -= samples,
+            NoteEvent::MidiNrpn { timing, .. } => *timing -= samples,
+            NoteEvent::MidiPerNotePitchBend { timing, .. } => *timing -= samples,
+            NoteEvent::MidiPerNoteController { timing, .. } => *timing -= samples,
+            NoteEvent::MidiTimingClock { timing } => *timing -= samples,
+            NoteEvent::MidiStart { timing } => *timing -= samples,
+            NoteEvent::MidiContinue { timing } => *timing -= samples,
+            NoteEvent::MidiStop { timing } => *timing -= samples,
+            NoteEvent::MidiSongPosition { timing, .. } => *timing -= samples,
+            NoteEvent::MidiTimecodeQuarterFrame { timing, .. } => *timing -= samples,
+            NoteEvent::MidiSysEx { timing, .. } => *timing -= samples,
+        }
+    }
+
+    /// Replace this event's timing. Used when converting events stored with absolute positions,
+    /// like the events from a [`smf::MidiFile`], to events with sample timings.
+    pub(crate) fn set_timing(&mut self, new_timing: u32) {
+        match self {
+            NoteEvent::NoteOn { timing, .. } => *timing = new_timing,
+            NoteEvent::NoteOff { timing, .. } => *timing = new_timing,
+            NoteEvent::Choke { timing, .. } => *timing = new_timing,
+            NoteEvent::VoiceTerminated { timing, .. } => *timing = new_timing,
+            NoteEvent::PolyModulation { timing, .. } => *timing = new_timing,
+            NoteEvent::MonoAutomation { timing, .. } => *timing = new_timing,
+            NoteEvent::PolyPressure { timing, .. } => *timing = new_timing,
+            NoteEvent::PolyVolume { timing, .. } => *timing = new_timing,
+            NoteEvent::PolyPan { timing, .. } => *timing = new_timing,
+            NoteEvent::PolyTuning { timing, .. } => *timing = new_timing,
+            NoteEvent::PolyVibrato { timing, .. } => *timing = new_timing,
+            NoteEvent::PolyExpression { timing, .. } => *timing = new_timing,
+            NoteEvent::PolyBrightness { timing, .. } => *timing = new_timing,
+            NoteEvent::MidiChannelPressure { timing, .. } => *timing = new_timing,
+            NoteEvent::MidiPitchBend { timing, .. } => *timing = new_timing,
+            NoteEvent::MidiCC { timing, .. } => *timing = new_timing,
+            NoteEvent::MidiProgramChange { timing, .. } => *timing = new_timing,
+            NoteEvent::MidiHighResCC { timing, .. } => *timing = new_timing,
+            NoteEvent::MidiRpn { timing, .. } => *timing = new_timing,
+            NoteEvent::MidiNrpn { timing, .. } => *timing = new_timing,
+            NoteEvent::MidiPerNotePitchBend { timing, .. } => *timing = new_timing,
+            NoteEvent::MidiPerNoteController { timing, .. } => *timing = new_timing,
+            NoteEvent::MidiTimingClock { timing } => *timing = new_timing,
+            NoteEvent::MidiStart { timing } => *timing = new_timing,
+            NoteEvent::MidiContinue { timing } => *timing = new_timing,
+            NoteEvent::MidiStop { timing } => *timing = new_timing,
+            NoteEvent::MidiSongPosition { timing, .. } => *timing = new_timing,
+            NoteEvent::MidiTimecodeQuarterFrame { timing, .. } => *timing = new_timing,
+            NoteEvent::MidiSysEx { timing, .. } => *timing = new_timing,
+        }
+    }
+}
+
+/// Build the CC messages for setting an RPN or NRPN parameter to a 14-bit value. The sequence ends
+/// with the null RPN so later data entry messages don't accidentally change the parameter.
+fn parameter_number_sequence(
+    channel: u8,
+    is_nrpn: bool,
+    parameter: u16,
+    value: u16,
+) -> [[u8; 3]; MAX_MIDI_SEQUENCE_LENGTH] {
+    let status = midi::CONTROL_CHANGE | channel;
+    let (msb_cc, lsb_cc) = if is_nrpn {
+        (
+            control_change::NON_REGISTERED_PARAMETER_NUMBER_MSB,
+            control_change::NON_REGISTERED_PARAMETER_NUMBER_LSB,
+        )
+    } else {
+        (
+            control_change::REGISTERED_PARAMETER_NUMBER_MSB,
+            control_change::REGISTERED_PARAMETER_NUMBER_LSB,
+        )
+    };
+
+    [
+        [status, msb_cc, ((parameter >> 7) & 0b1111111) as u8],
+        [status, lsb_cc, (parameter & 0b1111111) as u8],
+        [
+            status,
+            control_change::DATA_ENTRY_MSB,
+            ((value >> 7) & 0b1111111) as u8,
+        ],
+        [
+            status,
+            control_change::DATA_ENTRY_LSB,
+            (value & 0b1111111) as u8,
+        ],
+        [status, control_change::REGISTERED_PARAMETER_NUMBER_MSB, 127],
+        [status, control_change::REGISTERED_PARAMETER_NUMBER_LSB, 127],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    pub use super::*;
+
+    pub const TIMING: u32 = 5;
+
+    /// Converts an event to and from MIDI. Panics if any part of the conversion fails.
+    fn roundtrip_basic_event(event: NoteEvent<()>) -> NoteEvent<()> {
+        let midi_data = match event.as_midi().unwrap() {
+            MidiResult::Basic(midi_data) => midi_data,
+            MidiResult::SysEx(_, _) => panic!("Unexpected SysEx result"),
+            MidiResult::Sequence(_, _) => panic!("Unexpected sequence result"),
+            MidiResult::System(_, _) => panic!("Unexpected system message result"),
+        };
+
+        NoteEvent::from_midi(TIMING, &midi_data).unwrap()
+    }
+
+    #[test]
+    fn test_note_on_midi_conversion() {
+        let event = NoteEvent::<()>::NoteOn {
+            timing: TIMING,
+            voice_id: None,
+            channel: 1,
+            note: 2,
+            // The value will be rounded in the conversion to MIDI, hence this overly specific value
+            velocity: 0.6929134,
+        };
+
+        assert_eq!(roundtrip_basic_event(event), event);
+    }
+
+    #[test]
+    fn test_note_off_midi_conversion() {
+        let event = NoteEvent::<()>::NoteOff {
+            timing: TIMING,
+            voice_id: None,
+            channel: 1,
+            note: 2,
+            velocity: 0.6929134,
+        };
+
+        assert_eq!(roundtrip_basic_event(event), event);
+    }
+
+    #[test]
+    fn test_poly_pressure_midi_conversion() {
+        let event = NoteEvent::<()>::PolyPressure {
+            timing: TIMING,
+            voice_id: None,
+            channel: 1,
+            note: 2,
+            pressure: 0.6929134,
+        };
+
+        assert_eq!(roundtrip_basic_event(event), event);
+    }
+
+    #[test]
+    fn test_channel_pressure_midi_conversion() {
+        let event = NoteEvent::<()>::MidiChannelPressure {
+            timing: TIMING,
+            channel: 1,
+            pressure: 0.6929134,
+        };
+
+        assert_eq!(roundtrip_basic_event(event), event);
+    }
+
+    #[test]
+    fn test_pitch_bend_midi_conversion() {
+        let event = NoteEvent::<()>::MidiPitchBend {
+            timing: TIMING,
+            channel: 1,
+            value: 0.6929134,
+        };
+
+        assert_eq!(roundtrip_basic_event(event), event);
+    }
+
+    #[test]
+    fn test_cc_midi_conversion() {
+        let event = NoteEvent::<()>::MidiCC {
+            timing: TIMING,
+            channel: 1,
+            cc: 2,
+            value: 0.6929134,
+        };
+
+        assert_eq!(roundtrip_basic_event(event), event);
+    }
+
+    #[test]
+    fn test_program_change_midi_conversion() {
+        let event = NoteEvent::<()>::MidiProgramChange {
+            timing: TIMING,
+            channel: 1,
+            program: 42,
+        };
+
+        assert_eq!(roundtrip_basic_event(event), event);
+    }
+
+    #[test]
+    fn test_system_midi_conversion() {
+        for event in [
+            NoteEvent::<()>::MidiTimingClock { timing: TIMING },
+            NoteEvent::MidiStart { timing: TIMING },
+            NoteEvent::MidiContinue { timing: TIMING },
+            NoteEvent::MidiStop { timing: TIMING },
+            NoteEvent::MidiSongPosition {
+                timing: TIMING,
+                position: 0x1234,
+            },
+            NoteEvent::MidiTimecodeQuarterFrame {
+                timing: TIMING,
+                piece: 7,
+                value: 0b0110,
+            },
+        ] {
+            let (midi_data, length) = match event.as_midi().unwrap() {
+                MidiResult::System(midi_data, length) => (midi_data, length),
+                result => panic!("Unexpected result: {result:?}"),
+            };
+
+            assert_eq!(
+                NoteEvent::from_midi(TIMING, &midi_data[..length]),
+                Ok(event)
+            );
+        }
+
+        assert_eq!(
+            NoteEvent::<()>::MidiSongPosition {
+                timing: TIMING,
+                position: 0x1234,
+            }
+            .as_midi()
+            .map(|result| match result {
+                MidiResult::System(midi_data, 3) => midi_data,
+                result => panic!("Unexpected result: {result:?}"),
+            }),
+            Some([0xf2, 0x34, 0x24])
+        );
+    }
+
+    /// Converts an event to a MIDI sequence and decodes it again using a fresh [`MidiCCDecoder`].
+    /// Returns the last decoded event.
+    fn roundtrip_sequence_event(event: NoteEvent<()>) -> NoteEvent<()> {
+        let (sequence, length) = match event.as_midi().unwrap() {
+            MidiResult::Sequence(sequence, length) => (sequence, length),
+            result => panic!("Unexpected result: {result:?}"),
+        };
+
+        let mut decoder = MidiCCDecoder::new();
+        let mut last_event = None;
+        for midi_data in &sequence[..length] {
+            let event = NoteEvent::from_midi(TIMING, midi_data).unwrap();
+            if let Some(event) = decoder.decode(event) {
+                last_event = Some(event);
+            }
+        }
+
+        last_event.unwrap()
+    }
+
+    fn cc(channel: u8, cc: u8, value: u8) -> NoteEvent<()> {
+        NoteEvent::MidiCC {
+            timing: TIMING,
+            channel,
+            cc,
+            value: value as f32 / 127.0,
+        }
+    }
+
+    #[test]
+    fn test_high_res_cc_midi_conversion() {
+        let event = NoteEvent::<()>::MidiHighResCC {
+            timing: TIMING,
+            channel: 1,
+            cc: control_change::MODULATION_MSB,
+            value: 0.5000305,
+        };
+
+        assert_eq!(roundtrip_sequence_event(event), event);
+    }
+
+    #[test]
+    fn test_rpn_midi_conversion() {
+        for message in [
+            RpnMessage::PitchBendSensitivity { semitones: 12.5 },
+            RpnMessage::FineTuning { cents: -50.0 },
+            RpnMessage::CoarseTuning { semitones: -12 },
+            RpnMessage::MpeConfiguration {
+                member_channels: 15,
+            },
+            RpnMessage::Other {
+                parameter: 5,
+                value: 1234,
+            },
+        ] {
+            let event = NoteEvent::<()>::MidiRpn {
+                timing: TIMING,
+                channel: 0,
+                message,
+            };
+
+            assert_eq!(roundtrip_sequence_event(event), event);
+        }
+    }
+
+    #[test]
+    fn test_nrpn_midi_conversion() {
+        let event = NoteEvent::<()>::MidiNrpn {
+            timing: TIMING,
+            channel: 2,
+            parameter: 1000,
+            value: 0.25001526,
+        };
+
+        assert_eq!(roundtrip_sequence_event(event), event);
+    }
+
+    #[test]
+    fn test_decode_high_res_cc() {
+        let mut decoder = MidiCCDecoder::new();
+
+        assert_eq!(
+            decoder.decode(cc(3, 7, 64)),
+            Some(NoteEvent::MidiHighResCC {
+                timing: TIMING,
+                channel: 3,
+                cc: 7,
+                value: (64 << 7) as f32 / 16383.0,
+            })
+        );
+        assert_eq!(
+            decoder.decode(cc(3, 39, 1)),
+            Some(NoteEvent::MidiHighResCC {
+                timing: TIMING,
+                channel: 3,
+                cc: 7,
+                value: ((64 << 7) | 1) as f32 / 16383.0,
+            })
+        );
+
+        // Other channels have their own state
+        assert_eq!(
+            decoder.decode(cc(4, 39, 1)),
+            Some(NoteEvent::MidiHighResCC {
+                timing: TIMING,
+                channel: 4,
+                cc: 7,
+                value: 1.0 / 16383.0,
+            })
+        );
+
+        // CCs outside of the 14-bit range and non-CC events are passed through
+        assert_eq!(decoder.decode(cc(3, 64, 127)), Some(cc(3, 64, 127)));
+        let event = NoteEvent::<()>::MidiProgramChange {
+            timing: TIMING,
+            channel: 3,
+            program: 1,
+        };
+        assert_eq!(decoder.decode(event), Some(event));
+    }
+
+    #[test]
+    fn test_decode_rpn() {
+        let mut decoder = MidiCCDecoder::new();
+
+        // Without a selected parameter data entry messages are passed through
+        assert_eq!(decoder.decode(cc(0, 6, 2)), Some(cc(0, 6, 2)));
+
+        assert_eq!(decoder.decode(cc(0, 101, 0)), None);
+        assert_eq!(decoder.decode(cc(0, 100, 0)), None);
+        assert_eq!(
+            decoder.decode(cc(0, 6, 24)),
+            Some(NoteEvent::MidiRpn {
+                timing: TIMING,
+                channel: 0,
+                message: RpnMessage::PitchBendSensitivity { semitones: 24.0 },
+            })
+        );
+        assert_eq!(
+            decoder.decode(cc(0, 38, 50)),
+            Some(NoteEvent::MidiRpn {
+                timing: TIMING,
+                channel: 0,
+                message: RpnMessage::PitchBendSensitivity { semitones: 24.5 },
+            })
+        );
+        assert_eq!(
+            decoder.decode(cc(0, DATA_INCREMENT, 0)),
+            Some(NoteEvent::MidiRpn {
+                timing: TIMING,
+                channel: 0,
+                message: RpnMessage::PitchBendSensitivity { semitones: 24.51 },
+            })
+        );
+
+        // The null RPN deselects the parameter again
+        assert_eq!(decoder.decode(cc(0, 101, 127)), None);
+        assert_eq!(decoder.decode(cc(0, 100, 127)), None);
+        assert_eq!(decoder.decode(cc(0, 6, 2)), Some(cc(0, 6, 2)));
+
+        // MPE configuration messages
+        assert_eq!(decoder.decode(cc(15, 101, 0)), None);
+        assert_eq!(decoder.decode(cc(15, 100, 6)), None);
+        assert_eq!(
+            decoder.decode(cc(15, 6, 7)),
+            Some(NoteEvent::MidiRpn {
+                timing: TIMING,
+                channel: 15,
+                message: RpnMessage::MpeConfiguration { member_channels: 7 },
+            })
+        );
+    }
+
+    #[test]
+    fn test_decode_nrpn() {
+        let mut decoder = MidiCCDecoder::new();
+
+        assert_eq!(decoder.decode(cc(1, 99, 1)), None);
+        assert_eq!(decoder.decode(cc(1, 98, 2)), None);
+        assert_eq!(
+            decoder.decode(cc(1, 6, 127)),
+            Some(NoteEvent::MidiNrpn {
+                timing: TIMING,
+                channel: 1,
+                parameter: (1 << 7) | 2,
+                value: (127 << 7) as f32 / 16383.0,
+            })
+        );
+        assert_eq!(
+            decoder.decode(cc(1, 38, 127)),
+            Some(NoteEvent::MidiNrpn {
+                timing: TIMING,
+                channel: 1,
+                parameter: (1 << 7) | 2,
+                value: 1.0,
+            })
+        );
+
+        decoder.reset();
+        assert_eq!(decoder.decode(cc(1, 6, 127)), Some(cc(1, 6, 127)));
+    }
+
+    #[test]
+    fn test_decoding_order() {
+        let mut decoder = MidiCCDecoder::new();
+
+        // These would all be received at the same time, but in the wrong order
+        let mut events = [cc(2, 38, 50), cc(2, 6, 12), cc(2, 100, 0), cc(2, 101, 0)];
+        events.sort_by_key(MidiCCDecoder::decoding_order);
+        assert_eq!(
+            events,
+            [cc(2, 101, 0), cc(2, 100, 0), cc(2, 6, 12), cc(2, 38, 50)]
+        );
+
+        let decoded: Vec<_> = events
+            .into_iter()
+            .filter_map(|event| decoder.decode(event))
+            .collect();
+        assert_eq!(
+            decoded.last(),
+            Some(&NoteEvent::MidiRpn {
+                timing: TIMING,
+                channel: 2,
+                message: RpnMessage::PitchBendSensitivity { semitones: 12.5 },
+            })
+        );
+    }
+
+    mod sysex {
+        use super::*;
+
+        #[derive(Clone, Debug, PartialEq)]
+        enum MessageType {
+            Foo(f32),
+        }
+
+        impl SysExMessage for MessageType {
+            type Buffer = [u8; 4];
+
+            fn from_buffer(buffer: &[u8]) -> Option<Self> {
+                match buffer {
+                    [0xf0, 0x69, n, 0xf7] => Some(MessageType::Foo(*n as f32 / 127.0)),
+                    _ => None,
+                }
+            }
+
+            fn to_buffer(self) -> (Self::Buffer, usize) {
+                match self {
+                    MessageType::Foo(x) => ([0xf0, 0x69, (x * 127.0).round() as u8, 0xf7], 4),
+                }
+            }
+        }
+
+        #[test]
+        fn test_parse_from_buffer() {
+            let midi_data = [0xf0, 0x69, 127, 0xf7];
+            let parsed = NoteEvent::from_midi(TIMING, &midi_data).unwrap();
+
+            assert_eq!(
+                parsed,
+                NoteEvent::MidiSysEx {
+                    timing: TIMING,
+                    message: MessageType::Foo(1.0)
+                }
+            );
+        }
+
+        #[test]
+        fn test_convert_to_buffer() {
+            let message = MessageType::Foo(1.0);
+            let event = NoteEvent::MidiSysEx {
+                timing: TIMING,
+                message,
+            };
+
+            match event.as_midi() {
+                Some(MidiResult::SysEx(padded_sysex_buffer, length)) => {
+                    assert_eq!(padded_sysex_buffer[..length], [0xf0, 0x69, 127, 0xf7])
+                }
+                result => panic!("Unexpected result: {result:?}"),
+            }
+        }
+
+        #[test]
+        fn test_invalid_parse() {
+            let midi_data = [0xf0, 0x0, 127, 0xf7];
+            let parsed = NoteEvent::<MessageType>::from_midi(TIMING, &midi_data);
+
+            assert!(parsed.is_err());
+        }
+    }
+}
@@ -0,0 +1,489 @@
+//! A tempo-synced arpeggiator that turns the notes held in a [`NoteTracker`] into a sequence of
+//! note on and note off events. While the host's transport is playing the steps are aligned to the
+//! song position. Otherwise the arpeggiator runs freely at the host's tempo, starting as soon as a
+//! key is pressed.
+
+use std::ops::Range;
+
+use super::tracker::{HeldNote, NoteTracker, MAX_TRACKED_NOTES};
+use super::NoteEvent;
+use crate::context::process::Transport;
+
+/// The tempo used when the host does not report one.
+const DEFAULT_TEMPO: f64 = 120.0;
+
+/// The song position may differ this much from where the last range ended before it's treated as
+/// the host seeking or looping.
+const JUMP_THRESHOLD_SECONDS: f64 = 0.005;
+
+/// Steps that are this many beats before the song position are still played at the start of the
+/// range, to account for rounding errors in the host's transport information.
+const STEP_EPSILON_BEATS: f64 = 1e-6;
+
+/// The order in which the arpeggiator plays the held notes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArpeggiatorMode {
+    /// Play the notes from low to high.
+    Up,
+    /// Play the notes from high to low.
+    Down,
+    /// Play the notes from low to high and back again, without repeating the highest and lowest
+    /// notes.
+    UpDown,
+    /// Play the notes in the order they were pressed.
+    AsPlayed,
+    /// Play all held notes at once on every step.
+    Chord,
+}
+
+/// Generates arpeggiated notes from the held notes in a [`NoteTracker`] and the host's
+/// [`Transport`]. Call [`process()`][Self::process()] after updating the tracker with the events
+/// for that part of the buffer. The arpeggiator does not allocate after it has been created.
+///
+/// # Example
+///
+/// ```ignore
+/// let transport = context.transport().clone();
+/// for (block_start, block, events) in self.events.iter_blocks(buffer) {
+///     for event in events {
+///         self.notes.handle_event(event, |_| ());
+///     }
+///
+///     let block_range = block_start..block_start + block.samples();
+///     self.arpeggiator
+///         .process(&self.notes, &transport, block_range, |event| context.send_event(event));
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Arpeggiator {
+    mode: ArpeggiatorMode,
+    /// The length of a step in quarter notes.
+    step_length: f64,
+    /// The fraction of a step a note is held, in `(0, 1]`.
+    gate: f64,
+    /// The number of octaves the pattern spans.
+    octaves: u8,
+
+    /// The held notes in the order they're played. This is filled for every step, and it has
+    /// enough capacity to hold every note a `NoteTracker` can hold.
+    pattern_notes: Vec<HeldNote>,
+    /// The notes that are currently playing, along with the position in beats where they should be
+    /// released.
+    playing: Vec<(HeldNote, f64)>,
+    /// The index in the pattern of the next note.
+    pattern_pos: usize,
+    /// The index of the next step, counted from the start of the song or from when the
+    /// arpeggiator started running freely.
+    next_step: i64,
+    /// The position in beats where the last processed range ended, or `None` if the arpeggiator
+    /// is not running.
+    expected_pos_beats: Option<f64>,
+    /// Whether `expected_pos_beats` follows the host's transport instead of the free running
+    /// clock.
+    synced: bool,
+}
+
+impl Arpeggiator {
+    /// Create an arpeggiator that plays sixteenth notes with a 50% gate over a single octave.
+    pub fn new(mode: ArpeggiatorMode) -> Self {
+        Self {
+            mode,
+            step_length: 0.25,
+            gate: 0.5,
+            octaves: 1,
+
+            pattern_notes: Vec::with_capacity(MAX_TRACKED_NOTES),
+            playing: Vec::with_capacity(MAX_TRACKED_NOTES),
+            pattern_pos: 0,
+            next_step: 0,
+            expected_pos_beats: None,
+            synced: false,
+        }
+    }
+
+    /// Change the order in which notes are played.
+    pub fn set_mode(&mut self, mode: ArpeggiatorMode) {
+        self.mode = mode;
+    }
+
+    /// Change the length of a step in quarter notes. A step length of `0.25` plays sixteenth notes.
+    pub fn set_step_length(&mut self, beats: f64) {
+        nih_debug_assert!(beats > 0.0);
+        self.step_length = beats.max(1.0 / 256.0);
+    }
+
+    /// Change the fraction of a step a note is held, in `(0, 1]`.
+    pub fn set_gate(&mut self, gate: f64) {
+        self.gate = gate.clamp(0.01, 1.0);
+    }
+
+    /// Change the number of octaves the pattern spans, starting at the held notes and going up.
+    pub fn set_octaves(&mut self, octaves: u8) {
+        self.octaves = octaves.clamp(1, 10);
+    }
+
+    /// Forget the playing notes and the position in the pattern. Call this from the plugin's
+    /// `reset()` function. This does not send note off events, use [`stop()`][Self::stop()] for
+    /// that.
+    pub fn reset(&mut self) {
+        self.playing.clear();
+        self.pattern_pos = 0;
+        self.expected_pos_beats = None;
+    }
+
+    /// Release all playing notes at `timing` and reset the arpeggiator. Useful when the plugin is
+    /// bypassed or the arpeggiator is turned off.
+    pub fn stop<S>(&mut self, timing: u32, mut send_event: impl FnMut(NoteEvent<S>)) {
+        self.release_all(timing, &mut send_event);
+        self.reset();
+    }
+
+    /// Generate the note events for the samples in `samples`, using the notes currently held in
+    /// `notes`. The transport information should be for the start of the buffer, and the timings
+    /// passed to `send_event` are relative to the start of the buffer. Events are passed to
+    /// `send_event` in order, with note off events preceding note on events at the same timing.
+    /// The ranges passed to consecutive calls should follow each other.
+    pub fn process<S>(
+        &mut self,
+        notes: &NoteTracker,
+        transport: &Transport,
+        samples: Range<usize>,
+        mut send_event: impl FnMut(NoteEvent<S>),
+    ) {
+        let tempo = transport
+            .tempo
+            .filter(|tempo| *tempo > 0.0)
+            .unwrap_or(DEFAULT_TEMPO);
+        let beats_per_sample = tempo / 60.0 / transport.sample_rate as f64;
+        if samples.is_empty() || !beats_per_sample.is_finite() || beats_per_sample <= 0.0 {
+            return;
+        }
+
+        let host_pos_beats = if transport.playing {
+            transport
+                .pos_beats()
+                .map(|pos_beats| pos_beats + samples.start as f64 * beats_per_sample)
+        } else {
+            None
+        };
+        let pos_beats = match (host_pos_beats, self.expected_pos_beats) {
+            (Some(host_pos_beats), Some(expected_pos_beats))
+                if self.synced
+                    && (host_pos_beats - expected_pos_beats).abs()
+                        <= tempo / 60.0 * JUMP_THRESHOLD_SECONDS =>
+            {
+                host_pos_beats
+            }
+            // The transport started playing, or the host seeked or looped
+            (Some(host_pos_beats), _) => {
+                self.release_all(samples.start as u32, &mut send_event);
+                self.synced = true;
+                self.next_step =
+                    (host_pos_beats / self.step_length - STEP_EPSILON_BEATS).ceil() as i64;
+
+                host_pos_beats
+            }
+            (None, Some(expected_pos_beats)) if !self.synced => expected_pos_beats,
+            // The arpeggiator starts running freely as soon as a note is held, or right after the
+            // transport stops
+            (None, _) => {
+                self.release_all(samples.start as u32, &mut send_event);
+                self.synced = false;
+                self.next_step = 0;
+
+                0.0
+            }
+        };
+
+        // Timings are computed relative to the start of the range, and they're rounded before
+        // comparing them to the range's length so events on the boundary between two ranges are
+        // neither sent twice nor skipped
+        let num_samples = samples.len() as f64;
+        let offset = |beats: f64| ((beats - pos_beats) / beats_per_sample).round();
+        let timing = |offset: f64| (samples.start + offset.max(0.0) as usize) as u32;
+        loop {
+            let next_release = self
+                .playing
+                .iter()
+                .enumerate()
+                .map(|(idx, (_, release_beats))| (idx, offset(*release_beats)))
+                .min_by(|(_, a), (_, b)| a.total_cmp(b));
+            let step_beats = self.next_step as f64 * self.step_length;
+            let step_offset = offset(step_beats);
+
+            match next_release {
+                Some((idx, release_offset))
+                    if release_offset <= step_offset && release_offset < num_samples =>
+                {
+                    let (held_note, _) = self.playing.remove(idx);
+                    send_event(note_off(timing(release_offset), &held_note));
+                }
+                _ if step_offset < num_samples => {
+                    self.step(notes, timing(step_offset), step_beats, &mut send_event);
+                    self.next_step += 1;
+                }
+                _ => break,
+            }
+        }
+
+        // When running freely, the next held note should start a new pattern immediately
+        if !self.synced && notes.is_empty() && self.playing.is_empty() {
+            self.reset();
+        } else {
+            self.expected_pos_beats = Some(pos_beats + num_samples * beats_per_sample);
+        }
+    }
+
+    /// Play the notes for a step starting at `step_beats`.
+    fn step<S>(
+        &mut self,
+        notes: &NoteTracker,
+        timing: u32,
+        step_beats: f64,
+        send_event: &mut impl FnMut(NoteEvent<S>),
+    ) {
+        self.pattern_notes.clear();
+        self.pattern_notes.extend_from_slice(notes.notes());
+        if self.pattern_notes.is_empty() {
+            self.pattern_pos = 0;
+            return;
+        }
+
+        // This uses an unstable sort since a stable sort would allocate
+        if matches!(
+            self.mode,
+            ArpeggiatorMode::Up | ArpeggiatorMode::Down | ArpeggiatorMode::UpDown
+        ) {
+            self.pattern_notes
+                .sort_unstable_by_key(|held_note| (held_note.note, held_note.channel));
+        }
+
+        let release_beats = step_beats + self.step_length * self.gate;
+        let num_notes = self.pattern_notes.len();
+        if self.mode == ArpeggiatorMode::Chord {
+            for octave in 0..self.octaves as usize {
+                for idx in 0..num_notes {
+                    let held_note = self.pattern_notes[idx];
+                    self.play(held_note, octave, timing, release_beats, send_event);
+                }
+            }
+
+            return;
+        }
+
+        let octave_len = num_notes * self.octaves as usize;
+        let pattern_len = match self.mode {
+            ArpeggiatorMode::UpDown if octave_len > 1 => octave_len * 2 - 2,
+            _ => octave_len,
+        };
+        let pos = self.pattern_pos % pattern_len;
+        let idx = match self.mode {
+            ArpeggiatorMode::Down => octave_len - 1 - pos,
+            ArpeggiatorMode::UpDown if pos >= octave_len => pattern_len - pos,
+            _ => pos,
+        };
+
+        let held_note = self.pattern_notes[idx % num_notes];
+        self.play(
+            held_note,
+            idx / num_notes,
+            timing,
+            release_beats,
+            send_event,
+        );
+        self.pattern_pos = (pos + 1) % pattern_len;
+    }
+
+    /// Start playing a held note transposed up by a number of octaves.
+    fn play<S>(
+        &mut self,
+        held_note: HeldNote,
+        octave: usize,
+        timing: u32,
+        release_beats: f64,
+        send_event: &mut impl FnMut(NoteEvent<S>),
+    ) {
+        let note = held_note.note as usize + (octave * 12);
+        if note > 127 {
+            return;
+        }
+
+        let held_note = HeldNote {
+            voice_id: None,
+            note: note as u8,
+            sustained: false,
+            ..held_note
+        };
+
+        // With a 100% gate the previous note may still be playing
+        if let Some(idx) = self.playing.iter().position(|(playing_note, _)| {
+            playing_note.channel == held_note.channel && playing_note.note == held_note.note
+        }) {
+            let (playing_note, _) = self.playing.remove(idx);
+            send_event(note_off(timing, &playing_note));
+        }
+
+        if self.playing.len() < MAX_TRACKED_NOTES {
+            send_event(NoteEvent::NoteOn {
+                timing,
+                voice_id: None,
+                channel: held_note.channel,
+                note: held_note.note,
+                velocity: held_note.velocity,
+            });
+            self.playing.push((held_note, release_beats));
+        }
+    }
+
+    fn release_all<S>(&mut self, timing: u32, send_event: &mut impl FnMut(NoteEvent<S>)) {
+        for (held_note, _) in self.playing.drain(..) {
+            send_event(note_off(timing, &held_note));
+        }
+    }
+}
+
+fn note_off<S>(timing: u32, held_note: &HeldNote) -> NoteEvent<S> {
+    NoteEvent::NoteOff {
+        timing,
+        voice_id: None,
+        channel: held_note.channel,
+        note: held_note.note,
+        velocity: 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// At 120 BPM and 48 kHz a sixteenth note step is 6000 samples long.
+    fn transport(playing: bool, pos_samples: i64) -> Transport {
+        let mut transport = Transport::new(48000.0);
+        transport.playing = playing;
+        transport.tempo = Some(120.0);
+        transport.pos_samples = Some(pos_samples);
+
+        transport
+    }
+
+    fn hold(notes: &[u8]) -> NoteTracker {
+        let mut tracker = NoteTracker::new();
+        for &note in notes {
+            let event: NoteEvent<()> = NoteEvent::NoteOn {
+                timing: 0,
+                voice_id: None,
+                channel: 0,
+                note,
+                velocity: 1.0,
+            };
+            tracker.handle_event(&event, |_| ());
+        }
+
+        tracker
+    }
+
+    /// Returns `(timing, note, is_note_on)` tuples.
+    fn process(
+        arpeggiator: &mut Arpeggiator,
+        notes: &NoteTracker,
+        transport: &Transport,
+        samples: Range<usize>,
+    ) -> Vec<(u32, u8, bool)> {
+        let mut events = Vec::new();
+        arpeggiator.process(
+            notes,
+            transport,
+            samples,
+            |event: NoteEvent<()>| match event {
+                NoteEvent::NoteOn { timing, note, .. } => events.push((timing, note, true)),
+                NoteEvent::NoteOff { timing, note, .. } => events.push((timing, note, false)),
+                _ => unreachable!(),
+            },
+        );
+
+        events
+    }
+
+    #[test]
+    fn test_free_running() {
+        let mut arpeggiator = Arpeggiator::new(ArpeggiatorMode::Up);
+        let transport = transport(false, 0);
+        let notes = hold(&[64, 60]);
+
+        assert_eq!(
+            process(&mut arpeggiator, &notes, &transport, 500..12500),
+            [
+                (500, 60, true),
+                (3500, 60, false),
+                (6500, 64, true),
+                (9500, 64, false)
+            ]
+        );
+
+        // Once the keys are released and the last note has finished, the next key press starts a
+        // new pattern right away
+        assert!(process(&mut arpeggiator, &hold(&[]), &transport, 0..1000).is_empty());
+        assert_eq!(
+            process(&mut arpeggiator, &notes, &transport, 1000..2000),
+            [(1000, 60, true)]
+        );
+    }
+
+    #[test]
+    fn test_synced_up_down() {
+        let mut arpeggiator = Arpeggiator::new(ArpeggiatorMode::UpDown);
+        let notes = hold(&[60, 64, 67]);
+
+        // The buffer starts half a step into the song, and it's processed in two parts
+        let playing = transport(true, 3000);
+        let mut events = process(&mut arpeggiator, &notes, &playing, 0..4000);
+        events.extend(process(&mut arpeggiator, &notes, &playing, 4000..30000));
+        assert_eq!(
+            events,
+            [
+                (3000, 60, true),
+                (6000, 60, false),
+                (9000, 64, true),
+                (12000, 64, false),
+                (15000, 67, true),
+                (18000, 67, false),
+                (21000, 64, true),
+                (24000, 64, false),
+                (27000, 60, true),
+            ]
+        );
+
+        // Jumping releases the playing notes and realigns the steps to the new position
+        assert_eq!(
+            process(&mut arpeggiator, &notes, &transport(true, 12000), 0..1000),
+            [(0, 60, false), (0, 64, true)]
+        );
+    }
+
+    #[test]
+    fn test_chord_octaves() {
+        let mut arpeggiator = Arpeggiator::new(ArpeggiatorMode::Chord);
+        arpeggiator.set_octaves(2);
+        arpeggiator.set_gate(1.0);
+        let notes = hold(&[60, 64]);
+
+        let events = process(&mut arpeggiator, &notes, &transport(false, 0), 0..7000);
+        assert_eq!(
+            events,
+            [
+                (0, 60, true),
+                (0, 64, true),
+                (0, 72, true),
+                (0, 76, true),
+                (6000, 60, false),
+                (6000, 64, false),
+                (6000, 72, false),
+                (6000, 76, false),
+                (6000, 60, true),
+                (6000, 64, true),
+                (6000, 72, true),
+                (6000, 76, true),
+            ]
+        );
+    }
+}
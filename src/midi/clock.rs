@@ -0,0 +1,373 @@
+//! Generating MIDI clock, song position, and MIDI time code messages from the host's transport
+//! information. This allows sequencer and arpeggiator plugins to synchronize downstream hardware.
+//!
+//! [`MidiClockGenerator`] keeps track of the transport between blocks and emits
+//! [`NoteEvent::MidiTimingClock`], [`NoteEvent::MidiStart`], [`NoteEvent::MidiContinue`],
+//! [`NoteEvent::MidiStop`], [`NoteEvent::MidiSongPosition`], and optionally
+//! [`NoteEvent::MidiTimecodeQuarterFrame`] events. These can then be sent using
+//! [`ProcessContext::send_event()`][crate::prelude::ProcessContext::send_event()].
+
+use super::NoteEvent;
+use crate::context::process::Transport;
+
+/// The number of MIDI timing clocks per quarter note.
+pub const CLOCKS_PER_QUARTER_NOTE: u32 = 24;
+
+/// The song position may differ this much from where the last block ended before it's treated as
+/// the host seeking or looping. This accounts for rounding errors in the host's transport
+/// information.
+const JUMP_THRESHOLD_SECONDS: f64 = 0.005;
+
+/// The frame rate used for MIDI time code quarter frame messages. Drop frame timecode is not
+/// supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MtcFrameRate {
+    Fps24,
+    Fps25,
+    Fps30,
+}
+
+/// Generates MIDI clock and optionally MIDI time code messages from the host's [`Transport`]. Call
+/// [`process()`][Self::process()] once per block, and [`reset()`][Self::reset()] from the plugin's
+/// `reset()` function.
+///
+/// Starting playback at the start of the song sends a start message. Starting playback anywhere
+/// else, or the host seeking or looping while playing, sends a song position pointer followed by a
+/// continue message. Timing clocks are only sent while the transport is playing and the host
+/// reports a tempo.
+///
+/// # Example
+///
+/// ```ignore
+/// fn process(
+///     &mut self,
+///     buffer: &mut Buffer,
+///     _aux: &mut AuxiliaryBuffers,
+///     context: &mut impl ProcessContext<Self>,
+/// ) -> ProcessStatus {
+///     let transport = context.transport().clone();
+///     self.clock
+///         .process(&transport, buffer.samples(), |event| context.send_event(event));
+///
+///     ProcessStatus::Normal
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct MidiClockGenerator {
+    /// The frame rate for MIDI time code quarter frame messages, if those should be sent.
+    timecode: Option<MtcFrameRate>,
+
+    /// Whether the transport was playing during the last block.
+    was_playing: bool,
+    /// The song position in seconds where the last block ended, if known. Used to detect jumps.
+    expected_pos_seconds: Option<f64>,
+    /// The index of the next timing clock, counted from the start of the song.
+    next_clock: i64,
+    /// The index of the next MTC quarter frame, counted from the start of the song.
+    next_quarter_frame: i64,
+}
+
+impl MtcFrameRate {
+    /// The number of frames per second.
+    pub fn frames_per_second(self) -> u32 {
+        match self {
+            MtcFrameRate::Fps24 => 24,
+            MtcFrameRate::Fps25 => 25,
+            MtcFrameRate::Fps30 => 30,
+        }
+    }
+
+    /// The rate code stored in the last quarter frame piece.
+    fn rate_code(self) -> u8 {
+        match self {
+            MtcFrameRate::Fps24 => 0,
+            MtcFrameRate::Fps25 => 1,
+            MtcFrameRate::Fps30 => 3,
+        }
+    }
+}
+
+impl Default for MidiClockGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MidiClockGenerator {
+    /// Create a generator that only sends MIDI clock and song position messages.
+    pub fn new() -> Self {
+        Self {
+            timecode: None,
+
+            was_playing: false,
+            expected_pos_seconds: None,
+            next_clock: 0,
+            next_quarter_frame: 0,
+        }
+    }
+
+    /// Also send MIDI time code quarter frame messages at the specified frame rate.
+    pub fn with_timecode(mut self, frame_rate: MtcFrameRate) -> Self {
+        self.timecode = Some(frame_rate);
+        self
+    }
+
+    /// Forget the transport state. The next block where the transport is playing will send a start
+    /// or continue message again.
+    pub fn reset(&mut self) {
+        self.was_playing = false;
+        self.expected_pos_seconds = None;
+    }
+
+    /// Generate the messages for a block of `num_samples` samples with the current transport
+    /// information. Events are passed to `send_event` in order, with timings relative to the start
+    /// of the block.
+    pub fn process<S>(
+        &mut self,
+        transport: &Transport,
+        num_samples: usize,
+        mut send_event: impl FnMut(NoteEvent<S>),
+    ) {
+        if !transport.playing {
+            if self.was_playing {
+                send_event(NoteEvent::MidiStop { timing: 0 });
+            }
+
+            self.reset();
+            return;
+        }
+
+        let pos_seconds = transport.pos_seconds();
+        let pos_beats = transport.pos_beats();
+        let jumped = match (self.expected_pos_seconds, pos_seconds) {
+            (Some(expected_pos_seconds), Some(pos_seconds)) => {
+                (pos_seconds - expected_pos_seconds).abs() > JUMP_THRESHOLD_SECONDS
+            }
+            _ => false,
+        };
+
+        if !self.was_playing || jumped {
+            if self.was_playing {
+                send_event(NoteEvent::MidiStop { timing: 0 });
+            }
+
+            // The song position pointer is expressed in sixteenth notes
+            let start_beats = pos_beats.unwrap_or(0.0).max(0.0);
+            let start_seconds = pos_seconds.unwrap_or(0.0).max(0.0);
+            let position = (start_beats * 4.0).floor().min(16383.0) as u16;
+            if start_beats * 4.0 < 1.0 / CLOCKS_PER_QUARTER_NOTE as f64 {
+                send_event(NoteEvent::MidiStart { timing: 0 });
+            } else {
+                send_event(NoteEvent::MidiSongPosition {
+                    timing: 0,
+                    position,
+                });
+                send_event(NoteEvent::MidiContinue { timing: 0 });
+            }
+
+            self.next_clock = (start_beats * CLOCKS_PER_QUARTER_NOTE as f64).ceil() as i64;
+            if let Some(frame_rate) = self.timecode {
+                let quarter_frames_per_second = (frame_rate.frames_per_second() * 4) as f64;
+                self.next_quarter_frame = (start_seconds * quarter_frames_per_second).ceil() as i64;
+            }
+        }
+        self.was_playing = true;
+
+        // The clock and quarter frame timings are computed from the position at the start of the
+        // block, and the two streams of events are merged so they're sent in order. The offsets are
+        // rounded before comparing them to the block's length so events that fall on a block
+        // boundary are not sent twice or skipped because of rounding errors.
+        let sample_rate = transport.sample_rate as f64;
+        let clock_timing = |clock: i64| -> Option<u32> {
+            let (pos_beats, tempo) = (pos_beats?, transport.tempo?);
+            let beats_per_sample = tempo / 60.0 / sample_rate;
+            if beats_per_sample <= 0.0 {
+                return None;
+            }
+
+            let offset =
+                ((clock as f64 / CLOCKS_PER_QUARTER_NOTE as f64) - pos_beats) / beats_per_sample;
+            let offset = offset.round();
+            (offset < num_samples as f64).then_some(offset.max(0.0) as u32)
+        };
+        let quarter_frame_timing = |quarter_frame: i64| -> Option<u32> {
+            let (pos_seconds, frame_rate) = (pos_seconds?, self.timecode?);
+            let quarter_frames_per_second = (frame_rate.frames_per_second() * 4) as f64;
+
+            let offset =
+                ((quarter_frame as f64 / quarter_frames_per_second) - pos_seconds) * sample_rate;
+            let offset = offset.round();
+            (offset < num_samples as f64).then_some(offset.max(0.0) as u32)
+        };
+
+        loop {
+            let clock = clock_timing(self.next_clock);
+            let quarter_frame = quarter_frame_timing(self.next_quarter_frame);
+
+            // Clock events are sent before quarter frames with the same timing
+            let clock_is_next = match (clock, quarter_frame) {
+                (Some(clock), Some(quarter_frame)) => clock <= quarter_frame,
+                (clock, _) => clock.is_some(),
+            };
+            match (clock, quarter_frame) {
+                (Some(timing), _) if clock_is_next => {
+                    send_event(NoteEvent::MidiTimingClock { timing });
+                    self.next_clock += 1;
+                }
+                (_, Some(timing)) => {
+                    let (piece, value) = self.quarter_frame_data(self.next_quarter_frame);
+                    send_event(NoteEvent::MidiTimecodeQuarterFrame {
+                        timing,
+                        piece,
+                        value,
+                    });
+                    self.next_quarter_frame += 1;
+                }
+                _ => break,
+            }
+        }
+
+        self.expected_pos_seconds =
+            pos_seconds.map(|pos_seconds| pos_seconds + (num_samples as f64 / sample_rate));
+    }
+
+    /// Compute the piece and 4-bit value for a quarter frame. Every eight quarter frames encode the
+    /// timecode of the frame at the start of that sequence, so a sequence spans two frames.
+    fn quarter_frame_data(&self, quarter_frame: i64) -> (u8, u8) {
+        let frame_rate = self.timecode.unwrap_or(MtcFrameRate::Fps24);
+        let frames_per_second = frame_rate.frames_per_second() as i64;
+
+        let piece = quarter_frame.rem_euclid(8) as u8;
+        let total_frames = quarter_frame.div_euclid(8).max(0) * 2;
+        let frames = (total_frames % frames_per_second) as u8;
+        let total_seconds = total_frames / frames_per_second;
+        let seconds = (total_seconds % 60) as u8;
+        let minutes = ((total_seconds / 60) % 60) as u8;
+        let hours = ((total_seconds / 3600) % 24) as u8;
+
+        let value = match piece {
+            0 => frames & 0b1111,
+            1 => frames >> 4,
+            2 => seconds & 0b1111,
+            3 => seconds >> 4,
+            4 => minutes & 0b1111,
+            5 => minutes >> 4,
+            6 => hours & 0b1111,
+            _ => (hours >> 4) | (frame_rate.rate_code() << 1),
+        };
+
+        (piece, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transport(playing: bool, pos_samples: i64) -> Transport {
+        let mut transport = Transport::new(48000.0);
+        transport.playing = playing;
+        transport.tempo = Some(120.0);
+        transport.pos_samples = Some(pos_samples);
+
+        transport
+    }
+
+    fn process(
+        generator: &mut MidiClockGenerator,
+        transport: &Transport,
+        num_samples: usize,
+    ) -> Vec<NoteEvent<()>> {
+        let mut events = Vec::new();
+        generator.process(transport, num_samples, |event| events.push(event));
+
+        events
+    }
+
+    #[test]
+    fn test_start_stop() {
+        // At 120 BPM and 48 kHz there is a timing clock every 1000 samples
+        let mut generator = MidiClockGenerator::new();
+        assert_eq!(
+            process(&mut generator, &transport(true, 0), 2500),
+            [
+                NoteEvent::MidiStart { timing: 0 },
+                NoteEvent::MidiTimingClock { timing: 0 },
+                NoteEvent::MidiTimingClock { timing: 1000 },
+                NoteEvent::MidiTimingClock { timing: 2000 },
+            ]
+        );
+        assert_eq!(
+            process(&mut generator, &transport(true, 2500), 1000),
+            [NoteEvent::MidiTimingClock { timing: 500 }]
+        );
+        assert_eq!(
+            process(&mut generator, &transport(false, 3500), 1000),
+            [NoteEvent::MidiStop { timing: 0 }]
+        );
+        assert_eq!(process(&mut generator, &transport(false, 3500), 1000), []);
+    }
+
+    #[test]
+    fn test_continue_and_jump() {
+        // Two quarter notes in, which is the eighth sixteenth note
+        let mut generator = MidiClockGenerator::new();
+        assert_eq!(
+            process(&mut generator, &transport(true, 48000), 1000),
+            [
+                NoteEvent::MidiSongPosition {
+                    timing: 0,
+                    position: 8
+                },
+                NoteEvent::MidiContinue { timing: 0 },
+                NoteEvent::MidiTimingClock { timing: 0 },
+            ]
+        );
+
+        // Looping back to the start of the song
+        assert_eq!(
+            process(&mut generator, &transport(true, 0), 1000),
+            [
+                NoteEvent::MidiStop { timing: 0 },
+                NoteEvent::MidiStart { timing: 0 },
+                NoteEvent::MidiTimingClock { timing: 0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_timecode() {
+        // At 25 FPS and 48 kHz there is a quarter frame every 480 samples. 1:02:03 is quarter frame
+        // 372300, which is the fifth piece of a sequence starting at 1:02:02 and 24 frames.
+        let mut generator = MidiClockGenerator::new().with_timecode(MtcFrameRate::Fps25);
+        let events = process(&mut generator, &transport(true, 3723 * 48000), 1000);
+        let quarter_frames: Vec<_> = events
+            .into_iter()
+            .filter(|event| matches!(event, NoteEvent::MidiTimecodeQuarterFrame { .. }))
+            .collect();
+
+        assert_eq!(
+            quarter_frames,
+            [
+                NoteEvent::MidiTimecodeQuarterFrame {
+                    timing: 0,
+                    piece: 4,
+                    value: 2
+                },
+                NoteEvent::MidiTimecodeQuarterFrame {
+                    timing: 480,
+                    piece: 5,
+                    value: 0
+                },
+                NoteEvent::MidiTimecodeQuarterFrame {
+                    timing: 960,
+                    piece: 6,
+                    value: 1
+                },
+            ]
+        );
+        assert_eq!(generator.quarter_frame_data(372303), (7, 1 << 1));
+        assert_eq!(generator.quarter_frame_data(372296), (0, 24 & 0b1111));
+        assert_eq!(generator.quarter_frame_data(372297), (1, 1));
+    }
+}
@@ -0,0 +1,414 @@
+//! MIDI learn, for binding incoming MIDI CCs, pitch bend, and channel pressure messages to a
+//! plugin's parameters independently of the host. An editor arms a parameter using
+//! [`MidiLearn::arm()`], after which the next matching MIDI message the plugin receives is bound to
+//! that parameter. Access the plugin's mappings through [`GuiContext::midi_learn()`].
+//!
+//! The mappings are stored in the plugin's state, and the wrappers apply mapped MIDI messages as
+//! parameter changes. If [`Plugin::SAMPLE_ACCURATE_AUTOMATION`] is enabled, then the wrappers split
+//! the buffer on these changes the same way they do for automation. The CLAP and VST3 wrappers also
+//! report these changes to the host, just like changes made from the editor, so the host's
+//! automation stays in sync with the plugin. LV2 hosts own the parameters' control port values, so
+//! the LV2 wrapper applies the mappings restored from the plugin's state without reporting the
+//! changes to the host. It doesn't have an editor to arm parameters from.
+//!
+//! This requires [`Plugin::MIDI_INPUT`] to be set to [`MidiConfig::MidiCCs`], since VST3 plugins
+//! don't receive any MIDI CCs otherwise.
+//!
+//! [`GuiContext::midi_learn()`]: crate::prelude::GuiContext::midi_learn()
+//! [`Plugin::SAMPLE_ACCURATE_AUTOMATION`]: crate::prelude::Plugin::SAMPLE_ACCURATE_AUTOMATION
+//! [`Plugin::MIDI_INPUT`]: crate::prelude::Plugin::MIDI_INPUT
+//! [`MidiConfig::MidiCCs`]: super::MidiConfig::MidiCCs
+
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::NoteEvent;
+use crate::util::permit_alloc;
+use crate::wrapper::state::PluginState;
+
+/// The key used to store the MIDI mappings in [`PluginState::fields`].
+const MIDI_MAPPINGS_FIELD_KEY: &str = "nih_plug_midi_mappings";
+
+/// A MIDI message that can be bound to a parameter. All channel and CC numbers are zero-indexed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MidiLearnSource {
+    /// A MIDI CC on a channel.
+    Cc { channel: u8, cc: u8 },
+    /// Pitch bend on a channel.
+    PitchBend { channel: u8 },
+    /// Channel pressure, or channel aftertouch, on a channel.
+    ChannelPressure { channel: u8 },
+}
+
+/// How a MIDI value is shaped before it's mapped to the parameter's range.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MidiMappingCurve {
+    /// Map the MIDI value linearly.
+    #[default]
+    Linear,
+    /// Raise the MIDI value, in `[0, 1]`, to this power. Values above 1 give more resolution at
+    /// the start of the range, and values below 1 give more resolution at the end of the range.
+    Power(f32),
+}
+
+/// A binding from a MIDI message to a parameter.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MidiMapping {
+    /// The MIDI message controlling the parameter.
+    pub source: MidiLearnSource,
+    /// The ID of the parameter, as used in the plugin's [`Params`][crate::params::Params] struct.
+    pub param_id: String,
+    /// The parameter's normalized value when the MIDI value is at its minimum. This can be larger
+    /// than `max` to invert the mapping.
+    pub min: f32,
+    /// The parameter's normalized value when the MIDI value is at its maximum.
+    pub max: f32,
+    /// The curve applied to the MIDI value before it's mapped to the range.
+    #[serde(default)]
+    pub curve: MidiMappingCurve,
+}
+
+/// A plugin's MIDI learn state and MIDI mappings. This is owned by the wrapper, so the mappings are
+/// kept when the editor is closed and opened again. Access this through
+/// [`GuiContext::midi_learn()`][crate::prelude::GuiContext::midi_learn()].
+#[derive(Debug, Default)]
+pub struct MidiLearn {
+    mappings: RwLock<Vec<MidiMapping>>,
+    /// The ID of the parameter that should be bound to the next MIDI message, if any.
+    armed_param: Mutex<Option<String>>,
+    /// Whether `armed_param` contains a parameter. This allows the audio thread to skip locking
+    /// `armed_param` for every MIDI message.
+    is_armed: AtomicBool,
+}
+
+impl MidiLearnSource {
+    /// Get the source and the value in `[0, 1]` for a note event, if the event is a MIDI CC, pitch
+    /// bend, or channel pressure event. Pitch bend is centered at 0.5.
+    pub fn from_event<S>(event: &NoteEvent<S>) -> Option<(Self, f32)> {
+        match *event {
+            NoteEvent::MidiCC {
+                channel, cc, value, ..
+            } => Some((Self::Cc { channel, cc }, value)),
+            NoteEvent::MidiPitchBend { channel, value, .. } => {
+                Some((Self::PitchBend { channel }, value))
+            }
+            NoteEvent::MidiChannelPressure {
+                channel, pressure, ..
+            } => Some((Self::ChannelPressure { channel }, pressure)),
+            _ => None,
+        }
+    }
+}
+
+impl MidiMapping {
+    /// Map a MIDI message to the parameter's entire range.
+    pub fn new(source: MidiLearnSource, param_id: impl Into<String>) -> Self {
+        Self {
+            source,
+            param_id: param_id.into(),
+            min: 0.0,
+            max: 1.0,
+            curve: MidiMappingCurve::Linear,
+        }
+    }
+
+    /// Only map the MIDI message to part of the parameter's range. These are normalized values,
+    /// and `min` can be larger than `max` to invert the mapping.
+    pub fn with_range(mut self, min: f32, max: f32) -> Self {
+        self.min = min;
+        self.max = max;
+        self
+    }
+
+    /// Shape the MIDI value using a curve before it's mapped to the parameter's range.
+    pub fn with_curve(mut self, curve: MidiMappingCurve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    /// Compute the parameter's normalized value for a MIDI value in `[0, 1]`.
+    pub fn normalized_value(&self, midi_value: f32) -> f32 {
+        let midi_value = midi_value.clamp(0.0, 1.0);
+        let shaped = match self.curve {
+            MidiMappingCurve::Linear => midi_value,
+            MidiMappingCurve::Power(exponent) => midi_value.powf(exponent),
+        };
+
+        (self.min + ((self.max - self.min) * shaped)).clamp(0.0, 1.0)
+    }
+}
+
+impl MidiLearn {
+    /// Bind the next MIDI CC, pitch bend, or channel pressure message the plugin receives to a
+    /// parameter. This replaces any parameter that was armed before. Once the message has been
+    /// received, any existing mapping for the parameter is replaced with a mapping to the
+    /// parameter's entire range.
+    pub fn arm(&self, param_id: &str) {
+        *self.armed_param.lock() = Some(param_id.to_owned());
+        self.is_armed.store(true, Ordering::SeqCst);
+    }
+
+    /// Stop waiting for a MIDI message for the armed parameter, if any.
+    pub fn disarm(&self) {
+        self.is_armed.store(false, Ordering::SeqCst);
+        *self.armed_param.lock() = None;
+    }
+
+    /// The ID of the parameter waiting for a MIDI message, if any. This becomes `None` again after
+    /// the parameter has been bound, so editors can poll this to find out when learning has
+    /// finished.
+    pub fn armed_param(&self) -> Option<String> {
+        self.armed_param.lock().clone()
+    }
+
+    /// All of the plugin's MIDI mappings.
+    pub fn mappings(&self) -> Vec<MidiMapping> {
+        self.mappings.read().clone()
+    }
+
+    /// Get the mapping for a parameter, if it has one.
+    pub fn mapping(&self, param_id: &str) -> Option<MidiMapping> {
+        self.mappings
+            .read()
+            .iter()
+            .find(|mapping| mapping.param_id == param_id)
+            .cloned()
+    }
+
+    /// Add a mapping, or replace the existing mapping for the mapping's parameter. Use this to
+    /// change a learned mapping's range or curve.
+    pub fn set_mapping(&self, mapping: MidiMapping) {
+        Self::insert_mapping(&mut self.mappings.write(), mapping);
+    }
+
+    /// Remove the mapping for a parameter. Returns `false` if the parameter was not mapped.
+    pub fn remove_mapping(&self, param_id: &str) -> bool {
+        let mut mappings = self.mappings.write();
+        let old_len = mappings.len();
+        mappings.retain(|mapping| mapping.param_id != param_id);
+
+        mappings.len() != old_len
+    }
+
+    /// Remove all mappings.
+    pub fn clear(&self) {
+        self.mappings.write().clear();
+    }
+
+    /// Handle an incoming note event on the audio thread. If a parameter is armed and this is a MIDI
+    /// CC, pitch bend, or channel pressure event, then the parameter is bound to the event. `apply`
+    /// is then called with the parameter ID and the new normalized value for every parameter mapped
+    /// to the event.
+    pub(crate) fn handle_event<S>(&self, event: &NoteEvent<S>, mut apply: impl FnMut(&str, f32)) {
+        let (source, value) = match MidiLearnSource::from_event(event) {
+            Some(source_value) => source_value,
+            None => return,
+        };
+
+        if self.is_armed.load(Ordering::SeqCst) {
+            // This allocates, but it only happens once after a parameter has been armed. If the GUI
+            // thread is currently holding either lock, then the next message is used instead.
+            permit_alloc(|| {
+                if let (Some(mut armed_param), Some(mut mappings)) =
+                    (self.armed_param.try_lock(), self.mappings.try_write())
+                {
+                    if let Some(param_id) = armed_param.take() {
+                        self.is_armed.store(false, Ordering::SeqCst);
+                        Self::insert_mapping(&mut mappings, MidiMapping::new(source, param_id));
+                    }
+                }
+            });
+        }
+
+        // The mappings are only written to from the GUI thread, so this should rarely fail
+        if let Some(mappings) = self.mappings.try_read() {
+            for mapping in mappings.iter().filter(|mapping| mapping.source == source) {
+                apply(&mapping.param_id, mapping.normalized_value(value));
+            }
+        }
+    }
+
+    /// Whether a note event is mapped to one or more parameters. Used by the wrappers to decide
+    /// whether the buffer should be split for sample accurate automation.
+    pub(crate) fn is_mapped<S>(&self, event: &NoteEvent<S>) -> bool {
+        match (MidiLearnSource::from_event(event), self.mappings.try_read()) {
+            (Some((source, _)), Some(mappings)) => {
+                mappings.iter().any(|mapping| mapping.source == source)
+            }
+            _ => false,
+        }
+    }
+
+    /// Store the mappings in a state object for the host to save. Nothing is stored if the plugin
+    /// doesn't have any mappings.
+    pub(crate) fn write_to_state(&self, state: &mut PluginState) {
+        let mappings = self.mappings.read();
+        if mappings.is_empty() {
+            return;
+        }
+
+        match serde_json::to_string(&*mappings) {
+            Ok(json) => {
+                state
+                    .fields
+                    .insert(String::from(MIDI_MAPPINGS_FIELD_KEY), json);
+            }
+            Err(err) => nih_debug_assert_failure!("Could not serialize the MIDI mappings: {}", err),
+        }
+    }
+
+    /// Restore the mappings from a state object loaded by the host, and remove them from the state
+    /// so they don't end up in the plugin's persistent fields. Since no mappings are stored for
+    /// plugins without any mappings, the existing mappings are cleared if the state doesn't contain
+    /// any mappings.
+    pub(crate) fn read_from_state(&self, state: &mut PluginState) {
+        let json = match state.fields.remove(MIDI_MAPPINGS_FIELD_KEY) {
+            Some(json) => json,
+            None => {
+                self.mappings.write().clear();
+                return;
+            }
+        };
+
+        match serde_json::from_str(&json) {
+            Ok(mappings) => *self.mappings.write() = mappings,
+            Err(err) => {
+                nih_debug_assert_failure!("Could not deserialize the MIDI mappings: {}", err)
+            }
+        }
+    }
+
+    /// Add a mapping, replacing any existing mapping for the same parameter.
+    fn insert_mapping(mappings: &mut Vec<MidiMapping>, mapping: MidiMapping) {
+        match mappings
+            .iter_mut()
+            .find(|existing| existing.param_id == mapping.param_id)
+        {
+            Some(existing) => *existing = mapping,
+            None => mappings.push(mapping),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cc(cc: u8, value: f32) -> NoteEvent<()> {
+        NoteEvent::MidiCC {
+            timing: 0,
+            channel: 0,
+            cc,
+            value,
+        }
+    }
+
+    fn collect_changes(midi_learn: &MidiLearn, event: &NoteEvent<()>) -> Vec<(String, f32)> {
+        let mut changes = Vec::new();
+        midi_learn.handle_event(event, |param_id, normalized_value| {
+            changes.push((param_id.to_owned(), normalized_value))
+        });
+
+        changes
+    }
+
+    #[test]
+    fn learn_cc() {
+        let midi_learn = MidiLearn::default();
+        midi_learn.arm("gain");
+        assert_eq!(midi_learn.armed_param().as_deref(), Some("gain"));
+
+        // Note events can't be learned, so the parameter stays armed
+        let note_on = NoteEvent::<()>::NoteOn {
+            timing: 0,
+            voice_id: None,
+            channel: 0,
+            note: 60,
+            velocity: 1.0,
+        };
+        assert!(collect_changes(&midi_learn, &note_on).is_empty());
+        assert!(midi_learn.armed_param().is_some());
+
+        // The learned message is also applied immediately
+        assert_eq!(
+            collect_changes(&midi_learn, &cc(7, 0.25)),
+            [(String::from("gain"), 0.25)]
+        );
+        assert_eq!(midi_learn.armed_param(), None);
+        assert_eq!(
+            midi_learn.mapping("gain"),
+            Some(MidiMapping::new(
+                MidiLearnSource::Cc { channel: 0, cc: 7 },
+                "gain"
+            ))
+        );
+
+        assert!(collect_changes(&midi_learn, &cc(8, 0.25)).is_empty());
+        assert!(midi_learn.is_mapped(&cc(7, 0.0)));
+        assert!(!midi_learn.is_mapped(&cc(8, 0.0)));
+    }
+
+    #[test]
+    fn relearn_replaces_mapping() {
+        let midi_learn = MidiLearn::default();
+        midi_learn.arm("gain");
+        collect_changes(&midi_learn, &cc(7, 0.0));
+        midi_learn.arm("gain");
+        collect_changes(&midi_learn, &cc(1, 0.0));
+
+        let mappings = midi_learn.mappings();
+        assert_eq!(mappings.len(), 1);
+        assert_eq!(
+            mappings[0].source,
+            MidiLearnSource::Cc { channel: 0, cc: 1 }
+        );
+
+        assert!(midi_learn.remove_mapping("gain"));
+        assert!(!midi_learn.remove_mapping("gain"));
+        assert!(midi_learn.mappings().is_empty());
+    }
+
+    #[test]
+    fn range_and_curve() {
+        let source = MidiLearnSource::PitchBend { channel: 0 };
+
+        let inverted = MidiMapping::new(source, "cutoff").with_range(0.75, 0.25);
+        approx::assert_relative_eq!(inverted.normalized_value(0.0), 0.75);
+        approx::assert_relative_eq!(inverted.normalized_value(0.5), 0.5);
+        approx::assert_relative_eq!(inverted.normalized_value(1.0), 0.25);
+
+        let squared = MidiMapping::new(source, "cutoff").with_curve(MidiMappingCurve::Power(2.0));
+        approx::assert_relative_eq!(squared.normalized_value(0.5), 0.25);
+        approx::assert_relative_eq!(squared.normalized_value(2.0), 1.0);
+    }
+
+    #[test]
+    fn state_round_trip() {
+        let midi_learn = MidiLearn::default();
+        midi_learn.set_mapping(
+            MidiMapping::new(MidiLearnSource::ChannelPressure { channel: 3 }, "vibrato")
+                .with_range(0.0, 0.5)
+                .with_curve(MidiMappingCurve::Power(0.5)),
+        );
+
+        let mut state = PluginState {
+            version: String::new(),
+            params: Default::default(),
+            fields: Default::default(),
+        };
+        midi_learn.write_to_state(&mut state);
+        assert!(state.fields.contains_key(MIDI_MAPPINGS_FIELD_KEY));
+
+        let restored = MidiLearn::default();
+        restored.read_from_state(&mut state);
+        assert_eq!(restored.mappings(), midi_learn.mappings());
+        assert!(!state.fields.contains_key(MIDI_MAPPINGS_FIELD_KEY));
+
+        // States without mappings were saved by a plugin without any mappings
+        restored.read_from_state(&mut state);
+        assert!(restored.mappings().is_empty());
+    }
+}
@@ -0,0 +1,241 @@
+//! Per-key note names, for drum maps and keyswitches. A plugin returns a shared [`NoteNames`]
+//! object from [`Plugin::note_names()`], and it can then change the names at any point from its
+//! editor or background tasks. The CLAP wrapper exposes these names through the note-name
+//! extension, and the VST3 wrapper exposes them as program pitch names through `IUnitInfo`. Hosts
+//! are asked to rescan the names whenever they change.
+//!
+//! [`Plugin::note_names()`]: crate::prelude::Plugin::note_names()
+
+use parking_lot::{Mutex, RwLock};
+
+/// The name for a single key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoteName {
+    /// The zero-indexed MIDI channel this name applies to, or `None` if the name applies to all
+    /// channels.
+    pub channel: Option<u8>,
+    /// The MIDI note number, in `[0, 127]`.
+    pub note: u8,
+    /// The name the host should display for this key.
+    pub name: String,
+}
+
+/// A plugin's note names. This is shared between the plugin and the wrapper, so names can be
+/// changed at runtime. Modifying the names allocates and takes a lock, so this should not be done
+/// from the audio thread.
+#[derive(Default)]
+pub struct NoteNames {
+    /// The names, sorted by channel and note number. Channel-specific names take precedence over
+    /// names that apply to all channels.
+    names: RwLock<Vec<NoteName>>,
+    /// Called by the wrapper after the names have changed so it can inform the host.
+    on_change: Mutex<Option<Box<dyn Fn() + Send + Sync>>>,
+}
+
+impl std::fmt::Debug for NoteNames {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NoteNames")
+            .field("names", &*self.names.read())
+            .finish_non_exhaustive()
+    }
+}
+
+impl NoteNames {
+    /// Create an empty set of note names.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create note names from `(channel, note, name)` tuples. See [`set_name()`][Self::set_name()].
+    pub fn with_names<'a>(names: impl IntoIterator<Item = (Option<u8>, u8, &'a str)>) -> Self {
+        let note_names = Self::new();
+        note_names.set_names(names);
+
+        note_names
+    }
+
+    /// Replace all names with these `(channel, note, name)` tuples. See
+    /// [`set_name()`][Self::set_name()].
+    pub fn set_names<'a>(&self, names: impl IntoIterator<Item = (Option<u8>, u8, &'a str)>) {
+        {
+            let mut current_names = self.names.write();
+            current_names.clear();
+            for (channel, note, name) in names {
+                insert_name(&mut current_names, channel, note, name);
+            }
+        }
+
+        self.notify_changed();
+    }
+
+    /// Set the name for a key. If `channel` is `None`, then the name applies to every channel that
+    /// doesn't have its own name for this key.
+    pub fn set_name(&self, channel: Option<u8>, note: u8, name: impl Into<String>) {
+        insert_name(&mut self.names.write(), channel, note, name);
+        self.notify_changed();
+    }
+
+    /// Remove the name for a key, if it had one. Names for other channels are not affected.
+    pub fn remove_name(&self, channel: Option<u8>, note: u8) {
+        let removed = {
+            let mut names = self.names.write();
+            match find_name(&names, channel, note) {
+                Ok(idx) => {
+                    names.remove(idx);
+                    true
+                }
+                Err(_) => false,
+            }
+        };
+
+        if removed {
+            self.notify_changed();
+        }
+    }
+
+    /// Remove all names.
+    pub fn clear(&self) {
+        self.set_names([]);
+    }
+
+    /// Get the name for a key on a channel. This falls back to the name that applies to all
+    /// channels if the channel doesn't have a name for this key.
+    pub fn name(&self, channel: u8, note: u8) -> Option<String> {
+        let names = self.names.read();
+        find_name(&names, Some(channel), note)
+            .or_else(|_| find_name(&names, None, note))
+            .ok()
+            .map(|idx| names[idx].name.clone())
+    }
+
+    /// A snapshot of all names, sorted by channel and note number.
+    pub fn names(&self) -> Vec<NoteName> {
+        self.names.read().clone()
+    }
+
+    /// The total number of names, including channel-specific names.
+    pub fn len(&self) -> usize {
+        self.names.read().len()
+    }
+
+    /// Whether there are no names.
+    pub fn is_empty(&self) -> bool {
+        self.names.read().is_empty()
+    }
+
+    /// Get the name at an index in the sorted list of names. Used by the wrappers to enumerate the
+    /// names one at a time.
+    pub(crate) fn get(&self, index: usize) -> Option<NoteName> {
+        self.names.read().get(index).cloned()
+    }
+
+    /// Set the function that's called whenever the names change. The wrappers use this to ask the
+    /// host to rescan the names.
+    pub(crate) fn set_change_callback(&self, callback: impl Fn() + Send + Sync + 'static) {
+        *self.on_change.lock() = Some(Box::new(callback));
+    }
+
+    fn notify_changed(&self) {
+        // The lock on the names has already been released at this point, so the callback can query
+        // the new names
+        if let Some(callback) = &*self.on_change.lock() {
+            callback();
+        }
+    }
+}
+
+/// Find a name's index in the sorted `names`, or the index where it should be inserted.
+fn find_name(names: &[NoteName], channel: Option<u8>, note: u8) -> Result<usize, usize> {
+    names.binary_search_by_key(&(channel, note), |name| (name.channel, name.note))
+}
+
+fn insert_name(names: &mut Vec<NoteName>, channel: Option<u8>, note: u8, name: impl Into<String>) {
+    nih_debug_assert!(note <= 127, "Invalid MIDI note number {}", note);
+    nih_debug_assert!(
+        !matches!(channel, Some(channel) if channel > 15),
+        "Invalid MIDI channel {:?}",
+        channel
+    );
+
+    let name = NoteName {
+        channel,
+        note,
+        name: name.into(),
+    };
+    match find_name(names, channel, note) {
+        Ok(idx) => names[idx] = name,
+        Err(idx) => names.insert(idx, name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn channel_names_take_precedence() {
+        let note_names =
+            NoteNames::with_names([(None, 36, "Kick"), (None, 38, "Snare"), (Some(9), 36, "Bd")]);
+
+        assert_eq!(note_names.name(0, 36).as_deref(), Some("Kick"));
+        assert_eq!(note_names.name(9, 36).as_deref(), Some("Bd"));
+        assert_eq!(note_names.name(9, 38).as_deref(), Some("Snare"));
+        assert_eq!(note_names.name(0, 40), None);
+    }
+
+    #[test]
+    fn names_are_sorted_and_replaced() {
+        let note_names = NoteNames::new();
+        note_names.set_name(Some(1), 10, "c");
+        note_names.set_name(None, 20, "b");
+        note_names.set_name(None, 10, "a");
+        note_names.set_name(None, 20, "B");
+
+        let names: Vec<_> = note_names
+            .names()
+            .into_iter()
+            .map(|name| (name.channel, name.note, name.name))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                (None, 10, String::from("a")),
+                (None, 20, String::from("B")),
+                (Some(1), 10, String::from("c")),
+            ]
+        );
+
+        assert_eq!(note_names.len(), 3);
+        assert_eq!(
+            note_names.get(2).map(|name| name.name).as_deref(),
+            Some("c")
+        );
+
+        note_names.remove_name(None, 20);
+        assert_eq!(note_names.name(0, 20), None);
+        note_names.clear();
+        assert!(note_names.names().is_empty());
+    }
+
+    #[test]
+    fn changes_call_callback() {
+        let note_names = NoteNames::new();
+        let num_changes = Arc::new(AtomicUsize::new(0));
+        note_names.set_change_callback({
+            let num_changes = num_changes.clone();
+            move || {
+                num_changes.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+
+        note_names.set_name(None, 36, "Kick");
+        note_names.set_names([(None, 38, "Snare")]);
+        // Removing a name that doesn't exist doesn't change anything
+        note_names.remove_name(None, 36);
+        assert_eq!(num_changes.load(Ordering::SeqCst), 2);
+        note_names.remove_name(None, 38);
+        assert_eq!(num_changes.load(Ordering::SeqCst), 3);
+    }
+}
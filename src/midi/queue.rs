@@ -0,0 +1,247 @@
+//! A fixed-capacity queue that keeps note events sorted by their timing. This is useful for merging
+//! events from multiple sources, like the host and an on-screen keyboard, and for splitting the
+//! plugin's [`Buffer`] into sub-blocks so events can be handled sample accurately without checking
+//! every event for every sample.
+
+use std::marker::PhantomData;
+
+use super::NoteEvent;
+use crate::buffer::{Block, Buffer};
+
+/// A queue of note events sorted by timing. The queue's capacity is allocated upfront, and
+/// [`push()`][Self::push()] rejects events when the queue is full, so it can be used on the audio
+/// thread.
+///
+/// # Example
+///
+/// ```ignore
+/// self.events.clear();
+/// while let Some(event) = context.next_event() {
+///     let _ = self.events.push(event);
+/// }
+/// while let Some(event) = self.virtual_keyboard.next_event() {
+///     let _ = self.events.push(event);
+/// }
+///
+/// for (block_start, block, events) in self.events.iter_blocks(buffer) {
+///     for event in events {
+///         // Start and stop voices
+///     }
+///
+///     // Render `block.samples()` samples
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct NoteEventQueue<S> {
+    /// The events, sorted by timing. Events with the same timing are kept in insertion order.
+    events: Vec<NoteEvent<S>>,
+    /// The maximum number of events. `Vec::with_capacity()` may allocate more than requested.
+    capacity: usize,
+}
+
+/// An iterator over the blocks of a [`Buffer`], split at the timings of the events in a
+/// [`NoteEventQueue`]. See [`NoteEventQueue::iter_blocks()`]. Yields the block's offset from the
+/// start of the buffer, the block, and the events that should be handled at the start of the
+/// block.
+pub struct EventBlocksIter<'events, 'slice, 'sample: 'slice, S> {
+    /// The raw output buffers.
+    buffers: *mut [&'sample mut [f32]],
+    num_samples: usize,
+    current_block_start: usize,
+    /// The events that have not yet been yielded.
+    events: &'events [NoteEvent<S>],
+    _marker: PhantomData<&'slice mut [&'sample mut [f32]]>,
+}
+
+impl<S> NoteEventQueue<S> {
+    /// Create an empty queue that can hold up to `capacity` events. This is the only function that
+    /// allocates.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            events: Vec::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// The maximum number of events this queue can hold.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of events in the queue.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Whether the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Remove all events from the queue.
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    /// Add an event to the queue. The event is placed after all other events with the same or an
+    /// earlier timing. If the queue is full, then the event is returned back.
+    pub fn push(&mut self, event: NoteEvent<S>) -> Result<(), NoteEvent<S>> {
+        if self.events.len() >= self.capacity {
+            return Err(event);
+        }
+
+        let timing = event.timing();
+        let idx = self
+            .events
+            .partition_point(|other| other.timing() <= timing);
+        self.events.insert(idx, event);
+
+        Ok(())
+    }
+
+    /// The events in the queue, sorted by timing.
+    pub fn events(&self) -> &[NoteEvent<S>] {
+        &self.events
+    }
+
+    /// Split the buffer into blocks at the timings of the events in the queue. Every block starts
+    /// either at the start of the buffer or at an event's timing, and it's yielded together with
+    /// the events at that timing. Events with timings past the end of the buffer are yielded with
+    /// the last block. The events stay in the queue, so call [`clear()`][Self::clear()] before
+    /// adding the next buffer's events.
+    ///
+    /// Unlike [`Buffer::iter_blocks()`], this also yields blocks when the plugin has no audio
+    /// channels. That way MIDI-only plugins can use this to process their events.
+    pub fn iter_blocks<'events, 'slice, 'sample>(
+        &'events self,
+        buffer: &'slice mut Buffer<'sample>,
+    ) -> EventBlocksIter<'events, 'slice, 'sample, S> {
+        EventBlocksIter {
+            num_samples: buffer.samples(),
+            buffers: buffer.raw_slices(),
+            current_block_start: 0,
+            events: &self.events,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'events, 'slice, 'sample, S> Iterator for EventBlocksIter<'events, 'slice, 'sample, S> {
+    type Item = (usize, Block<'slice, 'sample>, &'events [NoteEvent<S>]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.current_block_start >= self.num_samples {
+            return None;
+        }
+
+        // Events at or before the start of this block are handled at the start of the block, and
+        // the block ends at the next event
+        let block_start = self.current_block_start;
+        let num_block_events = self
+            .events
+            .partition_point(|event| event.timing() as usize <= block_start);
+        let block_end = match self.events.get(num_block_events) {
+            Some(event) => (event.timing() as usize).min(self.num_samples),
+            None => self.num_samples,
+        };
+
+        let (block_events, remaining_events) = if block_end == self.num_samples {
+            (self.events, &self.events[self.events.len()..])
+        } else {
+            self.events.split_at(num_block_events)
+        };
+        self.events = remaining_events;
+        self.current_block_start = block_end;
+
+        // SAFETY: The block is in bounds, and blocks yielded by this iterator don't overlap
+        let block = unsafe { Block::from_raw(self.buffers, block_start, block_end) };
+
+        Some((block_start, block, block_events))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note_on(timing: u32, note: u8) -> NoteEvent<()> {
+        NoteEvent::NoteOn {
+            timing,
+            voice_id: None,
+            channel: 0,
+            note,
+            velocity: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_sorted_push() {
+        let mut queue = NoteEventQueue::with_capacity(3);
+        assert_eq!(queue.push(note_on(20, 1)), Ok(()));
+        assert_eq!(queue.push(note_on(10, 2)), Ok(()));
+        assert_eq!(queue.push(note_on(20, 3)), Ok(()));
+        assert_eq!(queue.push(note_on(0, 4)), Err(note_on(0, 4)));
+
+        assert_eq!(
+            queue.events(),
+            [note_on(10, 2), note_on(20, 1), note_on(20, 3)]
+        );
+    }
+
+    #[test]
+    fn test_iter_blocks() {
+        let mut queue = NoteEventQueue::with_capacity(8);
+        for event in [
+            note_on(30, 1),
+            note_on(0, 2),
+            note_on(30, 3),
+            note_on(80, 4),
+        ] {
+            queue.push(event).unwrap();
+        }
+
+        let mut real_buffers = vec![vec![0.0; 64]; 2];
+        let mut buffer = Buffer::default();
+        unsafe {
+            buffer.set_slices(64, |output_slices| {
+                let (first_channel, other_channels) = real_buffers.split_at_mut(1);
+                *output_slices = vec![&mut first_channel[0], &mut other_channels[0]];
+            })
+        };
+
+        let mut blocks = Vec::new();
+        for (block_start, mut block, events) in queue.iter_blocks(&mut buffer) {
+            for channel in block.iter_mut() {
+                channel.fill(block_start as f32);
+            }
+
+            blocks.push((block_start, block.samples(), events.to_vec()));
+        }
+
+        // The event past the end of the buffer is included with the last block
+        assert_eq!(
+            blocks,
+            [
+                (0, 30, vec![note_on(0, 2)]),
+                (30, 34, vec![note_on(30, 1), note_on(30, 3), note_on(80, 4)]),
+            ]
+        );
+        assert_eq!(real_buffers[1][29], 0.0);
+        assert_eq!(real_buffers[1][30], 30.0);
+    }
+
+    #[test]
+    fn test_iter_blocks_without_channels() {
+        let mut queue = NoteEventQueue::with_capacity(8);
+        queue.push(note_on(10, 1)).unwrap();
+
+        let mut buffer = Buffer::default();
+        unsafe { buffer.set_slices(16, |_| ()) };
+
+        let blocks: Vec<_> = queue
+            .iter_blocks(&mut buffer)
+            .map(|(block_start, block, events)| (block_start, block.samples(), events.len()))
+            .collect();
+        assert_eq!(blocks, [(0, 10, 0), (10, 6, 1)]);
+    }
+}
@@ -0,0 +1,808 @@
+//! Reading and writing Standard MIDI Files. This is meant for feeding note sequences to a plugin
+//! in offline tests and renders, and for capturing a plugin's MIDI output.
+//!
+//! [`MidiFile`] parses Type 0 and Type 1 files, merging the events from all tracks. The file's
+//! tempo map is used to convert the events' tick positions to sample positions with
+//! [`MidiFile::to_samples()`], and [`events_in_block()`] can then be used to get the events for a
+//! single block with timings relative to that block. [`SmfWriter`] records the events a plugin
+//! outputs through [`ProcessContext::send_event()`] and writes them to a Type 0 file.
+//!
+//! [`ProcessContext::send_event()`]: crate::prelude::ProcessContext::send_event()
+
+use anyhow::{Context, Result};
+use std::borrow::Borrow;
+use std::fs;
+use std::path::Path;
+
+use super::sysex::SysExMessage;
+use super::{MidiResult, NoteEvent};
+
+/// The tempo used until the first tempo event, in microseconds per quarter note. This corresponds
+/// to 120 BPM.
+const DEFAULT_TEMPO: u32 = 500_000;
+
+/// How a file's tick positions relate to time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmfTiming {
+    /// Ticks are a subdivision of a quarter note. The length of a quarter note is determined by the
+    /// file's tempo map.
+    Metrical { ticks_per_quarter_note: u16 },
+    /// Ticks are a subdivision of an SMPTE frame. Tempo events don't affect these files.
+    Timecode {
+        /// The frame rate, either 24, 25, 29 (for 29.97 drop frame), or 30.
+        frames_per_second: u8,
+        ticks_per_frame: u8,
+    },
+}
+
+/// A parsed Standard MIDI File.
+#[derive(Debug, Clone)]
+pub struct MidiFile<S> {
+    pub timing: SmfTiming,
+    pub tempo_map: TempoMap,
+    /// The note events from all tracks, sorted by their tick positions. Events at the same tick
+    /// keep the order from the file, with events from earlier tracks coming first. The events'
+    /// timings are set to zero, use [`to_samples()`][Self::to_samples()] to get events with sample
+    /// timings.
+    pub events: Vec<SmfEvent<S>>,
+}
+
+/// A note event at a tick position in a [`MidiFile`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmfEvent<S> {
+    pub tick: u64,
+    pub event: NoteEvent<S>,
+}
+
+/// Converts tick positions to time based on a file's tempo changes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoMap {
+    /// The tempo segments, sorted by tick. There's always at least one segment starting at tick 0.
+    segments: Vec<TempoSegment>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TempoSegment {
+    /// The tick this segment starts at.
+    tick: u64,
+    /// The time in seconds at the start of this segment.
+    seconds: f64,
+    /// The tempo in microseconds per quarter note. Only used to report the tempo.
+    tempo: u32,
+    seconds_per_tick: f64,
+}
+
+/// Records note events output by a plugin and writes them to a Type 0 Standard MIDI File. Events
+/// are converted to MIDI using [`NoteEvent::as_midi()`], and events without a MIDI equivalent are
+/// skipped.
+#[derive(Debug, Clone)]
+pub struct SmfWriter {
+    sample_rate: f32,
+    /// The file's tempo in beats per minute. This is written to the file as a tempo event and it's
+    /// used to convert the sample positions to ticks.
+    tempo: f64,
+    ticks_per_quarter_note: u16,
+    /// The recorded MIDI messages and their absolute sample positions, in the order they were
+    /// recorded.
+    messages: Vec<(u64, RecordedMessage)>,
+}
+
+#[derive(Debug, Clone)]
+enum RecordedMessage {
+    /// A channel message, without any padding.
+    Channel(Vec<u8>),
+    /// A SysEx message, including the `0xf0` start and `0xf7` end bytes.
+    SysEx(Vec<u8>),
+}
+
+impl<S: SysExMessage> MidiFile<S> {
+    /// Parse a Standard MIDI File. Supports Type 0 and Type 1 files. Channel messages and SysEx
+    /// messages are parsed with [`NoteEvent::from_midi()`], and messages that can't be converted to
+    /// a note event are skipped. Tempo events are used to build the file's [`TempoMap`], and all
+    /// other meta events are ignored.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(bytes);
+        let (chunk_type, header) = reader.chunk().context("Could not read the file's header")?;
+        anyhow::ensure!(chunk_type == *b"MThd", "Not a Standard MIDI File");
+        anyhow::ensure!(header.len() >= 6, "The file's header is too short");
+
+        let format = u16::from_be_bytes([header[0], header[1]]);
+        let num_tracks = u16::from_be_bytes([header[2], header[3]]);
+        let division = u16::from_be_bytes([header[4], header[5]]);
+        anyhow::ensure!(
+            format <= 1,
+            "Type {format} files are not supported, only Type 0 and Type 1 files are"
+        );
+
+        let timing = if division & 0x8000 == 0 {
+            anyhow::ensure!(division > 0, "The file has zero ticks per quarter note");
+            SmfTiming::Metrical {
+                ticks_per_quarter_note: division,
+            }
+        } else {
+            // The upper byte is the negative frame rate in two's complement
+            let frames_per_second = ((division >> 8) as u8 as i8).unsigned_abs();
+            let ticks_per_frame = (division & 0xff) as u8;
+            anyhow::ensure!(
+                matches!(frames_per_second, 24 | 25 | 29 | 30),
+                "Unsupported SMPTE frame rate of {frames_per_second} frames per second"
+            );
+            anyhow::ensure!(
+                ticks_per_frame > 0,
+                "The file has zero ticks per SMPTE frame"
+            );
+            SmfTiming::Timecode {
+                frames_per_second,
+                ticks_per_frame,
+            }
+        };
+
+        let mut events = Vec::new();
+        let mut tempo_changes = Vec::new();
+        let mut tracks_read = 0;
+        while tracks_read < num_tracks && !reader.is_empty() {
+            let (chunk_type, track) = reader
+                .chunk()
+                .with_context(|| format!("Could not read track {tracks_read}"))?;
+            // Unknown chunk types should be skipped
+            if chunk_type != *b"MTrk" {
+                continue;
+            }
+
+            read_track(track, &mut events, &mut tempo_changes)
+                .with_context(|| format!("Could not parse track {tracks_read}"))?;
+            tracks_read += 1;
+        }
+
+        // This sort is stable, so simultaneous events keep their order
+        events.sort_by_key(|event: &SmfEvent<S>| event.tick);
+
+        Ok(Self {
+            timing,
+            tempo_map: TempoMap::new(timing, &tempo_changes),
+            events,
+        })
+    }
+
+    /// Read and parse a Standard MIDI File. See [`from_bytes()`][Self::from_bytes()].
+    pub fn read(path: &Path) -> Result<Self> {
+        let bytes =
+            fs::read(path).with_context(|| format!("Could not read '{}'", path.display()))?;
+        Self::from_bytes(&bytes).with_context(|| format!("Could not parse '{}'", path.display()))
+    }
+
+    /// Get the file's events with their timings set to absolute sample positions at the given
+    /// sample rate. Sample positions past `u32::MAX` are clamped. Use [`events_in_block()`] to get
+    /// the events for a single block.
+    pub fn to_samples(&self, sample_rate: f32) -> Vec<NoteEvent<S>> {
+        self.events
+            .iter()
+            .map(|event| {
+                let sample = self.tempo_map.tick_to_samples(event.tick, sample_rate);
+                let mut event = event.event.clone();
+                event.set_timing(sample.min(u32::MAX as u64) as u32);
+
+                event
+            })
+            .collect()
+    }
+
+    /// The time in seconds of the last event in the file.
+    pub fn duration_seconds(&self) -> f64 {
+        self.events
+            .last()
+            .map(|event| self.tempo_map.tick_to_seconds(event.tick))
+            .unwrap_or(0.0)
+    }
+}
+
+/// Get the events from `events` that fall within the block starting at sample `block_start` with
+/// length `block_len`. `events` should contain events with absolute sample timings sorted by those
+/// timings, like the events returned by [`MidiFile::to_samples()`]. The returned events' timings
+/// are relative to the start of the block.
+pub fn events_in_block<S: SysExMessage>(
+    events: &[NoteEvent<S>],
+    block_start: u32,
+    block_len: u32,
+) -> impl Iterator<Item = NoteEvent<S>> + '_ {
+    let block_end = block_start.saturating_add(block_len);
+    let start_idx = events.partition_point(|event| event.timing() < block_start);
+    let end_idx = events.partition_point(|event| event.timing() < block_end);
+
+    events[start_idx..end_idx].iter().map(move |event| {
+        let mut event = event.clone();
+        event.subtract_timing(block_start);
+        event
+    })
+}
+
+impl TempoMap {
+    /// Build a tempo map from the tempo changes in a file, in microseconds per quarter note. Tempo
+    /// changes are ignored for SMPTE timing.
+    fn new(timing: SmfTiming, tempo_changes: &[(u64, u32)]) -> Self {
+        match timing {
+            SmfTiming::Metrical {
+                ticks_per_quarter_note,
+            } => {
+                let seconds_per_tick =
+                    |tempo: u32| tempo as f64 / 1_000_000.0 / ticks_per_quarter_note as f64;
+
+                let mut tempo_changes = tempo_changes.to_vec();
+                tempo_changes.sort_by_key(|(tick, _)| *tick);
+
+                let mut segments = vec![TempoSegment {
+                    tick: 0,
+                    seconds: 0.0,
+                    tempo: DEFAULT_TEMPO,
+                    seconds_per_tick: seconds_per_tick(DEFAULT_TEMPO),
+                }];
+                for (tick, tempo) in tempo_changes {
+                    let last_segment = segments.last_mut().unwrap();
+                    // Multiple tempo changes at the same tick replace each other
+                    if last_segment.tick == tick {
+                        last_segment.tempo = tempo;
+                        last_segment.seconds_per_tick = seconds_per_tick(tempo);
+                        continue;
+                    }
+
+                    let seconds = last_segment.seconds
+                        + (tick - last_segment.tick) as f64 * last_segment.seconds_per_tick;
+                    segments.push(TempoSegment {
+                        tick,
+                        seconds,
+                        tempo,
+                        seconds_per_tick: seconds_per_tick(tempo),
+                    });
+                }
+
+                Self { segments }
+            }
+            SmfTiming::Timecode {
+                frames_per_second,
+                ticks_per_frame,
+            } => {
+                // 29 frames per second means 29.97 drop frame
+                let frames_per_second = match frames_per_second {
+                    29 => 30_000.0 / 1001.0,
+                    fps => fps as f64,
+                };
+
+                Self {
+                    segments: vec![TempoSegment {
+                        tick: 0,
+                        seconds: 0.0,
+                        tempo: DEFAULT_TEMPO,
+                        seconds_per_tick: 1.0 / frames_per_second / ticks_per_frame as f64,
+                    }],
+                }
+            }
+        }
+    }
+
+    /// Convert a tick position to a time in seconds.
+    pub fn tick_to_seconds(&self, tick: u64) -> f64 {
+        let segment = self.segment_at(tick);
+
+        segment.seconds + (tick - segment.tick) as f64 * segment.seconds_per_tick
+    }
+
+    /// Convert a tick position to a sample position at the given sample rate.
+    pub fn tick_to_samples(&self, tick: u64, sample_rate: f32) -> u64 {
+        (self.tick_to_seconds(tick) * sample_rate as f64).round() as u64
+    }
+
+    /// The tempo in beats per minute at a tick position. Always returns 120 BPM for files using
+    /// SMPTE timing.
+    pub fn tempo_at(&self, tick: u64) -> f64 {
+        60_000_000.0 / self.segment_at(tick).tempo as f64
+    }
+
+    fn segment_at(&self, tick: u64) -> &TempoSegment {
+        let idx = self
+            .segments
+            .partition_point(|segment| segment.tick <= tick);
+
+        // The first segment always starts at tick 0
+        &self.segments[idx.saturating_sub(1)]
+    }
+}
+
+impl SmfWriter {
+    /// Create a writer for a plugin running at `sample_rate`, writing a file with a constant tempo
+    /// in beats per minute. The file uses 960 ticks per quarter note by default.
+    pub fn new(sample_rate: f32, tempo: f64) -> Self {
+        Self {
+            sample_rate,
+            tempo,
+            ticks_per_quarter_note: 960,
+            messages: Vec::new(),
+        }
+    }
+
+    /// Use a different number of ticks per quarter note.
+    pub fn with_ticks_per_quarter_note(mut self, ticks_per_quarter_note: u16) -> Self {
+        nih_debug_assert!(ticks_per_quarter_note > 0 && ticks_per_quarter_note & 0x8000 == 0);
+        self.ticks_per_quarter_note = ticks_per_quarter_note;
+        self
+    }
+
+    /// Record an event output by the plugin during the block starting at sample `block_start`. The
+    /// event's timing is relative to the start of the block.
+    pub fn record<S: SysExMessage>(&mut self, block_start: u64, event: NoteEvent<S>) {
+        let sample = block_start + event.timing() as u64;
+        match event.as_midi() {
+            Some(MidiResult::Basic(midi_data)) => {
+                self.messages.push((
+                    sample,
+                    RecordedMessage::Channel(
+                        midi_data[..channel_message_len(midi_data[0])].to_vec(),
+                    ),
+                ));
+            }
+            Some(MidiResult::Sequence(sequence, length)) => {
+                for midi_data in &sequence[..length] {
+                    self.messages
+                        .push((sample, RecordedMessage::Channel(midi_data.to_vec())));
+                }
+            }
+            Some(MidiResult::SysEx(padded_sysex_buffer, length)) => {
+                let padded_sysex_buffer = padded_sysex_buffer.borrow();
+                nih_debug_assert!(length <= padded_sysex_buffer.len());
+                self.messages.push((
+                    sample,
+                    RecordedMessage::SysEx(padded_sysex_buffer[..length].to_vec()),
+                ));
+            }
+            // Standard MIDI Files can't contain system real time messages, and the song position
+            // and timecode are implied by the events' positions
+            Some(MidiResult::System(_, _)) | None => (),
+        }
+    }
+
+    /// Record all events output by the plugin during the block starting at sample `block_start`.
+    pub fn record_all<S: SysExMessage>(
+        &mut self,
+        block_start: u64,
+        events: impl IntoIterator<Item = NoteEvent<S>>,
+    ) {
+        for event in events {
+            self.record(block_start, event);
+        }
+    }
+
+    /// Serialize the recorded events to a Type 0 Standard MIDI File.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let ticks_per_second = self.tempo / 60.0 * self.ticks_per_quarter_note as f64;
+        let sample_to_tick = |sample: u64| {
+            (sample as f64 / self.sample_rate as f64 * ticks_per_second).round() as u64
+        };
+
+        let mut messages: Vec<_> = self.messages.iter().collect();
+        messages.sort_by_key(|(sample, _)| *sample);
+
+        let mut track = Vec::new();
+        let tempo = (60_000_000.0 / self.tempo)
+            .round()
+            .clamp(1.0, 0xffffff as f64) as u32;
+        write_variable_length(&mut track, 0);
+        track.extend_from_slice(&[0xff, 0x51, 0x03]);
+        track.extend_from_slice(&tempo.to_be_bytes()[1..]);
+
+        let mut last_tick = 0;
+        for (sample, message) in messages {
+            let tick = sample_to_tick(*sample);
+            write_variable_length(&mut track, (tick - last_tick) as u32);
+            last_tick = tick;
+
+            match message {
+                RecordedMessage::Channel(midi_data) => track.extend_from_slice(midi_data),
+                // SysEx events don't include the start byte in their length. Messages that don't
+                // start with a SysEx start byte are written as escaped events instead.
+                RecordedMessage::SysEx(sysex) => match sysex.split_first() {
+                    Some((0xf0, data)) => {
+                        track.push(0xf0);
+                        write_variable_length(&mut track, data.len() as u32);
+                        track.extend_from_slice(data);
+                    }
+                    _ => {
+                        track.push(0xf7);
+                        write_variable_length(&mut track, sysex.len() as u32);
+                        track.extend_from_slice(sysex);
+                    }
+                },
+            }
+        }
+
+        write_variable_length(&mut track, 0);
+        track.extend_from_slice(&[0xff, 0x2f, 0x00]);
+
+        let mut bytes = Vec::with_capacity(track.len() + 22);
+        bytes.extend_from_slice(b"MThd");
+        bytes.extend_from_slice(&6u32.to_be_bytes());
+        bytes.extend_from_slice(&0u16.to_be_bytes());
+        bytes.extend_from_slice(&1u16.to_be_bytes());
+        bytes.extend_from_slice(&self.ticks_per_quarter_note.to_be_bytes());
+        bytes.extend_from_slice(b"MTrk");
+        bytes.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&track);
+
+        bytes
+    }
+
+    /// Write the recorded events to a Type 0 Standard MIDI File.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_bytes())
+            .with_context(|| format!("Could not write '{}'", path.display()))
+    }
+}
+
+/// Parse a single track chunk, adding its events to `events` and its tempo changes to
+/// `tempo_changes`.
+fn read_track<S: SysExMessage>(
+    track: &[u8],
+    events: &mut Vec<SmfEvent<S>>,
+    tempo_changes: &mut Vec<(u64, u32)>,
+) -> Result<()> {
+    let mut reader = Reader::new(track);
+    let mut tick = 0u64;
+    let mut running_status = None;
+    while !reader.is_empty() {
+        tick += reader.variable_length()? as u64;
+
+        let status = match reader.peek()? {
+            status if status & 0x80 != 0 => {
+                reader.skip(1)?;
+                status
+            }
+            // Running status reuses the last channel message's status byte
+            _ => running_status.context("Data byte without a status byte")?,
+        };
+
+        match status {
+            0xff => {
+                running_status = None;
+                let meta_type = reader.byte()?;
+                let length = reader.variable_length()? as usize;
+                let data = reader.bytes(length)?;
+                match (meta_type, data) {
+                    // End of track
+                    (0x2f, _) => break,
+                    (0x51, [a, b, c]) => {
+                        tempo_changes.push((tick, u32::from_be_bytes([0, *a, *b, *c])));
+                    }
+                    _ => (),
+                }
+            }
+            0xf0 | 0xf7 => {
+                running_status = None;
+                let length = reader.variable_length()? as usize;
+                let data = reader.bytes(length)?;
+
+                // The SysEx start byte is not included in the data, while escaped events contain
+                // the raw bytes
+                let message = if status == 0xf0 {
+                    [&[0xf0], data].concat()
+                } else {
+                    data.to_vec()
+                };
+                if let Ok(event) = NoteEvent::from_midi(0, &message) {
+                    events.push(SmfEvent { tick, event });
+                }
+            }
+            0x80..=0xef => {
+                running_status = Some(status);
+                let data = reader.bytes(channel_message_len(status) - 1)?;
+
+                let mut midi_data = [status, 0, 0];
+                midi_data[1..1 + data.len()].copy_from_slice(data);
+                if let Ok(event) = NoteEvent::from_midi(0, &midi_data[..1 + data.len()]) {
+                    events.push(SmfEvent { tick, event });
+                }
+            }
+            status => anyhow::bail!("Unexpected status byte {status:#x}"),
+        }
+    }
+
+    Ok(())
+}
+
+/// The length of a channel message with the given status byte, including the status byte.
+fn channel_message_len(status: u8) -> usize {
+    match status & 0xf0 {
+        0xc0 | 0xd0 => 2,
+        _ => 3,
+    }
+}
+
+fn write_variable_length(bytes: &mut Vec<u8>, mut value: u32) {
+    let mut buffer = [0u8; 5];
+    let mut idx = buffer.len() - 1;
+    buffer[idx] = (value & 0x7f) as u8;
+    value >>= 7;
+    while value > 0 {
+        idx -= 1;
+        buffer[idx] = (value & 0x7f) as u8 | 0x80;
+        value >>= 7;
+    }
+
+    bytes.extend_from_slice(&buffer[idx..]);
+}
+
+/// A cursor for reading big-endian data from a byte slice.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+
+    fn peek(&self) -> Result<u8> {
+        self.bytes
+            .get(self.position)
+            .copied()
+            .context("Unexpected end of data")
+    }
+
+    fn skip(&mut self, length: usize) -> Result<()> {
+        self.bytes(length).map(|_| ())
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        let byte = self.peek()?;
+        self.position += 1;
+
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position.saturating_add(length))
+            .context("Unexpected end of data")?;
+        self.position += length;
+
+        Ok(bytes)
+    }
+
+    fn variable_length(&mut self) -> Result<u32> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let byte = self.byte()?;
+            value = (value << 7) | (byte & 0x7f) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        anyhow::bail!("Variable length quantity is too long")
+    }
+
+    /// Read a chunk's type and its contents.
+    fn chunk(&mut self) -> Result<([u8; 4], &'a [u8])> {
+        let chunk_type = self.bytes(4)?;
+        let length = self.bytes(4)?;
+        let length = u32::from_be_bytes([length[0], length[1], length[2], length[3]]);
+
+        Ok((
+            [chunk_type[0], chunk_type[1], chunk_type[2], chunk_type[3]],
+            self.bytes(length as usize)?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note_on(timing: u32, note: u8) -> NoteEvent<()> {
+        NoteEvent::NoteOn {
+            timing,
+            voice_id: None,
+            channel: 0,
+            note,
+            velocity: 100.0 / 127.0,
+        }
+    }
+
+    fn note_off(timing: u32, note: u8) -> NoteEvent<()> {
+        NoteEvent::NoteOff {
+            timing,
+            voice_id: None,
+            channel: 0,
+            note,
+            velocity: 0.0,
+        }
+    }
+
+    /// Build a file from a header and a list of track contents, without the end of track events.
+    fn build_file(format: u16, division: u16, tracks: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"MThd");
+        bytes.extend_from_slice(&6u32.to_be_bytes());
+        bytes.extend_from_slice(&format.to_be_bytes());
+        bytes.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(&division.to_be_bytes());
+        for track in tracks {
+            bytes.extend_from_slice(b"MTrk");
+            bytes.extend_from_slice(&(track.len() as u32 + 4).to_be_bytes());
+            bytes.extend_from_slice(track);
+            bytes.extend_from_slice(&[0x00, 0xff, 0x2f, 0x00]);
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn test_variable_length() {
+        for value in [0, 0x40, 0x7f, 0x80, 0x2000, 0x3fff, 0x4000, 0x0fff_ffff] {
+            let mut bytes = Vec::new();
+            write_variable_length(&mut bytes, value);
+
+            assert_eq!(Reader::new(&bytes).variable_length().unwrap(), value);
+        }
+
+        let mut bytes = Vec::new();
+        write_variable_length(&mut bytes, 0x80);
+        assert_eq!(bytes, [0x81, 0x00]);
+    }
+
+    #[test]
+    fn test_parse_running_status() {
+        // Two note ons at tick 0 and 96 using running status, and a note off using a note on with
+        // zero velocity
+        let file = build_file(
+            0,
+            96,
+            &[&[
+                0x00, 0x90, 60, 100, 0x60, 64, 100, 0x60, 60, 0, 0x00, 0xc0, 5,
+            ]],
+        );
+        let file = MidiFile::<()>::from_bytes(&file).unwrap();
+
+        assert_eq!(
+            file.timing,
+            SmfTiming::Metrical {
+                ticks_per_quarter_note: 96
+            }
+        );
+        assert_eq!(
+            file.events,
+            [
+                SmfEvent {
+                    tick: 0,
+                    event: note_on(0, 60),
+                },
+                SmfEvent {
+                    tick: 96,
+                    event: note_on(0, 64),
+                },
+                SmfEvent {
+                    tick: 192,
+                    event: note_off(0, 60),
+                },
+                SmfEvent {
+                    tick: 192,
+                    event: NoteEvent::MidiProgramChange {
+                        timing: 0,
+                        channel: 0,
+                        program: 5,
+                    },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_type_1_tempo_map() {
+        // The first track contains the tempo map, with 120 BPM until the second quarter note and
+        // 60 BPM after that
+        let tempo_track: &[u8] = &[
+            0x00, 0xff, 0x51, 0x03, 0x07, 0xa1, 0x20, 0x81, 0x40, 0xff, 0x51, 0x03, 0x0f, 0x42,
+            0x40,
+        ];
+        let note_track: &[u8] = &[0x00, 0x90, 60, 100, 0x83, 0x00, 0x90, 64, 100];
+        let file = build_file(1, 96, &[tempo_track, note_track]);
+        let file = MidiFile::<()>::from_bytes(&file).unwrap();
+
+        assert_eq!(file.tempo_map.tempo_at(0), 120.0);
+        assert_eq!(file.tempo_map.tempo_at(192), 60.0);
+        assert_eq!(file.tempo_map.tick_to_seconds(192), 1.0);
+        assert_eq!(file.tempo_map.tick_to_seconds(384), 3.0);
+        assert_eq!(file.duration_seconds(), 3.0);
+
+        assert_eq!(
+            file.to_samples(48000.0),
+            [note_on(0, 60), note_on(144000, 64)]
+        );
+    }
+
+    #[test]
+    fn test_smpte_timing() {
+        // 25 frames per second with 40 ticks per frame, so 1000 ticks per second
+        let file = build_file(0, 0xe728, &[&[0x87, 0x68, 0x90, 60, 100]]);
+        let file = MidiFile::<()>::from_bytes(&file).unwrap();
+
+        assert_eq!(
+            file.timing,
+            SmfTiming::Timecode {
+                frames_per_second: 25,
+                ticks_per_frame: 40
+            }
+        );
+        assert_eq!(file.to_samples(44100.0), [note_on(44100, 60)]);
+    }
+
+    #[test]
+    fn test_events_in_block() {
+        let events = [note_on(10, 60), note_on(64, 62), note_off(100, 60)];
+
+        assert_eq!(
+            events_in_block(&events, 0, 64).collect::<Vec<_>>(),
+            [note_on(10, 60)]
+        );
+        assert_eq!(
+            events_in_block(&events, 64, 64).collect::<Vec<_>>(),
+            [note_on(0, 62), note_off(36, 60)]
+        );
+        assert_eq!(events_in_block(&events, 128, 64).count(), 0);
+    }
+
+    #[test]
+    fn test_writer_roundtrip() {
+        let mut writer = SmfWriter::new(48000.0, 120.0);
+        writer.record_all(0, [note_on(0, 60), note_off(24000, 60)]);
+        writer.record(
+            48000,
+            NoteEvent::<()>::MidiChannelPressure {
+                timing: 0,
+                channel: 1,
+                pressure: 1.0,
+            },
+        );
+        // Events without a MIDI equivalent are skipped
+        writer.record(
+            48000,
+            NoteEvent::<()>::Choke {
+                timing: 0,
+                voice_id: None,
+                channel: 0,
+                note: 60,
+            },
+        );
+
+        let file = MidiFile::<()>::from_bytes(&writer.to_bytes()).unwrap();
+        assert_eq!(file.tempo_map.tempo_at(0), 120.0);
+        assert_eq!(
+            file.to_samples(48000.0),
+            [
+                note_on(0, 60),
+                note_off(24000, 60),
+                NoteEvent::MidiChannelPressure {
+                    timing: 48000,
+                    channel: 1,
+                    pressure: 1.0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        assert!(MidiFile::<()>::from_bytes(b"RIFF").is_err());
+        assert!(MidiFile::<()>::from_bytes(&build_file(2, 96, &[])).is_err());
+        // SMPTE timing with invalid frame rates of 128 and 23 frames per second
+        assert!(MidiFile::<()>::from_bytes(&build_file(0, 0x8028, &[])).is_err());
+        assert!(MidiFile::<()>::from_bytes(&build_file(0, 0xe928, &[])).is_err());
+        // SMPTE timing without any ticks per frame
+        assert!(MidiFile::<()>::from_bytes(&build_file(0, 0xe700, &[])).is_err());
+        // Data byte without a running status
+        assert!(MidiFile::<()>::from_bytes(&build_file(0, 96, &[&[0x00, 60, 100]])).is_err());
+    }
+}
@@ -1,51 +1,733 @@
-//! Traits for working with MIDI SysEx data.
-
-use std::borrow::{Borrow, BorrowMut};
-use std::fmt::Debug;
-
-/// A type that can be converted to and from byte buffers containing MIDI SysEx messages.
-///
-/// # SysEx buffers
-///
-/// For maximum flexibility this trait works with RAW MIDI messages. This means that status bytes
-/// and end of SysEx (EOX) bytes are included in the input, and should also be included in the
-/// output. A consequence of this is that it is also possible to support system common and system
-/// real time messages as needed, as long as the plugin API supports those.
-///
-/// For example, the message to turn general MIDI mode on is `[0xf0, 0x7e, 0x7f, 0x09, 0x01, 0xf7]`,
-/// and has a length of 6 bytes. Note that this includes the `0xf0` start byte and `0xf7` end byte.
-pub trait SysExMessage: Debug + Clone + PartialEq + Send + Sync {
-    /// The byte array buffer the messages are read from and serialized to. Should be a `[u8; N]`,
-    /// where `N` is the maximum supported message length in bytes. This covers the full message,
-    /// see the trait's docstring for more information.
-    ///
-    /// Ideally this could just be a const generic but Rust doesn't let you use those as array
-    /// lengths just yet.
-    ///
-    /// <https://github.com/rust-lang/rust/issues/60551>
-    type Buffer: Borrow<[u8]> + BorrowMut<[u8]>;
-
-    /// Read a SysEx message from `buffer` and convert it to this message type if supported. This
-    /// covers the full message, see the trait's docstring for more information. `buffer`'s length
-    /// matches the received message. It is not padded to match [`Buffer`][Self::Buffer].
-    fn from_buffer(buffer: &[u8]) -> Option<Self>;
-
-    /// Serialize this message object as a SysEx message in a byte buffer. This returns a buffer
-    /// alongside the message's length in bytes. The buffer may contain padding at the end. This
-    /// should contain the full message including headers and the EOX byte, see the trait's
-    /// docstring for more information.
-    fn to_buffer(self) -> (Self::Buffer, usize);
-}
-
-/// A default implementation plugins that don't need SysEx support can use.
-impl SysExMessage for () {
-    type Buffer = [u8; 0];
-
-    fn from_buffer(_buffer: &[u8]) -> Option<Self> {
-        None
-    }
-
-    fn to_buffer(self) -> (Self::Buffer, usize) {
-        ([], 0)
-    }
-}
+//! Traits and ready-made message types for working with MIDI SysEx data.
+
+use std::borrow::{Borrow, BorrowMut};
+use std::fmt::Debug;
+
+/// Derive the [`SysExMessage`] trait for manufacturer-specific message enums. See
+/// [`SysExField`] for more information.
+pub use nih_plug_derive::SysExMessage;
+
+/// The device ID that addresses every device in a universal SysEx message.
+pub const ALL_DEVICES: u8 = 0x7f;
+
+/// The maximum number of note changes in a single [`UniversalSysEx::MtsNoteChange`] message. This
+/// keeps the message within the same buffer size as a bulk tuning dump.
+pub const MAX_MTS_NOTE_CHANGES: usize = 100;
+
+/// The size of [`UniversalSysEx`]'s buffer. This is the size of a MTS bulk tuning dump, the largest
+/// supported message.
+const UNIVERSAL_SYSEX_BUFFER_SIZE: usize = 408;
+
+const SYSEX_START: u8 = 0xf0;
+const SYSEX_END: u8 = 0xf7;
+const UNIVERSAL_NON_REALTIME: u8 = 0x7e;
+const UNIVERSAL_REALTIME: u8 = 0x7f;
+
+/// A type that can be converted to and from byte buffers containing MIDI SysEx messages.
+///
+/// # SysEx buffers
+///
+/// For maximum flexibility this trait works with RAW MIDI messages. This means that status bytes
+/// and end of SysEx (EOX) bytes are included in the input, and should also be included in the
+/// output. A consequence of this is that it is also possible to support system common and system
+/// real time messages as needed, as long as the plugin API supports those.
+///
+/// For example, the message to turn general MIDI mode on is `[0xf0, 0x7e, 0x7f, 0x09, 0x01, 0xf7]`,
+/// and has a length of 6 bytes. Note that this includes the `0xf0` start byte and `0xf7` end byte.
+pub trait SysExMessage: Debug + Clone + PartialEq + Send + Sync {
+    /// The byte array buffer the messages are read from and serialized to. Should be a `[u8; N]`,
+    /// where `N` is the maximum supported message length in bytes. This covers the full message,
+    /// see the trait's docstring for more information.
+    ///
+    /// Ideally this could just be a const generic but Rust doesn't let you use those as array
+    /// lengths just yet.
+    ///
+    /// <https://github.com/rust-lang/rust/issues/60551>
+    type Buffer: Borrow<[u8]> + BorrowMut<[u8]>;
+
+    /// Read a SysEx message from `buffer` and convert it to this message type if supported. This
+    /// covers the full message, see the trait's docstring for more information. `buffer`'s length
+    /// matches the received message. It is not padded to match [`Buffer`][Self::Buffer].
+    fn from_buffer(buffer: &[u8]) -> Option<Self>;
+
+    /// Serialize this message object as a SysEx message in a byte buffer. This returns a buffer
+    /// alongside the message's length in bytes. The buffer may contain padding at the end. This
+    /// should contain the full message including headers and the EOX byte, see the trait's
+    /// docstring for more information.
+    fn to_buffer(self) -> (Self::Buffer, usize);
+}
+
+/// A default implementation plugins that don't need SysEx support can use.
+impl SysExMessage for () {
+    type Buffer = [u8; 0];
+
+    fn from_buffer(_buffer: &[u8]) -> Option<Self> {
+        None
+    }
+
+    fn to_buffer(self) -> (Self::Buffer, usize) {
+        ([], 0)
+    }
+}
+
+/// A field in a manufacturer-specific SysEx message. This is used by the `SysExMessage` derive
+/// macro to serialize the fields of each enum variant in declaration order. Every byte written to
+/// the message must be a 7-bit data byte, and reading fails if a byte has its high bit set or is
+/// otherwise out of range.
+///
+/// # Example
+///
+/// ```
+/// # use nih_plug::prelude::*;
+/// #[derive(Debug, Clone, PartialEq, SysExMessage)]
+/// #[sysex(manufacturer = 0x7d)]
+/// enum DeviceMessage {
+///     /// Encoded as `[0xf0, 0x7d, 0x01, program, 0xf7]`.
+///     #[sysex(command = 0x01)]
+///     SelectProgram { program: u8 },
+///     /// Encoded as `[0xf0, 0x7d, 0x10, 0x02, param, value_msb, value_lsb, 0xf7]`.
+///     #[sysex(command(0x10, 0x02))]
+///     SetParameter(u8, u16),
+///     #[sysex(command = 0x7f)]
+///     Reset,
+/// }
+/// ```
+///
+/// The manufacturer ID is either a single byte or a three byte extended ID starting with `0x00`.
+pub trait SysExField: Sized {
+    /// The number of bytes this field takes up in the message.
+    const SIZE: usize;
+
+    /// Read the field from `bytes`, which is exactly [`SIZE`][Self::SIZE] bytes long. Returns
+    /// `None` if the bytes don't contain a valid value.
+    fn read(bytes: &[u8]) -> Option<Self>;
+
+    /// Write the field to `bytes`, which is exactly [`SIZE`][Self::SIZE] bytes long.
+    fn write(&self, bytes: &mut [u8]);
+}
+
+/// A single 7-bit data byte. Values above 127 are truncated when writing.
+impl SysExField for u8 {
+    const SIZE: usize = 1;
+
+    fn read(bytes: &[u8]) -> Option<Self> {
+        (bytes[0] < 0x80).then_some(bytes[0])
+    }
+
+    fn write(&self, bytes: &mut [u8]) {
+        bytes[0] = *self & 0x7f;
+    }
+}
+
+/// A single `0x00` or `0x01` byte.
+impl SysExField for bool {
+    const SIZE: usize = 1;
+
+    fn read(bytes: &[u8]) -> Option<Self> {
+        match bytes[0] {
+            0x00 => Some(false),
+            0x01 => Some(true),
+            _ => None,
+        }
+    }
+
+    fn write(&self, bytes: &mut [u8]) {
+        bytes[0] = *self as u8;
+    }
+}
+
+/// A 14-bit value stored as two 7-bit bytes, most significant byte first.
+impl SysExField for u16 {
+    const SIZE: usize = 2;
+
+    fn read(bytes: &[u8]) -> Option<Self> {
+        Some(((u8::read(&bytes[..1])? as u16) << 7) | u8::read(&bytes[1..])? as u16)
+    }
+
+    fn write(&self, bytes: &mut [u8]) {
+        bytes[0] = ((*self >> 7) & 0x7f) as u8;
+        bytes[1] = (*self & 0x7f) as u8;
+    }
+}
+
+/// A 28-bit value stored as four 7-bit bytes, most significant byte first.
+impl SysExField for u32 {
+    const SIZE: usize = 4;
+
+    fn read(bytes: &[u8]) -> Option<Self> {
+        bytes.iter().try_fold(0u32, |value, &byte| {
+            Some((value << 7) | u8::read(&[byte])? as u32)
+        })
+    }
+
+    fn write(&self, bytes: &mut [u8]) {
+        for (idx, byte) in bytes.iter_mut().enumerate() {
+            *byte = ((*self >> (7 * (3 - idx))) & 0x7f) as u8;
+        }
+    }
+}
+
+/// A fixed number of fields stored back to back.
+impl<T: SysExField + Copy + Default, const N: usize> SysExField for [T; N] {
+    const SIZE: usize = T::SIZE * N;
+
+    fn read(bytes: &[u8]) -> Option<Self> {
+        let mut result = [T::default(); N];
+        for (value, field_bytes) in result.iter_mut().zip(bytes.chunks_exact(T::SIZE)) {
+            *value = T::read(field_bytes)?;
+        }
+
+        Some(result)
+    }
+
+    fn write(&self, bytes: &mut [u8]) {
+        for (value, field_bytes) in self.iter().zip(bytes.chunks_exact_mut(T::SIZE)) {
+            value.write(field_bytes);
+        }
+    }
+}
+
+/// A MIDI manufacturer ID as used in identity replies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManufacturerId {
+    /// A single byte manufacturer ID.
+    Short(u8),
+    /// A three byte manufacturer ID. The leading `0x00` byte is implied.
+    Extended(u8, u8),
+}
+
+/// A note's tuning in the MIDI Tuning Standard's format. This is the equal tempered semitone at or
+/// below the frequency, plus a 14-bit fraction of a semitone in units of 100/16384 cents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MtsTuning {
+    /// The semitone, in `[0, 127]`.
+    pub semitone: u8,
+    /// The fraction of a semitone above `semitone`, in `[0, 16383]`.
+    pub fraction: u16,
+}
+
+/// A single note's tuning change in a [`UniversalSysEx::MtsNoteChange`] message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MtsNoteChange {
+    /// The MIDI note number being retuned.
+    pub note: u8,
+    /// The note's new tuning.
+    pub tuning: MtsTuning,
+}
+
+/// A fixed capacity list of at most [`MAX_MTS_NOTE_CHANGES`] note changes. This avoids
+/// allocations so the message can be sent and received on the audio thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MtsNoteChanges {
+    changes: [MtsNoteChange; MAX_MTS_NOTE_CHANGES],
+    len: usize,
+}
+
+/// Ready-made universal realtime and non-realtime SysEx messages. Every message is addressed to a
+/// device ID, which is [`ALL_DEVICES`] when the message applies to every device. Plugins can use
+/// this as their [`Plugin::SysExMessage`][crate::prelude::Plugin::SysExMessage] type, or wrap it
+/// in their own message type.
+///
+/// Note that a bulk tuning dump makes this a large type. The MIDI event queues store messages
+/// inline, so this adds about 600 bytes to every event.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UniversalSysEx {
+    /// Asks devices to reply with an [`IdentityReply`][Self::IdentityReply].
+    IdentityRequest { device_id: u8 },
+    /// A device's reply to an identity request.
+    IdentityReply {
+        device_id: u8,
+        manufacturer: ManufacturerId,
+        /// A 14-bit device family code.
+        family: u16,
+        /// A 14-bit device family member code.
+        member: u16,
+        /// The device's software revision. The meaning of these bytes is manufacturer-specific.
+        version: [u8; 4],
+    },
+    /// Enables General MIDI mode.
+    GeneralMidiOn { device_id: u8 },
+    /// Disables General MIDI mode.
+    GeneralMidiOff { device_id: u8 },
+    /// Enables General MIDI 2 mode.
+    GeneralMidi2On { device_id: u8 },
+    /// Sets the device's master volume as a 14-bit value.
+    MasterVolume { device_id: u8, volume: u16 },
+    /// Requests a MTS bulk tuning dump for a tuning program.
+    MtsBulkDumpRequest { device_id: u8, program: u8 },
+    /// A MTS bulk tuning dump containing the tuning for all 128 notes. The checksum is computed
+    /// when serializing this message, but it is not verified when parsing since many devices
+    /// compute it differently.
+    MtsBulkDump {
+        device_id: u8,
+        program: u8,
+        /// The tuning's name as 7-bit ASCII characters, padded with spaces.
+        name: [u8; 16],
+        /// The tuning for each note, indexed by MIDI note number.
+        tunings: [MtsTuning; 128],
+    },
+    /// A realtime MTS single note tuning change for one or more notes.
+    MtsNoteChange {
+        device_id: u8,
+        program: u8,
+        changes: MtsNoteChanges,
+    },
+}
+
+impl SysExField for MtsTuning {
+    const SIZE: usize = 3;
+
+    fn read(bytes: &[u8]) -> Option<Self> {
+        Some(Self {
+            semitone: u8::read(&bytes[..1])?,
+            fraction: u16::read(&bytes[1..])?,
+        })
+    }
+
+    fn write(&self, bytes: &mut [u8]) {
+        self.semitone.write(&mut bytes[..1]);
+        self.fraction.write(&mut bytes[1..]);
+    }
+}
+
+impl MtsTuning {
+    /// The reserved tuning value that signals that a note's tuning should not be changed.
+    pub const NO_CHANGE: Self = Self {
+        semitone: 0x7f,
+        fraction: 0x3fff,
+    };
+
+    /// Create a tuning from a fractional MIDI note number. The note number is clamped to the
+    /// representable range.
+    pub fn from_note(note: f32) -> Self {
+        let note = note.clamp(0.0, 127.0 + 16383.0 / 16384.0);
+        let semitone = note.floor();
+        let fraction = ((note - semitone) * 16384.0).round().min(16383.0);
+
+        Self {
+            semitone: semitone as u8,
+            fraction: fraction as u16,
+        }
+    }
+
+    /// Get the fractional MIDI note number for this tuning.
+    pub fn note(self) -> f32 {
+        self.semitone as f32 + (self.fraction as f32 / 16384.0)
+    }
+}
+
+impl Default for MtsNoteChanges {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MtsNoteChanges {
+    /// Create an empty list of note changes.
+    pub const fn new() -> Self {
+        Self {
+            changes: [MtsNoteChange {
+                note: 0,
+                tuning: MtsTuning {
+                    semitone: 0,
+                    fraction: 0,
+                },
+            }; MAX_MTS_NOTE_CHANGES],
+            len: 0,
+        }
+    }
+
+    /// Add a note change. Returns `false` if the list is already full.
+    pub fn push(&mut self, change: MtsNoteChange) -> bool {
+        match self.changes.get_mut(self.len) {
+            Some(slot) => {
+                *slot = change;
+                self.len += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The note changes in this list.
+    pub fn as_slice(&self) -> &[MtsNoteChange] {
+        &self.changes[..self.len]
+    }
+
+    /// The number of note changes in this list.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether this list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl SysExMessage for UniversalSysEx {
+    type Buffer = [u8; UNIVERSAL_SYSEX_BUFFER_SIZE];
+
+    fn from_buffer(buffer: &[u8]) -> Option<Self> {
+        let buffer = buffer
+            .strip_prefix(&[SYSEX_START])?
+            .strip_suffix(&[SYSEX_END])?;
+        let (&universal_id, buffer) = buffer.split_first()?;
+        let (&device_id, data) = buffer.split_first()?;
+        if device_id >= 0x80 || data.iter().any(|&byte| byte >= 0x80) {
+            return None;
+        }
+
+        match (universal_id, data) {
+            (UNIVERSAL_NON_REALTIME, [0x06, 0x01]) => Some(Self::IdentityRequest { device_id }),
+            (UNIVERSAL_NON_REALTIME, [0x06, 0x02, data @ ..]) => {
+                let (manufacturer, data) = match data {
+                    [0x00, first, second, data @ ..] => {
+                        (ManufacturerId::Extended(*first, *second), data)
+                    }
+                    [id, data @ ..] => (ManufacturerId::Short(*id), data),
+                    [] => return None,
+                };
+                match data {
+                    [family_lsb, family_msb, member_lsb, member_msb, version @ ..] => {
+                        Some(Self::IdentityReply {
+                            device_id,
+                            manufacturer,
+                            family: ((*family_msb as u16) << 7) | *family_lsb as u16,
+                            member: ((*member_msb as u16) << 7) | *member_lsb as u16,
+                            version: version.try_into().ok()?,
+                        })
+                    }
+                    _ => None,
+                }
+            }
+            (UNIVERSAL_NON_REALTIME, [0x09, 0x01]) => Some(Self::GeneralMidiOn { device_id }),
+            (UNIVERSAL_NON_REALTIME, [0x09, 0x02]) => Some(Self::GeneralMidiOff { device_id }),
+            (UNIVERSAL_NON_REALTIME, [0x09, 0x03]) => Some(Self::GeneralMidi2On { device_id }),
+            (UNIVERSAL_REALTIME, [0x04, 0x01, lsb, msb]) => Some(Self::MasterVolume {
+                device_id,
+                volume: ((*msb as u16) << 7) | *lsb as u16,
+            }),
+            (UNIVERSAL_NON_REALTIME, [0x08, 0x00, program]) => Some(Self::MtsBulkDumpRequest {
+                device_id,
+                program: *program,
+            }),
+            (UNIVERSAL_NON_REALTIME, [0x08, 0x01, program, data @ ..])
+                if data.len() == 16 + (128 * MtsTuning::SIZE) + 1 =>
+            {
+                Some(Self::MtsBulkDump {
+                    device_id,
+                    program: *program,
+                    name: data[..16].try_into().unwrap(),
+                    tunings: <[MtsTuning; 128]>::read(&data[16..data.len() - 1])?,
+                })
+            }
+            (UNIVERSAL_REALTIME, [0x08, 0x02, program, num_changes, data @ ..])
+                if *num_changes as usize <= MAX_MTS_NOTE_CHANGES
+                    && data.len() == *num_changes as usize * (1 + MtsTuning::SIZE) =>
+            {
+                let mut changes = MtsNoteChanges::new();
+                for change in data.chunks_exact(1 + MtsTuning::SIZE) {
+                    changes.push(MtsNoteChange {
+                        note: change[0],
+                        tuning: MtsTuning::read(&change[1..])?,
+                    });
+                }
+
+                Some(Self::MtsNoteChange {
+                    device_id,
+                    program: *program,
+                    changes,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn to_buffer(self) -> (Self::Buffer, usize) {
+        let mut buffer = [0; UNIVERSAL_SYSEX_BUFFER_SIZE];
+        let mut len = 0;
+        let mut push = |bytes: &[u8]| {
+            buffer[len..len + bytes.len()].copy_from_slice(bytes);
+            len += bytes.len();
+        };
+
+        push(&[SYSEX_START]);
+        match self {
+            UniversalSysEx::IdentityRequest { device_id } => {
+                push(&[UNIVERSAL_NON_REALTIME, device_id & 0x7f, 0x06, 0x01]);
+            }
+            UniversalSysEx::IdentityReply {
+                device_id,
+                manufacturer,
+                family,
+                member,
+                version,
+            } => {
+                push(&[UNIVERSAL_NON_REALTIME, device_id & 0x7f, 0x06, 0x02]);
+                match manufacturer {
+                    ManufacturerId::Short(id) => push(&[id & 0x7f]),
+                    ManufacturerId::Extended(first, second) => {
+                        push(&[0x00, first & 0x7f, second & 0x7f])
+                    }
+                }
+                push(&[
+                    (family & 0x7f) as u8,
+                    ((family >> 7) & 0x7f) as u8,
+                    (member & 0x7f) as u8,
+                    ((member >> 7) & 0x7f) as u8,
+                ]);
+                push(&version.map(|byte| byte & 0x7f));
+            }
+            UniversalSysEx::GeneralMidiOn { device_id } => {
+                push(&[UNIVERSAL_NON_REALTIME, device_id & 0x7f, 0x09, 0x01]);
+            }
+            UniversalSysEx::GeneralMidiOff { device_id } => {
+                push(&[UNIVERSAL_NON_REALTIME, device_id & 0x7f, 0x09, 0x02]);
+            }
+            UniversalSysEx::GeneralMidi2On { device_id } => {
+                push(&[UNIVERSAL_NON_REALTIME, device_id & 0x7f, 0x09, 0x03]);
+            }
+            UniversalSysEx::MasterVolume { device_id, volume } => push(&[
+                UNIVERSAL_REALTIME,
+                device_id & 0x7f,
+                0x04,
+                0x01,
+                (volume & 0x7f) as u8,
+                ((volume >> 7) & 0x7f) as u8,
+            ]),
+            UniversalSysEx::MtsBulkDumpRequest { device_id, program } => push(&[
+                UNIVERSAL_NON_REALTIME,
+                device_id & 0x7f,
+                0x08,
+                0x00,
+                program & 0x7f,
+            ]),
+            UniversalSysEx::MtsBulkDump {
+                device_id,
+                program,
+                name,
+                tunings,
+            } => {
+                let header = [
+                    UNIVERSAL_NON_REALTIME,
+                    device_id & 0x7f,
+                    0x08,
+                    0x01,
+                    program & 0x7f,
+                ];
+                let name = name.map(|byte| byte & 0x7f);
+                let mut tuning_bytes = [0; 128 * MtsTuning::SIZE];
+                tunings.write(&mut tuning_bytes);
+
+                // The checksum covers everything between the start byte and the checksum itself
+                let checksum = header
+                    .iter()
+                    .chain(&name)
+                    .chain(&tuning_bytes)
+                    .fold(0, |checksum, byte| checksum ^ byte);
+
+                push(&header);
+                push(&name);
+                push(&tuning_bytes);
+                push(&[checksum & 0x7f]);
+            }
+            UniversalSysEx::MtsNoteChange {
+                device_id,
+                program,
+                changes,
+            } => {
+                push(&[
+                    UNIVERSAL_REALTIME,
+                    device_id & 0x7f,
+                    0x08,
+                    0x02,
+                    program & 0x7f,
+                    changes.len() as u8,
+                ]);
+                for change in changes.as_slice() {
+                    let mut change_bytes = [change.note & 0x7f, 0, 0, 0];
+                    change.tuning.write(&mut change_bytes[1..]);
+                    push(&change_bytes);
+                }
+            }
+        }
+        push(&[SYSEX_END]);
+
+        (buffer, len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(message: UniversalSysEx) -> Vec<u8> {
+        let (buffer, len) = message.clone().to_buffer();
+        assert_eq!(UniversalSysEx::from_buffer(&buffer[..len]), Some(message));
+
+        buffer[..len].to_vec()
+    }
+
+    #[test]
+    fn test_fields() {
+        let mut bytes = [0; 4];
+        0x1234u16.write(&mut bytes[..2]);
+        assert_eq!(bytes[..2], [0x24, 0x34]);
+        assert_eq!(u16::read(&bytes[..2]), Some(0x1234));
+
+        0x0abc_def0u32.write(&mut bytes);
+        assert_eq!(u32::read(&bytes), Some(0x0abc_def0));
+
+        assert_eq!(u8::read(&[0x80]), None);
+        assert_eq!(bool::read(&[0x02]), None);
+        assert_eq!(
+            <[u16; 2]>::read(&[0x00, 0x01, 0x7f, 0x7f]),
+            Some([1, 0x3fff])
+        );
+    }
+
+    #[test]
+    fn test_general_midi() {
+        assert_eq!(
+            roundtrip(UniversalSysEx::GeneralMidiOn {
+                device_id: ALL_DEVICES
+            }),
+            [0xf0, 0x7e, 0x7f, 0x09, 0x01, 0xf7]
+        );
+        roundtrip(UniversalSysEx::GeneralMidiOff { device_id: 0x10 });
+        roundtrip(UniversalSysEx::GeneralMidi2On { device_id: 0x10 });
+        assert_eq!(
+            roundtrip(UniversalSysEx::MasterVolume {
+                device_id: ALL_DEVICES,
+                volume: 0x3fff
+            }),
+            [0xf0, 0x7f, 0x7f, 0x04, 0x01, 0x7f, 0x7f, 0xf7]
+        );
+    }
+
+    #[test]
+    fn test_identity() {
+        assert_eq!(
+            roundtrip(UniversalSysEx::IdentityRequest {
+                device_id: ALL_DEVICES
+            }),
+            [0xf0, 0x7e, 0x7f, 0x06, 0x01, 0xf7]
+        );
+        assert_eq!(
+            roundtrip(UniversalSysEx::IdentityReply {
+                device_id: 0x01,
+                manufacturer: ManufacturerId::Extended(0x21, 0x09),
+                family: 0x0081,
+                member: 0x0002,
+                version: [0x01, 0x02, 0x03, 0x04],
+            }),
+            [
+                0xf0, 0x7e, 0x01, 0x06, 0x02, 0x00, 0x21, 0x09, 0x01, 0x01, 0x02, 0x00, 0x01, 0x02,
+                0x03, 0x04, 0xf7
+            ]
+        );
+        roundtrip(UniversalSysEx::IdentityReply {
+            device_id: 0x01,
+            manufacturer: ManufacturerId::Short(0x41),
+            family: 0,
+            member: 0x3fff,
+            version: [0; 4],
+        });
+    }
+
+    #[test]
+    fn test_mts_bulk_dump() {
+        assert_eq!(
+            roundtrip(UniversalSysEx::MtsBulkDumpRequest {
+                device_id: ALL_DEVICES,
+                program: 3
+            }),
+            [0xf0, 0x7e, 0x7f, 0x08, 0x00, 0x03, 0xf7]
+        );
+
+        let mut tunings = [MtsTuning::default(); 128];
+        for (note, tuning) in tunings.iter_mut().enumerate() {
+            *tuning = MtsTuning::from_note(note as f32 + 0.5);
+        }
+        let bytes = roundtrip(UniversalSysEx::MtsBulkDump {
+            device_id: ALL_DEVICES,
+            program: 0,
+            name: *b"Quarter tones   ",
+            tunings,
+        });
+        assert_eq!(bytes.len(), UNIVERSAL_SYSEX_BUFFER_SIZE);
+        assert_eq!(bytes[22..25], [0x00, 0x40, 0x00]);
+
+        let checksum = bytes[1..406]
+            .iter()
+            .fold(0, |checksum, byte| checksum ^ byte);
+        assert_eq!(bytes[406], checksum & 0x7f);
+    }
+
+    #[test]
+    fn test_mts_note_change() {
+        let mut changes = MtsNoteChanges::new();
+        assert!(changes.push(MtsNoteChange {
+            note: 60,
+            tuning: MtsTuning::from_note(60.25),
+        }));
+        assert!(changes.push(MtsNoteChange {
+            note: 61,
+            tuning: MtsTuning::NO_CHANGE,
+        }));
+
+        assert_eq!(
+            roundtrip(UniversalSysEx::MtsNoteChange {
+                device_id: ALL_DEVICES,
+                program: 1,
+                changes,
+            }),
+            [
+                0xf0, 0x7f, 0x7f, 0x08, 0x02, 0x01, 0x02, 60, 60, 0x20, 0x00, 61, 0x7f, 0x7f, 0x7f,
+                0xf7
+            ]
+        );
+
+        while changes.push(MtsNoteChange::default()) {}
+        assert_eq!(changes.len(), MAX_MTS_NOTE_CHANGES);
+        roundtrip(UniversalSysEx::MtsNoteChange {
+            device_id: ALL_DEVICES,
+            program: 1,
+            changes,
+        });
+    }
+
+    #[test]
+    fn test_mts_tuning() {
+        assert_eq!(
+            MtsTuning::from_note(69.5),
+            MtsTuning {
+                semitone: 69,
+                fraction: 0x2000
+            }
+        );
+        assert_eq!(MtsTuning::from_note(69.5).note(), 69.5);
+        assert_eq!(MtsTuning::from_note(200.0).semitone, 127);
+        assert_eq!(MtsTuning::from_note(-1.0), MtsTuning::default());
+    }
+
+    #[test]
+    fn test_invalid_messages() {
+        assert_eq!(UniversalSysEx::from_buffer(&[]), None);
+        assert_eq!(
+            UniversalSysEx::from_buffer(&[0xf0, 0x7e, 0x7f, 0x09, 0x01]),
+            None
+        );
+        assert_eq!(
+            UniversalSysEx::from_buffer(&[0xf0, 0x7e, 0x7f, 0x09, 0x04, 0xf7]),
+            None
+        );
+        assert_eq!(
+            UniversalSysEx::from_buffer(&[
+                0xf0, 0x7f, 0x7f, 0x08, 0x02, 0x00, 0x02, 60, 0, 0, 0, 0xf7
+            ]),
+            None
+        );
+    }
+}
@@ -0,0 +1,347 @@
+//! Keeping track of which notes are held down, including notes that keep sounding because the
+//! sustain pedal is pressed. [`NoteTracker`] does not allocate after it has been created, so it can
+//! be updated directly from the plugin's `process()` function.
+
+use super::NoteEvent;
+
+/// The maximum number of notes a [`NoteTracker`] can keep track of. This is every key on every MIDI
+/// channel.
+pub const MAX_TRACKED_NOTES: usize = 16 * 128;
+
+/// The MIDI CC for the sustain, or damper, pedal.
+const SUSTAIN_PEDAL_CC: u8 = 64;
+/// The MIDI CC for the all notes off channel mode message.
+const ALL_NOTES_OFF_CC: u8 = 123;
+
+/// A note that's currently sounding.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeldNote {
+    /// The note's voice ID, if the host provided one.
+    pub voice_id: Option<i32>,
+    /// The note's channel, in `0..16`.
+    pub channel: u8,
+    /// The note's MIDI key number, in `0..128`.
+    pub note: u8,
+    /// The note's velocity, in `[0, 1]`.
+    pub velocity: f32,
+    /// Whether the key has been released while the sustain pedal was pressed. The note keeps
+    /// sounding until the pedal is released.
+    pub sustained: bool,
+}
+
+/// Tracks held notes and the sustain pedal for every MIDI channel. Pass every incoming event to
+/// [`handle_event()`][Self::handle_event()], and the tracker will report which notes stop sounding.
+/// Notes are kept in the order they were pressed, so this can also be used to implement last note
+/// priority for monophonic instruments.
+///
+/// # Example
+///
+/// ```ignore
+/// while let Some(event) = context.next_event() {
+///     self.notes.handle_event(&event, |released| {
+///         self.voices.release(released.channel, released.note)
+///     });
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct NoteTracker {
+    /// The sounding notes in the order they were pressed. This has enough capacity for
+    /// [`MAX_TRACKED_NOTES`] so it never needs to reallocate.
+    notes: Vec<HeldNote>,
+    /// Whether the sustain pedal is pressed, indexed by channel.
+    sustain_pedal: [bool; 16],
+}
+
+impl Default for NoteTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NoteTracker {
+    /// Create an empty note tracker.
+    pub fn new() -> Self {
+        Self {
+            notes: Vec::with_capacity(MAX_TRACKED_NOTES),
+            sustain_pedal: [false; 16],
+        }
+    }
+
+    /// Forget all notes and release every sustain pedal. This does not report the notes as
+    /// released.
+    pub fn reset(&mut self) {
+        self.notes.clear();
+        self.sustain_pedal = [false; 16];
+    }
+
+    /// Update the tracker with an incoming event. `on_release` is called for every note that stops
+    /// sounding because of this event. Note off events for notes held by the sustain pedal are
+    /// deferred until the pedal is released, and choke events always release the note immediately.
+    /// Pressing a key that is still sounding moves the note to the end of the list.
+    pub fn handle_event<S>(&mut self, event: &NoteEvent<S>, mut on_release: impl FnMut(HeldNote)) {
+        match *event {
+            NoteEvent::NoteOn {
+                voice_id,
+                channel,
+                note,
+                velocity,
+                ..
+            } => {
+                nih_debug_assert!(channel < 16 && note < 128);
+                if let Some(idx) = self.position(channel, note) {
+                    self.notes.remove(idx);
+                }
+
+                // This can only fail for out of range notes
+                if self.notes.len() < MAX_TRACKED_NOTES {
+                    self.notes.push(HeldNote {
+                        voice_id,
+                        channel,
+                        note,
+                        velocity,
+                        sustained: false,
+                    });
+                }
+            }
+            NoteEvent::NoteOff { channel, note, .. } => {
+                if let Some(idx) = self.position(channel, note) {
+                    if self.sustain_pedal(channel) {
+                        self.notes[idx].sustained = true;
+                    } else {
+                        on_release(self.notes.remove(idx));
+                    }
+                }
+            }
+            NoteEvent::Choke { channel, note, .. } => {
+                if let Some(idx) = self.position(channel, note) {
+                    on_release(self.notes.remove(idx));
+                }
+            }
+            NoteEvent::MidiCC {
+                channel,
+                cc: SUSTAIN_PEDAL_CC,
+                value,
+                ..
+            } => {
+                let pressed = value >= 0.5;
+                if let Some(sustain_pedal) = self.sustain_pedal.get_mut(channel as usize) {
+                    *sustain_pedal = pressed;
+                }
+
+                if !pressed {
+                    self.notes.retain(|held_note| {
+                        if held_note.channel == channel && held_note.sustained {
+                            on_release(*held_note);
+                            false
+                        } else {
+                            true
+                        }
+                    });
+                }
+            }
+            // This behaves as if every note on the channel received a note off event
+            NoteEvent::MidiCC {
+                channel,
+                cc: ALL_NOTES_OFF_CC,
+                ..
+            } => {
+                let sustain_pedal = self.sustain_pedal(channel);
+                self.notes.retain_mut(|held_note| {
+                    if held_note.channel != channel {
+                        true
+                    } else if sustain_pedal {
+                        held_note.sustained = true;
+                        true
+                    } else {
+                        on_release(*held_note);
+                        false
+                    }
+                });
+            }
+            _ => (),
+        }
+    }
+
+    /// All sounding notes, including notes held by the sustain pedal, in the order they were
+    /// pressed.
+    pub fn notes(&self) -> &[HeldNote] {
+        &self.notes
+    }
+
+    /// The number of sounding notes.
+    pub fn len(&self) -> usize {
+        self.notes.len()
+    }
+
+    /// Whether no notes are sounding.
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty()
+    }
+
+    /// Whether a note is sounding, either because its key is held down or because of the sustain
+    /// pedal.
+    pub fn is_sounding(&self, channel: u8, note: u8) -> bool {
+        self.position(channel, note).is_some()
+    }
+
+    /// Whether a note's key is held down. This excludes notes held by the sustain pedal.
+    pub fn is_held(&self, channel: u8, note: u8) -> bool {
+        self.position(channel, note)
+            .map(|idx| !self.notes[idx].sustained)
+            .unwrap_or(false)
+    }
+
+    /// Whether the sustain pedal is pressed on a channel.
+    pub fn sustain_pedal(&self, channel: u8) -> bool {
+        self.sustain_pedal
+            .get(channel as usize)
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// The most recently pressed note that's still sounding.
+    pub fn latest(&self) -> Option<&HeldNote> {
+        self.notes.last()
+    }
+
+    /// The lowest sounding note.
+    pub fn lowest(&self) -> Option<&HeldNote> {
+        self.notes.iter().min_by_key(|held_note| held_note.note)
+    }
+
+    /// The highest sounding note.
+    pub fn highest(&self) -> Option<&HeldNote> {
+        self.notes.iter().max_by_key(|held_note| held_note.note)
+    }
+
+    fn position(&self, channel: u8, note: u8) -> Option<usize> {
+        self.notes
+            .iter()
+            .position(|held_note| held_note.channel == channel && held_note.note == note)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note_on(channel: u8, note: u8) -> NoteEvent<()> {
+        NoteEvent::NoteOn {
+            timing: 0,
+            voice_id: None,
+            channel,
+            note,
+            velocity: 0.5,
+        }
+    }
+
+    fn note_off(channel: u8, note: u8) -> NoteEvent<()> {
+        NoteEvent::NoteOff {
+            timing: 0,
+            voice_id: None,
+            channel,
+            note,
+            velocity: 0.0,
+        }
+    }
+
+    fn sustain(channel: u8, pressed: bool) -> NoteEvent<()> {
+        NoteEvent::MidiCC {
+            timing: 0,
+            channel,
+            cc: SUSTAIN_PEDAL_CC,
+            value: if pressed { 1.0 } else { 0.0 },
+        }
+    }
+
+    /// Handle the events and return the released notes as `(channel, note)` pairs.
+    fn handle(tracker: &mut NoteTracker, events: &[NoteEvent<()>]) -> Vec<(u8, u8)> {
+        let mut released = Vec::new();
+        for event in events {
+            tracker.handle_event(event, |held_note| {
+                released.push((held_note.channel, held_note.note))
+            });
+        }
+
+        released
+    }
+
+    #[test]
+    fn test_note_order() {
+        let mut tracker = NoteTracker::new();
+        let released = handle(
+            &mut tracker,
+            &[
+                note_on(0, 60),
+                note_on(0, 64),
+                note_on(1, 60),
+                note_on(0, 60),
+            ],
+        );
+        assert!(released.is_empty());
+
+        let notes: Vec<_> = tracker
+            .notes()
+            .iter()
+            .map(|held_note| (held_note.channel, held_note.note))
+            .collect();
+        assert_eq!(notes, [(0, 64), (1, 60), (0, 60)]);
+        assert_eq!(tracker.lowest().map(|held_note| held_note.note), Some(60));
+        assert_eq!(tracker.highest().map(|held_note| held_note.note), Some(64));
+
+        assert_eq!(handle(&mut tracker, &[note_off(0, 60)]), [(0, 60)]);
+        assert_eq!(tracker.latest().map(|held_note| held_note.channel), Some(1));
+    }
+
+    #[test]
+    fn test_sustain_pedal() {
+        let mut tracker = NoteTracker::new();
+        let released = handle(
+            &mut tracker,
+            &[
+                note_on(0, 60),
+                note_on(1, 62),
+                sustain(0, true),
+                note_off(0, 60),
+                note_off(1, 62),
+                note_on(0, 64),
+            ],
+        );
+        // The pedal only applies to its own channel
+        assert_eq!(released, [(1, 62)]);
+        assert!(tracker.is_sounding(0, 60));
+        assert!(!tracker.is_held(0, 60));
+        assert!(tracker.is_held(0, 64));
+
+        assert_eq!(handle(&mut tracker, &[sustain(0, false)]), [(0, 60)]);
+        assert!(!tracker.sustain_pedal(0));
+        assert_eq!(tracker.len(), 1);
+    }
+
+    #[test]
+    fn test_choke_and_all_notes_off() {
+        let mut tracker = NoteTracker::new();
+        let released = handle(
+            &mut tracker,
+            &[
+                note_on(0, 60),
+                note_on(0, 62),
+                note_on(2, 64),
+                NoteEvent::Choke {
+                    timing: 0,
+                    voice_id: None,
+                    channel: 0,
+                    note: 60,
+                },
+                NoteEvent::MidiCC {
+                    timing: 0,
+                    channel: 0,
+                    cc: ALL_NOTES_OFF_CC,
+                    value: 0.0,
+                },
+            ],
+        );
+        assert_eq!(released, [(0, 60), (0, 62)]);
+        assert!(tracker.is_held(2, 64));
+    }
+}
@@ -0,0 +1,492 @@
+//! Parsing and serialization for MIDI 2.0 Universal MIDI Packets (UMP).
+//!
+//! Only channel voice messages are supported. MIDI 2.0 channel voice messages are converted to the
+//! same [`NoteEvent`]s used for MIDI 1.0, but with the full precision of the MIDI 2.0 message. This
+//! means that note velocities use the full 16-bit range, CCs use the full 32-bit range, and
+//! registered and assignable controllers are converted to [`NoteEvent::MidiRpn`] and
+//! [`NoteEvent::MidiNrpn`] events. Per-note pitch bend and per-note controllers don't have a MIDI
+//! 1.0 equivalent, and are converted to [`NoteEvent::MidiPerNotePitchBend`] and
+//! [`NoteEvent::MidiPerNoteController`] events instead. MIDI 1.0 channel voice messages wrapped in
+//! a UMP are parsed like regular MIDI 1.0 messages.
+//!
+//! The UMP group is ignored when parsing, and group 0 is used when serializing.
+
+use midi_consts::channel_event as midi;
+
+use super::sysex::SysExMessage;
+use super::{NoteEvent, RpnMessage};
+
+/// A MIDI 2.0 channel voice message. These consist of two 32-bit words.
+pub type UmpPacket = [u32; 2];
+
+/// The UMP message type for MIDI 1.0 channel voice messages. These consist of a single word.
+const MESSAGE_TYPE_MIDI1_CHANNEL_VOICE: u8 = 0x2;
+/// The UMP message type for MIDI 2.0 channel voice messages. These consist of two words.
+const MESSAGE_TYPE_MIDI2_CHANNEL_VOICE: u8 = 0x4;
+
+// These are the MIDI 2.0 channel voice statuses that don't exist in MIDI 1.0. The other statuses
+// reuse the MIDI 1.0 values.
+const REGISTERED_PER_NOTE_CONTROLLER: u8 = 0x00;
+const ASSIGNABLE_PER_NOTE_CONTROLLER: u8 = 0x10;
+const REGISTERED_CONTROLLER: u8 = 0x20;
+const ASSIGNABLE_CONTROLLER: u8 = 0x30;
+const PER_NOTE_PITCH_BEND: u8 = 0x60;
+
+impl<S: SysExMessage> NoteEvent<S> {
+    /// Parse a Universal MIDI Packet into a [`NoteEvent`]. Supports MIDI 1.0 and MIDI 2.0 channel
+    /// voice messages. `ump` should contain at least as many words as the message type requires.
+    /// Will return `Err(status)` if the parsing failed, where `status` is the first byte of the
+    /// packet.
+    pub fn from_ump(timing: u32, ump: &[u32]) -> Result<Self, u8> {
+        let first_word = ump.first().copied().unwrap_or_default();
+        let [header, status_byte, index, extra] = first_word.to_be_bytes();
+        let message_type = header >> 4;
+
+        match message_type {
+            MESSAGE_TYPE_MIDI1_CHANNEL_VOICE => {
+                NoteEvent::from_midi(timing, &[status_byte, index, extra]).map_err(|_| header)
+            }
+            MESSAGE_TYPE_MIDI2_CHANNEL_VOICE if ump.len() >= 2 => {
+                let event_type = status_byte & midi::EVENT_TYPE_MASK;
+                let channel = status_byte & midi::MIDI_CHANNEL_MASK;
+                let data = ump[1];
+
+                match event_type {
+                    midi::NOTE_ON => Ok(NoteEvent::NoteOn {
+                        timing,
+                        voice_id: None,
+                        channel,
+                        note: index,
+                        // Unlike in MIDI 1.0, a velocity of zero does not turn this into a note off
+                        velocity: unscale_16((data >> 16) as u16),
+                    }),
+                    midi::NOTE_OFF => Ok(NoteEvent::NoteOff {
+                        timing,
+                        voice_id: None,
+                        channel,
+                        note: index,
+                        velocity: unscale_16((data >> 16) as u16),
+                    }),
+                    midi::POLYPHONIC_KEY_PRESSURE => Ok(NoteEvent::PolyPressure {
+                        timing,
+                        voice_id: None,
+                        channel,
+                        note: index,
+                        pressure: unscale_32(data),
+                    }),
+                    midi::CONTROL_CHANGE => Ok(NoteEvent::MidiCC {
+                        timing,
+                        channel,
+                        cc: index,
+                        value: unscale_32(data),
+                    }),
+                    midi::PROGRAM_CHANGE => Ok(NoteEvent::MidiProgramChange {
+                        timing,
+                        channel,
+                        // The bank select stored in the lower bytes is ignored
+                        program: (data >> 24) as u8,
+                    }),
+                    midi::CHANNEL_KEY_PRESSURE => Ok(NoteEvent::MidiChannelPressure {
+                        timing,
+                        channel,
+                        pressure: unscale_32(data),
+                    }),
+                    midi::PITCH_BEND_CHANGE => Ok(NoteEvent::MidiPitchBend {
+                        timing,
+                        channel,
+                        value: unscale_32(data),
+                    }),
+                    PER_NOTE_PITCH_BEND => Ok(NoteEvent::MidiPerNotePitchBend {
+                        timing,
+                        voice_id: None,
+                        channel,
+                        note: index,
+                        value: unscale_32(data),
+                    }),
+                    REGISTERED_PER_NOTE_CONTROLLER | ASSIGNABLE_PER_NOTE_CONTROLLER => {
+                        Ok(NoteEvent::MidiPerNoteController {
+                            timing,
+                            voice_id: None,
+                            channel,
+                            note: index,
+                            registered: event_type == REGISTERED_PER_NOTE_CONTROLLER,
+                            controller: extra,
+                            value: unscale_32(data),
+                        })
+                    }
+                    // The bank and index correspond to the MIDI 1.0 (N)RPN MSB and LSB. RPN values
+                    // are stored in the upper 14 bits so they can be parsed the same way.
+                    REGISTERED_CONTROLLER => Ok(NoteEvent::MidiRpn {
+                        timing,
+                        channel,
+                        message: RpnMessage::from_raw(
+                            ((index as u16 & 0x7f) << 7) | (extra as u16 & 0x7f),
+                            (data >> 18) as u16,
+                        ),
+                    }),
+                    ASSIGNABLE_CONTROLLER => Ok(NoteEvent::MidiNrpn {
+                        timing,
+                        channel,
+                        parameter: ((index as u16 & 0x7f) << 7) | (extra as u16 & 0x7f),
+                        value: unscale_32(data),
+                    }),
+                    _ => {
+                        nih_trace!("Unhandled MIDI 2.0 channel voice message: {ump:08x?}");
+                        Err(header)
+                    }
+                }
+            }
+            _ => {
+                nih_trace!("Unhandled UMP message type {message_type:#x}");
+                Err(header)
+            }
+        }
+    }
+
+    /// Create a MIDI 2.0 channel voice message from this note event. Returns `None` if this event
+    /// does not have a direct MIDI 2.0 channel voice message equivalent. Like with
+    /// [`as_midi()`][Self::as_midi()], `PolyPressure` is converted to polyphonic key pressure, but
+    /// the other polyphonic note expression types are not converted. SysEx messages are not
+    /// converted either.
+    pub fn as_ump(self) -> Option<UmpPacket> {
+        match self {
+            NoteEvent::NoteOn {
+                timing: _,
+                voice_id: _,
+                channel,
+                note,
+                velocity,
+            } => Some(packet(
+                midi::NOTE_ON,
+                channel,
+                note,
+                0,
+                (scale_16(velocity) as u32) << 16,
+            )),
+            NoteEvent::NoteOff {
+                timing: _,
+                voice_id: _,
+                channel,
+                note,
+                velocity,
+            } => Some(packet(
+                midi::NOTE_OFF,
+                channel,
+                note,
+                0,
+                (scale_16(velocity) as u32) << 16,
+            )),
+            NoteEvent::PolyPressure {
+                timing: _,
+                voice_id: _,
+                channel,
+                note,
+                pressure,
+            } => Some(packet(
+                midi::POLYPHONIC_KEY_PRESSURE,
+                channel,
+                note,
+                0,
+                scale_32(pressure),
+            )),
+            NoteEvent::MidiChannelPressure {
+                timing: _,
+                channel,
+                pressure,
+            } => Some(packet(
+                midi::CHANNEL_KEY_PRESSURE,
+                channel,
+                0,
+                0,
+                scale_32(pressure),
+            )),
+            NoteEvent::MidiPitchBend {
+                timing: _,
+                channel,
+                value,
+            } => Some(packet(
+                midi::PITCH_BEND_CHANGE,
+                channel,
+                0,
+                0,
+                scale_32(value),
+            )),
+            NoteEvent::MidiCC {
+                timing: _,
+                channel,
+                cc,
+                value,
+            } => Some(packet(
+                midi::CONTROL_CHANGE,
+                channel,
+                cc,
+                0,
+                scale_32(value),
+            )),
+            NoteEvent::MidiProgramChange {
+                timing: _,
+                channel,
+                program,
+            } => Some(packet(
+                midi::PROGRAM_CHANGE,
+                channel,
+                0,
+                0,
+                (program as u32) << 24,
+            )),
+            // MIDI 2.0 CCs are already high resolution, so these are sent as regular CCs
+            NoteEvent::MidiHighResCC {
+                timing: _,
+                channel,
+                cc,
+                value,
+            } => Some(packet(
+                midi::CONTROL_CHANGE,
+                channel,
+                cc,
+                0,
+                scale_32(value),
+            )),
+            NoteEvent::MidiRpn {
+                timing: _,
+                channel,
+                message,
+            } => {
+                let (parameter, value) = message.to_raw();
+                Some(packet(
+                    REGISTERED_CONTROLLER,
+                    channel,
+                    (parameter >> 7) as u8,
+                    (parameter & 0x7f) as u8,
+                    (value as u32) << 18,
+                ))
+            }
+            NoteEvent::MidiNrpn {
+                timing: _,
+                channel,
+                parameter,
+                value,
+            } => Some(packet(
+                ASSIGNABLE_CONTROLLER,
+                channel,
+                ((parameter >> 7) & 0x7f) as u8,
+                (parameter & 0x7f) as u8,
+                scale_32(value),
+            )),
+            NoteEvent::MidiPerNotePitchBend {
+                timing: _,
+                voice_id: _,
+                channel,
+                note,
+                value,
+            } => Some(packet(
+                PER_NOTE_PITCH_BEND,
+                channel,
+                note,
+                0,
+                scale_32(value),
+            )),
+            NoteEvent::MidiPerNoteController {
+                timing: _,
+                voice_id: _,
+                channel,
+                note,
+                registered,
+                controller,
+                value,
+            } => Some(packet(
+                if registered {
+                    REGISTERED_PER_NOTE_CONTROLLER
+                } else {
+                    ASSIGNABLE_PER_NOTE_CONTROLLER
+                },
+                channel,
+                note,
+                controller,
+                scale_32(value),
+            )),
+            NoteEvent::Choke { .. }
+            | NoteEvent::VoiceTerminated { .. }
+            | NoteEvent::PolyModulation { .. }
+            | NoteEvent::MonoAutomation { .. }
+            | NoteEvent::PolyVolume { .. }
+            | NoteEvent::PolyPan { .. }
+            | NoteEvent::PolyTuning { .. }
+            | NoteEvent::PolyVibrato { .. }
+            | NoteEvent::PolyExpression { .. }
+            | NoteEvent::PolyBrightness { .. }
+            | NoteEvent::MidiTimingClock { .. }
+            | NoteEvent::MidiStart { .. }
+            | NoteEvent::MidiContinue { .. }
+            | NoteEvent::MidiStop { .. }
+            | NoteEvent::MidiSongPosition { .. }
+            | NoteEvent::MidiTimecodeQuarterFrame { .. }
+            | NoteEvent::MidiSysEx { .. } => None,
+        }
+    }
+}
+
+/// Build a MIDI 2.0 channel voice message for group 0.
+fn packet(event_type: u8, channel: u8, index: u8, extra: u8, data: u32) -> UmpPacket {
+    [
+        u32::from_be_bytes([
+            MESSAGE_TYPE_MIDI2_CHANNEL_VOICE << 4,
+            event_type | (channel & midi::MIDI_CHANNEL_MASK),
+            index & 0x7f,
+            extra & 0x7f,
+        ]),
+        data,
+    ]
+}
+
+fn unscale_16(value: u16) -> f32 {
+    value as f32 / u16::MAX as f32
+}
+
+fn scale_16(value: f32) -> u16 {
+    (value * u16::MAX as f32)
+        .round()
+        .clamp(0.0, u16::MAX as f32) as u16
+}
+
+fn unscale_32(value: u32) -> f32 {
+    (value as f64 / u32::MAX as f64) as f32
+}
+
+fn scale_32(value: f32) -> u32 {
+    (value as f64 * u32::MAX as f64)
+        .round()
+        .clamp(0.0, u32::MAX as f64) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMING: u32 = 5;
+
+    /// Converts an event to and from a UMP. Panics if any part of the conversion fails.
+    fn roundtrip_ump_event(event: NoteEvent<()>) -> NoteEvent<()> {
+        let ump = event.as_ump().unwrap();
+
+        NoteEvent::from_ump(TIMING, &ump).unwrap()
+    }
+
+    #[test]
+    fn test_note_ump_conversion() {
+        let note_on = NoteEvent::<()>::NoteOn {
+            timing: TIMING,
+            voice_id: None,
+            channel: 1,
+            note: 2,
+            // This is exactly representable as a 16-bit velocity, but not as a 7-bit velocity
+            velocity: 1000.0 / 65535.0,
+        };
+        let note_off = NoteEvent::<()>::NoteOff {
+            timing: TIMING,
+            voice_id: None,
+            channel: 1,
+            note: 2,
+            velocity: 0.0,
+        };
+
+        assert_eq!(roundtrip_ump_event(note_on), note_on);
+        assert_eq!(roundtrip_ump_event(note_off), note_off);
+    }
+
+    #[test]
+    fn test_cc_ump_conversion() {
+        let event = NoteEvent::<()>::MidiCC {
+            timing: TIMING,
+            channel: 15,
+            cc: 74,
+            value: 0.123456,
+        };
+
+        assert_eq!(
+            event.as_ump(),
+            Some([
+                0x40bf4a00,
+                (0.123456f32 as f64 * u32::MAX as f64).round() as u32
+            ])
+        );
+        assert_eq!(roundtrip_ump_event(event), event);
+    }
+
+    #[test]
+    fn test_pitch_bend_ump_conversion() {
+        let event = NoteEvent::<()>::MidiPitchBend {
+            timing: TIMING,
+            channel: 0,
+            value: 0.5,
+        };
+
+        assert_eq!(roundtrip_ump_event(event), event);
+    }
+
+    #[test]
+    fn test_per_note_ump_conversion() {
+        let pitch_bend = NoteEvent::<()>::MidiPerNotePitchBend {
+            timing: TIMING,
+            voice_id: None,
+            channel: 3,
+            note: 60,
+            value: 0.75,
+        };
+        let controller = NoteEvent::<()>::MidiPerNoteController {
+            timing: TIMING,
+            voice_id: None,
+            channel: 3,
+            note: 60,
+            registered: false,
+            controller: 12,
+            value: 0.25,
+        };
+
+        assert_eq!(roundtrip_ump_event(pitch_bend), pitch_bend);
+        assert_eq!(roundtrip_ump_event(controller), controller);
+    }
+
+    #[test]
+    fn test_controller_ump_conversion() {
+        let rpn = NoteEvent::<()>::MidiRpn {
+            timing: TIMING,
+            channel: 0,
+            message: RpnMessage::PitchBendSensitivity { semitones: 48.0 },
+        };
+        let nrpn = NoteEvent::<()>::MidiNrpn {
+            timing: TIMING,
+            channel: 0,
+            parameter: 1000,
+            value: 0.5,
+        };
+
+        assert_eq!(roundtrip_ump_event(rpn), rpn);
+        assert_eq!(roundtrip_ump_event(nrpn), nrpn);
+    }
+
+    #[test]
+    fn test_midi1_ump_parsing() {
+        // A MIDI 1.0 note on with velocity zero is still a note off
+        let ump = [0x20903c00];
+
+        assert_eq!(
+            NoteEvent::<()>::from_ump(TIMING, &ump),
+            Ok(NoteEvent::NoteOff {
+                timing: TIMING,
+                voice_id: None,
+                channel: 0,
+                note: 60,
+                velocity: 0.0,
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_ump() {
+        // Data messages are not supported
+        assert!(NoteEvent::<()>::from_ump(TIMING, &[0x30000000, 0]).is_err());
+        // MIDI 2.0 channel voice messages need two words
+        assert!(NoteEvent::<()>::from_ump(TIMING, &[0x40903c00]).is_err());
+    }
+}
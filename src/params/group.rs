@@ -0,0 +1,205 @@
+//! Runtime information about parameter groups. The groups are defined using the `group` options on
+//! the `#[nested(...)]` attribute when deriving [`Params`][super::Params], and they're used by the
+//! wrappers to build the host's parameter tree and by editors to lay out their controls.
+
+use std::collections::HashMap;
+
+use super::internals::ParamPtr;
+
+/// A group of parameters, defined by a `#[nested(group = "...")]` field. The groups form a tree
+/// through their [`parent`][Self::parent] fields. See
+/// [`Params::param_groups()`][super::Params::param_groups()].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamGroup {
+    /// The group's slash-separated path. This is the same as the group returned for the group's
+    /// parameters in [`Params::param_map()`][super::Params::param_map()].
+    pub id: String,
+    /// The group's display name. This is the last component of the ID, unless a different name was
+    /// set using `group_name = "..."`.
+    pub name: String,
+    /// The ID of the group containing this group, or `None` if this is a top level group.
+    pub parent: Option<String>,
+    /// An optional longer description of the group, set using `group_description = "..."`.
+    pub description: Option<String>,
+    /// Groups with the same parent are displayed in ascending order, and groups with the same order
+    /// are kept in declaration order. Set using `group_order = 1`, defaults to 0.
+    pub order: i32,
+    /// The ID of a [`BoolParam`][super::BoolParam] that bypasses this group, if any. Set using
+    /// `group_bypass = "param_id"`, where `param_id` is the parameter's ID on the nested struct.
+    /// This is the parameter's final ID, with any ID prefixes or array suffixes applied.
+    pub bypass_param_id: Option<String>,
+}
+
+impl ParamGroup {
+    /// Move a group from a nested `Params` struct into its parent struct. This is used by the
+    /// `Params` derive macro.
+    #[doc(hidden)]
+    pub fn nest(mut self, parent_id: Option<&str>, rename_param: impl Fn(&str) -> String) -> Self {
+        if let Some(parent_id) = parent_id {
+            self.id = format!("{}/{}", parent_id, self.id);
+            self.parent = Some(match self.parent {
+                Some(parent) => format!("{}/{}", parent_id, parent),
+                None => parent_id.to_owned(),
+            });
+        }
+        self.bypass_param_id = self.bypass_param_id.map(|param_id| rename_param(&param_id));
+
+        self
+    }
+}
+
+/// Get the direct children of a group, or the top level groups if `parent` is `None`, in display
+/// order.
+pub fn children<'a>(groups: &'a [ParamGroup], parent: Option<&str>) -> Vec<&'a ParamGroup> {
+    let mut children: Vec<&ParamGroup> = groups
+        .iter()
+        .filter(|group| group.parent.as_deref() == parent)
+        .collect();
+    // This is a stable sort, so the declaration order is kept for groups with the same order
+    children.sort_by_key(|group| group.order);
+
+    children
+}
+
+/// Flatten the group tree in display order, with every group followed by its children.
+pub fn display_order(groups: &[ParamGroup]) -> Vec<&ParamGroup> {
+    fn visit<'a>(groups: &'a [ParamGroup], parent: Option<&str>, result: &mut Vec<&'a ParamGroup>) {
+        for group in children(groups, parent) {
+            result.push(group);
+            visit(groups, Some(&group.id), result);
+        }
+    }
+
+    let mut result = Vec::with_capacity(groups.len());
+    visit(groups, None, &mut result);
+
+    result
+}
+
+/// Convert a slash-separated group path to the same path using the groups' display names. Used for
+/// the group paths shown by the host. Components without a matching group are kept as is.
+pub fn display_path(groups: &[ParamGroup], group_id: &str) -> String {
+    let mut display_path = String::new();
+    let mut path = String::new();
+    for component in group_id
+        .split('/')
+        .filter(|component| !component.is_empty())
+    {
+        if !path.is_empty() {
+            path.push('/');
+            display_path.push('/');
+        }
+        path.push_str(component);
+
+        match groups.iter().find(|group| group.id == path) {
+            Some(group) => display_path.push_str(&group.name),
+            None => display_path.push_str(component),
+        }
+    }
+
+    display_path
+}
+
+/// Check whether the groups' bypass parameters exist and are boolean parameters. Called by the
+/// wrappers in debug builds.
+pub(crate) fn check_bypass_params(groups: &[ParamGroup], param_map: &[(String, ParamPtr, String)]) {
+    let params_by_id: HashMap<&str, &ParamPtr> = param_map
+        .iter()
+        .map(|(id, ptr, _)| (id.as_str(), ptr))
+        .collect();
+    for group in groups {
+        if let Some(bypass_param_id) = &group.bypass_param_id {
+            match params_by_id.get(bypass_param_id.as_str()) {
+                Some(ParamPtr::BoolParam(_)) => (),
+                Some(_) => nih_debug_assert_failure!(
+                    "The bypass parameter '{}' for group '{}' is not a BoolParam",
+                    bypass_param_id,
+                    group.id
+                ),
+                None => nih_debug_assert_failure!(
+                    "The bypass parameter '{}' for group '{}' does not exist",
+                    bypass_param_id,
+                    group.id
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(id: &str, name: &str, parent: Option<&str>, order: i32) -> ParamGroup {
+        ParamGroup {
+            id: id.to_owned(),
+            name: name.to_owned(),
+            parent: parent.map(str::to_owned),
+            description: None,
+            order,
+            bypass_param_id: None,
+        }
+    }
+
+    fn make_groups() -> Vec<ParamGroup> {
+        vec![
+            group("fx", "Effects", None, 1),
+            group("fx/delay", "Delay", Some("fx"), 0),
+            group("osc", "Oscillators", None, 0),
+            group("fx/reverb", "Reverb", Some("fx"), -1),
+            group("env", "Envelope", None, 1),
+        ]
+    }
+
+    #[test]
+    fn children_order() {
+        let groups = make_groups();
+
+        let top_level: Vec<&str> = children(&groups, None)
+            .into_iter()
+            .map(|group| group.id.as_str())
+            .collect();
+        assert_eq!(top_level, ["osc", "fx", "env"]);
+
+        let fx: Vec<&str> = children(&groups, Some("fx"))
+            .into_iter()
+            .map(|group| group.id.as_str())
+            .collect();
+        assert_eq!(fx, ["fx/reverb", "fx/delay"]);
+    }
+
+    #[test]
+    fn tree_display_order() {
+        let groups = make_groups();
+
+        let ids: Vec<&str> = display_order(&groups)
+            .into_iter()
+            .map(|group| group.id.as_str())
+            .collect();
+        assert_eq!(ids, ["osc", "fx", "fx/reverb", "fx/delay", "env"]);
+    }
+
+    #[test]
+    fn display_paths() {
+        let groups = make_groups();
+
+        assert_eq!(display_path(&groups, ""), "");
+        assert_eq!(display_path(&groups, "fx/reverb"), "Effects/Reverb");
+        assert_eq!(display_path(&groups, "fx/unknown"), "Effects/unknown");
+    }
+
+    #[test]
+    fn nest() {
+        let mut nested = group("delay", "Delay", None, 0);
+        nested.bypass_param_id = Some(String::from("bypass"));
+
+        let nested = nested.nest(Some("fx"), |param_id| format!("fx_{param_id}"));
+        assert_eq!(nested.id, "fx/delay");
+        assert_eq!(nested.parent.as_deref(), Some("fx"));
+        assert_eq!(nested.bypass_param_id.as_deref(), Some("fx_bypass"));
+
+        let nested = nested.nest(Some("top"), |param_id| param_id.to_owned());
+        assert_eq!(nested.id, "top/fx/delay");
+        assert_eq!(nested.parent.as_deref(), Some("top/fx"));
+    }
+}
@@ -1,483 +1,992 @@
-//! Different ranges for numeric parameters.
-
-use crate::util;
-
-/// A distribution for a floating point parameter's range. All range endpoints are inclusive.
-#[derive(Debug, Clone, Copy)]
-pub enum FloatRange {
-    /// The values are uniformly distributed between `min` and `max`.
-    Linear { min: f32, max: f32 },
-    /// The range is skewed by a factor. Values above 1.0 will make the end of the range wider,
-    /// while values between 0 and 1 will skew the range towards the start. Use
-    /// [`FloatRange::skew_factor()`] for a more intuitively way to calculate the skew factor where
-    /// positive values skew the range towards the end while negative values skew the range toward
-    /// the start.
-    Skewed { min: f32, max: f32, factor: f32 },
-    /// The same as [`FloatRange::Skewed`], but with the skewing happening from a central point.
-    /// This central point is rescaled to be at 50% of the parameter's range for convenience of use.
-    /// Git blame this comment to find a version that doesn't do this.
-    SymmetricalSkewed {
-        min: f32,
-        max: f32,
-        factor: f32,
-        center: f32,
-    },
-    /// A reversed range that goes from high to low instead of from low to high.
-    Reversed(&'static FloatRange),
-}
-
-/// A distribution for an integer parameter's range. All range endpoints are inclusive. Only linear
-/// ranges are supported for integers since hosts expect discrete parameters to have a fixed step
-/// size.
-#[derive(Debug, Clone, Copy)]
-pub enum IntRange {
-    /// The values are uniformly distributed between `min` and `max`.
-    Linear { min: i32, max: i32 },
-    /// A reversed range that goes from high to low instead of from low to high.
-    Reversed(&'static IntRange),
-}
-
-impl FloatRange {
-    /// Calculate a skew factor for [`FloatRange::Skewed`] and [`FloatRange::SymmetricalSkewed`].
-    /// Positive values make the end of the range wider while negative make the start of the range
-    /// wider.
-    pub fn skew_factor(factor: f32) -> f32 {
-        2.0f32.powf(factor)
-    }
-
-    /// Calculate a skew factor for [`FloatRange::Skewed`] that makes a linear gain parameter range
-    /// appear as if it was linear when formatted as decibels.
-    pub fn gain_skew_factor(min_db: f32, max_db: f32) -> f32 {
-        nih_debug_assert!(min_db < max_db);
-
-        let min_gain = util::db_to_gain(min_db);
-        let max_gain = util::db_to_gain(max_db);
-        let middle_db = (max_db + min_db) / 2.0;
-        let middle_gain = util::db_to_gain(middle_db);
-
-        // Check the Skewed equation in the normalized function below, we need to solve the factor
-        // such that the a normalized value of 0.5 resolves to the middle of the range
-        0.5f32.log((middle_gain - min_gain) / (max_gain - min_gain))
-    }
-
-    /// Normalize a plain, unnormalized value. Will be clamped to the bounds of the range if the
-    /// normalized value exceeds `[0, 1]`.
-    pub fn normalize(&self, plain: f32) -> f32 {
-        match self {
-            FloatRange::Linear { min, max } => (plain.clamp(*min, *max) - min) / (max - min),
-            FloatRange::Skewed { min, max, factor } => {
-                ((plain.clamp(*min, *max) - min) / (max - min)).powf(*factor)
-            }
-            FloatRange::SymmetricalSkewed {
-                min,
-                max,
-                factor,
-                center,
-            } => {
-                // There's probably a much faster equivalent way to write this. Also, I have no clue
-                // how I managed to implement this correctly on the first try.
-                let unscaled_proportion = (plain.clamp(*min, *max) - min) / (max - min);
-                let center_proportion = (center - min) / (max - min);
-                if unscaled_proportion > center_proportion {
-                    // The part above the center gets normalized to a [0, 1] range, skewed, and then
-                    // unnormalized and scaled back to the original [center_proportion, 1] range
-                    let scaled_proportion = (unscaled_proportion - center_proportion)
-                        * (1.0 - center_proportion).recip();
-                    (scaled_proportion.powf(*factor) * 0.5) + 0.5
-                } else {
-                    // The part below the center gets scaled, inverted (so the range is [0, 1] where
-                    // 0 corresponds to the center proportion and 1 corresponds to the original
-                    // normalized 0 value), skewed, inverted back again, and then scaled back to the
-                    // original range
-                    let inverted_scaled_proportion =
-                        (center_proportion - unscaled_proportion) * (center_proportion).recip();
-                    (1.0 - inverted_scaled_proportion.powf(*factor)) * 0.5
-                }
-            }
-            FloatRange::Reversed(range) => 1.0 - range.normalize(plain),
-        }
-    }
-
-    /// Unnormalize a normalized value. Will be clamped to `[0, 1]` if the plain, unnormalized value
-    /// would exceed that range.
-    pub fn unnormalize(&self, normalized: f32) -> f32 {
-        let normalized = normalized.clamp(0.0, 1.0);
-        match self {
-            FloatRange::Linear { min, max } => (normalized * (max - min)) + min,
-            FloatRange::Skewed { min, max, factor } => {
-                (normalized.powf(factor.recip()) * (max - min)) + min
-            }
-            FloatRange::SymmetricalSkewed {
-                min,
-                max,
-                factor,
-                center,
-            } => {
-                // Reconstructing the subranges works the same as with the normal skewed ranges
-                let center_proportion = (center - min) / (max - min);
-                let skewed_proportion = if normalized > 0.5 {
-                    let scaled_proportion = (normalized - 0.5) * 2.0;
-                    (scaled_proportion.powf(factor.recip()) * (1.0 - center_proportion))
-                        + center_proportion
-                } else {
-                    let inverted_scaled_proportion = (0.5 - normalized) * 2.0;
-                    (1.0 - inverted_scaled_proportion.powf(factor.recip())) * center_proportion
-                };
-
-                (skewed_proportion * (max - min)) + min
-            }
-            FloatRange::Reversed(range) => range.unnormalize(1.0 - normalized),
-        }
-    }
-
-    /// The range's previous discrete step from a certain value with a certain step size. If the
-    /// step size is not set, then the normalized range is split into 50 segments instead. If
-    /// `finer` is true, then this is upped to 200 segments.
-    pub fn previous_step(&self, from: f32, step_size: Option<f32>, finer: bool) -> f32 {
-        // This one's slightly more involved than the integer version. We'll split the normalized
-        // range up into 50 segments, but if `self.step_size` would cause the range to be devided
-        // into less than 50 segments then we'll use that.
-        match self {
-            FloatRange::Linear { min, max }
-            | FloatRange::Skewed { min, max, .. }
-            | FloatRange::SymmetricalSkewed { min, max, .. } => {
-                let normalized_naive_step_size = if finer { 0.005 } else { 0.02 };
-                let naive_step =
-                    self.unnormalize(self.normalize(from) - normalized_naive_step_size);
-
-                match step_size {
-                    // Use the naive step size if it is larger than the configured step size
-                    Some(step_size) if (naive_step - from).abs() > step_size => {
-                        self.snap_to_step(naive_step, step_size)
-                    }
-                    Some(step_size) => from - step_size,
-                    None => naive_step,
-                }
-                .clamp(*min, *max)
-            }
-            FloatRange::Reversed(range) => range.next_step(from, step_size, finer),
-        }
-    }
-
-    /// The range's next discrete step from a certain value with a certain step size. If the step
-    /// size is not set, then the normalized range is split into 100 segments instead.
-    pub fn next_step(&self, from: f32, step_size: Option<f32>, finer: bool) -> f32 {
-        // See above
-        match self {
-            FloatRange::Linear { min, max }
-            | FloatRange::Skewed { min, max, .. }
-            | FloatRange::SymmetricalSkewed { min, max, .. } => {
-                let normalized_naive_step_size = if finer { 0.005 } else { 0.02 };
-                let naive_step =
-                    self.unnormalize(self.normalize(from) + normalized_naive_step_size);
-
-                match step_size {
-                    Some(step_size) if (naive_step - from).abs() > step_size => {
-                        self.snap_to_step(naive_step, step_size)
-                    }
-                    Some(step_size) => from + step_size,
-                    None => naive_step,
-                }
-                .clamp(*min, *max)
-            }
-            FloatRange::Reversed(range) => range.previous_step(from, step_size, finer),
-        }
-    }
-
-    /// Snap a value to a step size, clamping to the minimum and maximum value of the range.
-    pub fn snap_to_step(&self, value: f32, step_size: f32) -> f32 {
-        match self {
-            FloatRange::Linear { min, max }
-            | FloatRange::Skewed { min, max, .. }
-            | FloatRange::SymmetricalSkewed { min, max, .. } => {
-                ((value / step_size).round() * step_size).clamp(*min, *max)
-            }
-            FloatRange::Reversed(range) => range.snap_to_step(value, step_size),
-        }
-    }
-
-    /// Emits debug assertions to make sure that range minima are always less than the maxima and
-    /// that they are not equal.
-    pub(super) fn assert_validity(&self) {
-        match self {
-            FloatRange::Linear { min, max }
-            | FloatRange::Skewed { min, max, .. }
-            | FloatRange::SymmetricalSkewed { min, max, .. } => {
-                nih_debug_assert!(
-                    min < max,
-                    "The range minimum ({}) needs to be less than the range maximum ({}) and they \
-                     cannot be equal",
-                    min,
-                    max
-                );
-            }
-            FloatRange::Reversed(range) => range.assert_validity(),
-        }
-    }
-}
-
-impl IntRange {
-    /// Normalize a plain, unnormalized value. Will be clamped to the bounds of the range if the
-    /// normalized value exceeds `[0, 1]`.
-    pub fn normalize(&self, plain: i32) -> f32 {
-        match self {
-            IntRange::Linear { min, max } => (plain - min) as f32 / (max - min) as f32,
-            IntRange::Reversed(range) => 1.0 - range.normalize(plain),
-        }
-        .clamp(0.0, 1.0)
-    }
-
-    /// Unnormalize a normalized value. Will be clamped to `[0, 1]` if the plain, unnormalized value
-    /// would exceed that range.
-    pub fn unnormalize(&self, normalized: f32) -> i32 {
-        let normalized = normalized.clamp(0.0, 1.0);
-        match self {
-            IntRange::Linear { min, max } => (normalized * (max - min) as f32).round() as i32 + min,
-            IntRange::Reversed(range) => range.unnormalize(1.0 - normalized),
-        }
-    }
-
-    /// The range's previous discrete step from a certain value.
-    pub fn previous_step(&self, from: i32) -> i32 {
-        match self {
-            IntRange::Linear { min, max } => (from - 1).clamp(*min, *max),
-            IntRange::Reversed(range) => range.next_step(from),
-        }
-    }
-
-    /// The range's next discrete step from a certain value.
-    pub fn next_step(&self, from: i32) -> i32 {
-        match self {
-            IntRange::Linear { min, max } => (from + 1).clamp(*min, *max),
-            IntRange::Reversed(range) => range.previous_step(from),
-        }
-    }
-
-    /// The number of steps in this range. Used for the host's generic UI.
-    pub fn step_count(&self) -> usize {
-        match self {
-            IntRange::Linear { min, max } => (max - min) as usize,
-            IntRange::Reversed(range) => range.step_count(),
-        }
-    }
-
-    /// If this range is wrapped in an adapter, like `Reversed`, then return the wrapped range.
-    pub fn inner_range(&self) -> Self {
-        match self {
-            IntRange::Linear { .. } => *self,
-            IntRange::Reversed(range) => range.inner_range(),
-        }
-    }
-
-    /// Emits debug assertions to make sure that range minima are always less than the maxima and
-    /// that they are not equal.
-    pub(super) fn assert_validity(&self) {
-        match self {
-            IntRange::Linear { min, max } => {
-                nih_debug_assert!(
-                    min < max,
-                    "The range minimum ({}) needs to be less than the range maximum ({}) and they \
-                     cannot be equal",
-                    min,
-                    max
-                );
-            }
-            IntRange::Reversed(range) => range.assert_validity(),
-        }
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    const fn make_linear_float_range() -> FloatRange {
-        FloatRange::Linear {
-            min: 10.0,
-            max: 20.0,
-        }
-    }
-
-    const fn make_linear_int_range() -> IntRange {
-        IntRange::Linear { min: -10, max: 10 }
-    }
-
-    const fn make_skewed_float_range(factor: f32) -> FloatRange {
-        FloatRange::Skewed {
-            min: 10.0,
-            max: 20.0,
-            factor,
-        }
-    }
-
-    const fn make_symmetrical_skewed_float_range(factor: f32) -> FloatRange {
-        FloatRange::SymmetricalSkewed {
-            min: 10.0,
-            max: 20.0,
-            factor,
-            center: 12.5,
-        }
-    }
-
-    #[test]
-    fn step_size() {
-        // These are weird step sizes, but if it works here then it will work for anything
-        let range = make_linear_float_range();
-        // XXX: We round to decimal places when outputting, but not when snapping to steps
-        assert_eq!(range.snap_to_step(13.0, 4.73), 14.190001);
-    }
-
-    #[test]
-    fn step_size_clamping() {
-        let range = make_linear_float_range();
-        assert_eq!(range.snap_to_step(10.0, 4.73), 10.0);
-        assert_eq!(range.snap_to_step(20.0, 6.73), 20.0);
-    }
-
-    mod linear {
-        use super::*;
-
-        #[test]
-        fn range_normalize_float() {
-            let range = make_linear_float_range();
-            assert_eq!(range.normalize(17.5), 0.75);
-        }
-
-        #[test]
-        fn range_normalize_int() {
-            let range = make_linear_int_range();
-            assert_eq!(range.normalize(-5), 0.25);
-        }
-
-        #[test]
-        fn range_unnormalize_float() {
-            let range = make_linear_float_range();
-            assert_eq!(range.unnormalize(0.25), 12.5);
-        }
-
-        #[test]
-        fn range_unnormalize_int() {
-            let range = make_linear_int_range();
-            assert_eq!(range.unnormalize(0.75), 5);
-        }
-
-        #[test]
-        fn range_unnormalize_int_rounding() {
-            let range = make_linear_int_range();
-            assert_eq!(range.unnormalize(0.73), 5);
-        }
-    }
-
-    mod skewed {
-        use super::*;
-
-        #[test]
-        fn range_normalize_float() {
-            let range = make_skewed_float_range(FloatRange::skew_factor(-2.0));
-            assert_eq!(range.normalize(17.5), 0.9306049);
-        }
-
-        #[test]
-        fn range_unnormalize_float() {
-            let range = make_skewed_float_range(FloatRange::skew_factor(-2.0));
-            assert_eq!(range.unnormalize(0.9306049), 17.5);
-        }
-
-        #[test]
-        fn range_normalize_linear_equiv_float() {
-            let linear_range = make_linear_float_range();
-            let skewed_range = make_skewed_float_range(1.0);
-            assert_eq!(linear_range.normalize(17.5), skewed_range.normalize(17.5));
-        }
-
-        #[test]
-        fn range_unnormalize_linear_equiv_float() {
-            let linear_range = make_linear_float_range();
-            let skewed_range = make_skewed_float_range(1.0);
-            assert_eq!(
-                linear_range.unnormalize(0.25),
-                skewed_range.unnormalize(0.25)
-            );
-        }
-    }
-
-    mod symmetrical_skewed {
-        use super::*;
-
-        #[test]
-        fn range_normalize_float() {
-            let range = make_symmetrical_skewed_float_range(FloatRange::skew_factor(-2.0));
-            assert_eq!(range.normalize(17.5), 0.951801);
-        }
-
-        #[test]
-        fn range_unnormalize_float() {
-            let range = make_symmetrical_skewed_float_range(FloatRange::skew_factor(-2.0));
-            assert_eq!(range.unnormalize(0.951801), 17.5);
-        }
-    }
-
-    mod reversed_linear {
-        use super::*;
-
-        #[test]
-        fn range_normalize_int() {
-            const WRAPPED_RANGE: IntRange = make_linear_int_range();
-            let range = IntRange::Reversed(&WRAPPED_RANGE);
-            assert_eq!(range.normalize(-5), 1.0 - 0.25);
-        }
-
-        #[test]
-        fn range_unnormalize_int() {
-            const WRAPPED_RANGE: IntRange = make_linear_int_range();
-            let range = IntRange::Reversed(&WRAPPED_RANGE);
-            assert_eq!(range.unnormalize(1.0 - 0.75), 5);
-        }
-
-        #[test]
-        fn range_unnormalize_int_rounding() {
-            const WRAPPED_RANGE: IntRange = make_linear_int_range();
-            let range = IntRange::Reversed(&WRAPPED_RANGE);
-            assert_eq!(range.unnormalize(1.0 - 0.73), 5);
-        }
-    }
-
-    mod reversed_skewed {
-        use super::*;
-
-        #[test]
-        fn range_normalize_float() {
-            const WRAPPED_RANGE: FloatRange = make_skewed_float_range(0.25);
-            let range = FloatRange::Reversed(&WRAPPED_RANGE);
-            assert_eq!(range.normalize(17.5), 1.0 - 0.9306049);
-        }
-
-        #[test]
-        fn range_unnormalize_float() {
-            const WRAPPED_RANGE: FloatRange = make_skewed_float_range(0.25);
-            let range = FloatRange::Reversed(&WRAPPED_RANGE);
-            assert_eq!(range.unnormalize(1.0 - 0.9306049), 17.5);
-        }
-
-        #[test]
-        fn range_normalize_linear_equiv_float() {
-            const WRAPPED_LINEAR_RANGE: FloatRange = make_linear_float_range();
-            const WRAPPED_SKEWED_RANGE: FloatRange = make_skewed_float_range(1.0);
-            let linear_range = FloatRange::Reversed(&WRAPPED_LINEAR_RANGE);
-            let skewed_range = FloatRange::Reversed(&WRAPPED_SKEWED_RANGE);
-            assert_eq!(linear_range.normalize(17.5), skewed_range.normalize(17.5));
-        }
-
-        #[test]
-        fn range_unnormalize_linear_equiv_float() {
-            const WRAPPED_LINEAR_RANGE: FloatRange = make_linear_float_range();
-            const WRAPPED_SKEWED_RANGE: FloatRange = make_skewed_float_range(1.0);
-            let linear_range = FloatRange::Reversed(&WRAPPED_LINEAR_RANGE);
-            let skewed_range = FloatRange::Reversed(&WRAPPED_SKEWED_RANGE);
-            assert_eq!(
-                linear_range.unnormalize(0.25),
-                skewed_range.unnormalize(0.25)
-            );
-        }
-    }
-}
+//! Different ranges for numeric parameters.
+
+use crate::util;
+
+/// A distribution for a floating point parameter's range. All range endpoints are inclusive.
+#[derive(Debug, Clone, Copy)]
+pub enum FloatRange {
+    /// The values are uniformly distributed between `min` and `max`.
+    Linear { min: f32, max: f32 },
+    /// The range is skewed by a factor. Values above 1.0 will make the end of the range wider,
+    /// while values between 0 and 1 will skew the range towards the start. Use
+    /// [`FloatRange::skew_factor()`] for a more intuitively way to calculate the skew factor where
+    /// positive values skew the range towards the end while negative values skew the range toward
+    /// the start.
+    Skewed { min: f32, max: f32, factor: f32 },
+    /// The same as [`FloatRange::Skewed`], but with the skewing happening from a central point.
+    /// This central point is rescaled to be at 50% of the parameter's range for convenience of use.
+    /// Git blame this comment to find a version that doesn't do this.
+    SymmetricalSkewed {
+        min: f32,
+        max: f32,
+        factor: f32,
+        center: f32,
+    },
+    /// A logarithmic range where every doubling of the value takes up the same amount of the
+    /// normalized range. This is the natural mapping for frequencies and times. Both `min` and
+    /// `max` need to be positive.
+    Exponential { min: f32, max: f32 },
+    /// A range made up of linear segments between breakpoints. Every breakpoint is a `(plain,
+    /// normalized)` pair. The breakpoints need to be sorted, both the plain and the normalized
+    /// values need to be strictly increasing, and the first and last breakpoints need to have
+    /// normalized values of 0.0 and 1.0.
+    ///
+    /// ```
+    /// # use nih_plug::prelude::FloatRange;
+    /// // The first half of the range covers 20 Hz to 1 kHz
+    /// const FREQUENCY_RANGE: FloatRange = FloatRange::PiecewiseLinear {
+    ///     breakpoints: &[(20.0, 0.0), (1_000.0, 0.5), (20_000.0, 1.0)],
+    /// };
+    /// ```
+    PiecewiseLinear { breakpoints: &'static [(f32, f32)] },
+    /// A range with a user defined mapping. `normalize` maps plain values in `[min, max]` to `[0,
+    /// 1]`, and `unnormalize` is its inverse. Both functions need to be monotonically increasing,
+    /// with `min` mapping to 0.0 and `max` mapping to 1.0. The values are clamped before and after
+    /// calling these functions.
+    Custom {
+        min: f32,
+        max: f32,
+        normalize: fn(f32) -> f32,
+        unnormalize: fn(f32) -> f32,
+    },
+    /// A reversed range that goes from high to low instead of from low to high.
+    Reversed(&'static FloatRange),
+}
+
+/// A distribution for an integer parameter's range. All range endpoints are inclusive. Hosts
+/// expect discrete parameters to have a fixed step size in the normalized domain, so every kind of
+/// range maps its values to evenly spaced normalized steps.
+#[derive(Debug, Clone, Copy)]
+pub enum IntRange {
+    /// The values are uniformly distributed between `min` and `max`.
+    Linear { min: i32, max: i32 },
+    /// An explicit list of values, like a list of buffer sizes. Every value is a single step for
+    /// the host. The values need to be unique and sorted in ascending order. Plain values that are
+    /// not in the list are rounded to the nearest value.
+    Values(&'static [i32]),
+    /// Powers of two between `min` and `max`, which also need to be powers of two. For instance,
+    /// `min: 64, max: 8192` results in the eight values `64, 128, ..., 8192`. The parameter's plain
+    /// value is the power of two itself, not the exponent. Other values are rounded to the nearest
+    /// power of two in the logarithmic domain.
+    PowerOfTwo { min: i32, max: i32 },
+    /// A reversed range that goes from high to low instead of from low to high.
+    Reversed(&'static IntRange),
+}
+
+impl FloatRange {
+    /// Calculate a skew factor for [`FloatRange::Skewed`] and [`FloatRange::SymmetricalSkewed`].
+    /// Positive values make the end of the range wider while negative make the start of the range
+    /// wider.
+    pub fn skew_factor(factor: f32) -> f32 {
+        2.0f32.powf(factor)
+    }
+
+    /// Calculate a skew factor for [`FloatRange::Skewed`] that makes a linear gain parameter range
+    /// appear as if it was linear when formatted as decibels.
+    pub fn gain_skew_factor(min_db: f32, max_db: f32) -> f32 {
+        nih_debug_assert!(min_db < max_db);
+
+        let min_gain = util::db_to_gain(min_db);
+        let max_gain = util::db_to_gain(max_db);
+        let middle_db = (max_db + min_db) / 2.0;
+        let middle_gain = util::db_to_gain(middle_db);
+
+        // Check the Skewed equation in the normalized function below, we need to solve the factor
+        // such that the a normalized value of 0.5 resolves to the middle of the range
+        0.5f32.log((middle_gain - min_gain) / (max_gain - min_gain))
+    }
+
+    /// Normalize a plain, unnormalized value. Will be clamped to the bounds of the range if the
+    /// normalized value exceeds `[0, 1]`.
+    pub fn normalize(&self, plain: f32) -> f32 {
+        match self {
+            FloatRange::Linear { min, max } => (plain.clamp(*min, *max) - min) / (max - min),
+            FloatRange::Skewed { min, max, factor } => {
+                ((plain.clamp(*min, *max) - min) / (max - min)).powf(*factor)
+            }
+            FloatRange::SymmetricalSkewed {
+                min,
+                max,
+                factor,
+                center,
+            } => {
+                // There's probably a much faster equivalent way to write this. Also, I have no clue
+                // how I managed to implement this correctly on the first try.
+                let unscaled_proportion = (plain.clamp(*min, *max) - min) / (max - min);
+                let center_proportion = (center - min) / (max - min);
+                if unscaled_proportion > center_proportion {
+                    // The part above the center gets normalized to a [0, 1] range, skewed, and then
+                    // unnormalized and scaled back to the original [center_proportion, 1] range
+                    let scaled_proportion = (unscaled_proportion - center_proportion)
+                        * (1.0 - center_proportion).recip();
+                    (scaled_proportion.powf(*factor) * 0.5) + 0.5
+                } else {
+                    // The part below the center gets scaled, inverted (so the range is [0, 1] where
+                    // 0 corresponds to the center proportion and 1 corresponds to the original
+                    // normalized 0 value), skewed, inverted back again, and then scaled back to the
+                    // original range
+                    let inverted_scaled_proportion =
+                        (center_proportion - unscaled_proportion) * (center_proportion).recip();
+                    (1.0 - inverted_scaled_proportion.powf(*factor)) * 0.5
+                }
+            }
+            FloatRange::Exponential { min, max } => {
+                (plain.clamp(*min, *max) / min).ln() / (max / min).ln()
+            }
+            FloatRange::PiecewiseLinear { breakpoints } => {
+                let (min, max) = self.bounds();
+                let plain = plain.clamp(min, max);
+
+                // The breakpoints are sorted, so the first segment ending at or after the value
+                // contains the value
+                match breakpoints.windows(2).find(|segment| plain <= segment[1].0) {
+                    Some(&[(start_plain, start_normalized), (end_plain, end_normalized)]) => {
+                        let t = (plain - start_plain) / (end_plain - start_plain);
+                        start_normalized + (t * (end_normalized - start_normalized))
+                    }
+                    _ => 1.0,
+                }
+            }
+            FloatRange::Custom {
+                min,
+                max,
+                normalize,
+                ..
+            } => normalize(plain.clamp(*min, *max)).clamp(0.0, 1.0),
+            FloatRange::Reversed(range) => 1.0 - range.normalize(plain),
+        }
+    }
+
+    /// Unnormalize a normalized value. Will be clamped to `[0, 1]` if the plain, unnormalized value
+    /// would exceed that range.
+    pub fn unnormalize(&self, normalized: f32) -> f32 {
+        let normalized = normalized.clamp(0.0, 1.0);
+        match self {
+            FloatRange::Linear { min, max } => (normalized * (max - min)) + min,
+            FloatRange::Skewed { min, max, factor } => {
+                (normalized.powf(factor.recip()) * (max - min)) + min
+            }
+            FloatRange::SymmetricalSkewed {
+                min,
+                max,
+                factor,
+                center,
+            } => {
+                // Reconstructing the subranges works the same as with the normal skewed ranges
+                let center_proportion = (center - min) / (max - min);
+                let skewed_proportion = if normalized > 0.5 {
+                    let scaled_proportion = (normalized - 0.5) * 2.0;
+                    (scaled_proportion.powf(factor.recip()) * (1.0 - center_proportion))
+                        + center_proportion
+                } else {
+                    let inverted_scaled_proportion = (0.5 - normalized) * 2.0;
+                    (1.0 - inverted_scaled_proportion.powf(factor.recip())) * center_proportion
+                };
+
+                (skewed_proportion * (max - min)) + min
+            }
+            FloatRange::Exponential { min, max } => min * (max / min).powf(normalized),
+            FloatRange::PiecewiseLinear { breakpoints } => {
+                match breakpoints
+                    .windows(2)
+                    .find(|segment| normalized <= segment[1].1)
+                {
+                    Some(&[(start_plain, start_normalized), (end_plain, end_normalized)]) => {
+                        let t =
+                            (normalized - start_normalized) / (end_normalized - start_normalized);
+                        start_plain + (t * (end_plain - start_plain))
+                    }
+                    _ => self.bounds().1,
+                }
+            }
+            FloatRange::Custom {
+                min,
+                max,
+                unnormalize,
+                ..
+            } => unnormalize(normalized).clamp(*min, *max),
+            FloatRange::Reversed(range) => range.unnormalize(1.0 - normalized),
+        }
+    }
+
+    /// The range's minimum and maximum plain values. For [`FloatRange::Reversed`] these are the
+    /// wrapped range's bounds, so the minimum is still the smaller value.
+    pub fn bounds(&self) -> (f32, f32) {
+        match self {
+            FloatRange::Linear { min, max }
+            | FloatRange::Skewed { min, max, .. }
+            | FloatRange::SymmetricalSkewed { min, max, .. }
+            | FloatRange::Exponential { min, max }
+            | FloatRange::Custom { min, max, .. } => (*min, *max),
+            FloatRange::PiecewiseLinear { breakpoints } => match breakpoints {
+                [(min, _), .., (max, _)] => (*min, *max),
+                // This is invalid, but `assert_validity()` will already complain about this
+                [(value, _)] => (*value, *value),
+                [] => (0.0, 0.0),
+            },
+            FloatRange::Reversed(range) => range.bounds(),
+        }
+    }
+
+    /// The range's previous discrete step from a certain value with a certain step size. If the
+    /// step size is not set, then the normalized range is split into 50 segments instead. If
+    /// `finer` is true, then this is upped to 200 segments.
+    pub fn previous_step(&self, from: f32, step_size: Option<f32>, finer: bool) -> f32 {
+        // This one's slightly more involved than the integer version. We'll split the normalized
+        // range up into 50 segments, but if `self.step_size` would cause the range to be devided
+        // into less than 50 segments then we'll use that.
+        match self {
+            FloatRange::Reversed(range) => range.next_step(from, step_size, finer),
+            _ => {
+                let (min, max) = self.bounds();
+                let normalized_naive_step_size = if finer { 0.005 } else { 0.02 };
+                let naive_step =
+                    self.unnormalize(self.normalize(from) - normalized_naive_step_size);
+
+                match step_size {
+                    // Use the naive step size if it is larger than the configured step size
+                    Some(step_size) if (naive_step - from).abs() > step_size => {
+                        self.snap_to_step(naive_step, step_size)
+                    }
+                    Some(step_size) => from - step_size,
+                    None => naive_step,
+                }
+                .clamp(min, max)
+            }
+        }
+    }
+
+    /// The range's next discrete step from a certain value with a certain step size. If the step
+    /// size is not set, then the normalized range is split into 100 segments instead.
+    pub fn next_step(&self, from: f32, step_size: Option<f32>, finer: bool) -> f32 {
+        // See above
+        match self {
+            FloatRange::Reversed(range) => range.previous_step(from, step_size, finer),
+            _ => {
+                let (min, max) = self.bounds();
+                let normalized_naive_step_size = if finer { 0.005 } else { 0.02 };
+                let naive_step =
+                    self.unnormalize(self.normalize(from) + normalized_naive_step_size);
+
+                match step_size {
+                    Some(step_size) if (naive_step - from).abs() > step_size => {
+                        self.snap_to_step(naive_step, step_size)
+                    }
+                    Some(step_size) => from + step_size,
+                    None => naive_step,
+                }
+                .clamp(min, max)
+            }
+        }
+    }
+
+    /// Snap a value to a step size, clamping to the minimum and maximum value of the range. The
+    /// steps are multiples of the step size in the plain value domain for every kind of range, so
+    /// an [`FloatRange::Exponential`] range with a step size of 1.0 snaps to whole numbers.
+    pub fn snap_to_step(&self, value: f32, step_size: f32) -> f32 {
+        let (min, max) = self.bounds();
+
+        ((value / step_size).round() * step_size).clamp(min, max)
+    }
+
+    /// Emits debug assertions to make sure that range minima are always less than the maxima and
+    /// that they are not equal.
+    pub(super) fn assert_validity(&self) {
+        match self {
+            FloatRange::Linear { min, max }
+            | FloatRange::Skewed { min, max, .. }
+            | FloatRange::SymmetricalSkewed { min, max, .. } => {
+                nih_debug_assert!(
+                    min < max,
+                    "The range minimum ({}) needs to be less than the range maximum ({}) and they \
+                     cannot be equal",
+                    min,
+                    max
+                );
+            }
+            FloatRange::Exponential { min, max } => {
+                nih_debug_assert!(
+                    *min > 0.0 && min < max,
+                    "Exponential ranges need a positive minimum ({}) that's less than the maximum \
+                     ({})",
+                    min,
+                    max
+                );
+            }
+            FloatRange::PiecewiseLinear { breakpoints } => {
+                nih_debug_assert!(
+                    breakpoints.len() >= 2,
+                    "Piecewise linear ranges need at least two breakpoints"
+                );
+                nih_debug_assert!(
+                    breakpoints
+                        .windows(2)
+                        .all(|segment| segment[0].0 < segment[1].0 && segment[0].1 < segment[1].1),
+                    "The breakpoints of a piecewise linear range need to be strictly increasing"
+                );
+                if let (Some((_, first_normalized)), Some((_, last_normalized))) =
+                    (breakpoints.first(), breakpoints.last())
+                {
+                    nih_debug_assert!(
+                        *first_normalized == 0.0 && *last_normalized == 1.0,
+                        "The breakpoints of a piecewise linear range need to cover the entire \
+                         normalized range"
+                    );
+                }
+            }
+            FloatRange::Custom {
+                min,
+                max,
+                normalize,
+                ..
+            } => {
+                nih_debug_assert!(
+                    min < max,
+                    "The range minimum ({}) needs to be less than the range maximum ({}) and they \
+                     cannot be equal",
+                    min,
+                    max
+                );
+                nih_debug_assert!(
+                    normalize(*min).abs() < 1e-4 && (normalize(*max) - 1.0).abs() < 1e-4,
+                    "A custom range's normalize function needs to map the minimum to 0.0 and the \
+                     maximum to 1.0"
+                );
+            }
+            FloatRange::Reversed(range) => range.assert_validity(),
+        }
+    }
+}
+
+impl IntRange {
+    /// Normalize a plain, unnormalized value. Will be clamped to the bounds of the range if the
+    /// normalized value exceeds `[0, 1]`.
+    pub fn normalize(&self, plain: i32) -> f32 {
+        match self {
+            IntRange::Linear { min, max } => (plain - min) as f32 / (max - min) as f32,
+            IntRange::Values(values) => {
+                // The values are sorted, so the nearest value is either the first value that's at
+                // least as large as the plain value or the value right before that
+                let idx = values.partition_point(|value| *value < plain);
+                let idx = match idx {
+                    0 => 0,
+                    idx if idx == values.len() => idx - 1,
+                    idx if (plain as i64 - values[idx - 1] as i64)
+                        < (values[idx] as i64 - plain as i64) =>
+                    {
+                        idx - 1
+                    }
+                    idx => idx,
+                };
+
+                idx as f32 / self.step_count() as f32
+            }
+            IntRange::PowerOfTwo { min, max } => {
+                let exponent = (plain.max(1) as f32).log2().round() as i32;
+                let exponent =
+                    exponent.clamp(power_of_two_exponent(*min), power_of_two_exponent(*max));
+
+                (exponent - power_of_two_exponent(*min)) as f32 / self.step_count() as f32
+            }
+            IntRange::Reversed(range) => 1.0 - range.normalize(plain),
+        }
+        .clamp(0.0, 1.0)
+    }
+
+    /// Unnormalize a normalized value. Will be clamped to `[0, 1]` if the plain, unnormalized value
+    /// would exceed that range.
+    pub fn unnormalize(&self, normalized: f32) -> i32 {
+        let normalized = normalized.clamp(0.0, 1.0);
+        match self {
+            IntRange::Linear { min, max } => (normalized * (max - min) as f32).round() as i32 + min,
+            IntRange::Values(values) => {
+                let idx = (normalized * self.step_count() as f32).round() as usize;
+                values
+                    .get(idx.min(values.len().saturating_sub(1)))
+                    .copied()
+                    .unwrap_or_default()
+            }
+            IntRange::PowerOfTwo { min, .. } => {
+                let step = (normalized * self.step_count() as f32).round() as i32;
+                1 << (power_of_two_exponent(*min) + step)
+            }
+            IntRange::Reversed(range) => range.unnormalize(1.0 - normalized),
+        }
+    }
+
+    /// The range's minimum and maximum plain values. For [`IntRange::Reversed`] these are the
+    /// wrapped range's bounds, so the minimum is still the smaller value.
+    pub fn bounds(&self) -> (i32, i32) {
+        match self {
+            IntRange::Linear { min, max } | IntRange::PowerOfTwo { min, max } => (*min, *max),
+            IntRange::Values(values) => (
+                values.first().copied().unwrap_or_default(),
+                values.last().copied().unwrap_or_default(),
+            ),
+            IntRange::Reversed(range) => range.bounds(),
+        }
+    }
+
+    /// The range's previous discrete step from a certain value.
+    pub fn previous_step(&self, from: i32) -> i32 {
+        match self {
+            IntRange::Linear { min, max } => (from - 1).clamp(*min, *max),
+            IntRange::Values(_) | IntRange::PowerOfTwo { .. } => {
+                let step_size = (self.step_count() as f32).recip();
+                self.unnormalize(self.normalize(from) - step_size)
+            }
+            IntRange::Reversed(range) => range.next_step(from),
+        }
+    }
+
+    /// The range's next discrete step from a certain value.
+    pub fn next_step(&self, from: i32) -> i32 {
+        match self {
+            IntRange::Linear { min, max } => (from + 1).clamp(*min, *max),
+            IntRange::Values(_) | IntRange::PowerOfTwo { .. } => {
+                let step_size = (self.step_count() as f32).recip();
+                self.unnormalize(self.normalize(from) + step_size)
+            }
+            IntRange::Reversed(range) => range.previous_step(from),
+        }
+    }
+
+    /// The number of steps in this range. Used for the host's generic UI.
+    pub fn step_count(&self) -> usize {
+        match self {
+            IntRange::Linear { min, max } => (max - min) as usize,
+            // This avoids dividing by zero for invalid ranges, `assert_validity()` will already
+            // complain about those
+            IntRange::Values(values) => values.len().saturating_sub(1).max(1),
+            IntRange::PowerOfTwo { min, max } => {
+                (power_of_two_exponent(*max) - power_of_two_exponent(*min)).max(1) as usize
+            }
+            IntRange::Reversed(range) => range.step_count(),
+        }
+    }
+
+    /// If this range is wrapped in an adapter, like `Reversed`, then return the wrapped range.
+    pub fn inner_range(&self) -> Self {
+        match self {
+            IntRange::Linear { .. } | IntRange::Values(_) | IntRange::PowerOfTwo { .. } => *self,
+            IntRange::Reversed(range) => range.inner_range(),
+        }
+    }
+
+    /// Emits debug assertions to make sure that range minima are always less than the maxima and
+    /// that they are not equal.
+    pub(super) fn assert_validity(&self) {
+        match self {
+            IntRange::Linear { min, max } => {
+                nih_debug_assert!(
+                    min < max,
+                    "The range minimum ({}) needs to be less than the range maximum ({}) and they \
+                     cannot be equal",
+                    min,
+                    max
+                );
+            }
+            IntRange::Values(values) => {
+                nih_debug_assert!(
+                    values.len() >= 2,
+                    "Value list ranges need at least two values"
+                );
+                nih_debug_assert!(
+                    values.windows(2).all(|pair| pair[0] < pair[1]),
+                    "The values in a value list range need to be unique and sorted in ascending \
+                     order"
+                );
+            }
+            IntRange::PowerOfTwo { min, max } => {
+                nih_debug_assert!(
+                    *min > 0
+                        && (*min as u32).is_power_of_two()
+                        && *max > 0
+                        && (*max as u32).is_power_of_two(),
+                    "The range minimum ({}) and maximum ({}) of a power of two range need to be \
+                     powers of two",
+                    min,
+                    max
+                );
+                nih_debug_assert!(
+                    min < max,
+                    "The range minimum ({}) needs to be less than the range maximum ({}) and they \
+                     cannot be equal",
+                    min,
+                    max
+                );
+            }
+            IntRange::Reversed(range) => range.assert_validity(),
+        }
+    }
+}
+
+/// The exponent of a power of two, or of the next lower power of two if `value` is not a power of
+/// two.
+fn power_of_two_exponent(value: i32) -> i32 {
+    31 - (value.max(1) as u32).leading_zeros() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn make_linear_float_range() -> FloatRange {
+        FloatRange::Linear {
+            min: 10.0,
+            max: 20.0,
+        }
+    }
+
+    const fn make_linear_int_range() -> IntRange {
+        IntRange::Linear { min: -10, max: 10 }
+    }
+
+    const fn make_skewed_float_range(factor: f32) -> FloatRange {
+        FloatRange::Skewed {
+            min: 10.0,
+            max: 20.0,
+            factor,
+        }
+    }
+
+    const fn make_symmetrical_skewed_float_range(factor: f32) -> FloatRange {
+        FloatRange::SymmetricalSkewed {
+            min: 10.0,
+            max: 20.0,
+            factor,
+            center: 12.5,
+        }
+    }
+
+    #[test]
+    fn step_size() {
+        // These are weird step sizes, but if it works here then it will work for anything
+        let range = make_linear_float_range();
+        // XXX: We round to decimal places when outputting, but not when snapping to steps
+        assert_eq!(range.snap_to_step(13.0, 4.73), 14.190001);
+    }
+
+    #[test]
+    fn step_size_clamping() {
+        let range = make_linear_float_range();
+        assert_eq!(range.snap_to_step(10.0, 4.73), 10.0);
+        assert_eq!(range.snap_to_step(20.0, 6.73), 20.0);
+    }
+
+    mod linear {
+        use super::*;
+
+        #[test]
+        fn range_normalize_float() {
+            let range = make_linear_float_range();
+            assert_eq!(range.normalize(17.5), 0.75);
+        }
+
+        #[test]
+        fn range_normalize_int() {
+            let range = make_linear_int_range();
+            assert_eq!(range.normalize(-5), 0.25);
+        }
+
+        #[test]
+        fn range_unnormalize_float() {
+            let range = make_linear_float_range();
+            assert_eq!(range.unnormalize(0.25), 12.5);
+        }
+
+        #[test]
+        fn range_unnormalize_int() {
+            let range = make_linear_int_range();
+            assert_eq!(range.unnormalize(0.75), 5);
+        }
+
+        #[test]
+        fn range_unnormalize_int_rounding() {
+            let range = make_linear_int_range();
+            assert_eq!(range.unnormalize(0.73), 5);
+        }
+    }
+
+    mod skewed {
+        use super::*;
+
+        #[test]
+        fn range_normalize_float() {
+            let range = make_skewed_float_range(FloatRange::skew_factor(-2.0));
+            assert_eq!(range.normalize(17.5), 0.9306049);
+        }
+
+        #[test]
+        fn range_unnormalize_float() {
+            let range = make_skewed_float_range(FloatRange::skew_factor(-2.0));
+            assert_eq!(range.unnormalize(0.9306049), 17.5);
+        }
+
+        #[test]
+        fn range_normalize_linear_equiv_float() {
+            let linear_range = make_linear_float_range();
+            let skewed_range = make_skewed_float_range(1.0);
+            assert_eq!(linear_range.normalize(17.5), skewed_range.normalize(17.5));
+        }
+
+        #[test]
+        fn range_unnormalize_linear_equiv_float() {
+            let linear_range = make_linear_float_range();
+            let skewed_range = make_skewed_float_range(1.0);
+            assert_eq!(
+                linear_range.unnormalize(0.25),
+                skewed_range.unnormalize(0.25)
+            );
+        }
+    }
+
+    mod symmetrical_skewed {
+        use super::*;
+
+        #[test]
+        fn range_normalize_float() {
+            let range = make_symmetrical_skewed_float_range(FloatRange::skew_factor(-2.0));
+            assert_eq!(range.normalize(17.5), 0.951801);
+        }
+
+        #[test]
+        fn range_unnormalize_float() {
+            let range = make_symmetrical_skewed_float_range(FloatRange::skew_factor(-2.0));
+            assert_eq!(range.unnormalize(0.951801), 17.5);
+        }
+    }
+
+    const fn make_exponential_float_range() -> FloatRange {
+        FloatRange::Exponential {
+            min: 20.0,
+            max: 20_000.0,
+        }
+    }
+
+    const fn make_piecewise_linear_float_range() -> FloatRange {
+        FloatRange::PiecewiseLinear {
+            breakpoints: &[(10.0, 0.0), (12.0, 0.5), (20.0, 1.0)],
+        }
+    }
+
+    const fn make_custom_float_range() -> FloatRange {
+        // A quadratic curve
+        FloatRange::Custom {
+            min: 10.0,
+            max: 20.0,
+            normalize: |plain| ((plain - 10.0) / 10.0).sqrt(),
+            unnormalize: |normalized| (normalized * normalized * 10.0) + 10.0,
+        }
+    }
+
+    /// Check that normalizing and unnormalizing are each other's inverse, and that the mapping is
+    /// monotonically increasing, for a sweep over the entire range.
+    fn assert_round_trip(range: &FloatRange) {
+        const NUM_STEPS: usize = 1000;
+
+        let (min, max) = range.bounds();
+        let mut previous_normalized = -1.0;
+        for step in 0..=NUM_STEPS {
+            let plain = min + ((max - min) * (step as f32 / NUM_STEPS as f32));
+            let normalized = range.normalize(plain);
+            assert!(
+                (0.0..=1.0).contains(&normalized),
+                "{plain} normalized to {normalized}"
+            );
+            assert!(
+                normalized >= previous_normalized,
+                "The range is not monotonic at {plain}"
+            );
+            previous_normalized = normalized;
+
+            let round_tripped = range.unnormalize(normalized);
+            assert!(
+                (round_tripped - plain).abs() <= (max - min) * 1e-4,
+                "{plain} round tripped to {round_tripped}"
+            );
+        }
+
+        // Very skewed ranges lose precision in the normalized domain, so this compares the plain
+        // values instead
+        for step in 0..=NUM_STEPS {
+            let normalized = step as f32 / NUM_STEPS as f32;
+            let plain = range.unnormalize(normalized);
+            let round_tripped = range.unnormalize(range.normalize(plain));
+            assert!(
+                (round_tripped - plain).abs() <= (max - min) * 1e-4,
+                "{normalized} ({plain}) round tripped to {round_tripped}"
+            );
+        }
+    }
+
+    mod exponential {
+        use super::*;
+
+        #[test]
+        fn range_normalize_float() {
+            let range = make_exponential_float_range();
+            assert_eq!(range.normalize(20.0), 0.0);
+            assert!((range.normalize(632.4555) - 0.5).abs() < 1e-6);
+            assert_eq!(range.normalize(20_000.0), 1.0);
+        }
+
+        #[test]
+        fn range_unnormalize_float() {
+            let range = make_exponential_float_range();
+            // Every third of the range is a factor 10 increase
+            assert!((range.unnormalize(1.0 / 3.0) - 200.0).abs() < 1e-2);
+            assert!((range.unnormalize(2.0 / 3.0) - 2_000.0).abs() < 1e-1);
+        }
+
+        #[test]
+        fn round_trip() {
+            assert_round_trip(&make_exponential_float_range());
+        }
+
+        #[test]
+        fn step_size() {
+            let range = make_exponential_float_range();
+            assert_eq!(range.snap_to_step(440.4, 1.0), 440.0);
+            assert_eq!(range.snap_to_step(3.0, 10.0), 20.0);
+        }
+    }
+
+    mod piecewise_linear {
+        use super::*;
+
+        #[test]
+        fn range_normalize_float() {
+            let range = make_piecewise_linear_float_range();
+            assert_eq!(range.normalize(11.0), 0.25);
+            assert_eq!(range.normalize(12.0), 0.5);
+            assert_eq!(range.normalize(16.0), 0.75);
+            assert_eq!(range.normalize(25.0), 1.0);
+        }
+
+        #[test]
+        fn range_unnormalize_float() {
+            let range = make_piecewise_linear_float_range();
+            assert_eq!(range.unnormalize(0.25), 11.0);
+            assert_eq!(range.unnormalize(0.75), 16.0);
+            assert_eq!(range.unnormalize(-1.0), 10.0);
+        }
+
+        #[test]
+        fn round_trip() {
+            assert_round_trip(&make_piecewise_linear_float_range());
+        }
+
+        #[test]
+        fn steps() {
+            let range = make_piecewise_linear_float_range();
+            assert_eq!(range.snap_to_step(13.3, 0.5), 13.5);
+            assert_eq!(range.next_step(20.0, None, false), 20.0);
+            assert!(range.previous_step(12.0, None, false) < 12.0);
+        }
+    }
+
+    mod custom {
+        use super::*;
+
+        #[test]
+        fn range_normalize_float() {
+            let range = make_custom_float_range();
+            assert_eq!(range.normalize(12.5), 0.5);
+            assert_eq!(range.normalize(5.0), 0.0);
+        }
+
+        #[test]
+        fn range_unnormalize_float() {
+            let range = make_custom_float_range();
+            assert_eq!(range.unnormalize(0.5), 12.5);
+            assert_eq!(range.unnormalize(2.0), 20.0);
+        }
+
+        #[test]
+        fn round_trip() {
+            assert_round_trip(&make_custom_float_range());
+        }
+
+        #[test]
+        fn reversed() {
+            const WRAPPED_RANGE: FloatRange = make_custom_float_range();
+            let range = FloatRange::Reversed(&WRAPPED_RANGE);
+            assert_eq!(range.normalize(12.5), 0.5);
+            assert_eq!(range.unnormalize(0.0), 20.0);
+            assert_eq!(range.bounds(), (10.0, 20.0));
+        }
+    }
+
+    #[test]
+    fn round_trip_existing_ranges() {
+        assert_round_trip(&make_linear_float_range());
+        assert_round_trip(&make_skewed_float_range(FloatRange::skew_factor(-2.0)));
+        assert_round_trip(&make_symmetrical_skewed_float_range(
+            FloatRange::skew_factor(-2.0),
+        ));
+    }
+
+    mod values {
+        use super::*;
+
+        const BUFFER_SIZES: IntRange = IntRange::Values(&[32, 64, 100, 256, 1000]);
+
+        #[test]
+        fn range_normalize_int() {
+            assert_eq!(BUFFER_SIZES.normalize(32), 0.0);
+            assert_eq!(BUFFER_SIZES.normalize(100), 0.5);
+            assert_eq!(BUFFER_SIZES.normalize(1000), 1.0);
+        }
+
+        #[test]
+        fn range_normalize_int_nearest() {
+            // Values that aren't in the list are rounded to the nearest value
+            assert_eq!(BUFFER_SIZES.normalize(70), 0.25);
+            assert_eq!(BUFFER_SIZES.normalize(90), 0.5);
+            assert_eq!(BUFFER_SIZES.normalize(-5), 0.0);
+            assert_eq!(BUFFER_SIZES.normalize(5000), 1.0);
+        }
+
+        #[test]
+        fn range_unnormalize_int() {
+            assert_eq!(BUFFER_SIZES.unnormalize(0.25), 64);
+            assert_eq!(BUFFER_SIZES.unnormalize(0.3), 64);
+            assert_eq!(BUFFER_SIZES.unnormalize(0.4), 100);
+            assert_eq!(BUFFER_SIZES.unnormalize(1.0), 1000);
+        }
+
+        #[test]
+        fn steps() {
+            assert_eq!(BUFFER_SIZES.step_count(), 4);
+            assert_eq!(BUFFER_SIZES.next_step(100), 256);
+            assert_eq!(BUFFER_SIZES.previous_step(100), 64);
+            assert_eq!(BUFFER_SIZES.next_step(1000), 1000);
+            assert_eq!(BUFFER_SIZES.previous_step(32), 32);
+            assert_eq!(BUFFER_SIZES.bounds(), (32, 1000));
+        }
+
+        #[test]
+        fn round_trip() {
+            for value in [32, 64, 100, 256, 1000] {
+                assert_eq!(
+                    BUFFER_SIZES.unnormalize(BUFFER_SIZES.normalize(value)),
+                    value
+                );
+            }
+        }
+    }
+
+    mod power_of_two {
+        use super::*;
+
+        const FFT_SIZES: IntRange = IntRange::PowerOfTwo { min: 64, max: 8192 };
+
+        #[test]
+        fn range_normalize_int() {
+            assert_eq!(FFT_SIZES.normalize(64), 0.0);
+            assert_eq!(FFT_SIZES.normalize(512), 3.0 / 7.0);
+            assert_eq!(FFT_SIZES.normalize(8192), 1.0);
+            // Rounded in the logarithmic domain
+            assert_eq!(FFT_SIZES.normalize(700), 3.0 / 7.0);
+            assert_eq!(FFT_SIZES.normalize(800), 4.0 / 7.0);
+            assert_eq!(FFT_SIZES.normalize(0), 0.0);
+        }
+
+        #[test]
+        fn range_unnormalize_int() {
+            assert_eq!(FFT_SIZES.unnormalize(0.0), 64);
+            assert_eq!(FFT_SIZES.unnormalize(3.0 / 7.0), 512);
+            assert_eq!(FFT_SIZES.unnormalize(0.45), 512);
+            assert_eq!(FFT_SIZES.unnormalize(1.0), 8192);
+        }
+
+        #[test]
+        fn steps() {
+            assert_eq!(FFT_SIZES.step_count(), 7);
+            assert_eq!(FFT_SIZES.next_step(512), 1024);
+            assert_eq!(FFT_SIZES.previous_step(512), 256);
+            assert_eq!(FFT_SIZES.next_step(8192), 8192);
+        }
+
+        #[test]
+        fn round_trip() {
+            for exponent in 6..=13 {
+                let value = 1 << exponent;
+                assert_eq!(FFT_SIZES.unnormalize(FFT_SIZES.normalize(value)), value);
+            }
+        }
+
+        #[test]
+        fn reversed() {
+            let range = IntRange::Reversed(&FFT_SIZES);
+            assert_eq!(range.normalize(64), 1.0);
+            assert_eq!(range.unnormalize(1.0 - (3.0 / 7.0)), 512);
+            assert_eq!(range.next_step(512), 256);
+        }
+    }
+
+    mod reversed_linear {
+        use super::*;
+
+        #[test]
+        fn range_normalize_int() {
+            const WRAPPED_RANGE: IntRange = make_linear_int_range();
+            let range = IntRange::Reversed(&WRAPPED_RANGE);
+            assert_eq!(range.normalize(-5), 1.0 - 0.25);
+        }
+
+        #[test]
+        fn range_unnormalize_int() {
+            const WRAPPED_RANGE: IntRange = make_linear_int_range();
+            let range = IntRange::Reversed(&WRAPPED_RANGE);
+            assert_eq!(range.unnormalize(1.0 - 0.75), 5);
+        }
+
+        #[test]
+        fn range_unnormalize_int_rounding() {
+            const WRAPPED_RANGE: IntRange = make_linear_int_range();
+            let range = IntRange::Reversed(&WRAPPED_RANGE);
+            assert_eq!(range.unnormalize(1.0 - 0.73), 5);
+        }
+    }
+
+    mod reversed_skewed {
+        use super::*;
+
+        #[test]
+        fn range_normalize_float() {
+            const WRAPPED_RANGE: FloatRange = make_skewed_float_range(0.25);
+            let range = FloatRange::Reversed(&WRAPPED_RANGE);
+            assert_eq!(range.normalize(17.5), 1.0 - 0.9306049);
+        }
+
+        #[test]
+        fn range_unnormalize_float() {
+            const WRAPPED_RANGE: FloatRange = make_skewed_float_range(0.25);
+            let range = FloatRange::Reversed(&WRAPPED_RANGE);
+            assert_eq!(range.unnormalize(1.0 - 0.9306049), 17.5);
+        }
+
+        #[test]
+        fn range_normalize_linear_equiv_float() {
+            const WRAPPED_LINEAR_RANGE: FloatRange = make_linear_float_range();
+            const WRAPPED_SKEWED_RANGE: FloatRange = make_skewed_float_range(1.0);
+            let linear_range = FloatRange::Reversed(&WRAPPED_LINEAR_RANGE);
+            let skewed_range = FloatRange::Reversed(&WRAPPED_SKEWED_RANGE);
+            assert_eq!(linear_range.normalize(17.5), skewed_range.normalize(17.5));
+        }
+
+        #[test]
+        fn range_unnormalize_linear_equiv_float() {
+            const WRAPPED_LINEAR_RANGE: FloatRange = make_linear_float_range();
+            const WRAPPED_SKEWED_RANGE: FloatRange = make_skewed_float_range(1.0);
+            let linear_range = FloatRange::Reversed(&WRAPPED_LINEAR_RANGE);
+            let skewed_range = FloatRange::Reversed(&WRAPPED_SKEWED_RANGE);
+            assert_eq!(
+                linear_range.unnormalize(0.25),
+                skewed_range.unnormalize(0.25)
+            );
+        }
+    }
+}
@@ -0,0 +1,9 @@
+use super::Plugin;
+
+/// Provides auxiliary metadata needed for an LV2 plugin.
+pub trait Lv2Plugin: Plugin {
+    /// A unique URI that identifies this particular plugin, e.g.
+    /// `https://example.com/plugins/plugin-name`. Hosts use this to find the plugin and to restore
+    /// it in saved projects, so it should never change after the plugin has been released.
+    const LV2_URI: &'static str;
+}
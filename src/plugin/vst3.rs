@@ -1,43 +1,163 @@
-use super::Plugin;
-use crate::prelude::Vst3SubCategory;
-
-/// Provides auxiliary metadata needed for a VST3 plugin.
-pub trait Vst3Plugin: Plugin {
-    /// The unique class ID that identifies this particular plugin. You can use the
-    /// `*b"fooofooofooofooo"` syntax for this.
-    ///
-    /// This will be shuffled into a different byte order on Windows for project-compatibility.
-    const VST3_CLASS_ID: [u8; 16];
-    /// One or more subcategories. The host may use these to categorize the plugin. Internally this
-    /// slice will be converted to a string where each character is separated by a pipe character
-    /// (`|`). This string has a limit of 127 characters, and anything longer than that will be
-    /// truncated.
-    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory];
-
-    /// [`VST3_CLASS_ID`][Self::VST3_CLASS_ID`] in the correct order for the current platform so
-    /// projects and presets can be shared between platforms. This should not be overridden.
-    const PLATFORM_VST3_CLASS_ID: [u8; 16] = swap_vst3_uid_byte_order(Self::VST3_CLASS_ID);
-}
-
-#[cfg(not(target_os = "windows"))]
-const fn swap_vst3_uid_byte_order(uid: [u8; 16]) -> [u8; 16] {
-    uid
-}
-
-#[cfg(target_os = "windows")]
-const fn swap_vst3_uid_byte_order(mut uid: [u8; 16]) -> [u8; 16] {
-    // No mutable references in const functions, so we can't use `uid.swap()`
-    let original_uid = uid;
-
-    uid[0] = original_uid[3];
-    uid[1] = original_uid[2];
-    uid[2] = original_uid[1];
-    uid[3] = original_uid[0];
-
-    uid[4] = original_uid[5];
-    uid[5] = original_uid[4];
-    uid[6] = original_uid[7];
-    uid[7] = original_uid[6];
-
-    uid
-}
+use super::Plugin;
+use crate::prelude::Vst3SubCategory;
+
+/// Provides auxiliary metadata needed for a VST3 plugin.
+pub trait Vst3Plugin: Plugin {
+    /// The unique class ID that identifies this particular plugin. You can use the
+    /// `*b"fooofooofooofooo"` syntax for this.
+    ///
+    /// This will be shuffled into a different byte order on Windows for project-compatibility.
+    const VST3_CLASS_ID: [u8; 16];
+    /// One or more subcategories. The host may use these to categorize the plugin. Internally this
+    /// slice will be converted to a string where each character is separated by a pipe character
+    /// (`|`). This string has a limit of 127 characters, and anything longer than that will be
+    /// truncated.
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory];
+
+    /// Factory programs, as `.nihpreset` files embedded in the plugin using `include_bytes!()`.
+    /// See [`Preset`][crate::preset::Preset]. These are exposed to the host as a VST3 program list
+    /// in the order they're defined in, and they can be selected through a program change parameter
+    /// or through MIDI program change messages. Presets that belong to another plugin are skipped.
+    ///
+    /// ```ignore
+    /// const VST3_FACTORY_PROGRAMS: &'static [&'static [u8]] = &[
+    ///     include_bytes!("../presets/Init.nihpreset"),
+    ///     include_bytes!("../presets/Bass.nihpreset"),
+    /// ];
+    /// ```
+    const VST3_FACTORY_PROGRAMS: &'static [&'static [u8]] = &[];
+
+    /// Keyswitches the plugin responds to, for instance to select between articulations. These are
+    /// exposed through `IKeyswitchController` so hosts like Cubase can show and select the
+    /// articulations. The plugin still needs to handle the keyswitch notes itself. This requires
+    /// [`Plugin::MIDI_INPUT`] to be set to [`MidiConfig::Basic`][crate::prelude::MidiConfig::Basic]
+    /// or higher.
+    ///
+    /// ```ignore
+    /// const VST3_KEYSWITCHES: &'static [Vst3Keyswitch] = &[
+    ///     Vst3Keyswitch::new("Sustain", 24),
+    ///     Vst3Keyswitch::new("Staccato", 25),
+    /// ];
+    /// ```
+    const VST3_KEYSWITCHES: &'static [Vst3Keyswitch] = &[];
+
+    /// Maps the host's physical per-note controls, like the X and Y movement and pressure of MPE
+    /// controllers, to note expressions. These are exposed through
+    /// `INoteExpressionPhysicalUIMapping` so the host knows which note expression events to send
+    /// for those controls. Like [`VST3_KEYSWITCHES`][Self::VST3_KEYSWITCHES], this requires
+    /// [`Plugin::MIDI_INPUT`] to be set to [`MidiConfig::Basic`][crate::prelude::MidiConfig::Basic]
+    /// or higher.
+    const VST3_PHYSICAL_UI_MAPPINGS: &'static [Vst3PhysicalUIMapping] = &[];
+
+    /// [`VST3_CLASS_ID`][Self::VST3_CLASS_ID`] in the correct order for the current platform so
+    /// projects and presets can be shared between platforms. This should not be overridden.
+    const PLATFORM_VST3_CLASS_ID: [u8; 16] = swap_vst3_uid_byte_order(Self::VST3_CLASS_ID);
+}
+
+/// A keyswitch for [`Vst3Plugin::VST3_KEYSWITCHES`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Vst3Keyswitch {
+    /// How the keyswitch is played.
+    pub keyswitch_type: Vst3KeyswitchType,
+    /// The keyswitch's name, usually the name of the articulation.
+    pub title: &'static str,
+    /// A shorter version of the title.
+    pub short_title: &'static str,
+    /// The first MIDI key that triggers the keyswitch.
+    pub key_min: u8,
+    /// The last MIDI key that triggers the keyswitch. This is the same as `key_min` for keyswitches
+    /// triggered by a single key.
+    pub key_max: u8,
+    /// An alternative key the host can use to trigger the keyswitch, for instance to place all
+    /// keyswitches next to each other on the keyboard.
+    pub key_remapped: Option<u8>,
+}
+
+/// How a [`Vst3Keyswitch`] is played. These correspond to VST3's keyswitch type IDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vst3KeyswitchType {
+    /// The key is pressed before the note it applies to.
+    NoteOn,
+    /// The key is pressed while the note it applies to is playing.
+    OnTheFly,
+    /// The key is pressed before the note it applies to is released.
+    OnRelease,
+    /// The key needs to stay pressed while playing the notes it applies to.
+    KeyRange,
+}
+
+/// A mapping from a physical per-note control to a note expression for
+/// [`Vst3Plugin::VST3_PHYSICAL_UI_MAPPINGS`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Vst3PhysicalUIMapping {
+    /// The physical control.
+    pub physical_ui: Vst3PhysicalUI,
+    /// The note expression the host should send when the physical control is used.
+    pub note_expression: Vst3NoteExpression,
+}
+
+/// A physical per-note control on the host's side, like on an MPE controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vst3PhysicalUI {
+    /// Horizontal movement, usually along the keyboard.
+    XMovement,
+    /// Vertical movement, usually along the key.
+    YMovement,
+    /// The pressure applied to the key.
+    Pressure,
+}
+
+/// One of the predefined VST3 note expressions. The host sends these to the plugin as polyphonic
+/// expression events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vst3NoteExpression {
+    /// `kVolumeTypeID`.
+    Volume,
+    /// `kPanTypeID`.
+    Pan,
+    /// `kTuningTypeID`.
+    Tuning,
+    /// `kVibratoTypeID`.
+    Vibrato,
+    /// `kExpressionTypeID`.
+    Expression,
+    /// `kBrightnessTypeID`.
+    Brightness,
+}
+
+impl Vst3Keyswitch {
+    /// A keyswitch that's triggered by pressing a single key before playing a note.
+    pub const fn new(title: &'static str, key: u8) -> Self {
+        Self {
+            keyswitch_type: Vst3KeyswitchType::NoteOn,
+            title,
+            short_title: title,
+            key_min: key,
+            key_max: key,
+            key_remapped: None,
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+const fn swap_vst3_uid_byte_order(uid: [u8; 16]) -> [u8; 16] {
+    uid
+}
+
+#[cfg(target_os = "windows")]
+const fn swap_vst3_uid_byte_order(mut uid: [u8; 16]) -> [u8; 16] {
+    // No mutable references in const functions, so we can't use `uid.swap()`
+    let original_uid = uid;
+
+    uid[0] = original_uid[3];
+    uid[1] = original_uid[2];
+    uid[2] = original_uid[1];
+    uid[3] = original_uid[0];
+
+    uid[4] = original_uid[5];
+    uid[5] = original_uid[4];
+    uid[6] = original_uid[7];
+    uid[7] = original_uid[6];
+
+    uid
+}
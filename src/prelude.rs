@@ -1,44 +1,66 @@
-// Used in [`AudioIOLayout`]
-pub use std::num::NonZeroU32;
-
-// Re-export the macros, derive macros are already re-exported from their respective modules
-pub use crate::debug::*;
-
-pub use crate::nih_export_clap;
-#[cfg(feature = "vst3")]
-pub use crate::nih_export_vst3;
-#[cfg(feature = "standalone")]
-pub use crate::wrapper::standalone::{nih_export_standalone, nih_export_standalone_with_args};
-
-pub use crate::formatters;
-pub use crate::util;
-
-pub use crate::audio_setup::{
-    new_nonzero_u32, AudioIOLayout, AuxiliaryBuffers, BufferConfig, PortNames, ProcessMode,
-};
-pub use crate::buffer::Buffer;
-pub use crate::context::gui::{AsyncExecutor, GuiContext, ParamSetter};
-pub use crate::context::init::InitContext;
-pub use crate::context::process::{ProcessContext, Transport};
-pub use crate::context::remote_controls::{
-    RemoteControlsContext, RemoteControlsPage, RemoteControlsSection,
-};
-pub use crate::context::PluginApi;
-// This also includes the derive macro
-pub use crate::editor::{Editor, ParentWindowHandle};
-pub use crate::midi::sysex::SysExMessage;
-pub use crate::midi::{control_change, MidiConfig, NoteEvent, PluginNoteEvent};
-pub use crate::params::enums::{Enum, EnumParam};
-pub use crate::params::internals::ParamPtr;
-pub use crate::params::range::{FloatRange, IntRange};
-pub use crate::params::smoothing::{AtomicF32, Smoothable, Smoother, SmoothingStyle};
-pub use crate::params::Params;
-pub use crate::params::{BoolParam, FloatParam, IntParam, Param, ParamFlags};
-pub use crate::plugin::clap::{ClapPlugin, PolyModulationConfig};
-#[cfg(feature = "vst3")]
-pub use crate::plugin::vst3::Vst3Plugin;
-pub use crate::plugin::{Plugin, ProcessStatus, TaskExecutor};
-pub use crate::wrapper::clap::features::ClapFeature;
-pub use crate::wrapper::state::PluginState;
-#[cfg(feature = "vst3")]
-pub use crate::wrapper::vst3::subcategories::Vst3SubCategory;
+// Used in [`AudioIOLayout`]
+pub use std::num::NonZeroU32;
+
+// Re-export the macros, derive macros are already re-exported from their respective modules
+pub use crate::debug::*;
+
+pub use crate::nih_export_clap;
+#[cfg(feature = "lv2")]
+pub use crate::nih_export_lv2;
+#[cfg(feature = "vst3")]
+pub use crate::nih_export_vst3;
+#[cfg(feature = "standalone")]
+pub use crate::wrapper::standalone::{nih_export_standalone, nih_export_standalone_with_args};
+
+pub use crate::formatters;
+pub use crate::util;
+
+pub use crate::audio_setup::{
+    new_nonzero_u32, AudioIOLayout, AuxiliaryBuffers, BufferConfig, PortNames, ProcessMode,
+};
+pub use crate::buffer::Buffer;
+pub use crate::context::gui::{AsyncExecutor, GuiContext, ParamSetter};
+pub use crate::context::init::InitContext;
+pub use crate::context::process::{ProcessContext, Transport};
+pub use crate::context::remote_controls::{
+    RemoteControlsContext, RemoteControlsPage, RemoteControlsSection,
+};
+pub use crate::context::snapshots::{SnapshotConfig, Snapshots};
+pub use crate::context::undo::UndoHistory;
+pub use crate::context::PluginApi;
+// This also includes the derive macro
+pub use crate::editor::{Editor, ParentWindowHandle};
+pub use crate::midi::arpeggiator::{Arpeggiator, ArpeggiatorMode};
+pub use crate::midi::clock::{MidiClockGenerator, MtcFrameRate};
+pub use crate::midi::learn::{MidiLearn, MidiLearnSource, MidiMapping, MidiMappingCurve};
+pub use crate::midi::note_names::{NoteName, NoteNames};
+pub use crate::midi::queue::NoteEventQueue;
+pub use crate::midi::sysex::{
+    ManufacturerId, MtsNoteChange, MtsNoteChanges, MtsTuning, SysExField, SysExMessage,
+    UniversalSysEx,
+};
+pub use crate::midi::tracker::{HeldNote, NoteTracker};
+pub use crate::midi::{
+    control_change, MidiCCDecoder, MidiConfig, NoteEvent, PluginNoteEvent, RpnMessage,
+};
+pub use crate::params::enums::{Enum, EnumParam};
+pub use crate::params::group::ParamGroup;
+pub use crate::params::internals::ParamPtr;
+pub use crate::params::range::{FloatRange, IntRange};
+pub use crate::params::smoothing::{AtomicF32, Smoothable, Smoother, SmoothingStyle};
+pub use crate::params::Params;
+pub use crate::params::{BoolParam, FloatParam, IntParam, Param, ParamFlags};
+pub use crate::plugin::clap::{ClapPlugin, PolyModulationConfig};
+#[cfg(feature = "lv2")]
+pub use crate::plugin::lv2::Lv2Plugin;
+#[cfg(feature = "vst3")]
+pub use crate::plugin::vst3::{
+    Vst3Keyswitch, Vst3KeyswitchType, Vst3NoteExpression, Vst3PhysicalUI, Vst3PhysicalUIMapping,
+    Vst3Plugin,
+};
+pub use crate::plugin::{Plugin, ProcessStatus, TaskExecutor};
+pub use crate::preset::{Preset, PresetLibrary, PresetMetadata};
+pub use crate::wrapper::clap::features::ClapFeature;
+pub use crate::wrapper::state::{MigrationStep, PluginState, SerializedVariant, StateMigration};
+#[cfg(feature = "vst3")]
+pub use crate::wrapper::vst3::subcategories::Vst3SubCategory;
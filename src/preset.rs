@@ -0,0 +1,577 @@
+//! Preset files and preset libraries. A `.nihpreset` file contains a [`PluginState`] together with
+//! some metadata about the preset. The file is stored as JSON, or as Zstandard compressed JSON if
+//! the `zstd` feature is enabled and compression was requested when saving it.
+//!
+//! A [`PresetLibrary`] manages a plugin's factory presets, which can either be embedded in the
+//! plugin's binary or be stored in a directory, and the user's own presets. Presets can be loaded
+//! into the plugin from its editor using [`GuiContext::load_preset()`], and the plugin's current
+//! state can be turned into a preset with [`GuiContext::create_preset()`].
+//!
+//! [`GuiContext::load_preset()`]: crate::prelude::GuiContext::load_preset()
+//! [`GuiContext::create_preset()`]: crate::prelude::GuiContext::create_preset()
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::prelude::{Plugin, PluginState};
+use crate::util::sanitize_file_name;
+
+/// The file extension used for preset files, without the leading period.
+pub const PRESET_EXTENSION: &str = "nihpreset";
+
+/// The version of the preset file format written by this version of NIH-plug.
+pub(crate) const FORMAT_VERSION: u32 = 1;
+
+/// The magic number at the start of a Zstandard frame. Used to detect compressed presets.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// A preset, consisting of a plugin state and metadata describing it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Preset {
+    /// The version of the preset file format. Newer versions of NIH-plug can still read presets
+    /// written by older versions.
+    #[serde(default)]
+    pub format_version: u32,
+    pub metadata: PresetMetadata,
+    /// The plugin's state. Loading this goes through the same code path as the host restoring the
+    /// plugin's state, so the plugin's state migrations are also applied to presets.
+    pub state: PluginState,
+}
+
+/// Information about a preset. Everything except for the name is optional.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PresetMetadata {
+    pub name: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub comment: String,
+    /// Free-form tags, for instance a category like `bass` or `pad`. These are included when
+    /// searching for presets.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// The ID of the plugin the preset was created for, as returned by [`plugin_id()`]. Presets for
+    /// other plugins are ignored by [`PresetLibrary`].
+    #[serde(default)]
+    pub plugin_id: String,
+    /// The plugin version the preset was saved with.
+    #[serde(default)]
+    pub plugin_version: String,
+}
+
+/// Whether a preset ships with the plugin or has been saved by the user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PresetKind {
+    Factory,
+    User,
+}
+
+/// Where a preset in a [`PresetLibrary`] can be loaded from.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PresetLocation {
+    /// An embedded factory preset, identified by its index in the slice passed to
+    /// [`PresetLibrary::with_embedded_factory_presets()`].
+    Embedded(usize),
+    /// A preset file in either the factory or the user preset directory.
+    File(PathBuf),
+}
+
+/// A preset found by [`PresetLibrary::list()`] or [`PresetLibrary::search()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PresetEntry {
+    pub kind: PresetKind,
+    pub location: PresetLocation,
+    pub metadata: PresetMetadata,
+}
+
+/// A plugin's factory and user presets. This is cheap to create, and presets are read from disk
+/// whenever they are listed or loaded so changes made outside of the plugin are always picked up.
+/// None of these functions are realtime-safe.
+///
+/// ```ignore
+/// let library = PresetLibrary::new::<MyPlugin>()
+///     .with_embedded_factory_presets(&[include_bytes!("../presets/Init.nihpreset")]);
+///
+/// for entry in library.search("bass") {
+///     println!("{} by {}", entry.metadata.name, entry.metadata.author);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct PresetLibrary {
+    plugin_id: String,
+    plugin_version: String,
+    embedded_factory_presets: &'static [&'static [u8]],
+    factory_dir: Option<PathBuf>,
+    user_dir: Option<PathBuf>,
+    compress: bool,
+}
+
+/// The ID stored in presets to identify the plugin they belong to. This is based on the plugin's
+/// vendor and name, e.g. `Moist Plugins/Gain`.
+pub fn plugin_id<P: Plugin>() -> String {
+    format!("{}/{}", P::VENDOR, P::NAME)
+}
+
+/// The default directory for a plugin's user presets. This is `<data dir>/<vendor>/<name>/Presets`,
+/// where `<data dir>` is `~/.local/share` on Linux, `~/Library/Application Support` on macOS, and
+/// `%APPDATA%` on Windows. Returns `None` if the data directory can't be determined.
+pub fn default_user_preset_dir<P: Plugin>() -> Option<PathBuf> {
+    Some(
+        dirs::data_dir()?
+            .join(sanitize_file_name(P::VENDOR))
+            .join(sanitize_file_name(P::NAME))
+            .join("Presets"),
+    )
+}
+
+impl Preset {
+    /// Create a preset for plugin `P` from a state, for instance one obtained through
+    /// [`GuiContext::get_state()`][crate::prelude::GuiContext::get_state()]. This overwrites the
+    /// plugin ID and version in the metadata.
+    pub fn new<P: Plugin>(mut metadata: PresetMetadata, state: PluginState) -> Self {
+        metadata.plugin_id = plugin_id::<P>();
+        metadata.plugin_version = String::from(P::VERSION);
+
+        Self {
+            format_version: FORMAT_VERSION,
+            metadata,
+            state,
+        }
+    }
+
+    /// Parse a preset from the contents of a `.nihpreset` file. Compressed presets can only be read
+    /// when the `zstd` feature is enabled.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let preset: Preset = if bytes.starts_with(&ZSTD_MAGIC) {
+            #[cfg(feature = "zstd")]
+            {
+                let json = zstd::decode_all(bytes).context("Could not decompress the preset")?;
+                serde_json::from_slice(&json).context("Could not parse the preset")?
+            }
+            #[cfg(not(feature = "zstd"))]
+            {
+                anyhow::bail!("Compressed presets require NIH-plug's 'zstd' feature")
+            }
+        } else {
+            serde_json::from_slice(bytes).context("Could not parse the preset")?
+        };
+
+        if preset.format_version > FORMAT_VERSION {
+            anyhow::bail!(
+                "The preset uses format version {}, but only versions up to {} are supported",
+                preset.format_version,
+                FORMAT_VERSION
+            );
+        }
+
+        Ok(preset)
+    }
+
+    /// Serialize the preset to the contents of a `.nihpreset` file. If `compress` is set and the
+    /// `zstd` feature is enabled, then the JSON data is compressed using Zstandard.
+    pub fn to_bytes(&self, compress: bool) -> Result<Vec<u8>> {
+        let json = serde_json::to_vec_pretty(self).context("Could not format as JSON")?;
+
+        #[cfg(feature = "zstd")]
+        {
+            if compress {
+                return zstd::encode_all(json.as_slice(), zstd::DEFAULT_COMPRESSION_LEVEL)
+                    .context("Could not compress the preset");
+            }
+
+            Ok(json)
+        }
+        #[cfg(not(feature = "zstd"))]
+        {
+            let _ = compress;
+            Ok(json)
+        }
+    }
+
+    /// Read a preset from a file.
+    pub fn read(path: &Path) -> Result<Self> {
+        let bytes =
+            fs::read(path).with_context(|| format!("Could not read '{}'", path.display()))?;
+        Self::from_bytes(&bytes).with_context(|| format!("Invalid preset '{}'", path.display()))
+    }
+
+    /// Write the preset to a file, replacing the file if it already exists.
+    pub fn write(&self, path: &Path, compress: bool) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Could not create '{}'", parent.display()))?;
+        }
+
+        fs::write(path, self.to_bytes(compress)?)
+            .with_context(|| format!("Could not write '{}'", path.display()))
+    }
+}
+
+impl PresetMetadata {
+    /// Check whether all whitespace separated terms in `query` occur in the preset's name, author,
+    /// comment, or tags. The comparison is case insensitive.
+    pub fn matches(&self, query: &str) -> bool {
+        let haystack = [&self.name, &self.author, &self.comment]
+            .into_iter()
+            .chain(&self.tags)
+            .map(|s| s.to_lowercase())
+            .collect::<Vec<_>>();
+
+        query.split_whitespace().all(|term| {
+            let term = term.to_lowercase();
+            haystack.iter().any(|s| s.contains(&term))
+        })
+    }
+}
+
+impl PresetLibrary {
+    /// Create a preset library for plugin `P` that stores user presets in
+    /// [`default_user_preset_dir()`]. There are no factory presets by default.
+    pub fn new<P: Plugin>() -> Self {
+        Self {
+            plugin_id: plugin_id::<P>(),
+            plugin_version: String::from(P::VERSION),
+            embedded_factory_presets: &[],
+            factory_dir: None,
+            user_dir: default_user_preset_dir::<P>(),
+            compress: false,
+        }
+    }
+
+    /// Factory presets embedded in the plugin's binary, usually through `include_bytes!()`.
+    pub fn with_embedded_factory_presets(mut self, presets: &'static [&'static [u8]]) -> Self {
+        self.embedded_factory_presets = presets;
+        self
+    }
+
+    /// A directory containing factory presets. Subdirectories are searched as well. This directory
+    /// is never written to.
+    pub fn with_factory_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.factory_dir = Some(dir.into());
+        self
+    }
+
+    /// Store user presets in this directory instead of in [`default_user_preset_dir()`].
+    pub fn with_user_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.user_dir = Some(dir.into());
+        self
+    }
+
+    /// Compress presets saved through [`save()`][Self::save()]. This only has an effect when the
+    /// `zstd` feature is enabled.
+    pub fn with_compression(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    pub fn user_dir(&self) -> Option<&Path> {
+        self.user_dir.as_deref()
+    }
+
+    pub fn factory_dir(&self) -> Option<&Path> {
+        self.factory_dir.as_deref()
+    }
+
+    /// List all factory and user presets for this plugin, sorted by kind and then by name. Presets
+    /// that can't be read or that belong to another plugin are skipped.
+    pub fn list(&self) -> Vec<PresetEntry> {
+        let mut entries = self.list_embedded();
+        if let Some(factory_dir) = &self.factory_dir {
+            self.list_dir(factory_dir, PresetKind::Factory, &mut entries);
+        }
+        if let Some(user_dir) = &self.user_dir {
+            self.list_dir(user_dir, PresetKind::User, &mut entries);
+        }
+
+        entries.sort_by(|a, b| {
+            let kind_order = |kind| (kind == PresetKind::User) as u8;
+            kind_order(a.kind)
+                .cmp(&kind_order(b.kind))
+                .then_with(|| a.metadata.name.cmp(&b.metadata.name))
+        });
+
+        entries
+    }
+
+    /// Find all presets matching `query`. See [`PresetMetadata::matches()`].
+    pub fn search(&self, query: &str) -> Vec<PresetEntry> {
+        self.list()
+            .into_iter()
+            .filter(|entry| entry.metadata.matches(query))
+            .collect()
+    }
+
+    /// Load a preset from the library. The preset can then be loaded into the plugin using
+    /// [`GuiContext::load_preset()`][crate::prelude::GuiContext::load_preset()].
+    pub fn load(&self, location: &PresetLocation) -> Result<Preset> {
+        let preset = match location {
+            PresetLocation::Embedded(idx) => {
+                let bytes = self
+                    .embedded_factory_presets
+                    .get(*idx)
+                    .with_context(|| format!("There is no embedded factory preset {idx}"))?;
+                Preset::from_bytes(bytes)?
+            }
+            PresetLocation::File(path) => Preset::read(path)?,
+        };
+
+        if !self.is_own_preset(&preset) {
+            anyhow::bail!(
+                "The preset '{}' belongs to '{}'",
+                preset.metadata.name,
+                preset.metadata.plugin_id
+            );
+        }
+
+        Ok(preset)
+    }
+
+    /// List only the factory presets embedded in the plugin's binary, in the order they were passed
+    /// to [`with_embedded_factory_presets()`][Self::with_embedded_factory_presets()]. Unlike
+    /// [`list()`][Self::list()], this does not touch the file system.
+    pub fn list_embedded(&self) -> Vec<PresetEntry> {
+        let mut entries = Vec::new();
+        for (idx, bytes) in self.embedded_factory_presets.iter().enumerate() {
+            match Preset::from_bytes(bytes) {
+                Ok(preset) if self.is_own_preset(&preset) => entries.push(PresetEntry {
+                    kind: PresetKind::Factory,
+                    location: PresetLocation::Embedded(idx),
+                    metadata: preset.metadata,
+                }),
+                Ok(_) => (),
+                Err(err) => {
+                    nih_debug_assert_failure!("Invalid embedded factory preset {}: {:#}", idx, err)
+                }
+            }
+        }
+
+        entries
+    }
+
+    /// Save a preset to the user preset directory, using the preset's name as the file name. An
+    /// existing user preset with the same name is replaced. The preset's plugin ID and version are
+    /// set to this plugin's. Returns the path the preset was written to.
+    pub fn save(&self, mut preset: Preset) -> Result<PathBuf> {
+        let user_dir = self
+            .user_dir
+            .as_ref()
+            .context("The user preset directory could not be determined")?;
+        if preset.metadata.name.trim().is_empty() {
+            anyhow::bail!("Presets need a name");
+        }
+
+        preset.format_version = FORMAT_VERSION;
+        preset.metadata.plugin_id = self.plugin_id.clone();
+        preset.metadata.plugin_version = self.plugin_version.clone();
+
+        // `Path::with_extension()` would replace anything after a dot in the preset's name
+        let path = user_dir.join(format!(
+            "{}.{PRESET_EXTENSION}",
+            sanitize_file_name(preset.metadata.name.trim())
+        ));
+        preset.write(&path, self.compress)?;
+
+        Ok(path)
+    }
+
+    /// Delete a user preset. Factory presets cannot be deleted.
+    pub fn delete(&self, location: &PresetLocation) -> Result<()> {
+        let (PresetLocation::File(path), Some(user_dir)) = (location, &self.user_dir) else {
+            anyhow::bail!("Only user presets can be deleted");
+        };
+
+        // Both paths are canonicalized first, since a path containing `..` components could
+        // otherwise point outside of the user preset directory
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("Could not find '{}'", path.display()))?;
+        let user_dir = user_dir
+            .canonicalize()
+            .context("Could not find the user preset directory")?;
+        if !path.starts_with(user_dir) {
+            anyhow::bail!("Only user presets can be deleted");
+        }
+
+        fs::remove_file(&path).with_context(|| format!("Could not delete '{}'", path.display()))
+    }
+
+    fn is_own_preset(&self, preset: &Preset) -> bool {
+        preset.metadata.plugin_id == self.plugin_id
+    }
+
+    /// Add all presets in `dir` and its subdirectories to `entries`.
+    fn list_dir(&self, dir: &Path, kind: PresetKind, entries: &mut Vec<PresetEntry>) {
+        let Ok(dir_entries) = fs::read_dir(dir) else {
+            return;
+        };
+
+        for path in dir_entries.flatten().map(|entry| entry.path()) {
+            if path.is_dir() {
+                self.list_dir(&path, kind, entries);
+            } else if path
+                .extension()
+                .map_or(false, |ext| ext == PRESET_EXTENSION)
+            {
+                match Preset::read(&path) {
+                    Ok(preset) if self.is_own_preset(&preset) => entries.push(PresetEntry {
+                        kind,
+                        location: PresetLocation::File(path),
+                        metadata: preset.metadata,
+                    }),
+                    Ok(_) => (),
+                    Err(err) => nih_warn!("Skipping preset: {:#}", err),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::wrapper::state::ParamValue;
+
+    const PLUGIN_ID: &str = "NIH/Test";
+
+    fn make_preset(name: &str, tags: &[&str]) -> Preset {
+        Preset {
+            format_version: FORMAT_VERSION,
+            metadata: PresetMetadata {
+                name: String::from(name),
+                author: String::from("NIH"),
+                comment: String::new(),
+                tags: tags.iter().map(|tag| String::from(*tag)).collect(),
+                plugin_id: String::from(PLUGIN_ID),
+                plugin_version: String::from("0.1.0"),
+            },
+            state: PluginState {
+                version: String::from("0.1.0"),
+                params: BTreeMap::from([(String::from("gain"), ParamValue::F32(-6.0))]),
+                fields: BTreeMap::new(),
+            },
+        }
+    }
+
+    fn make_library(name: &str) -> PresetLibrary {
+        let user_dir =
+            std::env::temp_dir().join(format!("nih-plug-presets-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&user_dir);
+
+        PresetLibrary {
+            plugin_id: String::from(PLUGIN_ID),
+            plugin_version: String::from("0.1.0"),
+            embedded_factory_presets: &[],
+            factory_dir: None,
+            user_dir: Some(user_dir),
+            compress: false,
+        }
+    }
+
+    #[test]
+    fn preset_roundtrip() {
+        let preset = make_preset("Init", &[]);
+        let bytes = preset.to_bytes(false).unwrap();
+        let loaded = Preset::from_bytes(&bytes).unwrap();
+
+        assert_eq!(loaded.metadata, preset.metadata);
+        assert!(matches!(loaded.state.params["gain"], ParamValue::F32(v) if v == -6.0));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn compressed_preset_roundtrip() {
+        let preset = make_preset("Init", &[]);
+        let bytes = preset.to_bytes(true).unwrap();
+        assert!(bytes.starts_with(&ZSTD_MAGIC));
+
+        assert_eq!(
+            Preset::from_bytes(&bytes).unwrap().metadata,
+            preset.metadata
+        );
+    }
+
+    #[test]
+    fn newer_format_versions_are_rejected() {
+        let mut preset = make_preset("Init", &[]);
+        preset.format_version = FORMAT_VERSION + 1;
+        let bytes = serde_json::to_vec(&preset).unwrap();
+
+        assert!(Preset::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn save_list_and_search() {
+        let library = make_library("search");
+        library.save(make_preset("Deep Bass", &["bass"])).unwrap();
+        library
+            .save(make_preset("Glass Pad", &["pad", "bright"]))
+            .unwrap();
+        let mut foreign = make_preset("Other Plugin", &["bass"]);
+        foreign.metadata.plugin_id = String::from("NIH/Other");
+        foreign
+            .write(
+                &library.user_dir().unwrap().join("Other Plugin.nihpreset"),
+                false,
+            )
+            .unwrap();
+
+        let names = |entries: Vec<PresetEntry>| {
+            entries
+                .into_iter()
+                .map(|entry| entry.metadata.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(library.list()), ["Deep Bass", "Glass Pad"]);
+        assert_eq!(names(library.search("BASS")), ["Deep Bass"]);
+        assert_eq!(names(library.search("pad bright")), ["Glass Pad"]);
+        assert!(library.search("pad bass").is_empty());
+
+        let entry = library.search("glass").remove(0);
+        assert_eq!(entry.kind, PresetKind::User);
+        assert_eq!(
+            library.load(&entry.location).unwrap().metadata.name,
+            "Glass Pad"
+        );
+
+        library.delete(&entry.location).unwrap();
+        assert_eq!(names(library.list()), ["Deep Bass"]);
+
+        let _ = fs::remove_dir_all(library.user_dir().unwrap());
+    }
+
+    #[test]
+    fn presets_outside_of_the_user_dir_are_not_deleted() {
+        let library = make_library("delete");
+        let path = library.save(make_preset("Init", &[])).unwrap();
+        let user_dir = library.user_dir().unwrap().to_owned();
+        let outside = user_dir.with_extension("outside");
+        fs::write(&outside, "").unwrap();
+
+        let escaping_path = user_dir.join("..").join(outside.file_name().unwrap());
+        assert!(library
+            .delete(&PresetLocation::File(escaping_path))
+            .is_err());
+        assert!(outside.exists());
+
+        library.delete(&PresetLocation::File(path.clone())).unwrap();
+        assert!(!path.exists());
+
+        let _ = fs::remove_file(outside);
+        let _ = fs::remove_dir_all(user_dir);
+    }
+
+    #[test]
+    fn names_with_dots_are_kept() {
+        let library = make_library("dots");
+        let path = library.save(make_preset("Lead v1.2", &[])).unwrap();
+        assert_eq!(path.file_name().unwrap(), "Lead v1.2.nihpreset");
+        library.save(make_preset("Lead v1.3", &[])).unwrap();
+        assert_eq!(library.list().len(), 2);
+
+        let _ = fs::remove_dir_all(library.user_dir().unwrap());
+    }
+}
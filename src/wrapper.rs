@@ -1,14 +1,16 @@
-//! Wrappers for different plugin types. Each wrapper has an entry point macro that you can pass the
-//! name of a type that implements `Plugin` to. The macro will handle the rest.
-
-pub mod clap;
-pub mod state;
-pub(crate) mod util;
-
-#[cfg(feature = "standalone")]
-pub mod standalone;
-#[cfg(feature = "vst3")]
-pub mod vst3;
-
-// This is used by the wrappers.
-pub use util::setup_logger;
+//! Wrappers for different plugin types. Each wrapper has an entry point macro that you can pass the
+//! name of a type that implements `Plugin` to. The macro will handle the rest.
+
+pub mod clap;
+pub mod state;
+pub(crate) mod util;
+
+#[cfg(feature = "lv2")]
+pub mod lv2;
+#[cfg(feature = "standalone")]
+pub mod standalone;
+#[cfg(feature = "vst3")]
+pub mod vst3;
+
+// This is used by the wrappers.
+pub use util::setup_logger;
@@ -0,0 +1,413 @@
+//! An implementation of CLAP's preset discovery factory. This lets the host index a plugin's
+//! [`PresetLibrary`] without having to instantiate the plugin. Hosts load the presets found this
+//! way through the plugin's preset-load extension, which uses the same library.
+
+use clap_sys::factory::draft::preset_discovery::{
+    clap_plugin_id, clap_preset_discovery_factory, clap_preset_discovery_filetype,
+    clap_preset_discovery_indexer, clap_preset_discovery_location,
+    clap_preset_discovery_metadata_receiver, clap_preset_discovery_provider,
+    clap_preset_discovery_provider_descriptor, CLAP_PRESET_DISCOVERY_IS_FACTORY_CONTENT,
+    CLAP_PRESET_DISCOVERY_IS_USER_CONTENT, CLAP_PRESET_DISCOVERY_LOCATION_FILE,
+    CLAP_PRESET_DISCOVERY_LOCATION_PLUGIN,
+};
+use clap_sys::version::CLAP_VERSION;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_void};
+use std::path::{Path, PathBuf};
+
+use crate::prelude::ClapPlugin;
+use crate::preset::{
+    PresetEntry, PresetKind, PresetLibrary, PresetLocation, PresetMetadata, PRESET_EXTENSION,
+};
+
+/// The load key used for an embedded factory preset. This is the preset's index in the slice
+/// passed to [`PresetLibrary::with_embedded_factory_presets()`].
+pub(crate) fn embedded_load_key(idx: usize) -> CString {
+    CString::new(idx.to_string()).unwrap()
+}
+
+/// Convert a location and load key passed by the host back into a [`PresetLocation`]. Returns
+/// `None` if the location is invalid.
+///
+/// # Safety
+///
+/// `location` and `load_key` need to be either null pointers or valid C-strings.
+pub(crate) unsafe fn preset_location_from_clap(
+    location_kind: u32,
+    location: *const c_char,
+    load_key: *const c_char,
+) -> Option<PresetLocation> {
+    match location_kind {
+        CLAP_PRESET_DISCOVERY_LOCATION_FILE if !location.is_null() => Some(PresetLocation::File(
+            PathBuf::from(CStr::from_ptr(location).to_str().ok()?),
+        )),
+        CLAP_PRESET_DISCOVERY_LOCATION_PLUGIN if !load_key.is_null() => Some(
+            PresetLocation::Embedded(CStr::from_ptr(load_key).to_str().ok()?.parse().ok()?),
+        ),
+        _ => None,
+    }
+}
+
+/// A type erased preset discovery provider for a single plugin. Created by
+/// [`nih_export_clap!()`][crate::nih_export_clap!()] for every plugin that returns a preset
+/// library from [`ClapPlugin::clap_preset_library()`].
+pub struct ProviderDescriptor {
+    clap_id: CString,
+    id: CString,
+    name: CString,
+    vendor: CString,
+    library: PresetLibrary,
+
+    /// Contains pointers to the strings above.
+    provider_descriptor: Option<clap_preset_discovery_provider_descriptor>,
+}
+
+unsafe impl Send for ProviderDescriptor {}
+unsafe impl Sync for ProviderDescriptor {}
+
+impl ProviderDescriptor {
+    /// Construct the provider descriptor for a specific CLAP plugin, or return `None` if the plugin
+    /// doesn't have a preset library.
+    pub fn for_plugin<P: ClapPlugin>() -> Option<Self> {
+        let library = P::clap_preset_library()?;
+
+        let mut descriptor = Self {
+            clap_id: CString::new(P::CLAP_ID).expect("`CLAP_ID` contained null bytes"),
+            id: CString::new(format!("{}.presets", P::CLAP_ID))
+                .expect("`CLAP_ID` contained null bytes"),
+            name: CString::new(format!("{} presets", P::NAME))
+                .expect("`NAME` contained null bytes"),
+            vendor: CString::new(P::VENDOR).expect("`VENDOR` contained null bytes"),
+            library,
+
+            provider_descriptor: None,
+        };
+
+        descriptor.provider_descriptor = Some(clap_preset_discovery_provider_descriptor {
+            clap_version: CLAP_VERSION,
+            id: descriptor.id.as_ptr(),
+            name: descriptor.name.as_ptr(),
+            vendor: descriptor.vendor.as_ptr(),
+        });
+
+        Some(descriptor)
+    }
+
+    pub fn id(&self) -> &CStr {
+        self.id.as_c_str()
+    }
+
+    pub fn clap_provider_descriptor(&self) -> &clap_preset_discovery_provider_descriptor {
+        self.provider_descriptor.as_ref().unwrap()
+    }
+}
+
+/// The preset discovery factory exposed through the plugin's entry point. The `factory` field
+/// needs to come first so a pointer to the factory can be cast back to this struct.
+#[repr(C)]
+pub struct PresetDiscoveryFactory {
+    factory: clap_preset_discovery_factory,
+    providers: Vec<ProviderDescriptor>,
+}
+
+unsafe impl Send for PresetDiscoveryFactory {}
+unsafe impl Sync for PresetDiscoveryFactory {}
+
+impl PresetDiscoveryFactory {
+    /// Create a factory for all of the plugins in a `nih_export_clap!()` call that have a preset
+    /// library.
+    pub fn new(providers: impl IntoIterator<Item = Option<ProviderDescriptor>>) -> Self {
+        Self {
+            factory: clap_preset_discovery_factory {
+                count: Some(Self::count),
+                get_descriptor: Some(Self::get_descriptor),
+                create: Some(Self::create),
+            },
+            providers: providers.into_iter().flatten().collect(),
+        }
+    }
+
+    /// Whether any of the exported plugins has a preset library. The factory should only be
+    /// exposed to the host if this is the case.
+    pub fn is_empty(&self) -> bool {
+        self.providers.is_empty()
+    }
+
+    pub fn as_ptr(&self) -> *const c_void {
+        &self.factory as *const _ as *const c_void
+    }
+
+    unsafe extern "C" fn count(factory: *const clap_preset_discovery_factory) -> u32 {
+        check_null_ptr!(0, factory);
+        let this = &*(factory as *const Self);
+
+        this.providers.len() as u32
+    }
+
+    unsafe extern "C" fn get_descriptor(
+        factory: *const clap_preset_discovery_factory,
+        index: u32,
+    ) -> *const clap_preset_discovery_provider_descriptor {
+        check_null_ptr!(std::ptr::null(), factory);
+        let this = &*(factory as *const Self);
+
+        match this.providers.get(index as usize) {
+            Some(descriptor) => descriptor.clap_provider_descriptor(),
+            None => std::ptr::null(),
+        }
+    }
+
+    unsafe extern "C" fn create(
+        factory: *const clap_preset_discovery_factory,
+        indexer: *const clap_preset_discovery_indexer,
+        provider_id: *const c_char,
+    ) -> *const clap_preset_discovery_provider {
+        check_null_ptr!(std::ptr::null(), factory, indexer, provider_id);
+        let this = &*(factory as *const Self);
+
+        let provider_id = CStr::from_ptr(provider_id);
+        match this.providers.iter().find(|d| d.id() == provider_id) {
+            // This gets turned back into a box and dropped in [`Provider::destroy()`]
+            Some(descriptor) => Provider::new(descriptor, indexer),
+            None => std::ptr::null(),
+        }
+    }
+}
+
+/// A preset discovery provider instance created by the host. `clap_provider` needs to come first
+/// so the provider's `provider_data` can point to this struct.
+#[repr(C)]
+struct Provider {
+    clap_provider: clap_preset_discovery_provider,
+    indexer: *const clap_preset_discovery_indexer,
+    descriptor: &'static ProviderDescriptor,
+}
+
+impl Provider {
+    fn new(
+        descriptor: &ProviderDescriptor,
+        indexer: *const clap_preset_discovery_indexer,
+    ) -> *const clap_preset_discovery_provider {
+        // SAFETY: The descriptors live in the factory, which is stored in a static
+        let descriptor: &'static ProviderDescriptor = unsafe { &*(descriptor as *const _) };
+        let provider = Box::into_raw(Box::new(Self {
+            clap_provider: clap_preset_discovery_provider {
+                desc: descriptor.clap_provider_descriptor(),
+                provider_data: std::ptr::null_mut(),
+                init: Some(Self::init),
+                destroy: Some(Self::destroy),
+                get_metadata: Some(Self::get_metadata),
+                get_extension: Some(Self::get_extension),
+            },
+            indexer,
+            descriptor,
+        }));
+        unsafe { (*provider).clap_provider.provider_data = provider as *mut c_void };
+
+        provider as *const clap_preset_discovery_provider
+    }
+
+    fn library(&self) -> &PresetLibrary {
+        &self.descriptor.library
+    }
+
+    unsafe extern "C" fn init(provider: *const clap_preset_discovery_provider) -> bool {
+        check_null_ptr!(false, provider, (*provider).provider_data);
+        let this = &*((*provider).provider_data as *const Self);
+        let indexer = &*this.indexer;
+
+        let filetype_name = CString::new("NIH-plug preset").unwrap();
+        let file_extension = CString::new(PRESET_EXTENSION).unwrap();
+        let filetype = clap_preset_discovery_filetype {
+            name: filetype_name.as_ptr(),
+            description: std::ptr::null(),
+            file_extension: file_extension.as_ptr(),
+        };
+        if !clap_call! { indexer=>declare_filetype(indexer, &filetype) } {
+            nih_debug_assert_failure!("The host rejected the preset file type");
+            return false;
+        }
+
+        let library = this.library();
+        if !library.list_embedded().is_empty() {
+            let name = CString::new("Factory presets").unwrap();
+            let location = clap_preset_discovery_location {
+                flags: CLAP_PRESET_DISCOVERY_IS_FACTORY_CONTENT,
+                name: name.as_ptr(),
+                kind: CLAP_PRESET_DISCOVERY_LOCATION_PLUGIN,
+                location: std::ptr::null(),
+            };
+            clap_call! { indexer=>declare_location(indexer, &location) };
+        }
+
+        if let Some(factory_dir) = library.factory_dir() {
+            declare_directory(
+                indexer,
+                "Factory presets",
+                factory_dir,
+                CLAP_PRESET_DISCOVERY_IS_FACTORY_CONTENT,
+            );
+        }
+        if let Some(user_dir) = library.user_dir() {
+            // The host may otherwise not watch the directory until the first preset has been saved
+            if let Err(err) = std::fs::create_dir_all(user_dir) {
+                nih_debug_assert_failure!(
+                    "Could not create the user preset directory '{}': {}",
+                    user_dir.display(),
+                    err
+                );
+            }
+            declare_directory(
+                indexer,
+                "User presets",
+                user_dir,
+                CLAP_PRESET_DISCOVERY_IS_USER_CONTENT,
+            );
+        }
+
+        true
+    }
+
+    unsafe extern "C" fn destroy(provider: *const clap_preset_discovery_provider) {
+        check_null_ptr!((), provider, (*provider).provider_data);
+
+        drop(Box::from_raw((*provider).provider_data as *mut Self));
+    }
+
+    unsafe extern "C" fn get_metadata(
+        provider: *const clap_preset_discovery_provider,
+        location_kind: u32,
+        location: *const c_char,
+        metadata_receiver: *const clap_preset_discovery_metadata_receiver,
+    ) -> bool {
+        check_null_ptr!(
+            false,
+            provider,
+            (*provider).provider_data,
+            metadata_receiver
+        );
+        let this = &*((*provider).provider_data as *const Self);
+        let receiver = &*metadata_receiver;
+        let library = this.library();
+
+        match location_kind {
+            CLAP_PRESET_DISCOVERY_LOCATION_PLUGIN => {
+                // Only the embedded presets live in this location, so there's no need to scan the
+                // preset directories
+                for entry in library.list_embedded() {
+                    if let PresetEntry {
+                        kind,
+                        location: PresetLocation::Embedded(idx),
+                        metadata,
+                    } = entry
+                    {
+                        let load_key = embedded_load_key(idx);
+                        this.send_metadata(receiver, &metadata, kind, load_key.as_ptr());
+                    }
+                }
+
+                true
+            }
+            CLAP_PRESET_DISCOVERY_LOCATION_FILE if !location.is_null() => {
+                let path = match CStr::from_ptr(location).to_str() {
+                    Ok(path) => PathBuf::from(path),
+                    Err(_) => return false,
+                };
+                match library.load(&PresetLocation::File(path.clone())) {
+                    Ok(preset) => {
+                        let kind = match library.user_dir() {
+                            Some(user_dir) if path.starts_with(user_dir) => PresetKind::User,
+                            _ => PresetKind::Factory,
+                        };
+                        this.send_metadata(receiver, &preset.metadata, kind, std::ptr::null());
+
+                        true
+                    }
+                    Err(err) => {
+                        let message = CString::new(format!("{err:#}")).unwrap_or_default();
+                        clap_call! { receiver=>on_error(receiver, 0, message.as_ptr()) };
+
+                        false
+                    }
+                }
+            }
+            _ => {
+                nih_debug_assert_failure!("Unknown preset location kind {}", location_kind);
+                false
+            }
+        }
+    }
+
+    unsafe extern "C" fn get_extension(
+        _provider: *const clap_preset_discovery_provider,
+        _extension_id: *const c_char,
+    ) -> *const c_void {
+        std::ptr::null()
+    }
+
+    /// Describe a single preset to the host.
+    unsafe fn send_metadata(
+        &self,
+        receiver: &clap_preset_discovery_metadata_receiver,
+        metadata: &PresetMetadata,
+        kind: PresetKind,
+        load_key: *const c_char,
+    ) {
+        let to_cstring = |s: &str| CString::new(s.replace('\0', "")).unwrap();
+
+        let name = to_cstring(&metadata.name);
+        if !clap_call! { receiver=>begin_preset(receiver, name.as_ptr(), load_key) } {
+            return;
+        }
+
+        let abi = CString::new("clap").unwrap();
+        let plugin_id = clap_plugin_id {
+            abi: abi.as_ptr(),
+            id: self.descriptor.clap_id.as_ptr(),
+        };
+        clap_call! { receiver=>add_plugin_id(receiver, &plugin_id) };
+        let flags = match kind {
+            PresetKind::Factory => CLAP_PRESET_DISCOVERY_IS_FACTORY_CONTENT,
+            PresetKind::User => CLAP_PRESET_DISCOVERY_IS_USER_CONTENT,
+        };
+        clap_call! { receiver=>set_flags(receiver, flags) };
+
+        if !metadata.author.is_empty() {
+            let author = to_cstring(&metadata.author);
+            clap_call! { receiver=>add_creator(receiver, author.as_ptr()) };
+        }
+        if !metadata.comment.is_empty() {
+            let comment = to_cstring(&metadata.comment);
+            clap_call! { receiver=>set_description(receiver, comment.as_ptr()) };
+        }
+        for tag in &metadata.tags {
+            let tag = to_cstring(tag);
+            clap_call! { receiver=>add_feature(receiver, tag.as_ptr()) };
+        }
+    }
+}
+
+/// Declare a preset directory to the host.
+unsafe fn declare_directory(
+    indexer: &clap_preset_discovery_indexer,
+    name: &str,
+    path: &Path,
+    flags: u32,
+) {
+    let name = CString::new(name).unwrap();
+    let path = match CString::new(path.to_string_lossy().into_owned()) {
+        Ok(path) => path,
+        Err(_) => {
+            nih_debug_assert_failure!("Preset directory '{}' is not valid", path.display());
+            return;
+        }
+    };
+
+    let location = clap_preset_discovery_location {
+        flags,
+        name: name.as_ptr(),
+        kind: CLAP_PRESET_DISCOVERY_LOCATION_FILE,
+        location: path.as_ptr(),
+    };
+    clap_call! { indexer=>declare_location(indexer, &location) };
+}
@@ -0,0 +1,132 @@
+//! An LV2 wrapper. LV2 plugins are described by Turtle files in the plugin's bundle. These are
+//! generated from the plugin's metadata and parameters by calling the library's exported
+//! `nih_plug_lv2_write_ttl()` function after compiling the plugin. `cargo xtask bundle` does this
+//! automatically.
+//!
+//! The wrapper does not support editors.
+
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::path::Path;
+
+mod context;
+mod descriptor;
+mod ports;
+pub mod sys;
+mod ttl;
+mod wrapper;
+
+/// Re-export for the macro
+pub use self::descriptor::PluginDescriptor;
+pub use self::sys::LV2_Descriptor;
+
+/// Write the Turtle files for the plugins in `descriptors` to an LV2 bundle. `binary_name` is the
+/// file name of the plugin's library inside of the bundle. Returns `false` and logs the error if
+/// the files could not be written.
+///
+/// # Safety
+///
+/// Both pointers must be valid null terminated strings.
+pub unsafe fn write_ttl(
+    bundle_path: *const c_char,
+    binary_name: *const c_char,
+    descriptors: &[PluginDescriptor],
+) -> bool {
+    if bundle_path.is_null() || binary_name.is_null() {
+        nih_error!("Null pointer passed to the LV2 Turtle generator");
+        return false;
+    }
+
+    let (bundle_path, binary_name) = match (
+        CStr::from_ptr(bundle_path).to_str(),
+        CStr::from_ptr(binary_name).to_str(),
+    ) {
+        (Ok(bundle_path), Ok(binary_name)) => (bundle_path, binary_name),
+        _ => {
+            nih_error!("The LV2 bundle path and binary name must be valid UTF-8");
+            return false;
+        }
+    };
+
+    match ttl::write_bundle(Path::new(bundle_path), binary_name, descriptors) {
+        Ok(()) => true,
+        Err(err) => {
+            nih_error!("Could not write the LV2 bundle's Turtle files: {:#}", err);
+            false
+        }
+    }
+}
+
+/// Export one or more LV2 plugins from this library using the provided plugin types. Next to the
+/// `lv2_descriptor()` entry point, this also exports a function that writes the bundle's Turtle
+/// files. `cargo xtask bundle` calls that function to create the LV2 bundle.
+#[macro_export]
+macro_rules! nih_export_lv2 {
+    ($($plugin_ty:ty),+) => {
+        #[doc(hidden)]
+        mod lv2 {
+            use $crate::prelude::nih_debug_assert_eq;
+            use $crate::wrapper::lv2::PluginDescriptor;
+            use ::std::collections::HashSet;
+            use ::std::sync::OnceLock;
+
+            // Because the `$plugin_ty`s are likely defined in the enclosing scope. This works even
+            // if the types are not public because this is a child module.
+            use super::*;
+
+            // Sneaky way to get the number of expanded elements
+            const PLUGIN_COUNT: usize = [$(stringify!($plugin_ty)),+].len();
+
+            // This is a type erased version of the information stored on the plugin types
+            static PLUGIN_DESCRIPTORS: OnceLock<[PluginDescriptor; PLUGIN_COUNT]> = OnceLock::new();
+
+            pub fn plugin_descriptors() -> &'static [PluginDescriptor; PLUGIN_COUNT] {
+                PLUGIN_DESCRIPTORS.get_or_init(|| {
+                    let descriptors = [$(PluginDescriptor::for_plugin::<$plugin_ty>()),+];
+
+                    if cfg!(debug_assertions) {
+                        let unique_plugin_uris: HashSet<_> = descriptors.iter().map(|d| d.uri()).collect();
+                        nih_debug_assert_eq!(
+                            unique_plugin_uris.len(),
+                            descriptors.len(),
+                            "Duplicate plugin URIs found in `nih_export_lv2!()` call"
+                        );
+                    }
+
+                    descriptors
+                })
+            }
+        }
+
+        /// The LV2 plugin's entry point.
+        #[no_mangle]
+        pub extern "C" fn lv2_descriptor(index: u32) -> *const $crate::wrapper::lv2::LV2_Descriptor {
+            $crate::wrapper::setup_logger();
+
+            match self::lv2::plugin_descriptors().get(index as usize) {
+                Some(descriptor) => descriptor.lv2_descriptor(),
+                None => ::std::ptr::null(),
+            }
+        }
+
+        /// Writes the LV2 bundle's Turtle files. This is called by `cargo xtask bundle` after
+        /// copying the library to the bundle.
+        ///
+        /// # Safety
+        ///
+        /// Both arguments must be valid null terminated strings.
+        #[no_mangle]
+        pub unsafe extern "C" fn nih_plug_lv2_write_ttl(
+            bundle_path: *const ::std::os::raw::c_char,
+            binary_name: *const ::std::os::raw::c_char,
+        ) -> bool {
+            $crate::wrapper::setup_logger();
+
+            $crate::wrapper::lv2::write_ttl(
+                bundle_path,
+                binary_name,
+                self::lv2::plugin_descriptors(),
+            )
+        }
+    };
+}
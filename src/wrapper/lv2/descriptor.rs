@@ -0,0 +1,82 @@
+use std::ffi::{c_void, CString};
+use std::os::raw::c_char;
+
+use super::sys::{LV2_Descriptor, LV2_Feature, LV2_Handle};
+use super::ttl;
+use super::wrapper::Wrapper;
+use crate::prelude::Lv2Plugin;
+
+/// A static descriptor for a plugin. Contains the plugin's URI and the function pointers for the
+/// wrapper's LV2 callbacks.
+pub struct PluginDescriptor {
+    uri: CString,
+    /// Generates the plugin's Turtle file. This is a function pointer because the descriptors for
+    /// different plugin types are stored in the same array.
+    plugin_ttl: fn() -> String,
+
+    /// The `LV2_Descriptor` is filled in after initializing `uri` as it contains a pointer to that
+    /// string.
+    lv2_descriptor: Option<LV2_Descriptor>,
+}
+
+unsafe impl Send for PluginDescriptor {}
+unsafe impl Sync for PluginDescriptor {}
+
+impl PluginDescriptor {
+    /// Construct the plugin descriptor for a specific LV2 plugin.
+    pub fn for_plugin<P: Lv2Plugin>() -> Self {
+        let mut descriptor = Self {
+            uri: CString::new(P::LV2_URI).expect("`LV2_URI` contained null bytes"),
+            plugin_ttl: ttl::plugin_ttl::<P>,
+
+            lv2_descriptor: None,
+        };
+
+        // NOTE: This is safe without pinning this struct because the string is stored on the heap
+        descriptor.lv2_descriptor = Some(LV2_Descriptor {
+            URI: descriptor.uri.as_ptr(),
+            instantiate: Some(instantiate::<P>),
+            connect_port: Some(Wrapper::<P>::connect_port),
+            activate: Some(Wrapper::<P>::activate),
+            run: Some(Wrapper::<P>::run),
+            deactivate: Some(Wrapper::<P>::deactivate),
+            cleanup: Some(Wrapper::<P>::cleanup),
+            extension_data: Some(Wrapper::<P>::extension_data),
+        });
+
+        descriptor
+    }
+
+    pub fn lv2_descriptor(&self) -> *const LV2_Descriptor {
+        self.lv2_descriptor.as_ref().unwrap()
+    }
+
+    /// The plugin's URI.
+    pub fn uri(&self) -> &str {
+        self.uri.to_str().expect("`LV2_URI` is not valid UTF-8")
+    }
+
+    /// The name of the plugin's Turtle file in the bundle. This is derived from the plugin's URI so
+    /// every plugin in a bundle gets its own file.
+    pub fn ttl_file_name(&self) -> String {
+        ttl::file_name_for_uri(self.uri())
+    }
+
+    /// Generate the plugin's Turtle file.
+    pub fn plugin_ttl(&self) -> String {
+        (self.plugin_ttl)()
+    }
+}
+
+unsafe extern "C" fn instantiate<P: Lv2Plugin>(
+    _descriptor: *const LV2_Descriptor,
+    sample_rate: f64,
+    _bundle_path: *const c_char,
+    features: *const *const LV2_Feature,
+) -> LV2_Handle {
+    match Wrapper::<P>::new(sample_rate as f32, features) {
+        // This pointer gets turned back into an `Arc` in `Wrapper::cleanup()`
+        Some(wrapper) => std::sync::Arc::into_raw(wrapper) as *mut c_void,
+        None => std::ptr::null_mut(),
+    }
+}
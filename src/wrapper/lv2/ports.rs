@@ -0,0 +1,124 @@
+//! LV2 plugins have a fixed list of ports that's described in the plugin's Turtle file. Both the
+//! wrapper and the Turtle generator derive the port indices from the same [`PortLayout`] so they
+//! always agree with each other.
+
+use std::num::NonZeroU32;
+use std::ops::Range;
+
+use crate::prelude::AudioIOLayout;
+
+/// The indices of all of a plugin's ports. The ports are ordered as follows:
+///
+/// 1. One audio port per main input channel, followed by one per main output channel.
+/// 2. One audio port per channel for every auxiliary input, followed by the auxiliary outputs.
+/// 3. An atom sequence input for MIDI events and the host's transport information.
+/// 4. An atom sequence output for MIDI events, if the plugin outputs MIDI.
+/// 5. A control output reporting the plugin's latency.
+/// 6. A control input for every parameter, in the order they're returned from
+///    [`Params::param_map()`][crate::prelude::Params::param_map()].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortLayout {
+    pub main_inputs: Range<u32>,
+    pub main_outputs: Range<u32>,
+    pub aux_inputs: Vec<Range<u32>>,
+    pub aux_outputs: Vec<Range<u32>>,
+    pub events_in: u32,
+    pub events_out: Option<u32>,
+    pub latency: u32,
+    pub params: Range<u32>,
+}
+
+impl PortLayout {
+    /// Compute the port layout for an audio IO layout and a number of parameters.
+    pub fn new(audio_io_layout: &AudioIOLayout, has_events_out: bool, num_params: usize) -> Self {
+        let mut next_index = 0;
+        let mut take = |num_ports: u32| {
+            let range = next_index..next_index + num_ports;
+            next_index += num_ports;
+
+            range
+        };
+
+        let main_inputs = take(
+            audio_io_layout
+                .main_input_channels
+                .map(NonZeroU32::get)
+                .unwrap_or(0),
+        );
+        let main_outputs = take(
+            audio_io_layout
+                .main_output_channels
+                .map(NonZeroU32::get)
+                .unwrap_or(0),
+        );
+        let aux_inputs = audio_io_layout
+            .aux_input_ports
+            .iter()
+            .map(|num_channels| take(num_channels.get()))
+            .collect();
+        let aux_outputs = audio_io_layout
+            .aux_output_ports
+            .iter()
+            .map(|num_channels| take(num_channels.get()))
+            .collect();
+        let events_in = take(1).start;
+        let events_out = has_events_out.then(|| take(1).start);
+        let latency = take(1).start;
+        let params = take(num_params as u32);
+
+        Self {
+            main_inputs,
+            main_outputs,
+            aux_inputs,
+            aux_outputs,
+            events_in,
+            events_out,
+            latency,
+            params,
+        }
+    }
+
+    /// The total number of ports.
+    pub fn num_ports(&self) -> u32 {
+        self.params.end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::new_nonzero_u32;
+
+    #[test]
+    fn port_indices() {
+        const AUDIO_IO_LAYOUT: AudioIOLayout = AudioIOLayout {
+            main_input_channels: Some(new_nonzero_u32(2)),
+            main_output_channels: Some(new_nonzero_u32(2)),
+            aux_input_ports: &[new_nonzero_u32(1)],
+            aux_output_ports: &[new_nonzero_u32(2), new_nonzero_u32(1)],
+            ..AudioIOLayout::const_default()
+        };
+
+        let layout = PortLayout::new(&AUDIO_IO_LAYOUT, true, 3);
+        assert_eq!(layout.main_inputs, 0..2);
+        assert_eq!(layout.main_outputs, 2..4);
+        assert_eq!(layout.aux_inputs, [4..5]);
+        assert_eq!(layout.aux_outputs, [5..7, 7..8]);
+        assert_eq!(layout.events_in, 8);
+        assert_eq!(layout.events_out, Some(9));
+        assert_eq!(layout.latency, 10);
+        assert_eq!(layout.params, 11..14);
+        assert_eq!(layout.num_ports(), 14);
+    }
+
+    #[test]
+    fn port_indices_without_audio() {
+        let layout = PortLayout::new(&AudioIOLayout::const_default(), false, 1);
+        assert_eq!(layout.main_inputs, 0..0);
+        assert_eq!(layout.main_outputs, 0..0);
+        assert_eq!(layout.events_in, 0);
+        assert_eq!(layout.events_out, None);
+        assert_eq!(layout.latency, 1);
+        assert_eq!(layout.params, 2..3);
+    }
+}
@@ -0,0 +1,179 @@
+//! The parts of the LV2 C API used by the wrapper. LV2's core API and the handful of extensions we
+//! use are small and have been stable for over a decade, so these definitions are written by hand
+//! instead of pulling in generated bindings for the entire specification.
+
+#![allow(non_camel_case_types, non_snake_case)]
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+
+pub type LV2_Handle = *mut c_void;
+pub type LV2_URID = u32;
+
+pub type LV2_State_Handle = *mut c_void;
+pub type LV2_State_Status = u32;
+pub type LV2_State_Flags = u32;
+
+pub const LV2_STATE_SUCCESS: LV2_State_Status = 0;
+pub const LV2_STATE_ERR_UNKNOWN: LV2_State_Status = 1;
+pub const LV2_STATE_ERR_BAD_TYPE: LV2_State_Status = 2;
+pub const LV2_STATE_ERR_NO_PROPERTY: LV2_State_Status = 5;
+
+pub const LV2_STATE_IS_POD: LV2_State_Flags = 1 << 0;
+pub const LV2_STATE_IS_PORTABLE: LV2_State_Flags = 1 << 1;
+
+pub const LV2_URID__map: &CStr = c"http://lv2plug.in/ns/ext/urid#map";
+pub const LV2_OPTIONS__options: &CStr = c"http://lv2plug.in/ns/ext/options#options";
+pub const LV2_BUF_SIZE__maxBlockLength: &CStr = c"http://lv2plug.in/ns/ext/buf-size#maxBlockLength";
+pub const LV2_STATE__interface: &CStr = c"http://lv2plug.in/ns/ext/state#interface";
+
+pub const LV2_ATOM__Blank: &CStr = c"http://lv2plug.in/ns/ext/atom#Blank";
+pub const LV2_ATOM__Chunk: &CStr = c"http://lv2plug.in/ns/ext/atom#Chunk";
+pub const LV2_ATOM__Double: &CStr = c"http://lv2plug.in/ns/ext/atom#Double";
+pub const LV2_ATOM__Float: &CStr = c"http://lv2plug.in/ns/ext/atom#Float";
+pub const LV2_ATOM__frameTime: &CStr = c"http://lv2plug.in/ns/ext/atom#frameTime";
+pub const LV2_ATOM__Int: &CStr = c"http://lv2plug.in/ns/ext/atom#Int";
+pub const LV2_ATOM__Long: &CStr = c"http://lv2plug.in/ns/ext/atom#Long";
+pub const LV2_ATOM__Object: &CStr = c"http://lv2plug.in/ns/ext/atom#Object";
+pub const LV2_ATOM__Sequence: &CStr = c"http://lv2plug.in/ns/ext/atom#Sequence";
+
+pub const LV2_MIDI__MidiEvent: &CStr = c"http://lv2plug.in/ns/ext/midi#MidiEvent";
+
+pub const LV2_TIME__Position: &CStr = c"http://lv2plug.in/ns/ext/time#Position";
+pub const LV2_TIME__bar: &CStr = c"http://lv2plug.in/ns/ext/time#bar";
+pub const LV2_TIME__barBeat: &CStr = c"http://lv2plug.in/ns/ext/time#barBeat";
+pub const LV2_TIME__beatUnit: &CStr = c"http://lv2plug.in/ns/ext/time#beatUnit";
+pub const LV2_TIME__beatsPerBar: &CStr = c"http://lv2plug.in/ns/ext/time#beatsPerBar";
+pub const LV2_TIME__beatsPerMinute: &CStr = c"http://lv2plug.in/ns/ext/time#beatsPerMinute";
+pub const LV2_TIME__frame: &CStr = c"http://lv2plug.in/ns/ext/time#frame";
+pub const LV2_TIME__speed: &CStr = c"http://lv2plug.in/ns/ext/time#speed";
+
+#[repr(C)]
+pub struct LV2_Feature {
+    pub URI: *const c_char,
+    pub data: *mut c_void,
+}
+
+#[repr(C)]
+pub struct LV2_Descriptor {
+    pub URI: *const c_char,
+    pub instantiate: Option<
+        unsafe extern "C" fn(
+            descriptor: *const LV2_Descriptor,
+            sample_rate: f64,
+            bundle_path: *const c_char,
+            features: *const *const LV2_Feature,
+        ) -> LV2_Handle,
+    >,
+    pub connect_port:
+        Option<unsafe extern "C" fn(instance: LV2_Handle, port: u32, data_location: *mut c_void)>,
+    pub activate: Option<unsafe extern "C" fn(instance: LV2_Handle)>,
+    pub run: Option<unsafe extern "C" fn(instance: LV2_Handle, sample_count: u32)>,
+    pub deactivate: Option<unsafe extern "C" fn(instance: LV2_Handle)>,
+    pub cleanup: Option<unsafe extern "C" fn(instance: LV2_Handle)>,
+    pub extension_data: Option<unsafe extern "C" fn(uri: *const c_char) -> *const c_void>,
+}
+
+#[repr(C)]
+pub struct LV2_URID_Map {
+    pub handle: *mut c_void,
+    pub map: Option<unsafe extern "C" fn(handle: *mut c_void, uri: *const c_char) -> LV2_URID>,
+}
+
+#[repr(C)]
+pub struct LV2_Options_Option {
+    pub context: u32,
+    pub subject: u32,
+    pub key: LV2_URID,
+    pub size: u32,
+    pub type_: LV2_URID,
+    pub value: *const c_void,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct LV2_Atom {
+    pub size: u32,
+    pub type_: LV2_URID,
+}
+
+#[repr(C)]
+pub struct LV2_Atom_Sequence_Body {
+    pub unit: LV2_URID,
+    pub pad: u32,
+}
+
+#[repr(C)]
+pub struct LV2_Atom_Sequence {
+    pub atom: LV2_Atom,
+    pub body: LV2_Atom_Sequence_Body,
+}
+
+/// The event's time stamp is a union of a frame offset and a time in beats. We only use sequences
+/// with frame offsets, sequences using beat time are rejected.
+#[repr(C)]
+pub struct LV2_Atom_Event {
+    pub time_frames: i64,
+    pub body: LV2_Atom,
+}
+
+#[repr(C)]
+pub struct LV2_Atom_Object_Body {
+    pub id: LV2_URID,
+    pub otype: LV2_URID,
+}
+
+#[repr(C)]
+pub struct LV2_Atom_Property_Body {
+    pub key: LV2_URID,
+    pub context: LV2_URID,
+    pub value: LV2_Atom,
+}
+
+pub type LV2_State_Store_Function = Option<
+    unsafe extern "C" fn(
+        handle: LV2_State_Handle,
+        key: LV2_URID,
+        value: *const c_void,
+        size: usize,
+        type_: LV2_URID,
+        flags: LV2_State_Flags,
+    ) -> LV2_State_Status,
+>;
+
+pub type LV2_State_Retrieve_Function = Option<
+    unsafe extern "C" fn(
+        handle: LV2_State_Handle,
+        key: LV2_URID,
+        size: *mut usize,
+        type_: *mut LV2_URID,
+        flags: *mut LV2_State_Flags,
+    ) -> *const c_void,
+>;
+
+#[repr(C)]
+pub struct LV2_State_Interface {
+    pub save: Option<
+        unsafe extern "C" fn(
+            instance: LV2_Handle,
+            store: LV2_State_Store_Function,
+            handle: LV2_State_Handle,
+            flags: LV2_State_Flags,
+            features: *const *const LV2_Feature,
+        ) -> LV2_State_Status,
+    >,
+    pub restore: Option<
+        unsafe extern "C" fn(
+            instance: LV2_Handle,
+            retrieve: LV2_State_Retrieve_Function,
+            handle: LV2_State_Handle,
+            flags: LV2_State_Flags,
+            features: *const *const LV2_Feature,
+        ) -> LV2_State_Status,
+    >,
+}
+
+/// The size of an atom's body padded to 64 bits, like `lv2_atom_pad_size()`.
+pub const fn lv2_atom_pad_size(size: u32) -> u32 {
+    (size + 7) & !7
+}
@@ -0,0 +1,473 @@
+//! Generates the Turtle files describing an LV2 bundle. LV2 hosts discover plugins by reading these
+//! files without loading the plugin library, so `cargo xtask bundle` loads the library once and
+//! calls the function exported by [`nih_export_lv2!()`][crate::nih_export_lv2!()] to write them.
+
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::fs;
+use std::num::NonZeroU32;
+use std::path::Path;
+
+use super::descriptor::PluginDescriptor;
+use super::ports::PortLayout;
+use crate::prelude::{AudioIOLayout, Lv2Plugin, MidiConfig, ParamFlags, ParamPtr, Params};
+
+/// Enum parameters with more than this many variants don't list their variants as scale points.
+const MAX_SCALE_POINTS: usize = 64;
+
+const PREFIXES: &str = "@prefix atom: <http://lv2plug.in/ns/ext/atom#> .
+@prefix bufsz: <http://lv2plug.in/ns/ext/buf-size#> .
+@prefix doap: <http://usefulinc.com/ns/doap#> .
+@prefix foaf: <http://xmlns.com/foaf/0.1/> .
+@prefix lv2: <http://lv2plug.in/ns/lv2core#> .
+@prefix midi: <http://lv2plug.in/ns/ext/midi#> .
+@prefix opts: <http://lv2plug.in/ns/ext/options#> .
+@prefix pprop: <http://lv2plug.in/ns/ext/port-props#> .
+@prefix rdf: <http://www.w3.org/1999/02/22-rdf-syntax-ns#> .
+@prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .
+@prefix state: <http://lv2plug.in/ns/ext/state#> .
+@prefix time: <http://lv2plug.in/ns/ext/time#> .
+@prefix units: <http://lv2plug.in/ns/extensions/units#> .
+@prefix urid: <http://lv2plug.in/ns/ext/urid#> .
+";
+
+/// Write `manifest.ttl` and a Turtle file for every plugin to `bundle_path`. `binary_name` is the
+/// file name of the plugin library inside of the bundle.
+pub fn write_bundle(
+    bundle_path: &Path,
+    binary_name: &str,
+    descriptors: &[PluginDescriptor],
+) -> Result<()> {
+    let mut manifest = String::from(PREFIXES);
+    for descriptor in descriptors {
+        let ttl_name = descriptor.ttl_file_name();
+        write!(
+            manifest,
+            "\n<{}>\n    a lv2:Plugin ;\n    lv2:binary <{}> ;\n    rdfs:seeAlso <{}> .\n",
+            escape_iri(descriptor.uri()),
+            escape_iri(binary_name),
+            escape_iri(&ttl_name),
+        )
+        .unwrap();
+
+        let ttl_path = bundle_path.join(&ttl_name);
+        fs::write(&ttl_path, descriptor.plugin_ttl())
+            .with_context(|| format!("Could not write '{}'", ttl_path.display()))?;
+    }
+
+    let manifest_path = bundle_path.join("manifest.ttl");
+    fs::write(&manifest_path, manifest)
+        .with_context(|| format!("Could not write '{}'", manifest_path.display()))
+}
+
+/// Describe a plugin and all of its ports. This instantiates the plugin to get its parameters.
+pub fn plugin_ttl<P: Lv2Plugin>() -> String {
+    let plugin = P::default();
+    let params = plugin.params();
+    let param_map = params.param_map();
+
+    let audio_io_layout = P::AUDIO_IO_LAYOUTS.first().copied().unwrap_or_default();
+    let ports = PortLayout::new(
+        &audio_io_layout,
+        P::MIDI_OUTPUT >= MidiConfig::Basic,
+        param_map.len(),
+    );
+
+    let mut ttl = String::from(PREFIXES);
+    writeln!(ttl, "\n<{}>", escape_iri(P::LV2_URI)).unwrap();
+    if audio_io_layout.main_input_channels.is_none() && P::MIDI_INPUT >= MidiConfig::Basic {
+        writeln!(ttl, "    a lv2:Plugin , lv2:InstrumentPlugin ;").unwrap();
+    } else {
+        writeln!(ttl, "    a lv2:Plugin ;").unwrap();
+    }
+    writeln!(ttl, "    doap:name \"{}\" ;", escape_string(P::NAME)).unwrap();
+    write!(
+        ttl,
+        "    doap:maintainer [\n        foaf:name \"{}\" ;",
+        escape_string(P::VENDOR)
+    )
+    .unwrap();
+    if !P::URL.is_empty() {
+        write!(ttl, "\n        foaf:homepage <{}> ;", escape_iri(P::URL)).unwrap();
+    }
+    if !P::EMAIL.is_empty() {
+        write!(
+            ttl,
+            "\n        foaf:mbox <mailto:{}> ;",
+            escape_iri(P::EMAIL)
+        )
+        .unwrap();
+    }
+    writeln!(ttl, "\n    ] ;").unwrap();
+    writeln!(
+        ttl,
+        "    lv2:requiredFeature urid:map , bufsz:boundedBlockLength , opts:options ;\n    \
+         lv2:optionalFeature lv2:hardRTCapable ;\n    opts:requiredOption bufsz:maxBlockLength ;\n    \
+         lv2:extensionData state:interface ;"
+    )
+    .unwrap();
+
+    let mut port_descriptions = audio_port_descriptions(&audio_io_layout, &ports);
+
+    let mut supported_events = vec!["time:Position"];
+    if P::MIDI_INPUT >= MidiConfig::Basic {
+        supported_events.insert(0, "midi:MidiEvent");
+    }
+    port_descriptions.push(format!(
+        "a lv2:InputPort , atom:AtomPort ;\n        atom:bufferType atom:Sequence ;\n        \
+         atom:supports {} ;\n        lv2:designation lv2:control ;\n        lv2:index {} ;\n        \
+         lv2:symbol \"events_in\" ;\n        lv2:name \"Events In\" ;",
+        supported_events.join(" , "),
+        ports.events_in,
+    ));
+    if let Some(events_out) = ports.events_out {
+        port_descriptions.push(format!(
+            "a lv2:OutputPort , atom:AtomPort ;\n        atom:bufferType atom:Sequence ;\n        \
+             atom:supports midi:MidiEvent ;\n        lv2:index {events_out} ;\n        lv2:symbol \
+             \"events_out\" ;\n        lv2:name \"Events Out\" ;"
+        ));
+    }
+    port_descriptions.push(format!(
+        "a lv2:OutputPort , lv2:ControlPort ;\n        lv2:designation lv2:latency ;\n        \
+         lv2:portProperty lv2:reportsLatency , lv2:integer ;\n        lv2:index {} ;\n        \
+         lv2:symbol \"latency\" ;\n        lv2:name \"Latency\" ;\n        lv2:minimum 0 ;\n        \
+         lv2:maximum 192000 ;",
+        ports.latency,
+    ));
+
+    let mut used_symbols: HashSet<String> = ["events_in", "events_out", "latency"]
+        .into_iter()
+        .map(String::from)
+        .collect();
+    for ((param_id, param_ptr, _), index) in param_map.iter().zip(ports.params.clone()) {
+        let symbol = port_symbol(param_id, &mut used_symbols);
+
+        // SAFETY: The parameters live as long as `params`
+        port_descriptions.push(unsafe { param_port_description(index, &symbol, *param_ptr) });
+    }
+
+    write!(
+        ttl,
+        "    lv2:port [\n        {}\n    ] .\n",
+        port_descriptions.join("\n    ] , [\n        ")
+    )
+    .unwrap();
+
+    ttl
+}
+
+/// Describe the plugin's audio ports. Auxiliary inputs are marked as sidechain inputs.
+fn audio_port_descriptions(audio_io_layout: &AudioIOLayout, ports: &PortLayout) -> Vec<String> {
+    let audio_port =
+        |direction: &str, index: u32, symbol: String, name: String, sidechain: bool| {
+            format!(
+                "a lv2:AudioPort , lv2:{direction}Port ;\n        lv2:index {index} ;\n        \
+             lv2:symbol \"{symbol}\" ;\n        lv2:name \"{}\" ;{}",
+                escape_string(&name),
+                if sidechain {
+                    "\n        lv2:portProperty lv2:isSideChain ;"
+                } else {
+                    ""
+                }
+            )
+        };
+    let channel_name = |port_name: String, num_channels: u32, channel_idx: u32| {
+        if num_channels == 1 {
+            port_name
+        } else {
+            format!("{port_name} {}", channel_idx + 1)
+        }
+    };
+
+    let mut descriptions = Vec::new();
+    let num_main_inputs = audio_io_layout
+        .main_input_channels
+        .map(NonZeroU32::get)
+        .unwrap_or(0);
+    for (channel_idx, index) in ports.main_inputs.clone().enumerate() {
+        descriptions.push(audio_port(
+            "Input",
+            index,
+            format!("in_{}", channel_idx + 1),
+            channel_name(
+                audio_io_layout.main_input_name(),
+                num_main_inputs,
+                channel_idx as u32,
+            ),
+            false,
+        ));
+    }
+    let num_main_outputs = audio_io_layout
+        .main_output_channels
+        .map(NonZeroU32::get)
+        .unwrap_or(0);
+    for (channel_idx, index) in ports.main_outputs.clone().enumerate() {
+        descriptions.push(audio_port(
+            "Output",
+            index,
+            format!("out_{}", channel_idx + 1),
+            channel_name(
+                audio_io_layout.main_output_name(),
+                num_main_outputs,
+                channel_idx as u32,
+            ),
+            false,
+        ));
+    }
+    for (port_idx, indices) in ports.aux_inputs.iter().enumerate() {
+        let port_name = audio_io_layout.aux_input_name(port_idx).unwrap_or_default();
+        for (channel_idx, index) in indices.clone().enumerate() {
+            descriptions.push(audio_port(
+                "Input",
+                index,
+                format!("aux_in_{}_{}", port_idx + 1, channel_idx + 1),
+                channel_name(port_name.clone(), indices.len() as u32, channel_idx as u32),
+                true,
+            ));
+        }
+    }
+    for (port_idx, indices) in ports.aux_outputs.iter().enumerate() {
+        let port_name = audio_io_layout
+            .aux_output_name(port_idx)
+            .unwrap_or_default();
+        for (channel_idx, index) in indices.clone().enumerate() {
+            descriptions.push(audio_port(
+                "Output",
+                index,
+                format!("aux_out_{}_{}", port_idx + 1, channel_idx + 1),
+                channel_name(port_name.clone(), indices.len() as u32, channel_idx as u32),
+                false,
+            ));
+        }
+    }
+
+    descriptions
+}
+
+/// Describe a parameter's control port. Control ports use the parameter's plain values, so the
+/// host's generic UI shows the same values as the plugin.
+///
+/// # Safety
+///
+/// `param_ptr` needs to point to a live parameter.
+unsafe fn param_port_description(index: u32, symbol: &str, param_ptr: ParamPtr) -> String {
+    let min = param_ptr.preview_plain(0.0);
+    let max = param_ptr.preview_plain(1.0);
+    let (min, max) = if min <= max { (min, max) } else { (max, min) };
+
+    let mut description = format!(
+        "a lv2:InputPort , lv2:ControlPort ;\n        lv2:index {index} ;\n        lv2:symbol \
+         \"{symbol}\" ;\n        lv2:name \"{}\" ;\n        lv2:default {} ;\n        lv2:minimum \
+         {} ;\n        lv2:maximum {} ;",
+        escape_string(param_ptr.name()),
+        number(param_ptr.default_plain_value()),
+        number(min),
+        number(max),
+    );
+
+    let mut port_properties = Vec::new();
+    match param_ptr {
+        ParamPtr::BoolParam(_) => port_properties.push("lv2:toggled"),
+        ParamPtr::IntParam(_) => port_properties.push("lv2:integer"),
+        ParamPtr::EnumParam(_) => {
+            port_properties.push("lv2:integer");
+
+            let step_count = param_ptr.step_count().unwrap_or(0);
+            if step_count < MAX_SCALE_POINTS {
+                port_properties.push("lv2:enumeration");
+                for step in 0..=step_count {
+                    let normalized = if step_count == 0 {
+                        0.0
+                    } else {
+                        step as f32 / step_count as f32
+                    };
+                    write!(
+                        description,
+                        "\n        lv2:scalePoint [ rdfs:label \"{}\" ; rdf:value {} ] ;",
+                        escape_string(&param_ptr.normalized_value_to_string(normalized, false)),
+                        number(param_ptr.preview_plain(normalized)),
+                    )
+                    .unwrap();
+                }
+            }
+        }
+        ParamPtr::FloatParam(_) => (),
+    }
+
+    let flags = param_ptr.flags();
+    if flags.intersects(ParamFlags::HIDDEN | ParamFlags::HIDE_IN_GENERIC_UI) {
+        port_properties.push("pprop:notOnGUI");
+    }
+    if flags.contains(ParamFlags::NON_AUTOMATABLE) {
+        port_properties.push("pprop:notAutomatic");
+    }
+    if !port_properties.is_empty() {
+        write!(
+            description,
+            "\n        lv2:portProperty {} ;",
+            port_properties.join(" , ")
+        )
+        .unwrap();
+    }
+
+    // Units are usually prefixed with a space so they can be appended directly to a value. The
+    // render string is a printf format string, so percent signs in the unit need to be escaped.
+    let unit = param_ptr.unit().trim();
+    if !unit.is_empty() {
+        let render_unit = escape_string(&unit.replace('%', "%%"));
+        let unit = escape_string(unit);
+        write!(
+            description,
+            "\n        units:unit [\n            a units:Unit ;\n            rdfs:label \"{unit}\" \
+             ;\n            units:symbol \"{unit}\" ;\n            units:render \"%f {render_unit}\" \
+             ;\n        ] ;"
+        )
+        .unwrap();
+    }
+
+    description
+}
+
+/// Turn a parameter ID into a unique LV2 port symbol. Symbols may only contain ASCII letters,
+/// digits, and underscores, and they cannot start with a digit. Since hosts use the symbols to
+/// restore port values, these are derived from the parameter's stable ID.
+fn port_symbol(param_id: &str, used_symbols: &mut HashSet<String>) -> String {
+    let mut symbol: String = param_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if !symbol.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        symbol.insert(0, '_');
+    }
+
+    let mut unique_symbol = symbol.clone();
+    let mut suffix = 2;
+    while used_symbols.contains(&unique_symbol) {
+        unique_symbol = format!("{symbol}_{suffix}");
+        suffix += 1;
+    }
+    used_symbols.insert(unique_symbol.clone());
+
+    unique_symbol
+}
+
+/// Derive a Turtle file name from a plugin's URI. Characters that can't safely be used in file
+/// names are replaced by `_` followed by their hexadecimal UTF-8 bytes. `_` itself is also escaped
+/// this way, so different URIs always result in different file names.
+pub fn file_name_for_uri(uri: &str) -> String {
+    let mut file_name = String::with_capacity(uri.len() + 4);
+    for byte in uri.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' => file_name.push(byte as char),
+            byte => write!(file_name, "_{byte:02X}").unwrap(),
+        }
+    }
+    file_name.push_str(".ttl");
+
+    file_name
+}
+
+/// Escape a string so it can be used in a quoted Turtle string literal.
+fn escape_string(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len());
+    for c in string.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Percent-encode the characters that are not allowed in a Turtle IRI reference.
+fn escape_iri(iri: &str) -> String {
+    let mut escaped = String::with_capacity(iri.len());
+    for c in iri.chars() {
+        match c {
+            '\0'..=' ' | '<' | '>' | '"' | '{' | '}' | '|' | '^' | '`' | '\\' => {
+                write!(escaped, "%{:02X}", c as u32).unwrap()
+            }
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Format a number as a Turtle decimal or double literal.
+fn number(value: f32) -> String {
+    if value.is_finite() {
+        // Rust's debug representation always includes either a decimal point or an exponent
+        format!("{value:?}")
+    } else if value > 0.0 {
+        format!("{:?}", f32::MAX)
+    } else if value < 0.0 {
+        format!("{:?}", f32::MIN)
+    } else {
+        String::from("0.0")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unique_port_symbols() {
+        let mut used_symbols = HashSet::from([String::from("latency")]);
+        let symbols: Vec<_> = ["gain", "2nd", "gain!", "gain_", "latency", "wet-dry"]
+            .into_iter()
+            .map(|param_id| port_symbol(param_id, &mut used_symbols))
+            .collect();
+
+        assert_eq!(
+            symbols,
+            ["gain", "_2nd", "gain_", "gain__2", "latency_2", "wet_dry"]
+        );
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(
+            escape_string(r#"A "quoted" \ name"#),
+            r#"A \"quoted\" \\ name"#
+        );
+        assert_eq!(escape_string("Two\nlines"), "Two\\nlines");
+        assert_eq!(escape_iri("My Plugin.so"), "My%20Plugin.so");
+        assert_eq!(
+            escape_iri("https://example.com/a?b=c#d"),
+            "https://example.com/a?b=c#d"
+        );
+    }
+
+    #[test]
+    fn unique_file_names() {
+        assert_eq!(
+            file_name_for_uri("https://example.com/plugins/gain"),
+            "https_3A_2F_2Fexample.com_2Fplugins_2Fgain.ttl"
+        );
+        assert_ne!(
+            file_name_for_uri("urn:example:a_b"),
+            file_name_for_uri("urn:example:a-b")
+        );
+        assert_ne!(
+            file_name_for_uri("urn:example:a_2F"),
+            file_name_for_uri("urn:example:a/")
+        );
+    }
+
+    #[test]
+    fn number_literals() {
+        assert_eq!(number(0.0), "0.0");
+        assert_eq!(number(-30.0), "-30.0");
+        assert_eq!(number(1e-7), "1e-7");
+        assert_eq!(number(f32::NAN), "0.0");
+        assert_eq!(number(f32::INFINITY), format!("{:?}", f32::MAX));
+    }
+}
@@ -0,0 +1,1054 @@
+use atomic_refcell::AtomicRefCell;
+use parking_lot::Mutex;
+use std::borrow::Borrow;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::{c_void, CStr};
+use std::mem;
+use std::ops::Range;
+use std::os::raw::c_char;
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{AtomicPtr, AtomicU32, Ordering};
+use std::sync::Arc;
+
+use super::context::{WrapperInitContext, WrapperProcessContext};
+use super::ports::PortLayout;
+use super::sys::{
+    lv2_atom_pad_size, LV2_ATOM__Blank, LV2_ATOM__Chunk, LV2_ATOM__Double, LV2_ATOM__Float,
+    LV2_ATOM__Int, LV2_ATOM__Long, LV2_ATOM__Object, LV2_ATOM__Sequence, LV2_ATOM__frameTime,
+    LV2_Atom, LV2_Atom_Event, LV2_Atom_Object_Body, LV2_Atom_Property_Body, LV2_Atom_Sequence,
+    LV2_Atom_Sequence_Body, LV2_BUF_SIZE__maxBlockLength, LV2_Feature, LV2_Handle,
+    LV2_MIDI__MidiEvent, LV2_OPTIONS__options, LV2_Options_Option, LV2_STATE__interface,
+    LV2_State_Flags, LV2_State_Handle, LV2_State_Interface, LV2_State_Retrieve_Function,
+    LV2_State_Status, LV2_State_Store_Function, LV2_TIME__Position, LV2_TIME__bar,
+    LV2_TIME__barBeat, LV2_TIME__beatUnit, LV2_TIME__beatsPerBar, LV2_TIME__beatsPerMinute,
+    LV2_TIME__frame, LV2_TIME__speed, LV2_URID_Map, LV2_URID__map, LV2_STATE_ERR_BAD_TYPE,
+    LV2_STATE_ERR_NO_PROPERTY, LV2_STATE_ERR_UNKNOWN, LV2_STATE_IS_POD, LV2_STATE_IS_PORTABLE,
+    LV2_STATE_SUCCESS, LV2_URID,
+};
+use crate::event_loop::{EventLoop, MainThreadExecutor, OsEventLoop};
+use crate::midi::learn::MidiLearn;
+use crate::midi::{MidiCCDecoder, MidiResult};
+use crate::prelude::{
+    AudioIOLayout, AuxiliaryBuffers, BufferConfig, Lv2Plugin, MidiConfig, NoteEvent, ParamPtr,
+    Params, PluginNoteEvent, ProcessMode, ProcessStatus, TaskExecutor, Transport,
+};
+use crate::util::permit_alloc;
+use crate::wrapper::state::{self, PluginState};
+use crate::wrapper::util::buffer_management::{BufferManager, ChannelPointers};
+use crate::wrapper::util::{clamp_input_event_timing, clamp_output_event_timing, process_wrapper};
+
+/// How many events we can store in our input and output event queues. Events sent to or from the
+/// plugin beyond this point will cause allocations.
+const EVENT_QUEUE_CAPACITY: usize = 2048;
+
+/// The key the plugin's state is stored under using the LV2 state extension.
+const STATE_KEY: &CStr = c"https://github.com/robbert-vdh/nih-plug#state";
+
+/// Early exit out of an LV2 callback with a debug assertion failure if one of the pointers is null.
+macro_rules! check_null_ptr {
+    ($ret:expr, $ptr:expr $(, $ptrs:expr)* $(, )?) => {
+        // Clippy doesn't understand it when we use a unit in our `check_null_ptr!()` macro
+        #[allow(clippy::unused_unit)]
+        if $ptr.is_null() $(|| $ptrs.is_null())* {
+            nih_debug_assert_failure!("Null pointer passed to function");
+            return $ret;
+        }
+    };
+}
+
+pub struct Wrapper<P: Lv2Plugin> {
+    /// The wrapped plugin instance.
+    plugin: Mutex<P>,
+    /// The plugin's background task executor closure.
+    pub task_executor: Mutex<TaskExecutor<P>>,
+    /// The plugin's parameters. These are fetched once during initialization. That way the
+    /// `ParamPtr`s are guaranteed to live at least as long as this object and we can interact with
+    /// the `Params` object without having to acquire a lock on `plugin`.
+    params: Arc<dyn Params>,
+    /// The plugin's parameters in the same order as their control ports. This order matches the
+    /// order used in the plugin's Turtle file.
+    param_ptrs: Vec<ParamPtr>,
+    /// A mapping from parameter string IDs to parameter pointers. Used for serialization and
+    /// deserialization.
+    param_id_to_ptr: HashMap<String, ParamPtr>,
+
+    /// A realtime-safe task queue so the plugin can schedule tasks that need to be run later on the
+    /// GUI thread. LV2 does not provide its own main thread task queue, so this always uses our own
+    /// event loop.
+    event_loop: AtomicRefCell<Option<OsEventLoop<Task<P>, Self>>>,
+
+    /// LV2 plugins always use the first audio IO layout. The port layout is static and described
+    /// in the plugin's Turtle file.
+    audio_io_layout: AudioIOLayout,
+    /// The sample rate and the maximum block size are passed when instantiating the plugin and
+    /// cannot change afterwards.
+    buffer_config: BufferConfig,
+    /// The indices of all of the plugin's ports.
+    ports: PortLayout,
+    /// The host's buffers for every port, set in `connect_port()`. The host may change these at any
+    /// time outside of `run()`.
+    port_ptrs: Vec<AtomicPtr<c_void>>,
+    /// The values of the parameter control ports during the last `run()` call. Parameters are only
+    /// updated when the port's value has changed. These start out as NaN values so every parameter
+    /// is set during the first `run()` call.
+    last_param_port_values: AtomicRefCell<Vec<f32>>,
+
+    /// Used to create [`Buffer`][crate::prelude::Buffer]s from the host's port buffers.
+    buffer_manager: AtomicRefCell<BufferManager>,
+    /// Scratch storage for the channel pointers passed to `buffer_manager`.
+    channel_pointers: AtomicRefCell<ChannelPointerStorage>,
+
+    /// The incoming events for the plugin, if `P::MIDI_INPUT` is set to `MidiConfig::Basic` or
+    /// higher.
+    input_events: AtomicRefCell<VecDeque<PluginNoteEvent<P>>>,
+    /// Stores any events the plugin has output during the current processing cycle.
+    output_events: AtomicRefCell<VecDeque<PluginNoteEvent<P>>>,
+    /// Decodes 14-bit CCs and (N)RPN messages from the host's MIDI events if `P::MIDI_INPUT` is
+    /// set to `MidiConfig::HighResMidiCCs`.
+    midi_cc_decoder: AtomicRefCell<MidiCCDecoder>,
+    /// The plugin's MIDI learn mappings. These are applied to incoming MIDI events if
+    /// `P::MIDI_INPUT >= MidiConfig::MidiCCs`. The LV2 wrapper does not have an editor to arm
+    /// parameters from, so the mappings are only restored from the plugin's state.
+    midi_learn: MidiLearn,
+    /// The host only sends `time:Position` objects when the transport changes, so the last known
+    /// transport information is kept around and advanced after every `run()` call.
+    transport: AtomicRefCell<Transport>,
+    /// The current latency in samples, as set by the plugin through the [`InitContext`] and the
+    /// [`ProcessContext`]. This is written to the latency port at the end of every `run()` call.
+    ///
+    /// [`InitContext`]: crate::prelude::InitContext
+    /// [`ProcessContext`]: crate::prelude::ProcessContext
+    current_latency: AtomicU32,
+
+    /// The URIDs for all URIs used by the wrapper, mapped when the plugin gets instantiated.
+    urids: Urids,
+}
+
+/// Tasks that can be sent from the plugin to be executed on the main thread in a non-blocking
+/// realtime-safe way.
+pub enum Task<P: Lv2Plugin> {
+    /// Execute one of the plugin's background tasks.
+    PluginTask(P::BackgroundTask),
+}
+
+/// The URIDs for the URIs used by the wrapper.
+struct Urids {
+    atom_blank: LV2_URID,
+    atom_chunk: LV2_URID,
+    atom_double: LV2_URID,
+    atom_float: LV2_URID,
+    atom_frame_time: LV2_URID,
+    atom_int: LV2_URID,
+    atom_long: LV2_URID,
+    atom_object: LV2_URID,
+    atom_sequence: LV2_URID,
+    midi_event: LV2_URID,
+    time_position: LV2_URID,
+    time_bar: LV2_URID,
+    time_bar_beat: LV2_URID,
+    time_beat_unit: LV2_URID,
+    time_beats_per_bar: LV2_URID,
+    time_beats_per_minute: LV2_URID,
+    time_frame: LV2_URID,
+    time_speed: LV2_URID,
+    state_key: LV2_URID,
+}
+
+/// Scratch storage for the host's channel pointers. These are gathered from the audio ports at the
+/// start of every `run()` call.
+struct ChannelPointerStorage {
+    main_inputs: Vec<*mut f32>,
+    main_outputs: Vec<*mut f32>,
+    aux_inputs: Vec<Vec<*mut f32>>,
+    aux_outputs: Vec<Vec<*mut f32>>,
+}
+
+// SAFETY: Just like in `BufferManager`, these pointers are only used as scratch storage inside of
+//         `run()`.
+unsafe impl Send for ChannelPointerStorage {}
+unsafe impl Sync for ChannelPointerStorage {}
+
+impl<P: Lv2Plugin> MainThreadExecutor<Task<P>> for Wrapper<P> {
+    fn execute(&self, task: Task<P>, _is_gui_thread: bool) {
+        match task {
+            Task::PluginTask(task) => (self.task_executor.lock())(task),
+        }
+    }
+}
+
+impl<P: Lv2Plugin> Wrapper<P> {
+    const STATE_INTERFACE: LV2_State_Interface = LV2_State_Interface {
+        save: Some(Self::save),
+        restore: Some(Self::restore),
+    };
+
+    /// Instantiate the plugin. Returns `None` if the host did not provide the required features or
+    /// if the plugin failed to initialize.
+    ///
+    /// # Safety
+    ///
+    /// `features` needs to be a null terminated array of valid feature pointers.
+    pub(super) unsafe fn new(
+        sample_rate: f32,
+        features: *const *const LV2_Feature,
+    ) -> Option<Arc<Self>> {
+        let urid_map = find_feature(features, LV2_URID__map) as *const LV2_URID_Map;
+        if urid_map.is_null() {
+            nih_error!("The host does not support the required urid:map feature");
+            return None;
+        }
+        let map_fn = (*urid_map).map?;
+        let map = |uri: &CStr| map_fn((*urid_map).handle, uri.as_ptr());
+
+        let options = find_feature(features, LV2_OPTIONS__options) as *const LV2_Options_Option;
+        let max_buffer_size = find_int_option(
+            options,
+            map(LV2_BUF_SIZE__maxBlockLength),
+            map(LV2_ATOM__Int),
+        );
+        let max_buffer_size = match max_buffer_size {
+            Some(max_buffer_size) if max_buffer_size > 0 => max_buffer_size as u32,
+            _ => {
+                nih_error!("The host did not provide the required bufsz:maxBlockLength option");
+                return None;
+            }
+        };
+
+        let urids = Urids {
+            atom_blank: map(LV2_ATOM__Blank),
+            atom_chunk: map(LV2_ATOM__Chunk),
+            atom_double: map(LV2_ATOM__Double),
+            atom_float: map(LV2_ATOM__Float),
+            atom_frame_time: map(LV2_ATOM__frameTime),
+            atom_int: map(LV2_ATOM__Int),
+            atom_long: map(LV2_ATOM__Long),
+            atom_object: map(LV2_ATOM__Object),
+            atom_sequence: map(LV2_ATOM__Sequence),
+            midi_event: map(LV2_MIDI__MidiEvent),
+            time_position: map(LV2_TIME__Position),
+            time_bar: map(LV2_TIME__bar),
+            time_bar_beat: map(LV2_TIME__barBeat),
+            time_beat_unit: map(LV2_TIME__beatUnit),
+            time_beats_per_bar: map(LV2_TIME__beatsPerBar),
+            time_beats_per_minute: map(LV2_TIME__beatsPerMinute),
+            time_frame: map(LV2_TIME__frame),
+            time_speed: map(LV2_TIME__speed),
+            state_key: map(STATE_KEY),
+        };
+
+        let mut plugin = P::default();
+        let task_executor = Mutex::new(plugin.task_executor());
+        let params = plugin.params();
+        let param_map = params.param_map();
+
+        let audio_io_layout = P::AUDIO_IO_LAYOUTS.first().copied().unwrap_or_default();
+        let ports = PortLayout::new(
+            &audio_io_layout,
+            P::MIDI_OUTPUT >= MidiConfig::Basic,
+            param_map.len(),
+        );
+
+        let wrapper = Arc::new(Wrapper {
+            plugin: Mutex::new(plugin),
+            task_executor,
+            params,
+            param_ptrs: param_map
+                .iter()
+                .map(|(_, param_ptr, _)| *param_ptr)
+                .collect(),
+            param_id_to_ptr: param_map
+                .into_iter()
+                .map(|(param_id, param_ptr, _)| (param_id, param_ptr))
+                .collect(),
+
+            // Initialized later as it needs a reference to the wrapper for the executor
+            event_loop: AtomicRefCell::new(None),
+
+            audio_io_layout,
+            buffer_config: BufferConfig {
+                sample_rate,
+                min_buffer_size: None,
+                max_buffer_size,
+                // LV2 does not have a way to signal offline rendering
+                process_mode: ProcessMode::Realtime,
+            },
+            port_ptrs: (0..ports.num_ports())
+                .map(|_| AtomicPtr::new(ptr::null_mut()))
+                .collect(),
+            last_param_port_values: AtomicRefCell::new(vec![f32::NAN; ports.params.len()]),
+            ports,
+
+            buffer_manager: AtomicRefCell::new(BufferManager::for_audio_io_layout(
+                max_buffer_size as usize,
+                audio_io_layout,
+            )),
+            channel_pointers: AtomicRefCell::new(ChannelPointerStorage {
+                main_inputs: Vec::with_capacity(
+                    audio_io_layout.main_input_channels.map_or(0, |c| c.get()) as usize,
+                ),
+                main_outputs: Vec::with_capacity(
+                    audio_io_layout.main_output_channels.map_or(0, |c| c.get()) as usize,
+                ),
+                aux_inputs: audio_io_layout
+                    .aux_input_ports
+                    .iter()
+                    .map(|num_channels| Vec::with_capacity(num_channels.get() as usize))
+                    .collect(),
+                aux_outputs: audio_io_layout
+                    .aux_output_ports
+                    .iter()
+                    .map(|num_channels| Vec::with_capacity(num_channels.get() as usize))
+                    .collect(),
+            }),
+
+            input_events: AtomicRefCell::new(VecDeque::with_capacity(EVENT_QUEUE_CAPACITY)),
+            output_events: AtomicRefCell::new(VecDeque::with_capacity(EVENT_QUEUE_CAPACITY)),
+            midi_cc_decoder: AtomicRefCell::new(MidiCCDecoder::new()),
+            midi_learn: MidiLearn::default(),
+            transport: AtomicRefCell::new(Transport::new(sample_rate)),
+            current_latency: AtomicU32::new(0),
+
+            urids,
+        });
+
+        *wrapper.event_loop.borrow_mut() =
+            Some(OsEventLoop::new_and_spawn(Arc::downgrade(&wrapper)));
+
+        // Before initializing the plugin, make sure all smoothers are set the the default values
+        for param in &wrapper.param_ptrs {
+            param.update_smoother(wrapper.buffer_config.sample_rate, true);
+        }
+
+        {
+            let mut plugin = wrapper.plugin.lock();
+            if !plugin.initialize(
+                &wrapper.audio_io_layout,
+                &wrapper.buffer_config,
+                &mut wrapper.make_init_context(),
+            ) {
+                nih_error!("The plugin failed to initialize");
+                return None;
+            }
+        }
+
+        Some(wrapper)
+    }
+
+    /// Get the plugin's state object.
+    pub fn get_state_object(&self) -> PluginState {
+        unsafe {
+            state::serialize_object::<P>(
+                self.params.clone(),
+                self.param_id_to_ptr
+                    .iter()
+                    .map(|(param_id, param_ptr)| (param_id, *param_ptr)),
+            )
+        }
+    }
+
+    /// Posts the task to the background task queue using [`EventLoop::schedule_background()`] so it
+    /// can be run in the background without blocking either the GUI or the audio thread.
+    ///
+    /// If the task queue is full, then this will return false.
+    #[must_use]
+    pub fn schedule_background(&self, task: Task<P>) -> bool {
+        let event_loop = self.event_loop.borrow();
+        let event_loop = event_loop.as_ref().unwrap();
+        event_loop.schedule_background(task)
+    }
+
+    /// Posts the task to the task queue using [`EventLoop::schedule_gui()`] so it can be delegated
+    /// to the main thread. The task is run directly if this is the GUI thread.
+    ///
+    /// If the task queue is full, then this will return false.
+    #[must_use]
+    pub fn schedule_gui(&self, task: Task<P>) -> bool {
+        let event_loop = self.event_loop.borrow();
+        let event_loop = event_loop.as_ref().unwrap();
+        event_loop.schedule_gui(task)
+    }
+
+    pub fn set_latency_samples(&self, samples: u32) {
+        // The host reads this value from the latency port after every `run()` call
+        self.current_latency.store(samples, Ordering::SeqCst);
+    }
+
+    pub(super) unsafe extern "C" fn connect_port(
+        instance: LV2_Handle,
+        port: u32,
+        data_location: *mut c_void,
+    ) {
+        check_null_ptr!((), instance);
+        let wrapper = &*(instance as *const Self);
+
+        match wrapper.port_ptrs.get(port as usize) {
+            Some(port_ptr) => port_ptr.store(data_location, Ordering::SeqCst),
+            None => nih_debug_assert_failure!("Unknown port index {}", port),
+        }
+    }
+
+    pub(super) unsafe extern "C" fn activate(instance: LV2_Handle) {
+        check_null_ptr!((), instance);
+        let wrapper = &*(instance as *const Self);
+
+        process_wrapper(|| wrapper.plugin.lock().reset());
+    }
+
+    pub(super) unsafe extern "C" fn run(instance: LV2_Handle, sample_count: u32) {
+        check_null_ptr!((), instance);
+        let wrapper = &*(instance as *const Self);
+
+        process_wrapper(|| wrapper.run_inner(sample_count));
+    }
+
+    pub(super) unsafe extern "C" fn deactivate(_instance: LV2_Handle) {
+        // The plugin only gets deactivated when it gets cleaned up since `activate()` may be called
+        // again afterwards, and reinitializing the plugin there is not realtime-safe
+    }
+
+    pub(super) unsafe extern "C" fn cleanup(instance: LV2_Handle) {
+        check_null_ptr!((), instance);
+
+        // This turns the pointer created in `instantiate()` back into an `Arc` so it gets dropped
+        let wrapper = Arc::from_raw(instance as *const Self);
+        wrapper.plugin.lock().deactivate();
+    }
+
+    pub(super) unsafe extern "C" fn extension_data(uri: *const c_char) -> *const c_void {
+        check_null_ptr!(ptr::null(), uri);
+
+        if CStr::from_ptr(uri) == LV2_STATE__interface {
+            &Self::STATE_INTERFACE as *const LV2_State_Interface as *const c_void
+        } else {
+            ptr::null()
+        }
+    }
+
+    unsafe extern "C" fn save(
+        instance: LV2_Handle,
+        store: LV2_State_Store_Function,
+        handle: LV2_State_Handle,
+        _flags: LV2_State_Flags,
+        _features: *const *const LV2_Feature,
+    ) -> LV2_State_Status {
+        check_null_ptr!(LV2_STATE_ERR_UNKNOWN, instance);
+        let wrapper = &*(instance as *const Self);
+        let store = match store {
+            Some(store) => store,
+            None => return LV2_STATE_ERR_UNKNOWN,
+        };
+
+        let mut plugin_state = wrapper.get_state_object();
+        wrapper.midi_learn.write_to_state(&mut plugin_state);
+        let serialized = state::serialize_state_json(&plugin_state);
+        match serialized {
+            Ok(serialized) => {
+                let status = store(
+                    handle,
+                    wrapper.urids.state_key,
+                    serialized.as_ptr() as *const c_void,
+                    serialized.len(),
+                    wrapper.urids.atom_chunk,
+                    LV2_STATE_IS_POD | LV2_STATE_IS_PORTABLE,
+                );
+                if status == LV2_STATE_SUCCESS {
+                    nih_trace!("Saved state ({} bytes)", serialized.len());
+                }
+
+                status
+            }
+            Err(err) => {
+                nih_debug_assert_failure!("Could not save state: {:#}", err);
+                LV2_STATE_ERR_UNKNOWN
+            }
+        }
+    }
+
+    unsafe extern "C" fn restore(
+        instance: LV2_Handle,
+        retrieve: LV2_State_Retrieve_Function,
+        handle: LV2_State_Handle,
+        _flags: LV2_State_Flags,
+        _features: *const *const LV2_Feature,
+    ) -> LV2_State_Status {
+        check_null_ptr!(LV2_STATE_ERR_UNKNOWN, instance);
+        let wrapper = &*(instance as *const Self);
+        let retrieve = match retrieve {
+            Some(retrieve) => retrieve,
+            None => return LV2_STATE_ERR_UNKNOWN,
+        };
+
+        let mut size = 0;
+        let mut type_ = 0;
+        let mut flags = 0;
+        let data = retrieve(
+            handle,
+            wrapper.urids.state_key,
+            &mut size,
+            &mut type_,
+            &mut flags,
+        );
+        if data.is_null() {
+            return LV2_STATE_ERR_NO_PROPERTY;
+        }
+        if type_ != wrapper.urids.atom_chunk {
+            nih_debug_assert_failure!("Unexpected state type {}", type_);
+            return LV2_STATE_ERR_BAD_TYPE;
+        }
+
+        let serialized = std::slice::from_raw_parts(data as *const u8, size);
+        match state::deserialize_json(serialized) {
+            Some(mut state) => {
+                wrapper.midi_learn.read_from_state(&mut state);
+                if !wrapper.set_state_inner(&mut state) {
+                    return LV2_STATE_ERR_UNKNOWN;
+                }
+
+                nih_trace!("Loaded state ({} bytes)", serialized.len());
+                LV2_STATE_SUCCESS
+            }
+            None => LV2_STATE_ERR_UNKNOWN,
+        }
+    }
+
+    fn run_inner(&self, sample_count: u32) {
+        let sample_rate = self.buffer_config.sample_rate;
+        if sample_count == 0 {
+            // Hosts may call `run()` without any samples to query the latency
+            self.write_outputs(sample_count);
+            return;
+        }
+        nih_debug_assert!(sample_count <= self.buffer_config.max_buffer_size);
+
+        // LV2 control ports contain plain values. Parameters are only updated when the host changed
+        // the port's value.
+        {
+            let mut last_param_port_values = self.last_param_port_values.borrow_mut();
+            for ((param_ptr, last_value), port) in self
+                .param_ptrs
+                .iter()
+                .zip(last_param_port_values.iter_mut())
+                .zip(self.ports.params.clone())
+            {
+                let Some(value) = self.control_port_value(port) else {
+                    continue;
+                };
+                if value == *last_value {
+                    continue;
+                }
+
+                *last_value = value;
+                unsafe {
+                    let normalized_value = param_ptr.preview_normalized(value);
+                    if param_ptr.set_normalized_value(normalized_value) {
+                        param_ptr.update_smoother(sample_rate, false);
+                    }
+                }
+            }
+        }
+
+        let mut transport = self.transport.borrow_mut();
+        let events_in = self.port_ptrs[self.ports.events_in as usize].load(Ordering::Relaxed)
+            as *const LV2_Atom_Sequence;
+        self.input_events.borrow_mut().clear();
+        if !events_in.is_null() {
+            // This needs to be dropped before creating the process context
+            let mut input_events = self.input_events.borrow_mut();
+            unsafe {
+                for_each_event(events_in, self.urids.atom_frame_time, |timing, atom| {
+                    if atom.type_ == self.urids.midi_event {
+                        let midi_data = std::slice::from_raw_parts(
+                            (atom as *const LV2_Atom).add(1) as *const u8,
+                            atom.size as usize,
+                        );
+                        let timing = clamp_input_event_timing(timing, sample_count);
+                        self.handle_midi_event(timing, midi_data, &mut input_events);
+                    } else if atom.type_ == self.urids.atom_object
+                        || atom.type_ == self.urids.atom_blank
+                    {
+                        self.handle_object(atom, &mut transport);
+                    }
+                })
+            };
+        }
+
+        let mut buffer_manager = self.buffer_manager.borrow_mut();
+        let mut channel_pointers = self.channel_pointers.borrow_mut();
+        let ChannelPointerStorage {
+            main_inputs,
+            main_outputs,
+            aux_inputs,
+            aux_outputs,
+        } = &mut *channel_pointers;
+        let buffers = unsafe {
+            buffer_manager.create_buffers(0, sample_count as usize, |buffer_source| {
+                *buffer_source.main_input_channel_pointers =
+                    self.audio_port_pointers(self.ports.main_inputs.clone(), main_inputs);
+                *buffer_source.main_output_channel_pointers =
+                    self.audio_port_pointers(self.ports.main_outputs.clone(), main_outputs);
+
+                for ((channel_pointers, ports), storage) in buffer_source
+                    .aux_input_channel_pointers
+                    .iter_mut()
+                    .zip(&self.ports.aux_inputs)
+                    .zip(aux_inputs.iter_mut())
+                {
+                    *channel_pointers = self.audio_port_pointers(ports.clone(), storage);
+                }
+                for ((channel_pointers, ports), storage) in buffer_source
+                    .aux_output_channel_pointers
+                    .iter_mut()
+                    .zip(&self.ports.aux_outputs)
+                    .zip(aux_outputs.iter_mut())
+                {
+                    *channel_pointers = self.audio_port_pointers(ports.clone(), storage);
+                }
+            })
+        };
+
+        let mut aux = AuxiliaryBuffers {
+            inputs: buffers.aux_inputs,
+            outputs: buffers.aux_outputs,
+        };
+        let status = {
+            let mut plugin = self.plugin.lock();
+            let mut context = self.make_process_context(transport.clone());
+            plugin.process(buffers.main_buffer, &mut aux, &mut context)
+        };
+        if let ProcessStatus::Error(err) = status {
+            nih_debug_assert_failure!("Process error: {}", err);
+        }
+
+        // The host only informs us about changes to the transport, so the transport needs to be
+        // advanced manually. Anything that can be derived from the new position is recomputed when
+        // the plugin requests it.
+        if transport.playing {
+            if let Some(pos_samples) = &mut transport.pos_samples {
+                *pos_samples += sample_count as i64;
+            }
+            let tempo = transport.tempo;
+            if let (Some(pos_beats), Some(tempo)) = (&mut transport.pos_beats, tempo) {
+                *pos_beats += sample_count as f64 / sample_rate as f64 / 60.0 * tempo;
+            }
+            transport.pos_seconds = None;
+            transport.bar_start_pos_beats = None;
+            transport.bar_number = None;
+        }
+
+        self.write_outputs(sample_count);
+    }
+
+    /// Write the plugin's output events and its latency to the output ports.
+    fn write_outputs(&self, sample_count: u32) {
+        let mut output_events = self.output_events.borrow_mut();
+        if let Some(events_out) = self.ports.events_out {
+            let events_out = self.port_ptrs[events_out as usize].load(Ordering::Relaxed)
+                as *mut LV2_Atom_Sequence;
+            if !events_out.is_null() {
+                unsafe { self.write_output_events(events_out, sample_count, &mut output_events) };
+            }
+        }
+        output_events.clear();
+
+        let latency =
+            self.port_ptrs[self.ports.latency as usize].load(Ordering::Relaxed) as *mut f32;
+        if !latency.is_null() {
+            unsafe { *latency = self.current_latency.load(Ordering::SeqCst) as f32 };
+        }
+    }
+
+    /// Convert a MIDI event from the host to a note event, filtering out events the plugin did not
+    /// ask for. This uses the same rules as the VST3 and CLAP wrappers.
+    fn handle_midi_event(
+        &self,
+        timing: u32,
+        midi_data: &[u8],
+        input_events: &mut VecDeque<PluginNoteEvent<P>>,
+    ) {
+        match NoteEvent::from_midi(timing, midi_data) {
+            Ok(
+                note_event @ (NoteEvent::NoteOn { .. }
+                | NoteEvent::NoteOff { .. }
+                | NoteEvent::PolyPressure { .. }
+                | NoteEvent::MidiSysEx { .. }
+                | NoteEvent::MidiTimingClock { .. }
+                | NoteEvent::MidiStart { .. }
+                | NoteEvent::MidiContinue { .. }
+                | NoteEvent::MidiStop { .. }
+                | NoteEvent::MidiSongPosition { .. }
+                | NoteEvent::MidiTimecodeQuarterFrame { .. }),
+            ) if P::MIDI_INPUT >= MidiConfig::Basic => {
+                input_events.push_back(note_event);
+            }
+            Ok(note_event) if P::MIDI_INPUT >= MidiConfig::MidiCCs => {
+                self.handle_midi_learn(&note_event);
+
+                // The CCs making up 14-bit CCs and (N)RPN messages are combined into single events
+                if P::MIDI_INPUT >= MidiConfig::HighResMidiCCs {
+                    if let Some(note_event) = self.midi_cc_decoder.borrow_mut().decode(note_event) {
+                        input_events.push_back(note_event);
+                    }
+                } else {
+                    input_events.push_back(note_event);
+                }
+            }
+            Ok(_) => (),
+            Err(n) => nih_debug_assert_failure!("Unhandled MIDI message type {}", n),
+        }
+    }
+
+    /// Apply the MIDI learn mappings for an incoming MIDI event as parameter changes. The host owns
+    /// the values of the parameter control ports, so unlike the CLAP and VST3 wrappers these changes
+    /// cannot be reported back to the host. A mapped parameter keeps its new value until the host
+    /// changes the value of the parameter's control port.
+    fn handle_midi_learn(&self, event: &PluginNoteEvent<P>) {
+        let sample_rate = self.buffer_config.sample_rate;
+        self.midi_learn
+            .handle_event(event, |param_id, normalized_value| {
+                let param_ptr = match self.param_id_to_ptr.get(param_id) {
+                    Some(param_ptr) => *param_ptr,
+                    None => return,
+                };
+
+                unsafe {
+                    if param_ptr.set_normalized_value(normalized_value) {
+                        param_ptr.update_smoother(sample_rate, false);
+                    }
+                }
+            });
+    }
+
+    /// Update the transport information from a `time:Position` object. Other objects are ignored.
+    ///
+    /// # Safety
+    ///
+    /// `object` must be an object atom followed by its body.
+    unsafe fn handle_object(&self, object: &LV2_Atom, transport: &mut Transport) {
+        let object_ptr = object as *const LV2_Atom as *const u8;
+        let body = &*(object_ptr.add(mem::size_of::<LV2_Atom>()) as *const LV2_Atom_Object_Body);
+        if body.otype != self.urids.time_position {
+            return;
+        }
+
+        let mut bar = None;
+        let mut bar_beat = None;
+        let mut beat_unit = None;
+        let mut beats_per_bar = None;
+
+        let properties_end = mem::size_of::<LV2_Atom>() + object.size as usize;
+        let mut offset = mem::size_of::<LV2_Atom>() + mem::size_of::<LV2_Atom_Object_Body>();
+        while offset + mem::size_of::<LV2_Atom_Property_Body>() <= properties_end {
+            let property = &*(object_ptr.add(offset) as *const LV2_Atom_Property_Body);
+            let value = self.read_number(&property.value);
+
+            if property.key == self.urids.time_frame {
+                transport.pos_samples = value.map(|value| value as i64);
+            } else if property.key == self.urids.time_speed {
+                transport.playing = value.is_some_and(|speed| speed != 0.0);
+            } else if property.key == self.urids.time_beats_per_minute {
+                transport.tempo = value;
+            } else if property.key == self.urids.time_bar {
+                bar = value;
+            } else if property.key == self.urids.time_bar_beat {
+                bar_beat = value;
+            } else if property.key == self.urids.time_beat_unit {
+                beat_unit = value;
+            } else if property.key == self.urids.time_beats_per_bar {
+                beats_per_bar = value;
+            }
+
+            offset += mem::size_of::<LV2_Atom_Property_Body>()
+                + lv2_atom_pad_size(property.value.size) as usize;
+        }
+
+        if let (Some(beats_per_bar), Some(beat_unit)) = (beats_per_bar, beat_unit) {
+            transport.time_sig_numerator = Some(beats_per_bar as i32);
+            transport.time_sig_denominator = Some(beat_unit as i32);
+
+            // LV2 counts beats in the time signature's beat unit, while NIH-plug uses quarter notes
+            if let (Some(bar), Some(bar_beat)) = (bar, bar_beat) {
+                let quarter_notes_per_beat = 4.0 / beat_unit;
+                let bar_start_pos_beats = bar * beats_per_bar * quarter_notes_per_beat;
+
+                transport.bar_number = Some(bar as i32);
+                transport.bar_start_pos_beats = Some(bar_start_pos_beats);
+                transport.pos_beats = Some(bar_start_pos_beats + bar_beat * quarter_notes_per_beat);
+            }
+        }
+        transport.pos_seconds = None;
+    }
+
+    /// Read a numeric atom's value.
+    ///
+    /// # Safety
+    ///
+    /// `atom` must be followed by its body.
+    unsafe fn read_number(&self, atom: &LV2_Atom) -> Option<f64> {
+        let body = (atom as *const LV2_Atom).add(1);
+        if atom.type_ == self.urids.atom_int && atom.size >= 4 {
+            Some(ptr::read_unaligned(body as *const i32) as f64)
+        } else if atom.type_ == self.urids.atom_long && atom.size >= 8 {
+            Some(ptr::read_unaligned(body as *const i64) as f64)
+        } else if atom.type_ == self.urids.atom_float && atom.size >= 4 {
+            Some(ptr::read_unaligned(body as *const f32) as f64)
+        } else if atom.type_ == self.urids.atom_double && atom.size >= 8 {
+            Some(ptr::read_unaligned(body as *const f64))
+        } else {
+            None
+        }
+    }
+
+    /// Write the plugin's output events to the output atom sequence. The host sets the sequence's
+    /// size to the buffer's capacity before calling `run()`.
+    ///
+    /// # Safety
+    ///
+    /// `sequence` must point to a valid atom sequence buffer.
+    unsafe fn write_output_events(
+        &self,
+        sequence: *mut LV2_Atom_Sequence,
+        sample_count: u32,
+        output_events: &mut VecDeque<PluginNoteEvent<P>>,
+    ) {
+        let capacity = (*sequence).atom.size;
+        (*sequence).atom = LV2_Atom {
+            size: mem::size_of::<LV2_Atom_Sequence_Body>() as u32,
+            type_: self.urids.atom_sequence,
+        };
+        (*sequence).body = LV2_Atom_Sequence_Body { unit: 0, pad: 0 };
+
+        let mut write = |timing: u32, midi_data: &[u8]| {
+            let event_size =
+                mem::size_of::<LV2_Atom_Event>() as u32 + lv2_atom_pad_size(midi_data.len() as u32);
+            let used = (*sequence).atom.size;
+            if used + event_size > capacity {
+                nih_debug_assert_failure!("The MIDI output buffer is full");
+                return;
+            }
+
+            let event = (sequence as *mut u8).add(mem::size_of::<LV2_Atom>() + used as usize)
+                as *mut LV2_Atom_Event;
+            (*event).time_frames = timing as i64;
+            (*event).body = LV2_Atom {
+                size: midi_data.len() as u32,
+                type_: self.urids.midi_event,
+            };
+            ptr::copy_nonoverlapping(midi_data.as_ptr(), event.add(1) as *mut u8, midi_data.len());
+            (*sequence).atom.size += event_size;
+        };
+
+        for event in output_events.drain(..) {
+            // Out of bounds events are clamped to the buffer's size
+            let timing = clamp_output_event_timing(event.timing(), sample_count);
+
+            match event.as_midi() {
+                Some(MidiResult::Basic(midi_data)) => write(timing, &midi_data),
+                Some(MidiResult::SysEx(padded_sysex_buffer, length)) => {
+                    // The SysEx buffer may contain padding
+                    let padded_sysex_buffer = padded_sysex_buffer.borrow();
+                    nih_debug_assert!(length <= padded_sysex_buffer.len());
+                    write(timing, &padded_sysex_buffer[..length]);
+                }
+                Some(MidiResult::Sequence(midi_sequence, length)) => {
+                    for midi_data in &midi_sequence[..length] {
+                        write(timing, midi_data);
+                    }
+                }
+                Some(MidiResult::System(midi_data, length)) => write(timing, &midi_data[..length]),
+                None => (),
+            }
+        }
+    }
+
+    /// The value of a control port, or `None` if the host has not connected the port.
+    fn control_port_value(&self, port: u32) -> Option<f32> {
+        let port_ptr = self.port_ptrs[port as usize].load(Ordering::Relaxed) as *const f32;
+        if port_ptr.is_null() {
+            None
+        } else {
+            Some(unsafe { *port_ptr })
+        }
+    }
+
+    /// Gather the host's buffers for a range of audio ports into `storage`. Returns `None` if the
+    /// range is empty or if the host did not connect one of the ports.
+    fn audio_port_pointers(
+        &self,
+        ports: Range<u32>,
+        storage: &mut Vec<*mut f32>,
+    ) -> Option<ChannelPointers> {
+        storage.clear();
+        for port in ports {
+            let port_ptr = self.port_ptrs[port as usize].load(Ordering::Relaxed) as *mut f32;
+            if port_ptr.is_null() {
+                nih_debug_assert_failure!("The host did not connect audio port {}", port);
+                return None;
+            }
+
+            storage.push(port_ptr);
+        }
+
+        if storage.is_empty() {
+            None
+        } else {
+            Some(ChannelPointers {
+                ptrs: NonNull::new(storage.as_mut_ptr()).unwrap(),
+                num_channels: storage.len(),
+            })
+        }
+    }
+
+    fn make_init_context(&self) -> WrapperInitContext<'_, P> {
+        WrapperInitContext { wrapper: self }
+    }
+
+    fn make_process_context(&self, transport: Transport) -> WrapperProcessContext<'_, P> {
+        WrapperProcessContext {
+            wrapper: self,
+            input_events_guard: self.input_events.borrow_mut(),
+            output_events_guard: self.output_events.borrow_mut(),
+            transport,
+        }
+    }
+
+    /// Immediately set the plugin state. Returns `false` if the deserialization failed. The host
+    /// never restores state while the plugin is processing audio. The plugin is reinitialized after
+    /// loading the state so it can respond to the new parameter values.
+    fn set_state_inner(&self, state: &mut PluginState) -> bool {
+        let mut success = permit_alloc(|| unsafe {
+            state::deserialize_object::<P>(
+                state,
+                self.params.clone(),
+                |param_id| self.param_id_to_ptr.get(param_id).copied(),
+                Some(&self.buffer_config),
+            )
+        });
+        if !success {
+            nih_debug_assert_failure!("Deserializing plugin state from a state object failed");
+            return false;
+        }
+
+        {
+            // NOTE: This needs to be dropped after the `plugin` lock to avoid deadlocks
+            let mut init_context = self.make_init_context();
+            let mut plugin = self.plugin.lock();
+
+            success = permit_alloc(|| {
+                plugin.initialize(
+                    &self.audio_io_layout,
+                    &self.buffer_config,
+                    &mut init_context,
+                )
+            });
+            if success {
+                process_wrapper(|| plugin.reset());
+            }
+        }
+
+        nih_debug_assert!(
+            success,
+            "Plugin returned false when reinitializing after loading state"
+        );
+
+        // The host usually restores the control ports together with the state. Any ports it did
+        // not touch should not overwrite the restored parameter values during the next `run()`.
+        let mut last_param_port_values = self.last_param_port_values.borrow_mut();
+        for (last_value, port) in last_param_port_values
+            .iter_mut()
+            .zip(self.ports.params.clone())
+        {
+            if let Some(value) = self.control_port_value(port) {
+                *last_value = value;
+            }
+        }
+
+        success
+    }
+}
+
+/// Find a feature's data pointer in the host's null terminated feature array. Returns a null
+/// pointer if the host does not support the feature.
+///
+/// # Safety
+///
+/// `features` needs to be a null terminated array of valid feature pointers, or a null pointer.
+unsafe fn find_feature(features: *const *const LV2_Feature, uri: &CStr) -> *mut c_void {
+    if features.is_null() {
+        return ptr::null_mut();
+    }
+
+    let mut feature_ptr = features;
+    while !(*feature_ptr).is_null() {
+        let feature = &**feature_ptr;
+        if !feature.URI.is_null() && CStr::from_ptr(feature.URI) == uri {
+            return feature.data;
+        }
+
+        feature_ptr = feature_ptr.add(1);
+    }
+
+    ptr::null_mut()
+}
+
+/// Find an integer option in the host's option array. The array is terminated by an option with a
+/// zero key.
+///
+/// # Safety
+///
+/// `options` needs to be a valid options array, or a null pointer.
+unsafe fn find_int_option(
+    options: *const LV2_Options_Option,
+    key: LV2_URID,
+    atom_int: LV2_URID,
+) -> Option<i32> {
+    if options.is_null() {
+        return None;
+    }
+
+    let mut option_ptr = options;
+    while (*option_ptr).key != 0 {
+        let option = &*option_ptr;
+        if option.key == key
+            && option.type_ == atom_int
+            && option.size as usize == mem::size_of::<i32>()
+            && !option.value.is_null()
+        {
+            return Some(*(option.value as *const i32));
+        }
+
+        option_ptr = option_ptr.add(1);
+    }
+
+    None
+}
+
+/// Call `f` with the timing and the body of every event in an atom sequence. `frame_time` is the
+/// URID for `atom:frameTime`. Sequences timestamped in beats are ignored since their events cannot
+/// be converted to sample offsets without knowing the host's tempo map.
+///
+/// # Safety
+///
+/// `sequence` must point to a valid atom sequence.
+unsafe fn for_each_event(
+    sequence: *const LV2_Atom_Sequence,
+    frame_time: LV2_URID,
+    mut f: impl FnMut(u32, &LV2_Atom),
+) {
+    // A unit of 0 also means that the events use frame offsets
+    let unit = (*sequence).body.unit;
+    if unit != 0 && unit != frame_time {
+        nih_debug_assert_failure!("Ignoring an atom sequence that is not timestamped in frames");
+        return;
+    }
+
+    let sequence_ptr = sequence as *const u8;
+    let sequence_end = mem::size_of::<LV2_Atom>() + (*sequence).atom.size as usize;
+
+    let mut offset = mem::size_of::<LV2_Atom_Sequence>();
+    while offset + mem::size_of::<LV2_Atom_Event>() <= sequence_end {
+        let event = &*(sequence_ptr.add(offset) as *const LV2_Atom_Event);
+        f(event.time_frames.max(0) as u32, &event.body);
+
+        offset += mem::size_of::<LV2_Atom_Event>() + lv2_atom_pad_size(event.body.size) as usize;
+    }
+}
@@ -0,0 +1,418 @@
+//! Declarative migrations for old [`PluginState`]s. See [`Plugin::STATE_MIGRATIONS`].
+//!
+//! [`Plugin::STATE_MIGRATIONS`]: crate::prelude::Plugin::STATE_MIGRATIONS
+
+use std::cmp::Ordering;
+
+use super::{ParamValue, PluginState};
+
+/// A set of changes made to the plugin's parameters and persistent fields in a specific plugin
+/// version. A plugin lists these in its
+/// [`Plugin::STATE_MIGRATIONS`][crate::prelude::Plugin::STATE_MIGRATIONS]:
+///
+/// ```
+/// # use nih_plug::prelude::*;
+/// fn dry_wet_to_percentage(value: f32) -> f32 {
+///     value * 100.0
+/// }
+///
+/// const STATE_MIGRATIONS: &'static [StateMigration] = &[
+///     StateMigration {
+///         version: "0.2.0",
+///         steps: &[MigrationStep::RenameParam {
+///             from: "gain",
+///             to: "output_gain",
+///         }],
+///     },
+///     StateMigration {
+///         version: "0.3.0",
+///         steps: &[
+///             MigrationStep::MapFloatParam {
+///                 id: "mix",
+///                 map: dry_wet_to_percentage,
+///             },
+///             MigrationStep::RemapEnumParam {
+///                 id: "mode",
+///                 from: SerializedVariant::Index(2),
+///                 to: SerializedVariant::Id("tape"),
+///             },
+///         ],
+///     },
+/// ];
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct StateMigration {
+    /// The plugin version that introduced these changes, in the same format as
+    /// [`Plugin::VERSION`][crate::prelude::Plugin::VERSION]. The migration's steps are applied to
+    /// all states saved with an older version of the plugin.
+    pub version: &'static str,
+    /// The changes, applied in order.
+    pub steps: &'static [MigrationStep],
+}
+
+/// A single change to a [`PluginState`]. Steps that refer to parameters or fields that aren't
+/// part of the state don't do anything.
+#[derive(Debug, Clone, Copy)]
+pub enum MigrationStep {
+    /// The parameter's ID changed from `from` to `to`.
+    RenameParam {
+        from: &'static str,
+        to: &'static str,
+    },
+    /// Copy a parameter's value to a new parameter, for instance when a parameter has been split
+    /// up into two. The original parameter is kept.
+    CopyParam {
+        from: &'static str,
+        to: &'static str,
+    },
+    /// The parameter no longer exists.
+    RemoveParam { id: &'static str },
+    /// The parameter's range changed. Its value is linearly mapped from the old `from` range to
+    /// the new `to` range, where both ranges are `(min, max)` pairs in plain values. This also
+    /// works for integer parameters, in which case the result is rounded.
+    RescaleParam {
+        id: &'static str,
+        from: (f32, f32),
+        to: (f32, f32),
+    },
+    /// Apply an arbitrary function to a float parameter's plain value.
+    MapFloatParam {
+        id: &'static str,
+        map: fn(f32) -> f32,
+    },
+    /// Apply an arbitrary function to an integer parameter's plain value.
+    MapIntParam {
+        id: &'static str,
+        map: fn(i32) -> i32,
+    },
+    /// Replace an enum parameter's variant. Enum parameters are stored either by the variant's
+    /// index or by its `#[id = "..."]`, so this can also be used to switch between the two after
+    /// adding IDs to an existing enum.
+    RemapEnumParam {
+        id: &'static str,
+        from: SerializedVariant,
+        to: SerializedVariant,
+    },
+    /// The `#[persist = "..."]` field's key changed from `from` to `to`.
+    RenameField {
+        from: &'static str,
+        to: &'static str,
+    },
+    /// Convert a `#[persist = "..."]` field's JSON data. The field is left untouched if it does not
+    /// contain valid JSON.
+    ConvertField {
+        key: &'static str,
+        convert: fn(serde_json::Value) -> serde_json::Value,
+    },
+    /// Anything else that can't be expressed using the other steps.
+    Custom(fn(&mut PluginState)),
+}
+
+/// An enum parameter's value as it is stored in a [`PluginState`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerializedVariant {
+    /// The variant's index, used for variants without an explicit ID.
+    Index(i32),
+    /// The variant's `#[id = "..."]`.
+    Id(&'static str),
+}
+
+/// Apply all migrations for versions newer than the state's version, in order. The state's version
+/// is left as is so [`Plugin::filter_state()`][crate::prelude::Plugin::filter_state()] still
+/// receives the version the state was saved with.
+pub(crate) fn migrate_state(state: &mut PluginState, migrations: &[StateMigration]) {
+    nih_debug_assert!(
+        migrations
+            .windows(2)
+            .all(|w| compare_versions(w[0].version, w[1].version) == Ordering::Less),
+        "State migrations need to be sorted by version"
+    );
+
+    for migration in migrations {
+        if compare_versions(&state.version, migration.version) != Ordering::Less {
+            continue;
+        }
+
+        nih_trace!(
+            "Migrating state from version {:?} to {}",
+            state.version,
+            migration.version
+        );
+        for step in migration.steps {
+            step.apply(state);
+        }
+    }
+}
+
+impl MigrationStep {
+    fn apply(&self, state: &mut PluginState) {
+        match *self {
+            MigrationStep::RenameParam { from, to } => {
+                if let Some(value) = state.params.remove(from) {
+                    state.params.insert(String::from(to), value);
+                }
+            }
+            MigrationStep::CopyParam { from, to } => {
+                if let Some(value) = state.params.get(from).cloned() {
+                    state.params.insert(String::from(to), value);
+                }
+            }
+            MigrationStep::RemoveParam { id } => {
+                state.params.remove(id);
+            }
+            MigrationStep::RescaleParam { id, from, to } => {
+                let rescale = |value: f32| {
+                    let normalized = (value - from.0) / (from.1 - from.0);
+                    to.0 + (normalized * (to.1 - to.0))
+                };
+
+                match state.params.get_mut(id) {
+                    Some(ParamValue::F32(value)) => *value = rescale(*value),
+                    Some(ParamValue::I32(value)) => *value = rescale(*value as f32).round() as i32,
+                    Some(value) => {
+                        nih_debug_assert_failure!("Cannot rescale {:?} for \"{}\"", value, id)
+                    }
+                    None => (),
+                }
+            }
+            MigrationStep::MapFloatParam { id, map } => match state.params.get_mut(id) {
+                Some(ParamValue::F32(value)) => *value = map(*value),
+                Some(value) => nih_debug_assert_failure!(
+                    "Expected a float value for \"{}\", found {:?}",
+                    id,
+                    value
+                ),
+                None => (),
+            },
+            MigrationStep::MapIntParam { id, map } => match state.params.get_mut(id) {
+                Some(ParamValue::I32(value)) => *value = map(*value),
+                Some(value) => nih_debug_assert_failure!(
+                    "Expected an integer value for \"{}\", found {:?}",
+                    id,
+                    value
+                ),
+                None => (),
+            },
+            MigrationStep::RemapEnumParam { id, from, to } => {
+                if let Some(value) = state.params.get_mut(id) {
+                    if from.matches(value) {
+                        *value = to.into();
+                    }
+                }
+            }
+            MigrationStep::RenameField { from, to } => {
+                if let Some(value) = state.fields.remove(from) {
+                    state.fields.insert(String::from(to), value);
+                }
+            }
+            MigrationStep::ConvertField { key, convert } => {
+                if let Some(field) = state.fields.get_mut(key) {
+                    match serde_json::from_str(field) {
+                        Ok(json) => {
+                            *field = serde_json::to_string(&convert(json))
+                                .expect("JSON values can always be serialized");
+                        }
+                        Err(err) => nih_debug_assert_failure!(
+                            "Could not parse the persistent field \"{}\" as JSON: {}",
+                            key,
+                            err
+                        ),
+                    }
+                }
+            }
+            MigrationStep::Custom(migrate) => migrate(state),
+        }
+    }
+}
+
+impl SerializedVariant {
+    fn matches(&self, value: &ParamValue) -> bool {
+        match (self, value) {
+            (SerializedVariant::Index(index), ParamValue::I32(value)) => index == value,
+            (SerializedVariant::Id(id), ParamValue::String(value)) => id == value,
+            _ => false,
+        }
+    }
+}
+
+impl From<SerializedVariant> for ParamValue {
+    fn from(variant: SerializedVariant) -> Self {
+        match variant {
+            SerializedVariant::Index(index) => ParamValue::I32(index),
+            SerializedVariant::Id(id) => ParamValue::String(String::from(id)),
+        }
+    }
+}
+
+/// Compare two version strings like `1.2.3` numerically, component by component. Anything after a
+/// `-` or `+` is ignored, missing components count as zero, and an empty version (used by very old
+/// states) is older than any other version.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    fn components(version: &str) -> Vec<u64> {
+        version
+            .split(['-', '+'])
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map(|component| {
+                let digits: String = component.chars().take_while(char::is_ascii_digit).collect();
+                digits.parse().unwrap_or(0)
+            })
+            .collect()
+    }
+
+    match (a.is_empty(), b.is_empty()) {
+        (true, true) => return Ordering::Equal,
+        (true, false) => return Ordering::Less,
+        (false, true) => return Ordering::Greater,
+        (false, false) => (),
+    }
+
+    let (a, b) = (components(a), components(b));
+    for i in 0..a.len().max(b.len()) {
+        match a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0)) {
+            Ordering::Equal => (),
+            ordering => return ordering,
+        }
+    }
+
+    Ordering::Equal
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn make_state(version: &str) -> PluginState {
+        PluginState {
+            version: String::from(version),
+            params: BTreeMap::from([
+                (String::from("gain"), ParamValue::F32(0.5)),
+                (String::from("mode"), ParamValue::I32(2)),
+                (String::from("voices"), ParamValue::I32(4)),
+            ]),
+            fields: BTreeMap::from([(
+                String::from("editor-state"),
+                String::from(r#"{"size":[400,300]}"#),
+            )]),
+        }
+    }
+
+    fn double(value: f32) -> f32 {
+        value * 2.0
+    }
+
+    fn size_to_object(json: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({ "width": json["size"][0], "height": json["size"][1] })
+    }
+
+    const MIGRATIONS: &[StateMigration] = &[
+        StateMigration {
+            version: "0.2.0",
+            steps: &[
+                MigrationStep::RenameParam {
+                    from: "gain",
+                    to: "output_gain",
+                },
+                MigrationStep::RemapEnumParam {
+                    id: "mode",
+                    from: SerializedVariant::Index(2),
+                    to: SerializedVariant::Id("tape"),
+                },
+            ],
+        },
+        StateMigration {
+            version: "0.2.10",
+            steps: &[
+                // This refers to the name introduced in the previous migration
+                MigrationStep::MapFloatParam {
+                    id: "output_gain",
+                    map: double,
+                },
+                MigrationStep::RescaleParam {
+                    id: "voices",
+                    from: (1.0, 8.0),
+                    to: (1.0, 15.0),
+                },
+            ],
+        },
+        StateMigration {
+            version: "1.0.0",
+            steps: &[
+                MigrationStep::CopyParam {
+                    from: "output_gain",
+                    to: "input_gain",
+                },
+                MigrationStep::ConvertField {
+                    key: "editor-state",
+                    convert: size_to_object,
+                },
+                MigrationStep::RenameField {
+                    from: "editor-state",
+                    to: "editor_state",
+                },
+            ],
+        },
+    ];
+
+    #[test]
+    fn chained_migrations() {
+        let mut state = make_state("0.1.0");
+        migrate_state(&mut state, MIGRATIONS);
+
+        assert_eq!(state.version, "0.1.0");
+        assert!(!state.params.contains_key("gain"));
+        assert!(matches!(state.params["output_gain"], ParamValue::F32(v) if v == 1.0));
+        assert!(matches!(state.params["input_gain"], ParamValue::F32(v) if v == 1.0));
+        assert!(matches!(&state.params["mode"], ParamValue::String(id) if id == "tape"));
+        assert!(matches!(state.params["voices"], ParamValue::I32(7)));
+        assert!(!state.fields.contains_key("editor-state"));
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&state.fields["editor_state"]).unwrap(),
+            serde_json::json!({ "width": 400, "height": 300 })
+        );
+    }
+
+    #[test]
+    fn only_newer_migrations_are_applied() {
+        // The state was saved after the rename, so only the last two migrations apply
+        let mut state = make_state("0.2.0");
+        state.params.insert(
+            String::from("output_gain"),
+            state.params.remove("gain").unwrap(),
+        );
+        migrate_state(&mut state, MIGRATIONS);
+
+        assert!(matches!(state.params["output_gain"], ParamValue::F32(v) if v == 1.0));
+        // This would have been remapped by the first migration
+        assert!(matches!(state.params["mode"], ParamValue::I32(2)));
+    }
+
+    #[test]
+    fn current_state_is_untouched() {
+        let mut state = make_state("1.0.0");
+        migrate_state(&mut state, MIGRATIONS);
+
+        assert!(matches!(state.params["gain"], ParamValue::F32(v) if v == 0.5));
+        assert!(state.fields.contains_key("editor-state"));
+    }
+
+    #[test]
+    fn state_without_version_is_migrated() {
+        let mut state = make_state("");
+        migrate_state(&mut state, MIGRATIONS);
+
+        assert!(state.params.contains_key("output_gain"));
+    }
+
+    #[test]
+    fn version_ordering() {
+        assert_eq!(compare_versions("0.2.0", "0.10.0"), Ordering::Less);
+        assert_eq!(compare_versions("1.0", "1.0.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.0.1-beta", "1.0.1"), Ordering::Equal);
+        assert_eq!(compare_versions("", "0.0.1"), Ordering::Less);
+        assert_eq!(compare_versions("2.0.0", "1.9.9"), Ordering::Greater);
+    }
+}
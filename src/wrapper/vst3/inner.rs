@@ -1,653 +1,840 @@
-use atomic_refcell::AtomicRefCell;
-use crossbeam::atomic::AtomicCell;
-use crossbeam::channel::{self, SendTimeoutError};
-use parking_lot::{Mutex, RwLock};
-use std::collections::{HashMap, HashSet, VecDeque};
-use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
-use std::sync::Arc;
-use std::time::Duration;
-use vst3_sys::base::{kInvalidArgument, kResultOk, tresult};
-use vst3_sys::vst::{IComponentHandler, RestartFlags};
-
-use super::context::{WrapperGuiContext, WrapperInitContext, WrapperProcessContext};
-use super::note_expressions::NoteExpressionController;
-use super::param_units::ParamUnits;
-use super::util::{ObjectPtr, VstPtr, VST3_MIDI_PARAMS_END, VST3_MIDI_PARAMS_START};
-use super::view::WrapperView;
-use crate::event_loop::{EventLoop, MainThreadExecutor, OsEventLoop};
-use crate::prelude::{
-    AsyncExecutor, AudioIOLayout, BufferConfig, Editor, MidiConfig, ParamFlags, ParamPtr, Params,
-    Plugin, PluginNoteEvent, ProcessMode, ProcessStatus, TaskExecutor, Transport, Vst3Plugin,
-};
-use crate::util::permit_alloc;
-use crate::wrapper::state::{self, PluginState};
-use crate::wrapper::util::buffer_management::BufferManager;
-use crate::wrapper::util::{hash_param_id, process_wrapper};
-
-/// The actual wrapper bits. We need this as an `Arc<T>` so we can safely use our event loop API.
-/// Since we can't combine that with VST3's interior reference counting this just has to be moved to
-/// its own struct.
-pub(crate) struct WrapperInner<P: Vst3Plugin> {
-    /// The wrapped plugin instance.
-    pub plugin: Mutex<P>,
-    /// The plugin's background task executor closure.
-    pub task_executor: Mutex<TaskExecutor<P>>,
-    /// The plugin's parameters. These are fetched once during initialization. That way the
-    /// `ParamPtr`s are guaranteed to live at least as long as this object and we can interact with
-    /// the `Params` object without having to acquire a lock on `plugin`.
-    pub params: Arc<dyn Params>,
-    /// The plugin's editor, if it has one. This object does not do anything on its own, but we need
-    /// to instantiate this in advance so we don't need to lock the entire [`Plugin`] object when
-    /// creating an editor. Wrapped in an `AtomicRefCell` because it needs to be initialized late.
-    pub editor: AtomicRefCell<Option<Arc<Mutex<Box<dyn Editor>>>>>,
-
-    /// The host's [`IComponentHandler`] instance, if passed through
-    /// [`IEditController::set_component_handler`].
-    pub component_handler: AtomicRefCell<Option<VstPtr<dyn IComponentHandler>>>,
-
-    /// Our own [`IPlugView`] instance. This is set while the editor is actually visible (which is
-    /// different form the lifetime of [`WrapperView`][super::WrapperView] itself).
-    pub plug_view: RwLock<Option<ObjectPtr<WrapperView<P>>>>,
-
-    /// A realtime-safe task queue so the plugin can schedule tasks that need to be run later on the
-    /// GUI thread. This field should not be used directly for posting tasks. This should be done
-    /// through [`Self::schedule_gui()`] instead. That method posts the task to the host's
-    /// `IRunLoop` instead of it's available.
-    ///
-    /// This AtomicRefCell+Option is only needed because it has to be initialized late. There is no
-    /// reason to mutably borrow the event loop, so reads will never be contested.
-    ///
-    /// TODO: Is there a better type for Send+Sync late initialization?
-    pub event_loop: AtomicRefCell<Option<OsEventLoop<Task<P>, Self>>>,
-
-    /// Whether the plugin is currently processing audio. In other words, the last state
-    /// `IAudioProcessor::setActive()` has been called with.
-    pub is_processing: AtomicBool,
-    /// The current audio IO layout. Modified through `IAudioProcessor::setBusArrangements()` after
-    /// matching the proposed bus arrangement to one of the supported ones. The plugin's first audio
-    /// IO layout is chosen as the default. Because of the way VST3 works it's not possible to
-    /// change the number of busses from that default, only the channel counts can change.
-    pub current_audio_io_layout: AtomicCell<AudioIOLayout>,
-    /// The current buffer configuration, containing the sample rate and the maximum block size.
-    /// Will be set in `IAudioProcessor::setupProcessing()`.
-    pub current_buffer_config: AtomicCell<Option<BufferConfig>>,
-    /// The current audio processing mode. Set in `IAudioProcessor::setup_processing()`.
-    pub current_process_mode: AtomicCell<ProcessMode>,
-    /// The last process status returned by the plugin. This is used for tail handling.
-    pub last_process_status: AtomicCell<ProcessStatus>,
-    /// The current latency in samples, as set by the plugin through the [`InitContext`] and the
-    /// [`ProcessContext`].
-    pub current_latency: AtomicU32,
-    /// A data structure that helps manage and create buffers for all of the plugin's inputs and
-    /// outputs based on channel pointers provided by the host.
-    pub buffer_manager: AtomicRefCell<BufferManager>,
-    /// The incoming events for the plugin, if `P::ACCEPTS_MIDI` is set. If
-    /// `P::SAMPLE_ACCURATE_AUTOMATION`, this is also read in lockstep with the parameter change
-    /// block splitting.
-    ///
-    /// NOTE: Because with VST3 MIDI CC messages are sent as parameter changes and VST3 does not
-    ///       interleave parameter changes and note events, this queue has to be sorted when
-    ///       creating the process context
-    pub input_events: AtomicRefCell<VecDeque<PluginNoteEvent<P>>>,
-    /// Stores any events the plugin has output during the current processing cycle, analogous to
-    /// `input_events`.
-    pub output_events: AtomicRefCell<VecDeque<PluginNoteEvent<P>>>,
-    /// VST3 has several useful predefined note expressions, but for some reason they are the only
-    /// note event type that don't have MIDI note ID and channel fields. So we need to keep track of
-    /// the most recent VST3 note IDs we've seen, and then map those back to MIDI note IDs and
-    /// channels as needed.
-    pub note_expression_controller: AtomicRefCell<NoteExpressionController>,
-    /// Unprocessed parameter changes and note events sent by the host during a process call.
-    /// Parameter changes are sent as separate queues for each parameter, and note events are in
-    /// another queue on top of that. And if `P::MIDI_INPUT >= MidiConfig::MidiCCs`, then we can
-    /// also receive MIDI CC messages through special parameter changes. On top of that, we also
-    /// support sample accurate automation through block splitting if
-    /// `P::SAMPLE_ACCURATE_AUTOMATION` is set. To account for all of this, we'll read all of the
-    /// parameter changes and events into a vector at the start of the process call, sort it, and
-    /// then do the block splitting based on that. Note events need to have their timing adjusted to
-    /// match the block start, since they're all read upfront.
-    pub process_events: AtomicRefCell<Vec<ProcessEvent<P>>>,
-    /// The plugin is able to restore state through a method on the `GuiContext`. To avoid changing
-    /// parameters mid-processing and running into garbled data if the host also tries to load state
-    /// at the same time the restoring happens at the end of each processing call. If this zero
-    /// capacity channel contains state data at that point, then the audio thread will take the
-    /// state out of the channel, restore the state, and then send it back through the same channel.
-    /// In other words, the GUI thread acts as a sender and then as a receiver, while the audio
-    /// thread acts as a receiver and then as a sender. That way deallocation can happen on the GUI
-    /// thread. All of this happens without any blocking on the audio thread.
-    pub updated_state_sender: channel::Sender<PluginState>,
-    /// The receiver belonging to [`new_state_sender`][Self::new_state_sender].
-    pub updated_state_receiver: channel::Receiver<PluginState>,
-
-    /// The keys from `param_map` in a stable order.
-    pub param_hashes: Vec<u32>,
-    /// A mapping from parameter ID hashes (obtained from the string parameter IDs) to pointers to
-    /// parameters belonging to the plugin. These addresses will remain stable as long as the
-    /// `params` object does not get deallocated.
-    pub param_by_hash: HashMap<u32, ParamPtr>,
-    /// Mappings from parameter hashes to string parameter IDs. Used for notifying the plugin's
-    /// editor about parameter changes.
-    pub param_id_by_hash: HashMap<u32, String>,
-    pub param_units: ParamUnits,
-    /// Mappings from string parameter identifiers to parameter hashes. Useful for debug logging
-    /// and when storing and restoring plugin state.
-    pub param_id_to_hash: HashMap<String, u32>,
-    /// The inverse mapping from [`param_by_hash`][Self::param_by_hash]. This is needed to be able
-    /// to have an ergonomic parameter setting API that uses references to the parameters instead of
-    /// having to add a setter function to the parameter (or even worse, have it be completely
-    /// untyped).
-    pub param_ptr_to_hash: HashMap<ParamPtr, u32>,
-}
-
-/// Tasks that can be sent from the plugin to be executed on the main thread in a non-blocking
-/// realtime-safe way (either a random thread or `IRunLoop` on Linux, the OS' message loop on
-/// Windows and macOS).
-#[allow(clippy::enum_variant_names)]
-pub enum Task<P: Plugin> {
-    /// Execute one of the plugin's background tasks.
-    PluginTask(P::BackgroundTask),
-    /// Inform the plugin that one or more parameter values have changed.
-    ParameterValuesChanged,
-    /// Inform the plugin that one parameter's value has changed. This uses the parameter hashes
-    /// since the task will be created from the audio thread.
-    ParameterValueChanged(u32, f32),
-    /// Trigger a restart with the given restart flags. This is a bit set of the flags from
-    /// [`vst3_sys::vst::RestartFlags`].
-    TriggerRestart(i32),
-    /// Request the editor to be resized according to its current size. Right now there is no way to
-    /// handle "denied resize" requests yet.
-    RequestResize,
-}
-
-/// VST3 makes audio processing pretty complicated. In order to support both block splitting for
-/// sample accurate automation and MIDI CC handling through parameters we need to put all parameter
-/// changes and (translated) note events into a sorted array first.
-#[derive(Debug, PartialEq)]
-pub enum ProcessEvent<P: Plugin> {
-    /// An incoming parameter change sent by the host. This will only be used when sample accurate
-    /// automation has been enabled, and the parameters are only updated when we process this
-    /// spooled event at the start of a block.
-    ParameterChange {
-        /// The event's sample offset within the buffer. Used for sorting.
-        timing: u32,
-        /// The parameter's hash, as used everywhere else.
-        hash: u32,
-        /// The normalized values, as provided by the host.
-        normalized_value: f32,
-    },
-    /// An incoming parameter change sent by the host. This will only be used when sample accurate
-    /// automation has been enabled, and the parameters are only updated when we process this
-    /// spooled event at the start of a block.
-    ///
-    /// The timing stored within the note event needs to have the block start index subtraced from
-    /// it. make sure to subtract the block start index with [`NoteEvent::subtract_timing()`] before
-    /// putting this into the input event queue.
-    NoteEvent(PluginNoteEvent<P>),
-}
-
-impl<P: Vst3Plugin> WrapperInner<P> {
-    #[allow(unused_unsafe)]
-    pub fn new() -> Arc<Self> {
-        let mut plugin = P::default();
-        let task_executor = Mutex::new(plugin.task_executor());
-
-        // This is used to allow the plugin to restore preset data from its editor, see the comment
-        // on `Self::updated_state_sender`
-        let (updated_state_sender, updated_state_receiver) = channel::bounded(0);
-
-        // This is a mapping from the parameter IDs specified by the plugin to pointers to those
-        // parameters. These pointers are assumed to be safe to dereference as long as
-        // `wrapper.plugin` is alive. The plugin API identifiers these parameters by hashes, which
-        // we'll calculate from the string ID specified by the plugin. These parameters should also
-        // remain in the same order as the one returned by the plugin.
-        let params = plugin.params();
-        let param_id_hashes_ptrs_groups: Vec<_> = params
-            .param_map()
-            .into_iter()
-            .map(|(id, ptr, group)| {
-                let hash = hash_param_id(&id);
-                (id, hash, ptr, group)
-            })
-            .collect();
-        if cfg!(debug_assertions) {
-            let param_map = params.param_map();
-            let param_ids: HashSet<_> = param_id_hashes_ptrs_groups
-                .iter()
-                .map(|(id, _, _, _)| id.clone())
-                .collect();
-            nih_debug_assert_eq!(
-                param_map.len(),
-                param_ids.len(),
-                "The plugin has duplicate parameter IDs, weird things may happen. Consider using \
-                 6 character parameter IDs to avoid collisions."
-            );
-
-            let mut bypass_param_exists = false;
-            for (id, hash, ptr, _) in &param_id_hashes_ptrs_groups {
-                let flags = unsafe { ptr.flags() };
-                let is_bypass = flags.contains(ParamFlags::BYPASS);
-
-                if is_bypass && bypass_param_exists {
-                    nih_debug_assert_failure!(
-                        "Duplicate bypass parameters found, the host will only use the first one"
-                    );
-                }
-
-                bypass_param_exists |= is_bypass;
-
-                if P::MIDI_INPUT >= MidiConfig::MidiCCs
-                    && (VST3_MIDI_PARAMS_START..VST3_MIDI_PARAMS_END).contains(hash)
-                {
-                    nih_debug_assert_failure!(
-                        "Parameter '{}' collides with an automatically generated MIDI CC \
-                         parameter, consider giving it a different ID",
-                        id
-                    );
-                }
-            }
-        }
-
-        let param_hashes = param_id_hashes_ptrs_groups
-            .iter()
-            .map(|(_, hash, _, _)| *hash)
-            .collect();
-        let param_by_hash = param_id_hashes_ptrs_groups
-            .iter()
-            .map(|(_, hash, ptr, _)| (*hash, *ptr))
-            .collect();
-        let param_id_by_hash = param_id_hashes_ptrs_groups
-            .iter()
-            .map(|(id, hash, _, _)| (*hash, id.clone()))
-            .collect();
-        let param_units = ParamUnits::from_param_groups(
-            param_id_hashes_ptrs_groups
-                .iter()
-                .map(|(_, hash, _, group_name)| (*hash, group_name.as_str())),
-        )
-        .expect("Inconsistent parameter groups");
-        let param_id_to_hash = param_id_hashes_ptrs_groups
-            .iter()
-            .map(|(id, hash, _, _)| (id.clone(), *hash))
-            .collect();
-        let param_ptr_to_hash = param_id_hashes_ptrs_groups
-            .into_iter()
-            .map(|(_, hash, ptr, _)| (ptr, hash))
-            .collect();
-
-        let wrapper = Arc::new(Self {
-            plugin: Mutex::new(plugin),
-            task_executor,
-            params,
-            // Initialized later as it needs a reference to the wrapper for the async executor
-            editor: AtomicRefCell::new(None),
-
-            component_handler: AtomicRefCell::new(None),
-
-            plug_view: RwLock::new(None),
-
-            event_loop: AtomicRefCell::new(None),
-
-            is_processing: AtomicBool::new(false),
-            // Some hosts, like the current version of Bitwig and Ardour at the time of writing,
-            // will try using the plugin's default not yet initialized bus arrangement. Because of
-            // that, we'll always initialize this configuration even before the host requests a
-            // channel layout.
-            current_audio_io_layout: AtomicCell::new(
-                P::AUDIO_IO_LAYOUTS.first().copied().unwrap_or_default(),
-            ),
-            current_buffer_config: AtomicCell::new(None),
-            current_process_mode: AtomicCell::new(ProcessMode::Realtime),
-            last_process_status: AtomicCell::new(ProcessStatus::Normal),
-            current_latency: AtomicU32::new(0),
-            // This is initialized just before calling `Plugin::initialize()` so that during the
-            // process call buffers can be initialized without any allocations
-            buffer_manager: AtomicRefCell::new(BufferManager::for_audio_io_layout(
-                0,
-                AudioIOLayout::default(),
-            )),
-            input_events: AtomicRefCell::new(VecDeque::with_capacity(1024)),
-            output_events: AtomicRefCell::new(VecDeque::with_capacity(1024)),
-            note_expression_controller: AtomicRefCell::new(NoteExpressionController::default()),
-            process_events: AtomicRefCell::new(Vec::with_capacity(4096)),
-            updated_state_sender,
-            updated_state_receiver,
-
-            param_hashes,
-            param_by_hash,
-            param_id_by_hash,
-            param_units,
-            param_id_to_hash,
-            param_ptr_to_hash,
-        });
-
-        // FIXME: Right now this is safe, but if we are going to have a singleton main thread queue
-        //        serving multiple plugin instances, Arc can't be used because its reference count
-        //        is separate from the internal COM-style reference count.
-        *wrapper.event_loop.borrow_mut() =
-            Some(OsEventLoop::new_and_spawn(Arc::downgrade(&wrapper)));
-
-        // The editor also needs to be initialized later so the Async executor can work.
-        *wrapper.editor.borrow_mut() = wrapper
-            .plugin
-            .lock()
-            .editor(AsyncExecutor {
-                execute_background: Arc::new({
-                    let wrapper = wrapper.clone();
-
-                    move |task| {
-                        let task_posted = wrapper.schedule_background(Task::PluginTask(task));
-                        nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
-                    }
-                }),
-                execute_gui: Arc::new({
-                    let wrapper = wrapper.clone();
-
-                    move |task| {
-                        let task_posted = wrapper.schedule_gui(Task::PluginTask(task));
-                        nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
-                    }
-                }),
-            })
-            .map(|editor| Arc::new(Mutex::new(editor)));
-
-        wrapper
-    }
-
-    pub fn make_gui_context(self: Arc<Self>) -> Arc<WrapperGuiContext<P>> {
-        Arc::new(WrapperGuiContext {
-            inner: self,
-            #[cfg(debug_assertions)]
-            param_gesture_checker: Default::default(),
-        })
-    }
-
-    /// # Note
-    ///
-    /// The lock on the plugin must be dropped before this object is dropped to avoid deadlocks
-    /// caused by reentrant function calls.
-    pub fn make_init_context(&self) -> WrapperInitContext<'_, P> {
-        WrapperInitContext {
-            inner: self,
-            pending_requests: Default::default(),
-        }
-    }
-
-    pub fn make_process_context(&self, transport: Transport) -> WrapperProcessContext<'_, P> {
-        WrapperProcessContext {
-            inner: self,
-            input_events_guard: self.input_events.borrow_mut(),
-            output_events_guard: self.output_events.borrow_mut(),
-            transport,
-        }
-    }
-
-    /// Posts the task to the background task queue using [`EventLoop::schedule_background()`] so it
-    /// can be run in the background without blocking either the GUI or the audio thread.
-    ///
-    /// If the task queue is full, then this will return false.
-    #[must_use]
-    pub fn schedule_background(&self, task: Task<P>) -> bool {
-        let event_loop = self.event_loop.borrow();
-        let event_loop = event_loop.as_ref().unwrap();
-        event_loop.schedule_background(task)
-    }
-
-    /// Either posts the task to the task queue using [`EventLoop::schedule_gui()`] so it can be
-    /// delegated to the main thread, executes the task directly if this is the main thread, or runs
-    /// the task on the host's `IRunLoop` if the GUI is open and it exposes one.
-    ///
-    /// If the task queue is full, then this will return false.
-    #[must_use]
-    pub fn schedule_gui(&self, task: Task<P>) -> bool {
-        let event_loop = self.event_loop.borrow();
-        let event_loop = event_loop.as_ref().unwrap();
-        if event_loop.is_main_thread() {
-            self.execute(task, true);
-            true
-        } else {
-            // If the editor is open, and the host exposes the `IRunLoop` interface, then we'll run
-            // the task on the host's GUI thread using that interface. Otherwise we'll use the
-            // regular event loop. If the editor gets dropped while there's still outstanding work
-            // left in the run loop task queue, then those tasks will be posted to the regular event
-            // loop so no work is lost.
-            match &*self.plug_view.read() {
-                Some(plug_view) => match plug_view.do_maybe_in_run_loop(task) {
-                    Ok(()) => true,
-                    Err(task) => event_loop.schedule_gui(task),
-                },
-                None => event_loop.schedule_gui(task),
-            }
-        }
-    }
-
-    /// Get a parameter's ID based on a `ParamPtr`. Used in the `GuiContext` implementation for the
-    /// gesture checks.
-    #[allow(unused)]
-    pub fn param_id_from_ptr(&self, param: ParamPtr) -> Option<&str> {
-        self.param_ptr_to_hash
-            .get(&param)
-            .and_then(|hash| self.param_id_by_hash.get(hash))
-            .map(|s| s.as_str())
-    }
-
-    /// Convenience function for setting a value for a parameter as triggered by a VST3 parameter
-    /// update. The same rate is for updating parameter smoothing.
-    ///
-    /// After calling this function, you should call
-    /// [`notify_param_values_changed()`][Self::notify_param_values_changed()] to allow the editor
-    /// to update itself. This needs to be done separately so you can process parameter changes in
-    /// batches.
-    pub fn set_normalized_value_by_hash(
-        &self,
-        hash: u32,
-        normalized_value: f32,
-        sample_rate: Option<f32>,
-    ) -> tresult {
-        match self.param_by_hash.get(&hash) {
-            Some(param_ptr) => {
-                if unsafe { param_ptr.set_normalized_value(normalized_value) } {
-                    if let Some(sample_rate) = sample_rate {
-                        unsafe { param_ptr.update_smoother(sample_rate, false) };
-                    }
-
-                    let task_posted =
-                        self.schedule_gui(Task::ParameterValueChanged(hash, normalized_value));
-                    nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
-                }
-
-                kResultOk
-            }
-            _ => kInvalidArgument,
-        }
-    }
-
-    /// Get the plugin's state object, may be called by the plugin's GUI as part of its own preset
-    /// management. The wrapper doesn't use these functions and serializes and deserializes directly
-    /// the JSON in the relevant plugin API methods instead.
-    pub fn get_state_object(&self) -> PluginState {
-        unsafe {
-            state::serialize_object::<P>(
-                self.params.clone(),
-                state::make_params_iter(&self.param_by_hash, &self.param_id_to_hash),
-            )
-        }
-    }
-
-    /// Update the plugin's internal state, called by the plugin itself from the GUI thread. To
-    /// prevent corrupting data and changing parameters during processing the actual state is only
-    /// updated at the end of the audio processing cycle.
-    pub fn set_state_object_from_gui(&self, mut state: PluginState) {
-        // Use a loop and timeouts to handle the super rare edge case when this function gets called
-        // between a process call and the host disabling the plugin
-        loop {
-            if self.is_processing.load(Ordering::SeqCst) {
-                // If the plugin is currently processing audio, then we'll perform the restore
-                // operation at the end of the audio call. This involves sending the state to the
-                // audio thread, having the audio thread handle the state restore at the very end of
-                // the process function, and then sending the state back to this thread so it can be
-                // deallocated without blocking the audio thread.
-                match self
-                    .updated_state_sender
-                    .send_timeout(state, Duration::from_secs(1))
-                {
-                    Ok(_) => {
-                        // As mentioned above, the state object will be passed back to this thread
-                        // so we can deallocate it without blocking.
-                        let state = self.updated_state_receiver.recv();
-                        drop(state);
-                        break;
-                    }
-                    Err(SendTimeoutError::Timeout(value)) => {
-                        state = value;
-                        continue;
-                    }
-                    Err(SendTimeoutError::Disconnected(_)) => {
-                        nih_debug_assert_failure!("State update channel got disconnected");
-                        return;
-                    }
-                }
-            } else {
-                // Otherwise we'll set the state right here and now, since this function should be
-                // called from a GUI thread
-                self.set_state_inner(&mut state);
-                break;
-            }
-        }
-
-        // After the state has been updated, notify the host about the new parameter values
-        let task_posted =
-            self.event_loop
-                .borrow()
-                .as_ref()
-                .unwrap()
-                .schedule_gui(Task::TriggerRestart(
-                    RestartFlags::kParamValuesChanged as i32,
-                ));
-        nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
-    }
-
-    pub fn set_latency_samples(&self, samples: u32) {
-        // Only trigger a restart if it's actually needed
-        let old_latency = self.current_latency.swap(samples, Ordering::SeqCst);
-        if old_latency != samples {
-            let task_posted =
-                self.schedule_gui(Task::TriggerRestart(RestartFlags::kLatencyChanged as i32));
-            nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
-        }
-    }
-
-    /// Immediately set the plugin state. Returns `false` if the deserialization failed. The plugin
-    /// state is set from a couple places, so this function aims to deduplicate that. Includes
-    /// `permit_alloc()`s around the deserialization and initialization for the use case where
-    /// `set_state_object_from_gui()` was called while the plugin is process audio.
-    ///
-    /// Implicitly emits `Task::ParameterValuesChanged`.
-    ///
-    /// # Notes
-    ///
-    /// `self.plugin` must _not_ be locked while calling this function or it will deadlock.
-    pub fn set_state_inner(&self, state: &mut PluginState) -> bool {
-        let audio_io_layout = self.current_audio_io_layout.load();
-        let buffer_config = self.current_buffer_config.load();
-
-        // FIXME: This is obviously not realtime-safe, but loading presets without doing this could
-        //        lead to inconsistencies. It's the plugin's responsibility to not perform any
-        //        realtime-unsafe work when the initialize function is called a second time if it
-        //        supports runtime preset loading.  `state::deserialize_object()` normally never
-        //        allocates, but if the plugin has persistent non-parameter data then its
-        //        `deserialize_fields()` implementation may still allocate.
-        let mut success = permit_alloc(|| unsafe {
-            state::deserialize_object::<P>(
-                state,
-                self.params.clone(),
-                state::make_params_getter(&self.param_by_hash, &self.param_id_to_hash),
-                buffer_config.as_ref(),
-            )
-        });
-        if !success {
-            nih_debug_assert_failure!("Deserializing plugin state from a state object failed");
-            return false;
-        }
-
-        // If the plugin was already initialized then it needs to be reinitialized
-        if let Some(buffer_config) = buffer_config {
-            // NOTE: This needs to be dropped after the `plugin` lock to avoid deadlocks
-            let mut init_context = self.make_init_context();
-            let mut plugin = self.plugin.lock();
-
-            // See above
-            success = permit_alloc(|| {
-                plugin.initialize(&audio_io_layout, &buffer_config, &mut init_context)
-            });
-            if success {
-                process_wrapper(|| plugin.reset());
-            }
-        }
-
-        nih_debug_assert!(
-            success,
-            "Plugin returned false when reinitializing after loading state"
-        );
-
-        // Reinitialize the plugin after loading state so it can respond to the new parameter values
-        let task_posted = self.schedule_gui(Task::ParameterValuesChanged);
-        nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
-
-        // TODO: Right now there's no way to know if loading the state changed the GUI's size. We
-        //       could keep track of the last known size and compare the GUI's current size against
-        //       that but that also seems brittle.
-        if self.plug_view.read().is_some() {
-            let task_posted = self.schedule_gui(Task::RequestResize);
-            nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
-        }
-
-        success
-    }
-}
-
-impl<P: Vst3Plugin> MainThreadExecutor<Task<P>> for WrapperInner<P> {
-    fn execute(&self, task: Task<P>, is_gui_thread: bool) {
-        // This function is always called from the main thread
-        match task {
-            Task::PluginTask(task) => (self.task_executor.lock())(task),
-            Task::ParameterValuesChanged => {
-                if self.plug_view.read().is_some() {
-                    if let Some(editor) = self.editor.borrow().as_ref() {
-                        editor.lock().param_values_changed();
-                    }
-                }
-            }
-            Task::ParameterValueChanged(param_hash, normalized_value) => {
-                if self.plug_view.read().is_some() {
-                    if let Some(editor) = self.editor.borrow().as_ref() {
-                        let param_id = &self.param_id_by_hash[&param_hash];
-                        editor
-                            .lock()
-                            .param_value_changed(param_id, normalized_value);
-                    }
-                }
-            }
-            Task::TriggerRestart(flags) => match &*self.component_handler.borrow() {
-                Some(handler) => unsafe {
-                    nih_debug_assert!(is_gui_thread);
-                    let result = handler.restart_component(flags);
-                    nih_debug_assert_eq!(
-                        result,
-                        kResultOk,
-                        "Failed the restart request call with flags '{:?}'",
-                        flags
-                    );
-                },
-                None => nih_debug_assert_failure!("Component handler not yet set"),
-            },
-            Task::RequestResize => match &*self.plug_view.read() {
-                Some(plug_view) => unsafe {
-                    nih_debug_assert!(is_gui_thread);
-                    let success = plug_view.request_resize();
-                    nih_debug_assert!(success, "Failed requesting a window resize");
-                },
-                None => nih_debug_assert_failure!("Can't resize a closed editor"),
-            },
-        }
-    }
-}
+use atomic_refcell::AtomicRefCell;
+use crossbeam::atomic::AtomicCell;
+use crossbeam::channel::{self, SendTimeoutError};
+use parking_lot::{Mutex, RwLock};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use vst3_sys::base::{kInvalidArgument, kResultOk, tresult};
+use vst3_sys::vst::{kAllProgramInvalid, IComponentHandler, IUnitHandler, RestartFlags};
+
+use super::context::{WrapperGuiContext, WrapperInitContext, WrapperProcessContext};
+use super::note_expressions::NoteExpressionController;
+use super::param_units::ParamUnits;
+use super::util::{
+    ObjectPtr, VstPtr, VST3_FACTORY_PROGRAM_LIST_ID, VST3_MIDI_PARAMS_END, VST3_MIDI_PARAMS_START,
+    VST3_NO_PROGRAM_SELECTED, VST3_PROGRAM_CHANGE_PARAM_ID,
+};
+use super::view::WrapperView;
+use crate::context::snapshots::Snapshots;
+use crate::context::undo::UndoHistory;
+use crate::event_loop::{EventLoop, MainThreadExecutor, OsEventLoop};
+use crate::midi::learn::MidiLearn;
+use crate::midi::note_names::NoteNames;
+use crate::midi::MidiCCDecoder;
+use crate::params::group::{self, ParamGroup};
+use crate::prelude::{
+    AsyncExecutor, AudioIOLayout, BufferConfig, Editor, MidiConfig, ParamFlags, ParamPtr, Params,
+    Plugin, PluginNoteEvent, ProcessMode, ProcessStatus, TaskExecutor, Transport, Vst3Plugin,
+};
+use crate::preset::{Preset, PresetLibrary, PresetLocation};
+use crate::util::permit_alloc;
+use crate::wrapper::state::{self, PluginState};
+use crate::wrapper::util::buffer_management::BufferManager;
+use crate::wrapper::util::{hash_param_id, process_wrapper};
+
+/// The actual wrapper bits. We need this as an `Arc<T>` so we can safely use our event loop API.
+/// Since we can't combine that with VST3's interior reference counting this just has to be moved to
+/// its own struct.
+pub(crate) struct WrapperInner<P: Vst3Plugin> {
+    /// The wrapped plugin instance.
+    pub plugin: Mutex<P>,
+    /// The plugin's background task executor closure.
+    pub task_executor: Mutex<TaskExecutor<P>>,
+    /// The plugin's parameters. These are fetched once during initialization. That way the
+    /// `ParamPtr`s are guaranteed to live at least as long as this object and we can interact with
+    /// the `Params` object without having to acquire a lock on `plugin`.
+    pub params: Arc<dyn Params>,
+    /// The plugin's note names, if it has any. These are exposed as the program list's pitch names.
+    pub note_names: Option<Arc<NoteNames>>,
+    /// The plugin's editor, if it has one. This object does not do anything on its own, but we need
+    /// to instantiate this in advance so we don't need to lock the entire [`Plugin`] object when
+    /// creating an editor. Wrapped in an `AtomicRefCell` because it needs to be initialized late.
+    pub editor: AtomicRefCell<Option<Arc<Mutex<Box<dyn Editor>>>>>,
+
+    /// The host's [`IComponentHandler`] instance, if passed through
+    /// [`IEditController::set_component_handler`].
+    pub component_handler: AtomicRefCell<Option<VstPtr<dyn IComponentHandler>>>,
+
+    /// Our own [`IPlugView`] instance. This is set while the editor is actually visible (which is
+    /// different form the lifetime of [`WrapperView`][super::WrapperView] itself).
+    pub plug_view: RwLock<Option<ObjectPtr<WrapperView<P>>>>,
+
+    /// A realtime-safe task queue so the plugin can schedule tasks that need to be run later on the
+    /// GUI thread. This field should not be used directly for posting tasks. This should be done
+    /// through [`Self::schedule_gui()`] instead. That method posts the task to the host's
+    /// `IRunLoop` instead of it's available.
+    ///
+    /// This AtomicRefCell+Option is only needed because it has to be initialized late. There is no
+    /// reason to mutably borrow the event loop, so reads will never be contested.
+    ///
+    /// TODO: Is there a better type for Send+Sync late initialization?
+    pub event_loop: AtomicRefCell<Option<OsEventLoop<Task<P>, Self>>>,
+
+    /// Whether the plugin is currently processing audio. In other words, the last state
+    /// `IAudioProcessor::setActive()` has been called with.
+    pub is_processing: AtomicBool,
+    /// The current audio IO layout. Modified through `IAudioProcessor::setBusArrangements()` after
+    /// matching the proposed bus arrangement to one of the supported ones. The plugin's first audio
+    /// IO layout is chosen as the default. Because of the way VST3 works it's not possible to
+    /// change the number of busses from that default, only the channel counts can change.
+    pub current_audio_io_layout: AtomicCell<AudioIOLayout>,
+    /// The current buffer configuration, containing the sample rate and the maximum block size.
+    /// Will be set in `IAudioProcessor::setupProcessing()`.
+    pub current_buffer_config: AtomicCell<Option<BufferConfig>>,
+    /// The current audio processing mode. Set in `IAudioProcessor::setup_processing()`.
+    pub current_process_mode: AtomicCell<ProcessMode>,
+    /// The last process status returned by the plugin. This is used for tail handling.
+    pub last_process_status: AtomicCell<ProcessStatus>,
+    /// The current latency in samples, as set by the plugin through the [`InitContext`] and the
+    /// [`ProcessContext`].
+    pub current_latency: AtomicU32,
+    /// A data structure that helps manage and create buffers for all of the plugin's inputs and
+    /// outputs based on channel pointers provided by the host.
+    pub buffer_manager: AtomicRefCell<BufferManager>,
+    /// The incoming events for the plugin, if `P::ACCEPTS_MIDI` is set. If
+    /// `P::SAMPLE_ACCURATE_AUTOMATION`, this is also read in lockstep with the parameter change
+    /// block splitting.
+    ///
+    /// NOTE: Because with VST3 MIDI CC messages are sent as parameter changes and VST3 does not
+    ///       interleave parameter changes and note events, this queue has to be sorted when
+    ///       creating the process context
+    pub input_events: AtomicRefCell<VecDeque<PluginNoteEvent<P>>>,
+    /// Stores any events the plugin has output during the current processing cycle, analogous to
+    /// `input_events`.
+    pub output_events: AtomicRefCell<VecDeque<PluginNoteEvent<P>>>,
+    /// VST3 has several useful predefined note expressions, but for some reason they are the only
+    /// note event type that don't have MIDI note ID and channel fields. So we need to keep track of
+    /// the most recent VST3 note IDs we've seen, and then map those back to MIDI note IDs and
+    /// channels as needed.
+    pub note_expression_controller: AtomicRefCell<NoteExpressionController>,
+    /// Unprocessed parameter changes and note events sent by the host during a process call.
+    /// Parameter changes are sent as separate queues for each parameter, and note events are in
+    /// another queue on top of that. And if `P::MIDI_INPUT >= MidiConfig::MidiCCs`, then we can
+    /// also receive MIDI CC messages through special parameter changes. On top of that, we also
+    /// support sample accurate automation through block splitting if
+    /// `P::SAMPLE_ACCURATE_AUTOMATION` is set. To account for all of this, we'll read all of the
+    /// parameter changes and events into a vector at the start of the process call, sort it, and
+    /// then do the block splitting based on that. Note events need to have their timing adjusted to
+    /// match the block start, since they're all read upfront.
+    pub process_events: AtomicRefCell<Vec<ProcessEvent<P>>>,
+    /// Decodes 14-bit CCs and (N)RPN messages from the MIDI CC pseudo-parameters if
+    /// `P::MIDI_INPUT` is set to `MidiConfig::HighResMidiCCs`. This is applied to the sorted
+    /// `process_events` since each CC is sent through its own parameter queue.
+    pub midi_cc_decoder: AtomicRefCell<MidiCCDecoder>,
+    /// The plugin is able to restore state through a method on the `GuiContext`. To avoid changing
+    /// parameters mid-processing and running into garbled data if the host also tries to load state
+    /// at the same time the restoring happens at the end of each processing call. If this zero
+    /// capacity channel contains state data at that point, then the audio thread will take the
+    /// state out of the channel, restore the state, and then send it back through the same channel.
+    /// In other words, the GUI thread acts as a sender and then as a receiver, while the audio
+    /// thread acts as a receiver and then as a sender. That way deallocation can happen on the GUI
+    /// thread. All of this happens without any blocking on the audio thread.
+    pub updated_state_sender: channel::Sender<PluginState>,
+    /// The receiver belonging to [`new_state_sender`][Self::new_state_sender].
+    pub updated_state_receiver: channel::Receiver<PluginState>,
+
+    /// The keys from `param_map` in a stable order.
+    pub param_hashes: Vec<u32>,
+    /// A mapping from parameter ID hashes (obtained from the string parameter IDs) to pointers to
+    /// parameters belonging to the plugin. These addresses will remain stable as long as the
+    /// `params` object does not get deallocated.
+    pub param_by_hash: HashMap<u32, ParamPtr>,
+    /// Mappings from parameter hashes to string parameter IDs. Used for notifying the plugin's
+    /// editor about parameter changes.
+    pub param_id_by_hash: HashMap<u32, String>,
+    pub param_units: ParamUnits,
+    /// The plugin's parameter groups, as returned by [`Params::param_groups()`]. These are used
+    /// for the VST3 units' names and order, and they're exposed to the editor through the
+    /// [`GuiContext`][crate::prelude::GuiContext].
+    pub param_groups: Vec<ParamGroup>,
+    /// Mappings from string parameter identifiers to parameter hashes. Useful for debug logging
+    /// and when storing and restoring plugin state.
+    pub param_id_to_hash: HashMap<String, u32>,
+    /// The inverse mapping from [`param_by_hash`][Self::param_by_hash]. This is needed to be able
+    /// to have an ergonomic parameter setting API that uses references to the parameters instead of
+    /// having to add a setter function to the parameter (or even worse, have it be completely
+    /// untyped).
+    pub param_ptr_to_hash: HashMap<ParamPtr, u32>,
+
+    /// The plugin's factory programs, parsed from `P::VST3_FACTORY_PROGRAMS`. If this is not empty,
+    /// then these are exposed as a program list and a program change parameter is registered.
+    pub factory_programs: Vec<Preset>,
+    /// The index of the factory program that was last selected through the program change
+    /// parameter, or [`VST3_NO_PROGRAM_SELECTED`] if the host has not selected a program yet.
+    pub current_program: AtomicU32,
+
+    /// The undo history for changes made from the editor. This is kept here so it survives the
+    /// editor being closed.
+    pub undo_history: UndoHistory,
+    /// The snapshot slots, if the plugin has set [`Plugin::SNAPSHOT_CONFIG`]. Recalled snapshots
+    /// are loaded at the end of a processing cycle, and the parameters are then crossfaded to their
+    /// new values before the next blocks are processed.
+    pub snapshots: Option<Snapshots>,
+    /// The plugin's MIDI learn mappings. These are applied to the MIDI CC parameter changes if
+    /// `P::MIDI_INPUT >= MidiConfig::MidiCCs`.
+    pub midi_learn: MidiLearn,
+}
+
+/// Tasks that can be sent from the plugin to be executed on the main thread in a non-blocking
+/// realtime-safe way (either a random thread or `IRunLoop` on Linux, the OS' message loop on
+/// Windows and macOS).
+#[allow(clippy::enum_variant_names)]
+pub enum Task<P: Plugin> {
+    /// Execute one of the plugin's background tasks.
+    PluginTask(P::BackgroundTask),
+    /// Inform the plugin that one or more parameter values have changed.
+    ParameterValuesChanged,
+    /// Inform the plugin that one parameter's value has changed. This uses the parameter hashes
+    /// since the task will be created from the audio thread.
+    ParameterValueChanged(u32, f32),
+    /// Trigger a restart with the given restart flags. This is a bit set of the flags from
+    /// [`vst3_sys::vst::RestartFlags`].
+    TriggerRestart(i32),
+    /// Request the editor to be resized according to its current size. Right now there is no way to
+    /// handle "denied resize" requests yet.
+    RequestResize,
+    /// Load the factory program with this index. Sent when the host changes the program change
+    /// parameter, which may happen on the audio thread.
+    LoadProgram(usize),
+    /// Inform the host that the plugin's note names have changed, so the program list's pitch names
+    /// need to be queried again.
+    NoteNamesChanged,
+    /// Report a parameter change made by a MIDI learn mapping on the audio thread to the host. This
+    /// uses the same begin, perform, and end edit calls as changes made from the editor so the
+    /// host's automation stays in sync with the plugin.
+    MidiLearnParameterChange(u32, f32),
+}
+
+/// VST3 makes audio processing pretty complicated. In order to support both block splitting for
+/// sample accurate automation and MIDI CC handling through parameters we need to put all parameter
+/// changes and (translated) note events into a sorted array first.
+#[derive(Debug, PartialEq)]
+pub enum ProcessEvent<P: Plugin> {
+    /// An incoming parameter change sent by the host. This will only be used when sample accurate
+    /// automation has been enabled, and the parameters are only updated when we process this
+    /// spooled event at the start of a block.
+    ParameterChange {
+        /// The event's sample offset within the buffer. Used for sorting.
+        timing: u32,
+        /// The parameter's hash, as used everywhere else.
+        hash: u32,
+        /// The normalized values, as provided by the host.
+        normalized_value: f32,
+    },
+    /// An incoming parameter change sent by the host. This will only be used when sample accurate
+    /// automation has been enabled, and the parameters are only updated when we process this
+    /// spooled event at the start of a block.
+    ///
+    /// The timing stored within the note event needs to have the block start index subtraced from
+    /// it. make sure to subtract the block start index with [`NoteEvent::subtract_timing()`] before
+    /// putting this into the input event queue.
+    NoteEvent(PluginNoteEvent<P>),
+}
+
+impl<P: Vst3Plugin> WrapperInner<P> {
+    #[allow(unused_unsafe)]
+    pub fn new() -> Arc<Self> {
+        let mut plugin = P::default();
+        let task_executor = Mutex::new(plugin.task_executor());
+
+        // This is used to allow the plugin to restore preset data from its editor, see the comment
+        // on `Self::updated_state_sender`
+        let (updated_state_sender, updated_state_receiver) = channel::bounded(0);
+
+        // This is a mapping from the parameter IDs specified by the plugin to pointers to those
+        // parameters. These pointers are assumed to be safe to dereference as long as
+        // `wrapper.plugin` is alive. The plugin API identifiers these parameters by hashes, which
+        // we'll calculate from the string ID specified by the plugin. These parameters should also
+        // remain in the same order as the one returned by the plugin.
+        let params = plugin.params();
+        let note_names = plugin.note_names();
+        let param_id_hashes_ptrs_groups: Vec<_> = params
+            .param_map()
+            .into_iter()
+            .map(|(id, ptr, group)| {
+                let hash = hash_param_id(&id);
+                (id, hash, ptr, group)
+            })
+            .collect();
+        let param_groups = params.param_groups();
+        if cfg!(debug_assertions) {
+            let param_map = params.param_map();
+            group::check_bypass_params(&param_groups, &param_map);
+
+            let param_ids: HashSet<_> = param_id_hashes_ptrs_groups
+                .iter()
+                .map(|(id, _, _, _)| id.clone())
+                .collect();
+            nih_debug_assert_eq!(
+                param_map.len(),
+                param_ids.len(),
+                "The plugin has duplicate parameter IDs, weird things may happen. Consider using \
+                 6 character parameter IDs to avoid collisions."
+            );
+
+            let mut bypass_param_exists = false;
+            for (id, hash, ptr, _) in &param_id_hashes_ptrs_groups {
+                let flags = unsafe { ptr.flags() };
+                let is_bypass = flags.contains(ParamFlags::BYPASS);
+
+                if is_bypass && bypass_param_exists {
+                    nih_debug_assert_failure!(
+                        "Duplicate bypass parameters found, the host will only use the first one"
+                    );
+                }
+
+                bypass_param_exists |= is_bypass;
+
+                if !P::VST3_FACTORY_PROGRAMS.is_empty() && *hash == VST3_PROGRAM_CHANGE_PARAM_ID {
+                    nih_debug_assert_failure!(
+                        "Parameter '{}' collides with the program change parameter, consider \
+                         giving it a different ID",
+                        id
+                    );
+                }
+                if P::MIDI_INPUT >= MidiConfig::MidiCCs
+                    && (VST3_MIDI_PARAMS_START..VST3_MIDI_PARAMS_END).contains(hash)
+                {
+                    nih_debug_assert_failure!(
+                        "Parameter '{}' collides with an automatically generated MIDI CC \
+                         parameter, consider giving it a different ID",
+                        id
+                    );
+                }
+            }
+        }
+
+        let param_hashes = param_id_hashes_ptrs_groups
+            .iter()
+            .map(|(_, hash, _, _)| *hash)
+            .collect();
+        let param_by_hash = param_id_hashes_ptrs_groups
+            .iter()
+            .map(|(_, hash, ptr, _)| (*hash, *ptr))
+            .collect();
+        let param_id_by_hash = param_id_hashes_ptrs_groups
+            .iter()
+            .map(|(id, hash, _, _)| (*hash, id.clone()))
+            .collect();
+        let param_units = ParamUnits::from_param_groups(
+            param_id_hashes_ptrs_groups
+                .iter()
+                .map(|(_, hash, _, group_name)| (*hash, group_name.as_str())),
+            &param_groups,
+        )
+        .expect("Inconsistent parameter groups");
+        let param_id_to_hash = param_id_hashes_ptrs_groups
+            .iter()
+            .map(|(id, hash, _, _)| (id.clone(), *hash))
+            .collect();
+        let param_ptr_to_hash = param_id_hashes_ptrs_groups
+            .into_iter()
+            .map(|(_, hash, ptr, _)| (ptr, hash))
+            .collect();
+
+        // Programs that can't be parsed or that belong to another plugin are skipped
+        let program_library =
+            PresetLibrary::new::<P>().with_embedded_factory_presets(P::VST3_FACTORY_PROGRAMS);
+        let factory_programs = (0..P::VST3_FACTORY_PROGRAMS.len())
+            .filter_map(
+                |idx| match program_library.load(&PresetLocation::Embedded(idx)) {
+                    Ok(preset) => Some(preset),
+                    Err(err) => {
+                        nih_debug_assert_failure!("Skipping factory program {}: {:#}", idx, err);
+                        None
+                    }
+                },
+            )
+            .collect();
+
+        let wrapper = Arc::new(Self {
+            plugin: Mutex::new(plugin),
+            task_executor,
+            params,
+            note_names,
+            // Initialized later as it needs a reference to the wrapper for the async executor
+            editor: AtomicRefCell::new(None),
+
+            component_handler: AtomicRefCell::new(None),
+
+            plug_view: RwLock::new(None),
+
+            event_loop: AtomicRefCell::new(None),
+
+            is_processing: AtomicBool::new(false),
+            // Some hosts, like the current version of Bitwig and Ardour at the time of writing,
+            // will try using the plugin's default not yet initialized bus arrangement. Because of
+            // that, we'll always initialize this configuration even before the host requests a
+            // channel layout.
+            current_audio_io_layout: AtomicCell::new(
+                P::AUDIO_IO_LAYOUTS.first().copied().unwrap_or_default(),
+            ),
+            current_buffer_config: AtomicCell::new(None),
+            current_process_mode: AtomicCell::new(ProcessMode::Realtime),
+            last_process_status: AtomicCell::new(ProcessStatus::Normal),
+            current_latency: AtomicU32::new(0),
+            // This is initialized just before calling `Plugin::initialize()` so that during the
+            // process call buffers can be initialized without any allocations
+            buffer_manager: AtomicRefCell::new(BufferManager::for_audio_io_layout(
+                0,
+                AudioIOLayout::default(),
+            )),
+            input_events: AtomicRefCell::new(VecDeque::with_capacity(1024)),
+            output_events: AtomicRefCell::new(VecDeque::with_capacity(1024)),
+            note_expression_controller: AtomicRefCell::new(NoteExpressionController::default()),
+            process_events: AtomicRefCell::new(Vec::with_capacity(4096)),
+            midi_cc_decoder: AtomicRefCell::new(MidiCCDecoder::new()),
+            updated_state_sender,
+            updated_state_receiver,
+
+            param_hashes,
+            param_by_hash,
+            param_id_by_hash,
+            param_units,
+            param_groups,
+            param_id_to_hash,
+            param_ptr_to_hash,
+
+            factory_programs,
+            current_program: AtomicU32::new(VST3_NO_PROGRAM_SELECTED),
+
+            undo_history: UndoHistory::default(),
+            snapshots: P::SNAPSHOT_CONFIG.map(Snapshots::new),
+            midi_learn: MidiLearn::default(),
+        });
+
+        // FIXME: Right now this is safe, but if we are going to have a singleton main thread queue
+        //        serving multiple plugin instances, Arc can't be used because its reference count
+        //        is separate from the internal COM-style reference count.
+        *wrapper.event_loop.borrow_mut() =
+            Some(OsEventLoop::new_and_spawn(Arc::downgrade(&wrapper)));
+
+        // The editor also needs to be initialized later so the Async executor can work.
+        *wrapper.editor.borrow_mut() = wrapper
+            .plugin
+            .lock()
+            .editor(AsyncExecutor {
+                execute_background: Arc::new({
+                    let wrapper = wrapper.clone();
+
+                    move |task| {
+                        let task_posted = wrapper.schedule_background(Task::PluginTask(task));
+                        nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
+                    }
+                }),
+                execute_gui: Arc::new({
+                    let wrapper = wrapper.clone();
+
+                    move |task| {
+                        let task_posted = wrapper.schedule_gui(Task::PluginTask(task));
+                        nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
+                    }
+                }),
+            })
+            .map(|editor| Arc::new(Mutex::new(editor)));
+
+        // The host needs to query the pitch names again whenever the plugin changes them
+        if let Some(note_names) = &wrapper.note_names {
+            note_names.set_change_callback({
+                let wrapper = Arc::downgrade(&wrapper);
+
+                move || {
+                    if let Some(wrapper) = wrapper.upgrade() {
+                        let task_posted = wrapper.schedule_gui(Task::NoteNamesChanged);
+                        nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
+                    }
+                }
+            });
+        }
+
+        wrapper
+    }
+
+    /// Whether the plugin exposes a program list. This is needed for the factory programs, and
+    /// for note names since VST3 ties pitch names to programs.
+    pub fn has_program_list(&self) -> bool {
+        !self.factory_programs.is_empty() || self.note_names.is_some()
+    }
+
+    /// The number of programs in the program list. If the plugin only has note names, then the
+    /// program list contains a single default program.
+    pub fn num_programs(&self) -> usize {
+        if self.has_program_list() {
+            self.factory_programs.len().max(1)
+        } else {
+            0
+        }
+    }
+
+    pub fn make_gui_context(self: Arc<Self>) -> Arc<WrapperGuiContext<P>> {
+        Arc::new(WrapperGuiContext {
+            inner: self,
+            #[cfg(debug_assertions)]
+            param_gesture_checker: Default::default(),
+        })
+    }
+
+    /// # Note
+    ///
+    /// The lock on the plugin must be dropped before this object is dropped to avoid deadlocks
+    /// caused by reentrant function calls.
+    pub fn make_init_context(&self) -> WrapperInitContext<'_, P> {
+        WrapperInitContext {
+            inner: self,
+            pending_requests: Default::default(),
+        }
+    }
+
+    pub fn make_process_context(&self, transport: Transport) -> WrapperProcessContext<'_, P> {
+        WrapperProcessContext {
+            inner: self,
+            input_events_guard: self.input_events.borrow_mut(),
+            output_events_guard: self.output_events.borrow_mut(),
+            transport,
+        }
+    }
+
+    /// Posts the task to the background task queue using [`EventLoop::schedule_background()`] so it
+    /// can be run in the background without blocking either the GUI or the audio thread.
+    ///
+    /// If the task queue is full, then this will return false.
+    #[must_use]
+    pub fn schedule_background(&self, task: Task<P>) -> bool {
+        let event_loop = self.event_loop.borrow();
+        let event_loop = event_loop.as_ref().unwrap();
+        event_loop.schedule_background(task)
+    }
+
+    /// Either posts the task to the task queue using [`EventLoop::schedule_gui()`] so it can be
+    /// delegated to the main thread, executes the task directly if this is the main thread, or runs
+    /// the task on the host's `IRunLoop` if the GUI is open and it exposes one.
+    ///
+    /// If the task queue is full, then this will return false.
+    #[must_use]
+    pub fn schedule_gui(&self, task: Task<P>) -> bool {
+        let event_loop = self.event_loop.borrow();
+        let event_loop = event_loop.as_ref().unwrap();
+        if event_loop.is_main_thread() {
+            self.execute(task, true);
+            true
+        } else {
+            // If the editor is open, and the host exposes the `IRunLoop` interface, then we'll run
+            // the task on the host's GUI thread using that interface. Otherwise we'll use the
+            // regular event loop. If the editor gets dropped while there's still outstanding work
+            // left in the run loop task queue, then those tasks will be posted to the regular event
+            // loop so no work is lost.
+            match &*self.plug_view.read() {
+                Some(plug_view) => match plug_view.do_maybe_in_run_loop(task) {
+                    Ok(()) => true,
+                    Err(task) => event_loop.schedule_gui(task),
+                },
+                None => event_loop.schedule_gui(task),
+            }
+        }
+    }
+
+    /// Get a parameter's ID based on a `ParamPtr`. Used in the `GuiContext` implementation for the
+    /// gesture checks.
+    #[allow(unused)]
+    pub fn param_id_from_ptr(&self, param: ParamPtr) -> Option<&str> {
+        self.param_ptr_to_hash
+            .get(&param)
+            .and_then(|hash| self.param_id_by_hash.get(hash))
+            .map(|s| s.as_str())
+    }
+
+    /// Convenience function for setting a value for a parameter as triggered by a VST3 parameter
+    /// update. The same rate is for updating parameter smoothing.
+    ///
+    /// After calling this function, you should call
+    /// [`notify_param_values_changed()`][Self::notify_param_values_changed()] to allow the editor
+    /// to update itself. This needs to be done separately so you can process parameter changes in
+    /// batches.
+    pub fn set_normalized_value_by_hash(
+        &self,
+        hash: u32,
+        normalized_value: f32,
+        sample_rate: Option<f32>,
+    ) -> tresult {
+        if hash == VST3_PROGRAM_CHANGE_PARAM_ID && !self.factory_programs.is_empty() {
+            // Loading a program is not realtime-safe, so this is done on the GUI thread
+            let program_idx = self.program_index_from_normalized(normalized_value);
+            let old_program_idx = self
+                .current_program
+                .swap(program_idx as u32, Ordering::SeqCst);
+            if old_program_idx != program_idx as u32 {
+                let task_posted = self.schedule_gui(Task::LoadProgram(program_idx));
+                nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
+            }
+
+            return kResultOk;
+        }
+
+        match self.param_by_hash.get(&hash) {
+            Some(param_ptr) => {
+                if unsafe { param_ptr.set_normalized_value(normalized_value) } {
+                    if let Some(sample_rate) = sample_rate {
+                        unsafe { param_ptr.update_smoother(sample_rate, false) };
+                    }
+
+                    let task_posted =
+                        self.schedule_gui(Task::ParameterValueChanged(hash, normalized_value));
+                    nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
+                }
+
+                kResultOk
+            }
+            _ => kInvalidArgument,
+        }
+    }
+
+    /// The index of the factory program selected by a normalized program change parameter value.
+    pub fn program_index_from_normalized(&self, normalized_value: f32) -> usize {
+        let max_idx = self.factory_programs.len().saturating_sub(1);
+
+        ((normalized_value.clamp(0.0, 1.0) * max_idx as f32).round() as usize).min(max_idx)
+    }
+
+    /// The normalized program change parameter value for a factory program.
+    pub fn program_index_to_normalized(&self, program_idx: usize) -> f32 {
+        match self.factory_programs.len() {
+            0 | 1 => 0.0,
+            num_programs => program_idx as f32 / (num_programs - 1) as f32,
+        }
+    }
+
+    /// Get the plugin's state object, may be called by the plugin's GUI as part of its own preset
+    /// management. The wrapper doesn't use these functions and serializes and deserializes directly
+    /// the JSON in the relevant plugin API methods instead.
+    pub fn get_state_object(&self) -> PluginState {
+        unsafe {
+            state::serialize_object::<P>(
+                self.params.clone(),
+                state::make_params_iter(&self.param_by_hash, &self.param_id_to_hash),
+            )
+        }
+    }
+
+    /// Update the plugin's internal state, called by the plugin itself from the GUI thread. To
+    /// prevent corrupting data and changing parameters during processing the actual state is only
+    /// updated at the end of the audio processing cycle.
+    pub fn set_state_object_from_gui(&self, mut state: PluginState) {
+        // Use a loop and timeouts to handle the super rare edge case when this function gets called
+        // between a process call and the host disabling the plugin
+        loop {
+            if self.is_processing.load(Ordering::SeqCst) {
+                // If the plugin is currently processing audio, then we'll perform the restore
+                // operation at the end of the audio call. This involves sending the state to the
+                // audio thread, having the audio thread handle the state restore at the very end of
+                // the process function, and then sending the state back to this thread so it can be
+                // deallocated without blocking the audio thread.
+                match self
+                    .updated_state_sender
+                    .send_timeout(state, Duration::from_secs(1))
+                {
+                    Ok(_) => {
+                        // As mentioned above, the state object will be passed back to this thread
+                        // so we can deallocate it without blocking.
+                        let state = self.updated_state_receiver.recv();
+                        drop(state);
+                        break;
+                    }
+                    Err(SendTimeoutError::Timeout(value)) => {
+                        state = value;
+                        continue;
+                    }
+                    Err(SendTimeoutError::Disconnected(_)) => {
+                        nih_debug_assert_failure!("State update channel got disconnected");
+                        return;
+                    }
+                }
+            } else {
+                // Otherwise we'll set the state right here and now, since this function should be
+                // called from a GUI thread
+                self.set_state_inner(&mut state);
+                break;
+            }
+        }
+
+        // After the state has been updated, notify the host about the new parameter values
+        let task_posted =
+            self.event_loop
+                .borrow()
+                .as_ref()
+                .unwrap()
+                .schedule_gui(Task::TriggerRestart(
+                    RestartFlags::kParamValuesChanged as i32,
+                ));
+        nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
+    }
+
+    pub fn set_latency_samples(&self, samples: u32) {
+        // Only trigger a restart if it's actually needed
+        let old_latency = self.current_latency.swap(samples, Ordering::SeqCst);
+        if old_latency != samples {
+            let task_posted =
+                self.schedule_gui(Task::TriggerRestart(RestartFlags::kLatencyChanged as i32));
+            nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
+        }
+    }
+
+    /// Immediately set the plugin state. Returns `false` if the deserialization failed. The plugin
+    /// state is set from a couple places, so this function aims to deduplicate that. Includes
+    /// `permit_alloc()`s around the deserialization and initialization for the use case where
+    /// `set_state_object_from_gui()` was called while the plugin is process audio.
+    ///
+    /// Implicitly emits `Task::ParameterValuesChanged`.
+    ///
+    /// # Notes
+    ///
+    /// `self.plugin` must _not_ be locked while calling this function or it will deadlock.
+    pub fn set_state_inner(&self, state: &mut PluginState) -> bool {
+        let audio_io_layout = self.current_audio_io_layout.load();
+        let buffer_config = self.current_buffer_config.load();
+
+        // A crossfade towards a recalled snapshot would otherwise overwrite the new parameter values
+        if let Some(snapshots) = &self.snapshots {
+            snapshots.stop_crossfade();
+        }
+
+        // FIXME: This is obviously not realtime-safe, but loading presets without doing this could
+        //        lead to inconsistencies. It's the plugin's responsibility to not perform any
+        //        realtime-unsafe work when the initialize function is called a second time if it
+        //        supports runtime preset loading.  `state::deserialize_object()` normally never
+        //        allocates, but if the plugin has persistent non-parameter data then its
+        //        `deserialize_fields()` implementation may still allocate.
+        let mut success = permit_alloc(|| unsafe {
+            state::deserialize_object::<P>(
+                state,
+                self.params.clone(),
+                state::make_params_getter(&self.param_by_hash, &self.param_id_to_hash),
+                buffer_config.as_ref(),
+            )
+        });
+        if !success {
+            nih_debug_assert_failure!("Deserializing plugin state from a state object failed");
+            return false;
+        }
+
+        // If the plugin was already initialized then it needs to be reinitialized
+        if let Some(buffer_config) = buffer_config {
+            // NOTE: This needs to be dropped after the `plugin` lock to avoid deadlocks
+            let mut init_context = self.make_init_context();
+            let mut plugin = self.plugin.lock();
+
+            // See above
+            success = permit_alloc(|| {
+                plugin.initialize(&audio_io_layout, &buffer_config, &mut init_context)
+            });
+            if success {
+                process_wrapper(|| plugin.reset());
+            }
+        }
+
+        nih_debug_assert!(
+            success,
+            "Plugin returned false when reinitializing after loading state"
+        );
+
+        // Reinitialize the plugin after loading state so it can respond to the new parameter values
+        let task_posted = self.schedule_gui(Task::ParameterValuesChanged);
+        nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
+
+        // TODO: Right now there's no way to know if loading the state changed the GUI's size. We
+        //       could keep track of the last known size and compare the GUI's current size against
+        //       that but that also seems brittle.
+        if self.plug_view.read().is_some() {
+            let task_posted = self.schedule_gui(Task::RequestResize);
+            nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
+        }
+
+        success
+    }
+}
+
+impl<P: Vst3Plugin> MainThreadExecutor<Task<P>> for WrapperInner<P> {
+    fn execute(&self, task: Task<P>, is_gui_thread: bool) {
+        // This function is always called from the main thread
+        match task {
+            Task::PluginTask(task) => (self.task_executor.lock())(task),
+            Task::ParameterValuesChanged => {
+                if self.plug_view.read().is_some() {
+                    if let Some(editor) = self.editor.borrow().as_ref() {
+                        editor.lock().param_values_changed();
+                    }
+                }
+            }
+            Task::ParameterValueChanged(param_hash, normalized_value) => {
+                if self.plug_view.read().is_some() {
+                    if let Some(editor) = self.editor.borrow().as_ref() {
+                        let param_id = &self.param_id_by_hash[&param_hash];
+                        editor
+                            .lock()
+                            .param_value_changed(param_id, normalized_value);
+                    }
+                }
+            }
+            Task::TriggerRestart(flags) => match &*self.component_handler.borrow() {
+                Some(handler) => unsafe {
+                    nih_debug_assert!(is_gui_thread);
+                    let result = handler.restart_component(flags);
+                    nih_debug_assert_eq!(
+                        result,
+                        kResultOk,
+                        "Failed the restart request call with flags '{:?}'",
+                        flags
+                    );
+                },
+                None => nih_debug_assert_failure!("Component handler not yet set"),
+            },
+            Task::RequestResize => match &*self.plug_view.read() {
+                Some(plug_view) => unsafe {
+                    nih_debug_assert!(is_gui_thread);
+                    let success = plug_view.request_resize();
+                    nih_debug_assert!(success, "Failed requesting a window resize");
+                },
+                None => nih_debug_assert_failure!("Can't resize a closed editor"),
+            },
+            Task::LoadProgram(program_idx) => match self.factory_programs.get(program_idx) {
+                Some(program) => {
+                    // This uses the same path as `GuiContext::set_state()` so the state is only
+                    // loaded in between process calls
+                    self.set_state_object_from_gui(program.state.clone());
+                    nih_trace!("Loaded factory program '{}'", program.metadata.name);
+                }
+                None => nih_debug_assert_failure!("Unknown factory program {}", program_idx),
+            },
+            Task::MidiLearnParameterChange(param_hash, normalized_value) => {
+                match &*self.component_handler.borrow() {
+                    Some(handler) => unsafe {
+                        nih_debug_assert!(is_gui_thread);
+                        handler.begin_edit(param_hash);
+                        handler.perform_edit(param_hash, normalized_value as f64);
+                        handler.end_edit(param_hash);
+                    },
+                    None => nih_debug_assert_failure!("Component handler not yet set"),
+                }
+            }
+            Task::NoteNamesChanged => {
+                // The names are exposed through the program list, so the host is told that the
+                // entire list has changed
+                if let Some(unit_handler) = self
+                    .component_handler
+                    .borrow()
+                    .as_ref()
+                    .and_then(|handler| handler.cast::<dyn IUnitHandler>())
+                {
+                    nih_debug_assert!(is_gui_thread);
+                    unsafe {
+                        unit_handler.notify_program_list_change(
+                            VST3_FACTORY_PROGRAM_LIST_ID,
+                            kAllProgramInvalid,
+                        )
+                    };
+                }
+            }
+        }
+    }
+}
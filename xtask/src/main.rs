@@ -1,3 +1,3 @@
-fn main() -> nih_plug_xtask::Result<()> {
-    nih_plug_xtask::main()
-}
+fn main() -> nih_plug_xtask::Result<()> {
+    nih_plug_xtask::main()
+}